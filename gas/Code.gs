@@ -1,6 +1,9 @@
 function doGet(e) {
-  // 直接実行時（eがない場合）のフォールバック
-  const action = (e && e.parameter && e.parameter.action) || 'getTemplates';
+  const rejected = verifyRequest(e, 'GET', canonicalParams(e));
+  if (rejected) {
+    return rejected;
+  }
+  const action = e.parameter.action || 'getTemplates';
 
   if (action === 'getTemplates') {
     return getTemplates();
@@ -26,6 +29,92 @@ function doGet(e) {
     .setMimeType(ContentService.MimeType.JSON);
 }
 
+// ===== リクエストの署名 =====
+// スクリプトプロパティ SHARED_SECRET にアプリの設定画面の「共有シークレット」と同じ値を設定する。
+// アプリはリクエストごとに ts（UNIX秒）・nonce・sig（HMAC-SHA256）をクエリに付ける。
+// 署名のないもの・正しくないもの・古いもの・同じ nonce の再送は拒否する。
+const SIGNATURE_MAX_AGE_SECONDS = 300;
+const NONCE_PREFIX = 'nonce_';
+const SIGNATURE_PARAMS = ['ts', 'nonce', 'sig'];
+/** スクリプトロックを取れないとき（例外にすると HTML のエラーページが返るので JSON のエラーにする） */
+const LOCK_BUSY_MESSAGE = 'サーバーが混み合っています。しばらくしてから再試行してください';
+
+/** 拒否するときの応答を返す（受け付けるなら null） */
+function verifyRequest(e, method, content) {
+  const secret = PropertiesService.getScriptProperties().getProperty('SHARED_SECRET');
+  if (!secret) {
+    return unauthorized('スクリプトプロパティ SHARED_SECRET が設定されていません');
+  }
+  const params = (e && e.parameter) || {};
+  if (!params.ts || !params.nonce || !params.sig) {
+    return unauthorized('署名がありません');
+  }
+  const ts = Number(params.ts);
+  const now = Math.floor(Date.now() / 1000);
+  if (!isFinite(ts) || Math.abs(now - ts) > SIGNATURE_MAX_AGE_SECONDS) {
+    return unauthorized('署名の有効期限が切れています。PCの時計を確認してください');
+  }
+  if (!/^[0-9a-f]{16,64}$/.test(params.nonce)) {
+    return unauthorized('署名が正しくありません');
+  }
+
+  const message = [method, params.ts, params.nonce, content].join('\n');
+  const expected = Utilities.computeHmacSha256Signature(message, secret, Utilities.Charset.UTF_8)
+    .map(b => ('0' + (b & 0xff).toString(16)).slice(-2))
+    .join('');
+  if (!constantTimeEquals(expected, String(params.sig))) {
+    return unauthorized('署名が正しくありません');
+  }
+
+  // 有効期限内に同じ nonce が届いたら再送（リプレイ）として拒否する
+  // ロックを取れないときは、署名の拒否とは別の JSON のエラーとして返す
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const cache = CacheService.getScriptCache();
+    const key = NONCE_PREFIX + params.nonce;
+    if (cache.get(key)) {
+      return unauthorized('同じリクエストが再送されました');
+    }
+    cache.put(key, '1', SIGNATURE_MAX_AGE_SECONDS * 2);
+  } finally {
+    lock.releaseLock();
+  }
+  return null;
+}
+
+/**
+ * GET の署名対象: 署名用以外のパラメータを名前順に「名前=値」で & につなぐ
+ * 値に & や = が入っても区切りと紛れないように、名前と値は encodeURIComponent でエンコードする
+ */
+function canonicalParams(e) {
+  const params = (e && e.parameter) || {};
+  return Object.keys(params)
+    .filter(key => SIGNATURE_PARAMS.indexOf(key) < 0)
+    .sort()
+    .map(key => encodeURIComponent(key) + '=' + encodeURIComponent(params[key]))
+    .join('&');
+}
+
+function constantTimeEquals(a, b) {
+  if (a.length !== b.length) {
+    return false;
+  }
+  let diff = 0;
+  for (let i = 0; i < a.length; i++) {
+    diff |= a.charCodeAt(i) ^ b.charCodeAt(i);
+  }
+  return diff === 0;
+}
+
+/** ウェブアプリは HTTP ステータスを返せないので、拒否したことは unauthorized で伝える */
+function unauthorized(message) {
+  return ContentService.createTextOutput(JSON.stringify({ success: false, unauthorized: true, error: message }))
+    .setMimeType(ContentService.MimeType.JSON);
+}
+
 // スプレッドシートのURLを返す
 function getSpreadsheetUrl() {
   const ss = SpreadsheetApp.getActiveSpreadsheet();
@@ -44,24 +133,30 @@ function testShowSpreadsheetUrl() {
 }
 
 function getTemplates() {
-  const ss = SpreadsheetApp.getActiveSpreadsheet();
-  let sheet = ss.getSheetByName('テンプレート');
-  
-  if (!sheet) {
-    sheet = ss.insertSheet('テンプレート');
-    sheet.appendRow(['Name', 'Subject', 'Body']);
-    sheet.appendRow(['Greeting', 'Hello', 'Hi {{name}},\n\nHow are you?']);
+  const sheet = templateSheet();
+
+  // ID のない行（シートに直接追加した行など）に ID を振る。ロックを取れなければ次の読み込みで振る
+  const lock = LockService.getScriptLock();
+  if (lock.tryLock(10000)) {
+    try {
+      assignTemplateIds(sheet);
+    } finally {
+      lock.releaseLock();
+    }
   }
 
   const data = sheet.getDataRange().getValues();
   const templates = [];
   
   for (let i = 1; i < data.length; i++) {
+    const id = String(data[i][TEMPLATE_ID_COL] || '');
+    if (!id) continue;
     templates.push({
-      id: String(i + 1),
+      id: id,
       name: data[i][0],
       subject: data[i][1],
-      body: data[i][2]
+      body: data[i][2],
+      updatedAt: String(data[i][3] || '')
     });
   }
 
@@ -69,26 +164,29 @@ function getTemplates() {
     .setMimeType(ContentService.MimeType.JSON);
 }
 
-function getRecipients() {
-  const ss = SpreadsheetApp.getActiveSpreadsheet();
-  let sheet = ss.getSheetByName('宛先リスト');
+// UpdatedAt は保存のたびに更新する版。アプリは読み込んだ版を送り、別の人が先に更新していれば競合として保存しない
+// ID は紐付けマスターから参照される。行を削除しても変わらないよう列に持つ
+const TEMPLATE_HEADERS = ['Name', 'Subject', 'Body', 'UpdatedAt', 'ID'];
+const TEMPLATE_ID_COL = 4;
 
-  if (!sheet) {
-    // 宛先リストがない場合は新規作成（デモデータ付き）
-    sheet = ss.insertSheet('宛先リスト');
-    sheet.appendRow(['ID', '会社名', '氏名', 'メールアドレス']);
-    sheet.appendRow(['1', 'Sample Corp', '田中 太郎 様', 'info@example.com']);
-  }
+// キーワードは添付ファイル名に含まれる語（例: 請求書）。空欄はその宛先の既定のテンプレート
+const LINKING_HEADERS = ['宛先ID', 'テンプレートID', 'キーワード'];
 
-  const data = sheet.getDataRange().getValues();
-  const recipients = [];
+const RECIPIENT_HEADERS = ['ID', '会社名', '氏名', 'メールアドレス', 'CC', 'BCC', '返信先'];
 
-  // ヘッダー行からカラムインデックスを動的に取得
-  const headers = data[0];
+/**
+ * 見出しから宛先リストの列の位置（0始まり）を求める
+ * 読み込み・書き込みとも、列の並び順ではなくこの位置を使う
+ */
+function recipientColumnMap(headers) {
   const colMap = {};
   for (let j = 0; j < headers.length; j++) {
     const h = String(headers[j]).toLowerCase().trim();
-    if (h === 'id' || h === 'ID') colMap.id = j;
+    if (h === 'id') colMap.id = j;
+    // CC・BCC・返信先は「メールアドレス」より先に判定する（「返信先メール」などの見出しのため）
+    else if (h === 'bcc' || h.startsWith('bcc')) colMap.bcc = j;
+    else if (h === 'cc' || h.startsWith('cc')) colMap.cc = j;
+    else if (h.includes('reply') || h.includes('返信')) colMap.replyTo = j;
     else if (h.includes('会社') || h.includes('company')) colMap.company = j;
     else if (h.includes('氏名') || h.includes('name') || h.includes('名前') || h.includes('担当')) colMap.name = j;
     else if (h.includes('メール') || h.includes('email') || h.includes('mail') || h.includes('アドレス')) colMap.email = j;
@@ -99,14 +197,64 @@ function getRecipients() {
   if (colMap.company === undefined) colMap.company = 1;
   if (colMap.name === undefined) colMap.name = 2;
   if (colMap.email === undefined) colMap.email = 3;
+  return colMap;
+}
+
+/** 既存の宛先リストに CC・BCC・返信先の列がなければ、末尾に見出しを追加する */
+function ensureRecipientColumns(sheet) {
+  const lastCol = sheet.getLastColumn();
+  const colMap = recipientColumnMap(sheet.getRange(1, 1, 1, Math.max(lastCol, 1)).getValues()[0]);
+  const missing = [];
+  if (colMap.cc === undefined) missing.push('CC');
+  if (colMap.bcc === undefined) missing.push('BCC');
+  if (colMap.replyTo === undefined) missing.push('返信先');
+  if (missing.length > 0) {
+    sheet.getRange(1, lastCol + 1, 1, missing.length).setValues([missing]);
+  }
+}
+
+function getRecipients() {
+  const ss = SpreadsheetApp.getActiveSpreadsheet();
+  let sheet = ss.getSheetByName('宛先リスト');
+
+  if (!sheet) {
+    // 宛先リストがない場合は新規作成（デモデータ付き）
+    sheet = ss.insertSheet('宛先リスト');
+    sheet.appendRow(RECIPIENT_HEADERS);
+    sheet.appendRow(['1', 'Sample Corp', '田中 太郎 様', 'info@example.com', '', '', '']);
+  }
+
+  const data = sheet.getDataRange().getValues();
+  const recipients = [];
+
+  // ヘッダー行からカラムインデックスを動的に取得
+  const headers = data[0];
+  const colMap = recipientColumnMap(headers);
+
+  // 上記以外の列（部署・役職・顧客コードなど）は見出しをキーにした追加項目として返す
+  const knownCols = Object.values(colMap);
+  const customCols = [];
+  for (let j = 0; j < headers.length; j++) {
+    const header = String(headers[j]).trim();
+    if (header && knownCols.indexOf(j) === -1) customCols.push({ col: j, header: header });
+  }
+  const tz = Session.getScriptTimeZone();
+  const cellText = (v) => v instanceof Date ? Utilities.formatDate(v, tz, 'yyyy-MM-dd') : String(v === null || v === undefined ? "" : v);
 
   // ヘッダー行をスキップ
   for (let i = 1; i < data.length; i++) {
+    const optional = (col) => col === undefined ? "" : String(data[i][col] || "");
+    const customFields = {};
+    customCols.forEach(c => { customFields[c.header] = cellText(data[i][c.col]); });
     recipients.push({
       id: String(data[i][colMap.id] || i + 1),
       company: String(data[i][colMap.company] || ""),
       name: String(data[i][colMap.name] || ""),
-      email: String(data[i][colMap.email] || "")
+      email: String(data[i][colMap.email] || ""),
+      cc: optional(colMap.cc),
+      bcc: optional(colMap.bcc),
+      replyTo: optional(colMap.replyTo),
+      customFields: customFields
     });
   }
 
@@ -114,17 +262,19 @@ function getRecipients() {
     .setMimeType(ContentService.MimeType.JSON);
 }
 
-function getSignatures() {
+function signatureSheet() {
   const ss = SpreadsheetApp.getActiveSpreadsheet();
   let sheet = ss.getSheetByName('署名');
-  
   if (!sheet) {
     sheet = ss.insertSheet('署名');
     sheet.appendRow(['名前', '署名内容']);
-    sheet.appendRow(['デフォルト', '--\n株式会社サンプル\n田中 太郎\ninfo@example.com']);
+    sheet.appendRow(['デフォルト', '--\n株式会社サンプル\n{{sender_name}}\ninfo@example.com']);
   }
+  return sheet;
+}
 
-  const data = sheet.getDataRange().getValues();
+function getSignatures() {
+  const data = signatureSheet().getDataRange().getValues();
   const signatures = [];
   
   for (let i = 1; i < data.length; i++) {
@@ -144,8 +294,8 @@ function getLinkings() {
   
   if (!sheet) {
     sheet = ss.insertSheet('紐付けマスター');
-    sheet.appendRow(['宛先ID', 'テンプレートID']);
-    sheet.appendRow(['1', '2']);  // Sample: Recipient ID 1 linked to Template ID 2
+    sheet.appendRow(LINKING_HEADERS);
+    sheet.appendRow(['1', '2', '']);  // Sample: Recipient ID 1 linked to Template ID 2
   }
 
   const data = sheet.getDataRange().getValues();
@@ -154,7 +304,8 @@ function getLinkings() {
   for (let i = 1; i < data.length; i++) {
     linkings.push({
       recipient_id: String(data[i][0]),
-      template_id: String(data[i][1])
+      template_id: String(data[i][1]),
+      keyword: String(data[i][2] || '')
     });
   }
 
@@ -163,6 +314,11 @@ function getLinkings() {
 }
 
 function doPost(e) {
+  const rejected = verifyRequest(e, 'POST', e && e.postData ? e.postData.contents : '');
+  if (rejected) {
+    return rejected;
+  }
+
   let payload;
   try {
      payload = JSON.parse(e.postData.contents);
@@ -177,6 +333,8 @@ function doPost(e) {
     return sendMail(payload);
   } else if (action === 'sendBatchMail') {
     return sendBatchMail(payload);
+  } else if (action === 'getDeliveryStatus') {
+    return getDeliveryStatus(payload);
   } else if (action === 'saveSettings') {
     return saveSettings(payload);
   } else if (action === 'saveTemplate') {
@@ -185,15 +343,39 @@ function doPost(e) {
     return deleteTemplate(payload);
   } else if (action === 'saveRecipient') {
     return saveRecipient(payload);
+  } else if (action === 'updateRecipient') {
+    return updateRecipient(payload);
+  } else if (action === 'deleteRecipient') {
+    return deleteRecipient(payload);
+  } else if (action === 'saveLinking') {
+    return saveLinking(payload);
+  } else if (action === 'deleteLinking') {
+    return deleteLinking(payload);
+  } else if (action === 'saveSignature') {
+    return saveSignature(payload);
+  } else if (action === 'deleteSignature') {
+    return deleteSignature(payload);
   }
 
   return ContentService.createTextOutput(JSON.stringify({ error: 'Unknown action' }))
     .setMimeType(ContentService.MimeType.JSON);
 }
 
+/**
+ * cc・bcc・replyTo・htmlBody を GmailApp.sendEmail の options に設定する
+ * htmlBody があると body はプレーンテキストパートになる（multipart/alternative）
+ */
+function applyMailOptions(options, email) {
+  if (email.cc) options.cc = email.cc;
+  if (email.bcc) options.bcc = email.bcc;
+  if (email.replyTo) options.replyTo = email.replyTo;
+  if (email.htmlBody) options.htmlBody = email.htmlBody;
+  return options;
+}
+
 function sendMail(payload) {
   try {
-    const options = {};
+    const options = applyMailOptions({}, payload);
     if (payload.attachments && payload.attachments.length > 0) {
       options.attachments = payload.attachments.map(att => {
         return Utilities.newBlob(
@@ -234,42 +416,75 @@ function sendBatchMail(payload) {
       .setMimeType(ContentService.MimeType.JSON);
   }
 
-  for (const email of emails) {
+  // 結果は emails と同じ順序・同じ件数で返す（index でクライアント側と対応付ける）
+  emails.forEach((email, index) => {
+    if (!(email.to && email.subject && email.body)) {
+      results.push({ index: index, to: email.to || "", success: false, error: "宛先・件名・本文のいずれかが空です" });
+      return;
+    }
+
+    let claimed = false;
+    let sent = false;
     try {
-      if (email.to && email.subject && email.body) {
-        const options = {};
-        if (email.attachments && email.attachments.length > 0) {
-          options.attachments = email.attachments.map(att => {
-            return Utilities.newBlob(
-              Utilities.base64Decode(att.data),
-              att.mimeType,
-              att.fileName
-            );
-          });
+      // 同じ messageId は一度しか送らない（タイムアウト後の再試行・送信待ちからの再送で二重送信しない）
+      if (email.messageId) {
+        const claim = claimMessageId(email.messageId);
+        if (claim === 'sent') {
+          results.push({ index: index, to: email.to, success: true, alreadySent: true });
+          return;
         }
-        GmailApp.sendEmail(email.to, email.subject, email.body, options);
-        results.push({ to: email.to, success: true });
-        
-        // Log history
-        logSentMail({
-          to: email.to,
-          subject: email.subject,
-          body: email.body,
-          status: "Success"
+        if (claim === 'inFlight') {
+          results.push({ index: index, to: email.to, success: false, inFlight: true, error: "同じメールを別の処理が送信中です" });
+          return;
+        }
+        claimed = true;
+      }
+
+      const options = applyMailOptions({}, email);
+      if (email.attachments && email.attachments.length > 0) {
+        options.attachments = email.attachments.map(att => {
+          return Utilities.newBlob(
+            Utilities.base64Decode(att.data),
+            att.mimeType,
+            att.fileName
+          );
         });
       }
-    } catch (error) {
-      results.push({ to: email.to, success: false, error: error.toString() });
-      
-      // Log failure
+      GmailApp.sendEmail(email.to, email.subject, email.body, options);
+      sent = true;
+      if (claimed) {
+        markMessageSent(email.messageId);
+      }
+
+      // Log history
       logSentMail({
         to: email.to,
         subject: email.subject,
         body: email.body,
-        status: "Error: " + error.toString()
+        status: "Success"
       });
+    } catch (error) {
+      if (!sent) {
+        if (claimed) {
+          releaseMessageId(email.messageId);
+        }
+        results.push({ index: index, to: email.to, success: false, error: error.toString() });
+
+        // Log failure
+        logSentMail({
+          to: email.to,
+          subject: email.subject,
+          body: email.body,
+          status: "Error: " + error.toString()
+        });
+        return;
+      }
+      // 送信した後の記録に失敗しただけなので、送信済みとして返す
     }
-  }
+    results.push({ index: index, to: email.to, success: true });
+  });
+
+  pruneMessageIds();
 
   return ContentService.createTextOutput(JSON.stringify({
     success: true,
@@ -278,6 +493,96 @@ function sendBatchMail(payload) {
     .setMimeType(ContentService.MimeType.JSON);
 }
 
+const CLAIM_ID_PREFIX = 'claim_';
+const SENT_ID_PREFIX = 'sent_';
+
+/** 送信中の記録の有効期間（GAS の実行時間の上限6分より長くする） */
+const CLAIM_TTL_MS = 10 * 60 * 1000;
+
+/** 送信済みの記録を残す期間（送信待ちからの自動再送が終わるまで） */
+const SENT_ID_TTL_MS = 7 * 24 * 60 * 60 * 1000;
+
+/**
+ * messageId を送信中として記録する
+ * 'claimed' なら送信してよい。'sent' は送信済み、'inFlight' は別の実行が送信中
+ * 送信中の記録が CLAIM_TTL_MS より古ければ、その実行は送信を終えずに止まったとみなして送り直す
+ */
+function claimMessageId(messageId) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(30000)) {
+    return 'inFlight';
+  }
+  try {
+    const props = PropertiesService.getScriptProperties();
+    if (props.getProperty(SENT_ID_PREFIX + messageId)) {
+      return 'sent';
+    }
+    const claimedAt = Date.parse(props.getProperty(CLAIM_ID_PREFIX + messageId) || '');
+    if (!isNaN(claimedAt) && Date.now() - claimedAt < CLAIM_TTL_MS) {
+      return 'inFlight';
+    }
+    props.setProperty(CLAIM_ID_PREFIX + messageId, new Date().toISOString());
+    return 'claimed';
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+/** 送信できた messageId を送信済みとして記録する */
+function markMessageSent(messageId) {
+  const props = PropertiesService.getScriptProperties();
+  props.setProperty(SENT_ID_PREFIX + messageId, new Date().toISOString());
+  props.deleteProperty(CLAIM_ID_PREFIX + messageId);
+}
+
+/** 送信に失敗した messageId の送信中の記録を取り消す（再送できるようにする） */
+function releaseMessageId(messageId) {
+  try {
+    PropertiesService.getScriptProperties().deleteProperty(CLAIM_ID_PREFIX + messageId);
+  } catch (error) {
+    // 消せなくても CLAIM_TTL_MS を過ぎれば再送できる
+  }
+}
+
+/** 期限を過ぎた送信中・送信済みの記録を消す（スクリプトプロパティの容量を超えないように） */
+function pruneMessageIds() {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(1000)) {
+    return;  // 次の送信で消す
+  }
+  try {
+    const props = PropertiesService.getScriptProperties();
+    const all = props.getProperties();
+    const now = Date.now();
+    Object.keys(all).forEach(key => {
+      let ttl;
+      if (key.indexOf(SENT_ID_PREFIX) === 0) {
+        ttl = SENT_ID_TTL_MS;
+      } else if (key.indexOf(CLAIM_ID_PREFIX) === 0) {
+        ttl = CLAIM_TTL_MS;
+      } else {
+        return;
+      }
+      const at = Date.parse(all[key]);
+      if (isNaN(at) || now - at > ttl) {
+        props.deleteProperty(key);
+      }
+    });
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+/** messageIds のうち送信済みのものを返す（送信中のものは含めない） */
+function getDeliveryStatus(payload) {
+  const ids = Array.isArray(payload.messageIds) ? payload.messageIds : [];
+  const props = PropertiesService.getScriptProperties();
+  const delivered = ids.filter(id => props.getProperty(SENT_ID_PREFIX + id));
+
+  return ContentService.createTextOutput(JSON.stringify({ success: true, delivered: delivered }))
+    .setMimeType(ContentService.MimeType.JSON);
+}
+
 function getSettings() {
   const ss = SpreadsheetApp.getActiveSpreadsheet();
   let sheet = ss.getSheetByName('設定');
@@ -397,95 +702,428 @@ function getLogs() {
     .setMimeType(ContentService.MimeType.JSON);
 }
 
+function templateSheet() {
+  const ss = SpreadsheetApp.getActiveSpreadsheet();
+  let sheet = ss.getSheetByName('テンプレート');
+  if (!sheet) {
+    sheet = ss.insertSheet('テンプレート');
+    sheet.appendRow(TEMPLATE_HEADERS);
+    sheet.appendRow(['Greeting', 'Hello', 'Hi {{name}},\n\nHow are you?', '', Utilities.getUuid()]);
+  }
+  return sheet;
+}
+
+/**
+ * ID の列がなければ追加し、ID のない行に ID を振る（スクリプトロックを取ってから呼ぶ）
+ * 列を追加するときは、既存の紐付けがそのまま使えるよう以前の ID（行番号）を入れる
+ */
+function assignTemplateIds(sheet) {
+  const lastCol = sheet.getLastColumn();
+  if (lastCol < TEMPLATE_HEADERS.length) {
+    sheet.getRange(1, 1, 1, TEMPLATE_HEADERS.length).setValues([TEMPLATE_HEADERS]);
+    for (let row = 2; row <= sheet.getLastRow(); row++) {
+      sheet.getRange(row, TEMPLATE_ID_COL + 1).setValue(String(row));
+    }
+    return;
+  }
+  const data = sheet.getDataRange().getValues();
+  for (let i = 1; i < data.length; i++) {
+    if (!String(data[i][TEMPLATE_ID_COL] || '') && String(data[i][0] || '')) {
+      sheet.getRange(i + 1, TEMPLATE_ID_COL + 1).setValue(Utilities.getUuid());
+    }
+  }
+}
+
+function templateConflict(message) {
+  return ContentService.createTextOutput(JSON.stringify({ success: false, conflict: true, error: message }))
+    .setMimeType(ContentService.MimeType.JSON);
+}
+
+function findTemplateRow(data, name) {
+  for (let i = 1; i < data.length; i++) {
+    if (data[i][0] === name) return i;
+  }
+  return -1;
+}
+
+/**
+ * テンプレートを保存する
+ * payload.originalName: シート上の元の名前（新規作成なら null）
+ * payload.template.updatedAt: アプリが読み込んだ版。シートの版と違えば競合として保存しない
+ */
 function saveTemplate(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
   try {
-    const ss = SpreadsheetApp.getActiveSpreadsheet();
-    let sheet = ss.getSheetByName('テンプレート');
-    if (!sheet) {
-      sheet = ss.insertSheet('テンプレート');
-      sheet.appendRow(['Name', 'Subject', 'Body']);
-    }
+    const sheet = templateSheet();
+    assignTemplateIds(sheet);
 
     const template = payload.template;
+    const originalName = payload.originalName;
     const data = sheet.getDataRange().getValues();
-    let rowIndex = -1;
+    const row = findTemplateRow(data, originalName || template.name);
+    const updatedAt = new Date().toISOString();
+    const values = [[template.name, template.subject, template.body, updatedAt]];
 
-    // Search by Name (unique identifier for now)
-    for (let i = 1; i < data.length; i++) {
-      if (data[i][0] === template.name) {
-        rowIndex = i + 1;
-        break;
-      }
-    }
-
-    if (rowIndex > 0) {
-      sheet.getRange(rowIndex, 1, 1, 3).setValues([[template.name, template.subject, template.body]]);
+    if (!originalName) {
+      if (row > 0) return templateConflict('同じ名前のテンプレート「' + template.name + '」がすでにあります');
+      sheet.appendRow(values[0].concat([Utilities.getUuid()]));
     } else {
-      sheet.appendRow([template.name, template.subject, template.body]);
+      if (row < 0) return templateConflict('テンプレート「' + originalName + '」は他のユーザーが削除しました');
+      // 通信の再試行で同じ内容を保存し直した場合は成功扱い
+      if (data[row][0] === template.name && data[row][1] === template.subject && data[row][2] === template.body) {
+        return ContentService.createTextOutput(JSON.stringify({ success: true, updatedAt: String(data[row][3] || '') }))
+          .setMimeType(ContentService.MimeType.JSON);
+      }
+      if (String(data[row][3] || '') !== (template.updatedAt || '')) {
+        return templateConflict('テンプレート「' + originalName + '」は他のユーザーが更新しました');
+      }
+      if (template.name !== originalName && findTemplateRow(data, template.name) > 0) {
+        return templateConflict('同じ名前のテンプレート「' + template.name + '」がすでにあります');
+      }
+      sheet.getRange(row + 1, 1, 1, 4).setValues(values);
     }
 
-    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+    return ContentService.createTextOutput(JSON.stringify({ success: true, updatedAt: updatedAt }))
       .setMimeType(ContentService.MimeType.JSON);
   } catch (error) {
     return ContentService.createTextOutput(JSON.stringify({ success: false, error: error.toString() }))
       .setMimeType(ContentService.MimeType.JSON);
+  } finally {
+    lock.releaseLock();
   }
 }
 
+/** テンプレートを削除し、そのテンプレートへの紐付けも消す（確認から削除までロックを取る） */
 function deleteTemplate(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
   try {
-    const ss = SpreadsheetApp.getActiveSpreadsheet();
-    const sheet = ss.getSheetByName('テンプレート');
-    if (!sheet) throw "Sheet not found";
+    const sheet = templateSheet();
+    assignTemplateIds(sheet);
 
     const name = payload.name;
     const data = sheet.getDataRange().getValues();
+
+    // 読み込んだ後に他のユーザーが更新していたら削除しない（すでに削除済みなら成功扱い）
+    const row = findTemplateRow(data, name);
+    if (row > 0 && payload.updatedAt !== undefined && String(data[row][3] || '') !== (payload.updatedAt || '')) {
+      return templateConflict('テンプレート「' + name + '」は他のユーザーが更新しました');
+    }
     
+    const deletedIds = [];
     for (let i = data.length - 1; i >= 1; i--) {
       if (data[i][0] === name) {
+        deletedIds.push(String(data[i][TEMPLATE_ID_COL]));
         sheet.deleteRow(i + 1);
       }
     }
+    deleteLinkingsWhere(link => deletedIds.indexOf(String(link[1])) !== -1);
 
     return ContentService.createTextOutput(JSON.stringify({ success: true }))
       .setMimeType(ContentService.MimeType.JSON);
   } catch (error) {
     return ContentService.createTextOutput(JSON.stringify({ success: false, error: error.toString() }))
       .setMimeType(ContentService.MimeType.JSON);
+  } finally {
+    lock.releaseLock();
   }
 }
 
-function saveRecipient(payload) {
+/** 条件に合う紐付けの行を消す（紐付けの行 [宛先ID, テンプレートID, キーワード] を渡す） */
+function deleteLinkingsWhere(matches) {
+  const sheet = SpreadsheetApp.getActiveSpreadsheet().getSheetByName('紐付けマスター');
+  if (!sheet) return;
+  const data = sheet.getDataRange().getValues();
+  for (let i = data.length - 1; i >= 1; i--) {
+    if (matches(data[i])) sheet.deleteRow(i + 1);
+  }
+}
+
+function linkingSheet() {
+  const ss = SpreadsheetApp.getActiveSpreadsheet();
+  let sheet = ss.getSheetByName('紐付けマスター');
+  if (!sheet) {
+    sheet = ss.insertSheet('紐付けマスター');
+    sheet.appendRow(LINKING_HEADERS);
+  }
+  if (sheet.getLastColumn() < LINKING_HEADERS.length) {
+    sheet.getRange(1, 1, 1, LINKING_HEADERS.length).setValues([LINKING_HEADERS]);
+  }
+  return sheet;
+}
+
+/** 宛先とキーワードの組ごとに1件（同じ組があればテンプレートを差し替える） */
+function saveLinking(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
   try {
-    const ss = SpreadsheetApp.getActiveSpreadsheet();
-    let sheet = ss.getSheetByName('宛先リスト');
-    if (!sheet) {
-      sheet = ss.insertSheet('宛先リスト');
-      sheet.appendRow(['ID', '会社名', '氏名', 'メールアドレス']);
+    const sheet = linkingSheet();
+    const link = payload.linking;
+    const keyword = link.keyword || '';
+    const data = sheet.getDataRange().getValues();
+    for (let i = 1; i < data.length; i++) {
+      if (String(data[i][0]) === link.recipient_id && String(data[i][2] || '') === keyword) {
+        sheet.getRange(i + 1, 2).setValue(link.template_id);
+        return ContentService.createTextOutput(JSON.stringify({ success: true }))
+          .setMimeType(ContentService.MimeType.JSON);
+      }
+    }
+    sheet.appendRow([link.recipient_id, link.template_id, keyword]);
+    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+      .setMimeType(ContentService.MimeType.JSON);
+  } catch (error) {
+    return jsonError(error.toString());
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+/** 宛先とキーワードの組で削除する（すでにない場合も成功） */
+function deleteLinking(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const sheet = linkingSheet();
+    const link = payload.linking;
+    const keyword = link.keyword || '';
+    const data = sheet.getDataRange().getValues();
+    for (let i = data.length - 1; i >= 1; i--) {
+      if (String(data[i][0]) === link.recipient_id && String(data[i][2] || '') === keyword) {
+        sheet.deleteRow(i + 1);
+      }
     }
+    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+      .setMimeType(ContentService.MimeType.JSON);
+  } catch (error) {
+    return jsonError(error.toString());
+  } finally {
+    lock.releaseLock();
+  }
+}
 
+/**
+ * 署名を保存する。originalName があればその行を書き換える（名前の変更）
+ * 新規作成・名前の変更で同じ名前の署名があればエラー
+ */
+function saveSignature(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const sheet = signatureSheet();
+    const sig = payload.signature;
+    const original = payload.originalName;
+    const data = sheet.getDataRange().getValues();
+    let rowIndex = -1;
+    for (let i = 1; i < data.length; i++) {
+      const name = String(data[i][0]);
+      if (name === sig.name && name !== original) {
+        return jsonError('同じ名前の署名「' + sig.name + '」がすでにあります');
+      }
+      if (original && name === original) {
+        rowIndex = i + 1;
+      }
+    }
+    if (rowIndex > 0) {
+      sheet.getRange(rowIndex, 1, 1, 2).setValues([[sig.name, sig.content]]);
+    } else {
+      sheet.appendRow([sig.name, sig.content]);
+    }
+    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+      .setMimeType(ContentService.MimeType.JSON);
+  } catch (error) {
+    return jsonError(error.toString());
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+/** 名前で削除する（すでにない場合も成功） */
+function deleteSignature(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const sheet = signatureSheet();
+    const data = sheet.getDataRange().getValues();
+    for (let i = data.length - 1; i >= 1; i--) {
+      if (String(data[i][0]) === payload.name) {
+        sheet.deleteRow(i + 1);
+      }
+    }
+    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+      .setMimeType(ContentService.MimeType.JSON);
+  } catch (error) {
+    return jsonError(error.toString());
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+function saveRecipient(payload) {
+  // 同時に追加すると同じ ID を振ってしまうので、ID の採番から書き込みまでロックを取る
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const sheet = recipientSheet();
     const rec = payload.recipient;
     const data = sheet.getDataRange().getValues();
+    const colMap = recipientColumnMap(data[0]);
     let rowIndex = -1;
 
     // Search by ID or Email
     for (let i = 1; i < data.length; i++) {
-      if (data[i][0] === rec.id || (rec.email && data[i][3] === rec.email)) {
+      if (String(data[i][colMap.id]) === rec.id || (rec.email && data[i][colMap.email] === rec.email)) {
         rowIndex = i + 1;
         break;
       }
     }
 
     if (rowIndex > 0) {
-      sheet.getRange(rowIndex, 1, 1, 4).setValues([[rec.id, rec.company, rec.name, rec.email]]);
+      rec.id = rec.id || String(data[rowIndex - 1][colMap.id]);
     } else {
-      sheet.appendRow([rec.id || (data.length).toString(), rec.company, rec.name, rec.email]);
+      rec.id = rec.id || nextRecipientId(data, colMap);
+      rowIndex = sheet.getLastRow() + 1;
     }
+    writeRecipientRow(sheet, rowIndex, rec);
 
     return ContentService.createTextOutput(JSON.stringify({ success: true }))
       .setMimeType(ContentService.MimeType.JSON);
   } catch (error) {
     return ContentService.createTextOutput(JSON.stringify({ success: false, error: error.toString() }))
       .setMimeType(ContentService.MimeType.JSON);
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+/**
+ * ID で宛先を更新する（メールアドレスの修正にも使う）
+ * 他の行と同じメールアドレスにはできない
+ */
+function updateRecipient(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const sheet = recipientSheet();
+    const rec = payload.recipient;
+    const data = sheet.getDataRange().getValues();
+    const colMap = recipientColumnMap(data[0]);
+    let rowIndex = -1;
+
+    for (let i = 1; i < data.length; i++) {
+      if (String(data[i][colMap.id]) === rec.id) {
+        rowIndex = i + 1;
+      } else if (rec.email && String(data[i][colMap.email]).toLowerCase() === rec.email.toLowerCase()) {
+        return jsonError('メールアドレス ' + rec.email + ' は他の宛先（ID: ' + data[i][colMap.id] + '）で使われています');
+      }
+    }
+    if (rowIndex < 0) {
+      return jsonError('宛先（ID: ' + rec.id + '）が見つかりません。他のユーザーが削除した可能性があります');
+    }
+    writeRecipientRow(sheet, rowIndex, rec);
+
+    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+      .setMimeType(ContentService.MimeType.JSON);
+  } catch (error) {
+    return jsonError(error.toString());
+  } finally {
+    lock.releaseLock();
+  }
+}
+
+/** ID で宛先を削除し、その宛先の紐付けも消す（すでにない場合も成功） */
+function deleteRecipient(payload) {
+  const lock = LockService.getScriptLock();
+  if (!lock.tryLock(10000)) {
+    return jsonError(LOCK_BUSY_MESSAGE);
+  }
+  try {
+    const sheet = recipientSheet();
+    const data = sheet.getDataRange().getValues();
+    const colMap = recipientColumnMap(data[0]);
+    for (let i = data.length - 1; i >= 1; i--) {
+      if (String(data[i][colMap.id]) === payload.id) {
+        sheet.deleteRow(i + 1);
+      }
+    }
+    deleteLinkingsWhere(link => String(link[0]) === payload.id);
+    return ContentService.createTextOutput(JSON.stringify({ success: true }))
+      .setMimeType(ContentService.MimeType.JSON);
+  } catch (error) {
+    return jsonError(error.toString());
+  } finally {
+    lock.releaseLock();
   }
 }
+
+function jsonError(message) {
+  return ContentService.createTextOutput(JSON.stringify({ success: false, error: message }))
+    .setMimeType(ContentService.MimeType.JSON);
+}
+
+function recipientSheet() {
+  const ss = SpreadsheetApp.getActiveSpreadsheet();
+  let sheet = ss.getSheetByName('宛先リスト');
+  if (!sheet) {
+    sheet = ss.insertSheet('宛先リスト');
+    sheet.appendRow(RECIPIENT_HEADERS);
+  }
+  ensureRecipientColumns(sheet);
+  return sheet;
+}
+
+/**
+ * 新しい宛先 ID（スクリプトロックを取ってから呼ぶ）
+ * 最後に振った ID をスクリプトプロパティに残し、削除された宛先の ID を使い回さない
+ */
+function nextRecipientId(data, colMap) {
+  const props = PropertiesService.getScriptProperties();
+  let max = parseInt(props.getProperty('lastRecipientId') || '0', 10);
+  for (let i = 1; i < data.length; i++) {
+    const id = parseInt(data[i][colMap.id], 10);
+    if (!isNaN(id) && id > max) max = id;
+  }
+  props.setProperty('lastRecipientId', String(max + 1));
+  return String(max + 1);
+}
+
+/** 標準の項目も追加項目も、見出しから求めた列に書き込む */
+function writeRecipientRow(sheet, rowIndex, rec) {
+  const headers = sheet.getRange(1, 1, 1, sheet.getLastColumn()).getValues()[0].map(h => String(h).trim());
+  const colMap = recipientColumnMap(headers);
+  const standard = {
+    id: rec.id, company: rec.company, name: rec.name, email: rec.email,
+    cc: rec.cc || '', bcc: rec.bcc || '', replyTo: rec.replyTo || ''
+  };
+  Object.keys(standard).forEach(key => {
+    if (colMap[key] !== undefined) sheet.getRange(rowIndex, colMap[key] + 1).setValue(standard[key]);
+  });
+
+  // 追加項目は見出しが一致する列に書き込む（なければ列を追加）
+  const customFields = rec.customFields || {};
+  Object.keys(customFields).forEach(key => {
+    let col = headers.indexOf(key);
+    if (col === -1) {
+      headers.push(key);
+      col = headers.length - 1;
+      sheet.getRange(1, col + 1).setValue(key);
+    }
+    sheet.getRange(rowIndex, col + 1).setValue(customFields[key]);
+  });
+}
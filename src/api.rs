@@ -1,10 +1,20 @@
-use crate::models::{Template, RecipientData, Signature, LinkingData};
+use crate::backend::MailBackend;
+use crate::models::{Template, RecipientData, Signature, LinkingData, Attachment};
+use crate::template_engine;
+use crate::utils::now_unix_secs;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::time::Duration;
+use sha2::Sha256;
 use thiserror::Error;
 
+type HmacSha256 = Hmac<Sha256>;
+
 /// API エラーの種類を表す列挙型
 #[derive(Error, Debug, Clone)]
 pub enum ApiError {
@@ -26,12 +36,38 @@ pub enum ApiError {
     #[error("API エラー: {0}")]
     ApiResponseError(String),
 
+    #[error("設定エラー: {0}")]
+    ConfigError(String),
+
+    #[error("テンプレートの書式エラー: {0}")]
+    TemplateError(String),
+
+    /// 読み込んだ後に他のユーザーがシートを更新していた
+    #[error("競合: {0}")]
+    Conflict(String),
+
+    /// 署名がない・正しくない・期限切れ・再送としてサーバーに拒否された
+    #[error("認証エラー: {0}")]
+    Unauthorized(String),
+
     #[error("リトライ失敗 ({attempts}回試行): {last_error}")]
     RetryExhausted { attempts: u32, last_error: String },
 }
 
+impl ApiError {
+    /// 時間をおいて再試行すれば成功する可能性があるエラーか
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            ApiError::UrlNotSet | ApiError::ConfigError(_) | ApiError::TemplateError(_) | ApiError::Conflict(_)
+                | ApiError::ParseError(_) | ApiError::ApiResponseError(_) | ApiError::Unauthorized(_)
+        )
+    }
+}
+
 /// リトライ設定
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RetryConfig {
     pub max_attempts: u32,
     pub initial_delay_ms: u64,
@@ -48,12 +84,21 @@ impl Default for RetryConfig {
     }
 }
 
+impl RetryConfig {
+    /// attempt 回目（1始まり）の失敗後に待つ時間。失敗ごとに倍にし、max_delay_ms で頭打ち
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(self.initial_delay_ms.saturating_mul(factor).min(self.max_delay_ms))
+    }
+}
+
 #[derive(Clone)]
 pub struct GasClient {
     client: Client,
     url: String,
+    /// リクエストの署名に使う共有シークレット（Code.gs のスクリプトプロパティ SHARED_SECRET と同じ値）
+    shared_secret: String,
     retry_config: RetryConfig,
-    timeout: Duration,
 }
 
 #[derive(Deserialize)]
@@ -87,17 +132,236 @@ pub struct GetLogsResponse {
     pub logs: Vec<crate::models::HistoryItem>,
 }
 
+#[derive(Deserialize)]
+struct UnauthorizedResponse {
+    #[serde(default)]
+    unauthorized: bool,
+    error: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct PostResponse {
     success: bool,
     error: Option<String>,
+    #[serde(default)]
+    conflict: bool,
+    #[serde(default, rename = "updatedAt")]
+    updated_at: Option<String>,
+}
+
+impl PostResponse {
+    /// success: false を ApiError に変換する（競合はそれとわかるように分ける）
+    fn into_error(self, fallback: &str) -> ApiError {
+        let message = self.error.unwrap_or_else(|| fallback.to_string());
+        if self.conflict {
+            ApiError::Conflict(message)
+        } else {
+            ApiError::ApiResponseError(message)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchMailResponse {
+    success: bool,
+    error: Option<String>,
+    #[serde(default)]
+    results: Vec<BatchResultEntry>,
+}
+
+#[derive(Deserialize)]
+struct DeliveryStatusResponse {
+    #[serde(default)]
+    success: bool,
+    error: Option<String>,
+    #[serde(default)]
+    delivered: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BatchResultEntry {
+    #[serde(default)]
+    index: Option<usize>,
+    #[serde(default)]
+    to: String,
+    success: bool,
+    #[serde(default)]
+    error: Option<String>,
+    /// 別の処理が同じ messageId を送信中（時間をおいて再送すれば、送信済みか分かる）
+    #[serde(default, rename = "inFlight")]
+    in_flight: bool,
 }
 
-#[derive(Serialize)]
-struct BatchMailItem<'a> {
-    to: &'a str,
-    subject: &'a str,
-    body: &'a str,
+/// 宛先ごとの送信結果
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipientSendResult {
+    pub to: String,
+    pub success: bool,
+    pub error: Option<String>,
+    /// 通信障害など、時間をおいて再送すべき失敗か
+    pub retryable: bool,
+}
+
+/// 一括送信の結果。results は送信した items と同じ順序・同じ件数
+#[derive(Clone, Debug, Default)]
+pub struct BatchSendReport {
+    pub results: Vec<RecipientSendResult>,
+}
+
+impl BatchSendReport {
+    /// サーバーの results を items の順に対応付ける
+    /// index がなければ宛先アドレスで照合し、結果が見つからない宛先は失敗扱いにする
+    fn from_entries(items: &[BatchMailItem], entries: Vec<BatchResultEntry>) -> Self {
+        let mut entries: Vec<Option<BatchResultEntry>> = entries.into_iter().map(Some).collect();
+
+        let results = items.iter().enumerate()
+            .map(|(i, item)| {
+                let pos = entries.iter()
+                    .position(|e| e.as_ref().is_some_and(|e| e.index == Some(i)))
+                    .or_else(|| entries.iter()
+                        .position(|e| e.as_ref().is_some_and(|e| e.index.is_none() && e.to == item.to)));
+
+                match pos.and_then(|p| entries[p].take()) {
+                    Some(entry) => RecipientSendResult {
+                        to: item.to.to_string(),
+                        success: entry.success,
+                        error: if entry.success {
+                            None
+                        } else {
+                            Some(entry.error.unwrap_or_else(|| "不明なエラー".to_string()))
+                        },
+                        retryable: !entry.success && entry.in_flight,
+                    },
+                    // 送信済みかどうか分からないので自動再送はしない
+                    None => RecipientSendResult {
+                        to: item.to.to_string(),
+                        success: false,
+                        error: Some("サーバーから送信結果が返されませんでした".to_string()),
+                        retryable: false,
+                    },
+                }
+            })
+            .collect();
+
+        Self { results }
+    }
+
+    /// 再試行で送り直さなかった送信済みの宛先を成功として補い、items の順に並べる
+    /// self.results は items のうち delivered に含まれないものの結果
+    fn with_delivered(self, items: &[BatchMailItem], delivered: &HashSet<String>) -> Self {
+        let mut sent_now = self.results.into_iter();
+        let results = items.iter()
+            .filter_map(|item| {
+                if delivered.contains(item.message_id) {
+                    Some(RecipientSendResult { to: item.to.to_string(), success: true, error: None, retryable: false })
+                } else {
+                    sent_now.next()
+                }
+            })
+            .collect();
+        Self { results }
+    }
+}
+
+#[cfg(test)]
+impl BatchSendReport {
+    pub fn is_all_success(&self) -> bool {
+        self.results.iter().all(|r| r.success)
+    }
+
+    pub fn success_count(&self) -> usize {
+        self.results.iter().filter(|r| r.success).count()
+    }
+}
+
+/// 一括送信の1通分。添付ファイルは宛先ごとに指定する
+/// message_id はクライアントで生成した一意なID。再試行しても同じメールが二重に送られないようにサーバーが照合する
+#[derive(Clone, Copy)]
+pub struct BatchMailItem<'a> {
+    pub message_id: &'a str,
+    pub to: &'a str,
+    pub cc: &'a [String],
+    pub bcc: &'a [String],
+    pub reply_to: &'a str,  // 空なら指定しない
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub html_body: Option<&'a str>,  // あれば multipart/alternative で送る
+    pub attachments: &'a [Attachment],
+}
+
+impl BatchMailItem<'_> {
+    /// sendBatchMail の emails 配列の1要素に変換
+    fn to_json(self) -> serde_json::Value {
+        let mut email_obj = json!({
+            "messageId": self.message_id,
+            "to": self.to,
+            "subject": self.subject,
+            "body": self.body,
+        });
+
+        // GmailApp.sendEmail の options と同じくカンマ区切りで渡す
+        if !self.cc.is_empty() {
+            email_obj["cc"] = json!(self.cc.join(","));
+        }
+        if !self.bcc.is_empty() {
+            email_obj["bcc"] = json!(self.bcc.join(","));
+        }
+        if !self.reply_to.is_empty() {
+            email_obj["replyTo"] = json!(self.reply_to);
+        }
+        if let Some(html_body) = self.html_body {
+            email_obj["htmlBody"] = json!(html_body);
+        }
+
+        if !self.attachments.is_empty() {
+            let attachments_json: Vec<serde_json::Value> = self.attachments.iter()
+                .map(|att| json!({
+                    "fileName": att.file_name,
+                    "mimeType": att.mime_type,
+                    "data": att.data,
+                }))
+                .collect();
+            email_obj["attachments"] = json!(attachments_json);
+        }
+
+        email_obj
+    }
+}
+
+/// リクエストの署名（HMAC-SHA256 の16進）。Code.gs の verifyRequest と同じ文字列に署名する
+///
+/// content は POST なら本文そのもの、GET なら署名用以外のパラメータを名前順に `名前=値` を & でつないだもの
+/// （名前と値は encodeURIComponent と同じくエンコードする）。
+pub(crate) fn request_signature(secret: &str, method: &str, timestamp: u64, nonce: &str, content: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC は任意の長さの鍵を受け付ける");
+    mac.update(format!("{}\n{}\n{}\n{}", method, timestamp, nonce, content).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// GET パラメータの署名対象（名前順に並べてから渡す）
+/// 値に & や = が入っても区切りと紛れないように、Code.gs の canonicalParams と同じくエンコードする
+pub(crate) fn canonical_params<K: AsRef<str>, V: AsRef<str>>(sorted: &[(K, V)]) -> String {
+    sorted.iter()
+        .map(|(k, v)| format!("{}={}", encode_uri_component(k.as_ref()), encode_uri_component(v.as_ref())))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// JavaScript の encodeURIComponent と同じ（英数字と -_.!~*'() 以外は UTF-8 のバイトごとに %XX）
+fn encode_uri_component(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))
 }
 
 impl GasClient {
@@ -111,11 +375,17 @@ impl GasClient {
         Self {
             client,
             url,
+            shared_secret: String::new(),
             retry_config: RetryConfig::default(),
-            timeout,
         }
     }
 
+    /// リクエストに署名する共有シークレットを設定
+    pub fn with_shared_secret(mut self, secret: String) -> Self {
+        self.shared_secret = secret;
+        self
+    }
+
     /// リトライ設定をカスタマイズ
     pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
         self.retry_config = config;
@@ -124,7 +394,6 @@ impl GasClient {
 
     /// タイムアウトをカスタマイズ
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
-        self.timeout = timeout;
         self.client = Client::builder()
             .timeout(timeout)
             .build()
@@ -138,7 +407,6 @@ impl GasClient {
         F: Fn() -> Result<T, ApiError>,
     {
         let mut last_error = ApiError::NetworkError("不明なエラー".to_string());
-        let mut delay_ms = self.retry_config.initial_delay_ms;
 
         for attempt in 1..=self.retry_config.max_attempts {
             match operation() {
@@ -147,17 +415,13 @@ impl GasClient {
                     last_error = e.clone();
 
                     // リトライ不可能なエラーは即座に返す
-                    match &e {
-                        ApiError::UrlNotSet | ApiError::ParseError(_) | ApiError::ApiResponseError(_) => {
-                            return Err(e);
-                        }
-                        _ => {}
+                    if !e.is_retryable() {
+                        return Err(e);
                     }
 
                     if attempt < self.retry_config.max_attempts {
                         eprintln!("リトライ {}/{}: {:?}", attempt, self.retry_config.max_attempts, e);
-                        std::thread::sleep(Duration::from_millis(delay_ms));
-                        delay_ms = (delay_ms * 2).min(self.retry_config.max_delay_ms);
+                        std::thread::sleep(self.retry_config.delay_for_attempt(attempt));
                     }
                 }
             }
@@ -193,25 +457,131 @@ impl GasClient {
         Ok(base_url)
     }
 
-    pub fn get_templates(&self) -> Result<Vec<Template>, ApiError> {
+    /// action などのパラメータで GET する。HTTP エラー・認証エラーでなければ本文を返す
+    fn get_signed(&self, params: &[(&str, &str)], failure: &str) -> Result<String, ApiError> {
+        let base_url = self.get_base_url()?;
+        let mut sorted = params.to_vec();
+        sorted.sort();
+        let content = canonical_params(&sorted);
+        let auth = self.auth_params("GET", &content)?;
+
+        let response = self.client.get(&base_url)
+            .query(params)
+            .query(&auth)
+            .send()
+            .map_err(|e| self.convert_reqwest_error(e))?;
+        Self::read_body(response, failure)
+    }
+
+    /// JSON を POST する。署名した本文をそのまま送る
+    fn post_signed(&self, payload: &serde_json::Value, failure: &str) -> Result<String, ApiError> {
+        let base_url = self.get_base_url()?;
+        let body = payload.to_string();
+        let auth = self.auth_params("POST", &body)?;
+
+        let response = self.client.post(&base_url)
+            .query(&auth)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .map_err(|e| self.convert_reqwest_error(e))?;
+        Self::read_body(response, failure)
+    }
+
+    /// 署名のクエリパラメータ。再試行のたびに作り直すので nonce は毎回変わる
+    fn auth_params(&self, method: &str, content: &str) -> Result<[(&'static str, String); 3], ApiError> {
+        if self.shared_secret.is_empty() {
+            return Err(ApiError::ConfigError("共有シークレットが設定されていません。設定画面で入力してください".to_string()));
+        }
+        let timestamp = now_unix_secs();
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let nonce = hex::encode(bytes);
+        let signature = request_signature(&self.shared_secret, method, timestamp, &nonce, content);
+        Ok([("ts", timestamp.to_string()), ("nonce", nonce), ("sig", signature)])
+    }
+
+    fn read_body(response: reqwest::blocking::Response, failure: &str) -> Result<String, ApiError> {
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ApiError::ServerError { status: status.as_u16(), message: failure.to_string() });
+        }
+        let body = response.text()
+            .map_err(|e| ApiError::ParseError(format!("レスポンス読み取りエラー: {}", e)))?;
+
+        // Code.gs は HTTP ステータスを返せないので、署名の検証に失敗したことは本文で伝える
+        if let Ok(rejected) = serde_json::from_str::<UnauthorizedResponse>(&body) {
+            if rejected.unauthorized {
+                return Err(ApiError::Unauthorized(rejected.error.unwrap_or_else(|| "リクエストが拒否されました".to_string())));
+            }
+        }
+        Ok(body)
+    }
+
+    /// sendBatchMail を1回だけ送る（リトライは呼び出し側で行う）
+    fn post_batch_mail(&self, items: &[BatchMailItem]) -> Result<BatchSendReport, ApiError> {
+        let emails: Vec<serde_json::Value> = items.iter()
+            .map(|item| item.to_json())
+            .collect();
+
+        let payload = json!({
+            "action": "sendBatchMail",
+            "emails": emails,
+        });
+
+        let body = self.post_signed(&payload, "一括メール送信に失敗しました")?;
+        let parsed: BatchMailResponse = parse_json(&body)?;
+
+        if !parsed.success {
+            return Err(ApiError::ApiResponseError(
+                parsed.error.unwrap_or_else(|| "一括メール送信に失敗しました".to_string())
+            ));
+        }
+        Ok(BatchSendReport::from_entries(items, parsed.results))
+    }
+
+    /// items のうちサーバーが送信済みとして記録している message_id
+    fn get_delivered_ids(&self, items: &[BatchMailItem]) -> Result<HashSet<String>, ApiError> {
+        let ids: Vec<&str> = items.iter().map(|item| item.message_id).collect();
+
+        let payload = json!({
+            "action": "getDeliveryStatus",
+            "messageIds": ids,
+        });
+
+        let body = self.post_signed(&payload, "送信状況の確認に失敗しました")?;
+        let parsed: DeliveryStatusResponse = parse_json(&body)?;
+
+        // 確認できないまま送り直すと二重送信になるため、古い Code.gs ではエラーにする
+        if !parsed.success {
+            return Err(ApiError::ApiResponseError(format!(
+                "送信状況を確認できませんでした（{}）。Code.gs を最新版に更新してください",
+                parsed.error.unwrap_or_default()
+            )));
+        }
+        Ok(parsed.delivered.into_iter().collect())
+    }
+
+    /// 結果が成否だけの POST（紐付け・署名の保存と削除）
+    fn post_action(&self, payload: serde_json::Value, failure: &str) -> Result<(), ApiError> {
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let response = self.client.get(&base_url)
-                .query(&[("action", "getTemplates")])
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: format!("テンプレート取得に失敗しました"),
-                });
+            let body = self.post_signed(&payload, failure)?;
+
+            let parsed: PostResponse = parse_json(&body)?;
+
+            if !parsed.success {
+                return Err(parsed.into_error(failure));
             }
+            Ok(())
+        })
+    }
+}
 
-            let parsed: GetTemplatesResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+impl MailBackend for GasClient {
+    fn get_templates(&self) -> Result<Vec<Template>, ApiError> {
+        self.execute_with_retry(|| {
+            let body = self.get_signed(&[("action", "getTemplates")], "テンプレート取得に失敗しました")?;
+            let parsed: GetTemplatesResponse = parse_json(&body)?;
 
             if let Some(error) = parsed.error {
                 return Err(ApiError::ApiResponseError(error));
@@ -221,123 +591,72 @@ impl GasClient {
         })
     }
 
-    pub fn get_recipients(&self) -> Result<Vec<RecipientData>, ApiError> {
+    fn get_recipients(&self) -> Result<Vec<RecipientData>, ApiError> {
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let response = self.client.get(&base_url)
-                .query(&[("action", "getRecipients")])
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "宛先リスト取得に失敗しました".to_string(),
-                });
-            }
-
-            let parsed: GetRecipientsResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let body = self.get_signed(&[("action", "getRecipients")], "宛先リスト取得に失敗しました")?;
+            let parsed: GetRecipientsResponse = parse_json(&body)?;
 
             Ok(parsed.recipients)
         })
     }
 
-    pub fn get_signatures(&self) -> Result<Vec<Signature>, ApiError> {
+    fn get_signatures(&self) -> Result<Vec<Signature>, ApiError> {
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let url = format!("{}?action=getSignatures", base_url);
-            let response = self.client.get(&url)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "署名取得に失敗しました".to_string(),
-                });
-            }
-
-            let parsed: GetSignaturesResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let body = self.get_signed(&[("action", "getSignatures")], "署名取得に失敗しました")?;
+            let parsed: GetSignaturesResponse = parse_json(&body)?;
             Ok(parsed.signatures)
         })
     }
 
-    pub fn get_linkings(&self) -> Result<Vec<LinkingData>, ApiError> {
-        self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let url = format!("{}?action=getLinkings", base_url);
-            let response = self.client.get(&url)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "紐付けデータ取得に失敗しました".to_string(),
-                });
-            }
+    fn save_signature(&self, signature: &Signature, original_name: Option<&str>) -> Result<(), ApiError> {
+        template_engine::validate(&signature.content)
+            .map_err(|e| ApiError::TemplateError(format!("署名「{}」の {}", signature.name, e)))?;
+        let payload = json!({
+            "action": "saveSignature",
+            "signature": signature,
+            "originalName": original_name,
+        });
+        self.post_action(payload, &format!("署名「{}」の保存に失敗しました", signature.name))
+    }
+
+    fn delete_signature(&self, name: &str) -> Result<(), ApiError> {
+        self.post_action(json!({ "action": "deleteSignature", "name": name }), &format!("署名「{}」の削除に失敗しました", name))
+    }
 
-            let parsed: GetLinkingsResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+    fn get_linkings(&self) -> Result<Vec<LinkingData>, ApiError> {
+        self.execute_with_retry(|| {
+            let body = self.get_signed(&[("action", "getLinkings")], "紐付けデータ取得に失敗しました")?;
+            let parsed: GetLinkingsResponse = parse_json(&body)?;
             Ok(parsed.linkings)
         })
     }
 
-    pub fn get_settings(&self) -> Result<std::collections::HashMap<String, String>, ApiError> {
-        self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let url = format!("{}?action=getSettings", base_url);
-            let response = self.client.get(&url)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "設定取得に失敗しました".to_string(),
-                });
-            }
+    fn save_linking(&self, linking: &LinkingData) -> Result<(), ApiError> {
+        self.post_action(json!({ "action": "saveLinking", "linking": linking }), "紐付けの保存に失敗しました")
+    }
+
+    fn delete_linking(&self, linking: &LinkingData) -> Result<(), ApiError> {
+        self.post_action(json!({ "action": "deleteLinking", "linking": linking }), "紐付けの削除に失敗しました")
+    }
 
-            let parsed: GetSettingsResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+    fn get_settings(&self) -> Result<std::collections::HashMap<String, String>, ApiError> {
+        self.execute_with_retry(|| {
+            let body = self.get_signed(&[("action", "getSettings")], "設定取得に失敗しました")?;
+            let parsed: GetSettingsResponse = parse_json(&body)?;
             Ok(parsed.settings)
         })
     }
 
-    pub fn save_settings(&self, settings: &std::collections::HashMap<String, String>) -> Result<(), ApiError> {
+    fn save_settings(&self, settings: &std::collections::HashMap<String, String>) -> Result<(), ApiError> {
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
             let payload = json!({
                 "action": "saveSettings",
                 "settings": settings,
             });
 
-            let response = self.client.post(&base_url)
-                .json(&payload)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "設定保存に失敗しました".to_string(),
-                });
-            }
+            let body = self.post_signed(&payload, "設定保存に失敗しました")?;
 
-            let parsed: PostResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let parsed: PostResponse = parse_json(&body)?;
 
             if !parsed.success {
                 return Err(ApiError::ApiResponseError(
@@ -348,241 +667,713 @@ impl GasClient {
         })
     }
 
-    pub fn send_mail(&self, to: &str, subject: &str, body: &str) -> Result<(), ApiError> {
-        let to = to.to_string();
-        let subject = subject.to_string();
-        let body = body.to_string();
+    /// 宛先ごとの本文・添付ファイルで一括送信し、宛先ごとの結果を返す
+    fn send_batch_mail(&self, items: &[BatchMailItem]) -> Result<BatchSendReport, ApiError> {
+        let attempt = Cell::new(0);
+        self.execute_with_retry(|| {
+            attempt.set(attempt.get() + 1);
+
+            // タイムアウト等で再試行する場合、前回のリクエストでサーバーが送信済みのものは送り直さない
+            let delivered = if attempt.get() > 1 {
+                self.get_delivered_ids(items)?
+            } else {
+                HashSet::new()
+            };
+            let remaining: Vec<BatchMailItem> = items.iter()
+                .filter(|item| !delivered.contains(item.message_id))
+                .copied()
+                .collect();
+
+            let report = if remaining.is_empty() {
+                BatchSendReport::default()
+            } else {
+                self.post_batch_mail(&remaining)?
+            };
+            Ok(report.with_delivered(items, &delivered))
+        })
+    }
 
+    fn get_history(&self) -> Result<Vec<crate::models::HistoryItem>, ApiError> {
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
+            let body = self.get_signed(&[("action", "getLogs")], "送信履歴取得に失敗しました")?;
+            let parsed: GetLogsResponse = parse_json(&body)?;
+            Ok(parsed.logs)
+        })
+    }
+
+    fn save_template(&self, template: &Template, original_name: Option<&str>) -> Result<String, ApiError> {
+        // 書式の誤ったテンプレートはシートに保存しない
+        for (label, text) in [("件名", &template.subject), ("本文", &template.body)] {
+            template_engine::validate(text)
+                .map_err(|e| ApiError::TemplateError(format!("「{}」の{} {}", template.name, label, e)))?;
+        }
+        let template_owned = template.clone();
+        let original_name = original_name.map(str::to_string);
 
+        self.execute_with_retry(|| {
             let payload = json!({
-                "action": "sendMail",
-                "to": &to,
-                "subject": &subject,
-                "body": &body,
+                "action": "saveTemplate",
+                "template": &template_owned,
+                "originalName": &original_name,
             });
 
-            let response = self.client.post(&base_url)
-                .json(&payload)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: format!("メール送信に失敗しました (宛先: {})", &to),
-                });
-            }
+            let body = self.post_signed(&payload, &format!("テンプレート「{}」の保存に失敗しました", &template_owned.name))?;
 
-            let parsed: PostResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let parsed: PostResponse = serde_json::from_str(&body)
+                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {} | レスポンス: {}", e, body)))?;
 
             if !parsed.success {
-                return Err(ApiError::ApiResponseError(
-                    parsed.error.unwrap_or_else(|| "メール送信に失敗しました".to_string())
-                ));
+                return Err(parsed.into_error("テンプレート保存に失敗しました"));
             }
-            Ok(())
+            Ok(parsed.updated_at.unwrap_or_default())
         })
     }
 
-    pub fn send_batch_mail(&self, items: Vec<(&str, &str, &str)>, attachments: &[crate::models::Attachment]) -> Result<(), ApiError> {
-        // Clone data for retry
-        let items_owned: Vec<(String, String, String)> = items.into_iter()
-            .map(|(to, sub, body)| (to.to_string(), sub.to_string(), body.to_string()))
-            .collect();
-        let attachments_owned: Vec<crate::models::Attachment> = attachments.to_vec();
+    fn delete_template(&self, name: &str, updated_at: &str) -> Result<(), ApiError> {
+        let name_owned = name.to_string();
+        let updated_at = updated_at.to_string();
 
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let emails: Vec<serde_json::Value> = items_owned.iter()
-                .map(|(to, sub, body)| {
-                    let mut email_obj = serde_json::json!({
-                        "to": to,
-                        "subject": sub,
-                        "body": body,
-                    });
-
-                    if !attachments_owned.is_empty() {
-                        let attachments_json: Vec<serde_json::Value> = attachments_owned.iter()
-                            .filter(|att| att.enabled)
-                            .map(|att| serde_json::json!({
-                                "fileName": att.file_name,
-                                "mimeType": att.mime_type,
-                                "data": att.data,
-                            }))
-                            .collect();
-                        email_obj["attachments"] = serde_json::json!(attachments_json);
-                    }
-
-                    email_obj
-                })
-                .collect();
-
             let payload = json!({
-                "action": "sendBatchMail",
-                "emails": emails,
+                "action": "deleteTemplate",
+                "name": &name_owned,
+                "updatedAt": &updated_at,
             });
 
-            let response = self.client.post(&base_url)
-                .json(&payload)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "一括メール送信に失敗しました".to_string(),
-                });
-            }
+            let body = self.post_signed(&payload, &format!("テンプレート「{}」の削除に失敗しました", &name_owned))?;
 
-            let parsed: PostResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let parsed: PostResponse = parse_json(&body)?;
 
             if !parsed.success {
-                return Err(ApiError::ApiResponseError(
-                    parsed.error.unwrap_or_else(|| "一括メール送信に失敗しました".to_string())
-                ));
+                return Err(parsed.into_error("テンプレート削除に失敗しました"));
             }
             Ok(())
         })
     }
 
-    pub fn get_history(&self) -> Result<Vec<crate::models::HistoryItem>, ApiError> {
-        self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
-            let url = format!("{}?action=getLogs", base_url);
-            let response = self.client.get(&url)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: "送信履歴取得に失敗しました".to_string(),
-                });
-            }
-
-            let parsed: GetLogsResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
-            Ok(parsed.logs)
-        })
-    }
-
-    pub fn save_template(&self, template: &crate::models::Template) -> Result<(), ApiError> {
-        let template_owned = template.clone();
+    fn save_recipient(&self, recipient: &crate::models::RecipientData) -> Result<(), ApiError> {
+        let recipient_owned = recipient.clone();
 
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
             let payload = json!({
-                "action": "saveTemplate",
-                "template": &template_owned,
+                "action": "saveRecipient",
+                "recipient": &recipient_owned,
             });
 
-            let response = self.client.post(&base_url)
-                .json(&payload)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: format!("テンプレート「{}」の保存に失敗しました", &template_owned.name),
-                });
-            }
-
-            let text = response.text()
-                .map_err(|e| ApiError::ParseError(format!("レスポンス読み取りエラー: {}", e)))?;
+            let body = self.post_signed(&payload, &format!("宛先「{}」の保存に失敗しました", &recipient_owned.name))?;
 
-            let parsed: PostResponse = serde_json::from_str(&text)
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {} | レスポンス: {}", e, text)))?;
+            let parsed: PostResponse = parse_json(&body)?;
 
             if !parsed.success {
                 return Err(ApiError::ApiResponseError(
-                    parsed.error.unwrap_or_else(|| "テンプレート保存に失敗しました".to_string())
+                    parsed.error.unwrap_or_else(|| "宛先保存に失敗しました".to_string())
                 ));
             }
             Ok(())
         })
     }
 
-    pub fn delete_template(&self, name: &str) -> Result<(), ApiError> {
-        let name_owned = name.to_string();
+    fn update_recipient(&self, recipient: &crate::models::RecipientData) -> Result<(), ApiError> {
+        let recipient_owned = recipient.clone();
 
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
             let payload = json!({
-                "action": "deleteTemplate",
-                "name": &name_owned,
+                "action": "updateRecipient",
+                "recipient": &recipient_owned,
             });
 
-            let response = self.client.post(&base_url)
-                .json(&payload)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: format!("テンプレート「{}」の削除に失敗しました", &name_owned),
-                });
-            }
+            let body = self.post_signed(&payload, &format!("宛先「{}」の更新に失敗しました", &recipient_owned.name))?;
 
-            let parsed: PostResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let parsed: PostResponse = parse_json(&body)?;
 
             if !parsed.success {
-                return Err(ApiError::ApiResponseError(
-                    parsed.error.unwrap_or_else(|| "テンプレート削除に失敗しました".to_string())
-                ));
+                return Err(parsed.into_error("宛先更新に失敗しました"));
             }
             Ok(())
         })
     }
 
-    pub fn save_recipient(&self, recipient: &crate::models::RecipientData) -> Result<(), ApiError> {
-        let recipient_owned = recipient.clone();
+    fn delete_recipient(&self, id: &str) -> Result<(), ApiError> {
+        let id_owned = id.to_string();
 
         self.execute_with_retry(|| {
-            let base_url = self.get_base_url()?;
-
             let payload = json!({
-                "action": "saveRecipient",
-                "recipient": &recipient_owned,
+                "action": "deleteRecipient",
+                "id": &id_owned,
             });
 
-            let response = self.client.post(&base_url)
-                .json(&payload)
-                .send()
-                .map_err(|e| self.convert_reqwest_error(e))?;
-
-            let status = response.status();
-            if !status.is_success() {
-                return Err(ApiError::ServerError {
-                    status: status.as_u16(),
-                    message: format!("宛先「{}」の保存に失敗しました", &recipient_owned.name),
-                });
-            }
+            let body = self.post_signed(&payload, &format!("宛先（ID: {}）の削除に失敗しました", &id_owned))?;
 
-            let parsed: PostResponse = response.json()
-                .map_err(|e| ApiError::ParseError(format!("JSON解析エラー: {}", e)))?;
+            let parsed: PostResponse = parse_json(&body)?;
 
             if !parsed.success {
-                return Err(ApiError::ApiResponseError(
-                    parsed.error.unwrap_or_else(|| "宛先保存に失敗しました".to_string())
-                ));
+                return Err(parsed.into_error("宛先削除に失敗しました"));
             }
             Ok(())
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_gas::{Fault, MockGasServer, MockSheets, TemplateRow, MOCK_SHARED_SECRET};
+
+    fn attachment(file_name: &str, linked_recipient_index: usize) -> Attachment {
+        Attachment {
+            file_name: file_name.to_string(),
+            mime_type: "application/pdf".to_string(),
+            data: "ZHVtbXk=".to_string(),
+            linked_recipient_index: Some(linked_recipient_index),
+            ..Default::default()
+        }
+    }
+
+    /// 件名「件名」・本文「本文」で、CC・添付ファイルのない1通（テストごとに必要な項目だけ変える）
+    fn item<'a>(message_id: &'a str, to: &'a str) -> BatchMailItem<'a> {
+        BatchMailItem {
+            message_id,
+            to,
+            cc: &[],
+            bcc: &[],
+            reply_to: "",
+            subject: "件名",
+            body: "本文",
+            html_body: None,
+            attachments: &[],
+        }
+    }
+
+    #[test]
+    fn test_batch_item_only_carries_its_own_attachments() {
+        let a_files = vec![attachment("請求書_A社.pdf", 0)];
+        let b_files = vec![attachment("請求書_B社.pdf", 1)];
+        let items = [
+            BatchMailItem { body: "本文A", attachments: &a_files, ..item("m-a", "a@example.com") },
+            BatchMailItem { body: "本文B", attachments: &b_files, ..item("m-b", "b@example.com") },
+            BatchMailItem { body: "本文C", ..item("m-c", "c@example.com") },
+        ];
+
+        let emails: Vec<serde_json::Value> = items.iter().map(|item| item.to_json()).collect();
+
+        assert_eq!(emails[0]["attachments"][0]["fileName"], "請求書_A社.pdf");
+        assert_eq!(emails[0]["attachments"].as_array().unwrap().len(), 1);
+        assert_eq!(emails[1]["attachments"][0]["fileName"], "請求書_B社.pdf");
+        assert_eq!(emails[1]["attachments"].as_array().unwrap().len(), 1);
+        assert!(emails[2].get("attachments").is_none());
+    }
+
+    #[test]
+    fn test_batch_report_matches_results_to_items() {
+        let items = [
+            item("m-a", "a@example.com"),
+            item("m-b", "b@example.com"),
+            item("m-c", "c@example.com"),
+        ];
+        let response: BatchMailResponse = serde_json::from_str(r#"{
+            "success": true,
+            "results": [
+                {"index": 1, "to": "b@example.com", "success": false, "error": "Invalid email"},
+                {"to": "a@example.com", "success": true}
+            ]
+        }"#).unwrap();
+
+        let report = BatchSendReport::from_entries(&items, response.results);
+
+        assert_eq!(report.results.len(), 3);
+        assert!(report.results[0].success);
+        assert_eq!(report.results[1].error.as_deref(), Some("Invalid email"));
+        assert!(!report.results[2].success, "結果のない宛先は失敗扱い");
+        assert_eq!(report.success_count(), 1);
+        assert!(!report.is_all_success());
+    }
+
+    /// テストが速く終わるよう待ち時間を短くしたクライアント
+    fn mock_client(server: &MockGasServer) -> GasClient {
+        GasClient::new(server.url())
+            .with_shared_secret(MOCK_SHARED_SECRET.to_string())
+            .with_retry_config(RetryConfig { max_attempts: 3, initial_delay_ms: 10, max_delay_ms: 20 })
+            .with_timeout(Duration::from_millis(500))
+    }
+
+    fn sample_sheets() -> MockSheets {
+        MockSheets {
+            templates: vec![TemplateRow {
+                id: "2".to_string(),
+                name: "請求書".to_string(),
+                subject: "ご請求書送付のご案内".to_string(),
+                body: "{{company}} {{name}}\n請求書を送付します".to_string(),
+                updated_at: "v1".to_string(),
+            }],
+            recipients: vec![RecipientData {
+                id: "1".to_string(),
+                company: "A社".to_string(),
+                name: "田中".to_string(),
+                email: "tanaka@example.com".to_string(),
+                ..Default::default()
+            }],
+            signatures: vec![Signature { name: "デフォルト".to_string(), content: "--\n日興".to_string() }],
+            linkings: vec![LinkingData { recipient_id: "1".to_string(), template_id: "2".to_string(), ..Default::default() }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_master_data_round_trip() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+
+        let templates = client.get_templates().unwrap();
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].id, "2");
+        assert_eq!(templates[0].subject, "ご請求書送付のご案内");
+
+        assert_eq!(client.get_recipients().unwrap()[0].email, "tanaka@example.com");
+        assert_eq!(client.get_signatures().unwrap()[0].name, "デフォルト");
+        assert_eq!(client.get_linkings().unwrap()[0].template_id, "2");
+    }
+
+    #[test]
+    fn test_save_and_delete_template() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+
+        let template = Template {
+            name: "見積書".to_string(),
+            subject: "お見積り".to_string(),
+            body: "{{name}} 様".to_string(),
+            ..Default::default()
+        };
+        let version = client.save_template(&template, None).unwrap();
+        assert!(!version.is_empty());
+        let names: Vec<String> = client.get_templates().unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["請求書", "見積書"]);
+
+        // 名前の変更は元の名前の行を書き換える
+        let mut invoice = client.get_templates().unwrap().remove(0);
+        invoice.name = "請求書（改）".to_string();
+        client.save_template(&invoice, Some("請求書")).unwrap();
+        let names: Vec<String> = client.get_templates().unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["請求書（改）", "見積書"]);
+
+        let invoice = client.get_templates().unwrap().remove(0);
+        client.delete_template("請求書（改）", &invoice.updated_at).unwrap();
+        let names: Vec<String> = client.get_templates().unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["見積書"]);
+    }
+
+    #[test]
+    fn test_save_rename_and_delete_signature() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+        let names = || -> Vec<String> { client.get_signatures().unwrap().into_iter().map(|s| s.name).collect() };
+
+        let sales = Signature { name: "営業用".to_string(), content: "--\n{{sender_name}}".to_string() };
+        client.save_signature(&sales, None).unwrap();
+        assert_eq!(names(), vec!["デフォルト", "営業用"]);
+
+        // 新規作成で同じ名前は上書きしない
+        let duplicate = Signature { name: "デフォルト".to_string(), content: "上書き".to_string() };
+        assert!(matches!(client.save_signature(&duplicate, None), Err(ApiError::ApiResponseError(_))));
+
+        let renamed = Signature { name: "営業部".to_string(), ..sales };
+        client.save_signature(&renamed, Some("営業用")).unwrap();
+        assert_eq!(names(), vec!["デフォルト", "営業部"]);
+
+        // 書式の誤った署名は送らない
+        let broken = Signature { name: "壊れた署名".to_string(), content: "{{#if sender_name}}".to_string() };
+        assert!(matches!(client.save_signature(&broken, None), Err(ApiError::TemplateError(_))));
+
+        client.delete_signature("営業部").unwrap();
+        assert_eq!(names(), vec!["デフォルト"]);
+        assert_eq!(client.get_signatures().unwrap()[0].content, "--\n日興");
+    }
+
+    #[test]
+    fn test_linkings_by_keyword_follow_template_deletion() {
+        let mut sheets = sample_sheets();
+        sheets.templates.push(TemplateRow {
+            id: "3".to_string(),
+            name: "見積書".to_string(),
+            subject: "お見積り".to_string(),
+            body: String::new(),
+            updated_at: "v1".to_string(),
+        });
+        let server = MockGasServer::start_with(sheets);
+        let client = mock_client(&server);
+
+        let quote = LinkingData { recipient_id: "1".to_string(), template_id: "3".to_string(), keyword: "見積書".to_string() };
+        client.save_linking(&quote).unwrap();
+        // 同じ宛先・キーワードの組は差し替え
+        client.save_linking(&LinkingData { template_id: "2".to_string(), ..quote.clone() }).unwrap();
+        client.save_linking(&quote).unwrap();
+        assert_eq!(client.get_linkings().unwrap().len(), 2);
+
+        // 請求書（ID 2）を削除すると、その紐付けは消え、見積書の ID は変わらない
+        let invoice = client.get_templates().unwrap().remove(0);
+        client.delete_template(&invoice.name, &invoice.updated_at).unwrap();
+        assert_eq!(client.get_linkings().unwrap(), vec![quote.clone()]);
+        assert_eq!(client.get_templates().unwrap()[0].id, "3");
+
+        // 後から追加したテンプレートは削除したテンプレートの ID を使わない
+        client.save_template(&Template { name: "納品書".to_string(), subject: "納品書".to_string(), ..Default::default() }, None).unwrap();
+        assert!(client.get_templates().unwrap().iter().all(|t| t.id != invoice.id));
+
+        client.delete_linking(&quote).unwrap();
+        assert!(client.get_linkings().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stale_template_is_a_conflict() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+
+        let mine = client.get_templates().unwrap().remove(0);
+        let mut theirs = mine.clone();
+        theirs.body = "他の人の変更".to_string();
+        client.save_template(&theirs, Some("請求書")).unwrap();
+
+        let mut edited = mine.clone();
+        edited.body = "自分の変更".to_string();
+        assert!(matches!(client.save_template(&edited, Some("請求書")), Err(ApiError::Conflict(_))));
+        assert!(matches!(client.delete_template("請求書", &mine.updated_at), Err(ApiError::Conflict(_))));
+        assert!(matches!(client.save_template(&Template { name: "請求書".to_string(), ..Default::default() }, None), Err(ApiError::Conflict(_))));
+        assert_eq!(server.sheets().templates[0].body, "他の人の変更", "上書きされない");
+        assert_eq!(server.requests().iter().filter(|r| *r == "saveTemplate").count(), 3, "競合は再試行しない");
+    }
+
+    #[test]
+    fn test_save_template_rejects_parse_error() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+
+        let template = Template {
+            name: "督促".to_string(),
+            body: "{{#if department}}{{department}} 御中\n".to_string(),
+            ..Default::default()
+        };
+        match client.save_template(&template, None) {
+            Err(ApiError::TemplateError(message)) => assert!(message.contains("1行目"), "{}", message),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert_eq!(server.sheets().templates.len(), 1, "保存されない");
+    }
+
+    #[test]
+    fn test_save_recipient_updates_by_email() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+
+        client.save_recipient(&RecipientData {
+            id: "99".to_string(),
+            company: "A社".to_string(),
+            name: "田中 太郎".to_string(),
+            email: "tanaka@example.com".to_string(),
+            ..Default::default()
+        }).unwrap();
+        client.save_recipient(&RecipientData {
+            id: "2".to_string(),
+            company: "B社".to_string(),
+            name: "鈴木".to_string(),
+            email: "suzuki@example.com".to_string(),
+            custom_fields: [("部署".to_string(), "経理部".to_string())].into_iter().collect(),
+            ..Default::default()
+        }).unwrap();
+
+        let recipients = server.sheets().recipients;
+        assert_eq!(recipients.len(), 2);
+        assert_eq!(recipients[0].name, "田中 太郎");
+        assert_eq!(recipients[1].company, "B社");
+        assert_eq!(client.get_recipients().unwrap()[1].custom_fields["部署"], "経理部");
+    }
+
+    #[test]
+    fn test_update_and_delete_recipient_by_id() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+        client.save_recipient(&RecipientData { email: "suzuki@example.com".to_string(), ..Default::default() }).unwrap();
+
+        // メールアドレスの誤りを ID で直す
+        let mut tanaka = client.get_recipients().unwrap().remove(0);
+        tanaka.email = "tanaka@example.co.jp".to_string();
+        client.update_recipient(&tanaka).unwrap();
+        assert_eq!(server.sheets().recipients[0].email, "tanaka@example.co.jp");
+
+        tanaka.email = "SUZUKI@example.com".to_string();
+        assert!(matches!(client.update_recipient(&tanaka), Err(ApiError::ApiResponseError(_))), "他の宛先と重複");
+
+        client.delete_recipient("1").unwrap();
+        assert!(matches!(client.update_recipient(&tanaka), Err(ApiError::ApiResponseError(_))), "削除済み");
+
+        // 削除で空いた ID を使い回さない
+        client.save_recipient(&RecipientData { email: "sato@example.com".to_string(), ..Default::default() }).unwrap();
+        let ids: Vec<String> = client.get_recipients().unwrap().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["2", "3"]);
+    }
+
+    #[test]
+    fn test_deleting_highest_recipient_does_not_pass_on_its_id_or_linkings() {
+        let server = MockGasServer::start_with(sample_sheets());
+        let client = mock_client(&server);
+        client.save_recipient(&RecipientData { email: "suzuki@example.com".to_string(), ..Default::default() }).unwrap();
+        let suzuki = client.get_recipients().unwrap().remove(1);
+        assert_eq!(suzuki.id, "2");
+        client.save_linking(&LinkingData { recipient_id: "2".to_string(), template_id: "2".to_string(), keyword: String::new() }).unwrap();
+
+        // 最大の ID の宛先を削除してから追加する
+        client.delete_recipient(&suzuki.id).unwrap();
+        assert!(client.get_linkings().unwrap().iter().all(|l| l.recipient_id != "2"), "削除した宛先の紐付けも消える");
+        client.save_recipient(&RecipientData { email: "sato@example.com".to_string(), ..Default::default() }).unwrap();
+
+        let sato = client.get_recipients().unwrap().remove(1);
+        assert_eq!(sato.id, "3");
+        assert!(client.get_linkings().unwrap().iter().all(|l| l.recipient_id != sato.id));
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let server = MockGasServer::start();
+        let client = mock_client(&server);
+
+        let mut settings = std::collections::HashMap::new();
+        settings.insert("selected_signature_index".to_string(), "1".to_string());
+        client.save_settings(&settings).unwrap();
+
+        assert_eq!(client.get_settings().unwrap().get("selected_signature_index").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn test_send_batch_mail_reports_each_recipient_and_logs_history() {
+        let server = MockGasServer::start();
+        server.reject_address("bad@example.com");
+        let client = mock_client(&server);
+
+        let invoice = vec![Attachment { file_name: "請求書.pdf".to_string(), ..Default::default() }];
+        let items = [
+            BatchMailItem { attachments: &invoice, ..item("m-a", "a@example.com") },
+            item("m-bad", "bad@example.com"),
+        ];
+        let report = client.send_batch_mail(&items).unwrap();
+
+        assert!(report.results[0].success);
+        assert!(!report.results[1].success);
+        assert!(report.results[1].error.as_deref().unwrap().contains("Invalid email"));
+
+        let sent = server.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["attachments"][0]["fileName"], "請求書.pdf");
+
+        let history = client.get_history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].status.starts_with("Error"), "履歴は新しい順");
+    }
+
+    #[test]
+    fn test_batch_sends_cc_bcc_and_reply_to() {
+        let server = MockGasServer::start();
+        let client = mock_client(&server);
+
+        let cc = vec!["sales@example.com".to_string(), "boss@example.com".to_string()];
+        let bcc = vec!["archive@example.com".to_string()];
+        client.send_batch_mail(&[BatchMailItem {
+            cc: &cc,
+            bcc: &bcc,
+            reply_to: "support@example.com",
+            ..item("m-a", "a@example.com")
+        }]).unwrap();
+
+        let sent = &server.sent()[0];
+        assert_eq!(sent["to"], "a@example.com");
+        assert_eq!(sent["cc"], "sales@example.com,boss@example.com");
+        assert_eq!(sent["bcc"], "archive@example.com");
+        assert_eq!(sent["replyTo"], "support@example.com");
+    }
+
+    #[test]
+    fn test_retries_after_http_500() {
+        let server = MockGasServer::start_with(sample_sheets());
+        server.inject(Fault::Http500);
+        server.inject(Fault::Http500);
+        let client = mock_client(&server);
+
+        assert_eq!(client.get_templates().unwrap().len(), 1);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_retry_exhausted() {
+        let server = MockGasServer::start();
+        for _ in 0..3 {
+            server.inject(Fault::Http500);
+        }
+        let client = mock_client(&server);
+
+        match client.get_recipients() {
+            Err(ApiError::RetryExhausted { attempts, last_error }) => {
+                assert_eq!(attempts, 3);
+                assert!(last_error.contains("500"));
+            }
+            other => panic!("RetryExhausted を期待: {:?}", other.map(|r| r.len())),
+        }
+    }
+
+    #[test]
+    fn test_retries_after_timeout() {
+        let server = MockGasServer::start_with(sample_sheets());
+        server.inject(Fault::Timeout(Duration::from_secs(2)));
+        let client = mock_client(&server);
+
+        assert_eq!(client.get_signatures().unwrap().len(), 1);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_timeout_after_send_does_not_resend() {
+        let server = MockGasServer::start();
+        // GAS は送信を終えたが、応答が返る前にクライアントがタイムアウトする
+        server.inject(Fault::TimeoutAfterProcessing(Duration::from_secs(2)));
+        let client = mock_client(&server);
+
+        let items = [
+            item("m-a", "a@example.com"),
+            item("m-b", "b@example.com"),
+        ];
+        let report = client.send_batch_mail(&items).unwrap();
+
+        assert!(report.is_all_success());
+        assert_eq!(report.results[1].to, "b@example.com");
+        assert_eq!(server.sent().len(), 2, "再試行で二重送信しない");
+        assert_eq!(server.requests(), vec!["sendBatchMail", "getDeliveryStatus"]);
+    }
+
+    #[test]
+    fn test_send_interrupted_after_claim_is_sent_again() {
+        let server = MockGasServer::start();
+        // 送信中の記録を取り直せる（止まった実行から時間がたった）状態で、送信前に実行が止まる
+        server.set_claim_ttl(Duration::ZERO);
+        server.inject(Fault::CrashAfterClaim);
+        let client = mock_client(&server);
+
+        let items = [
+            item("m-a", "a@example.com"),
+            item("m-b", "b@example.com"),
+        ];
+        let report = client.send_batch_mail(&items).unwrap();
+
+        assert!(report.is_all_success());
+        assert_eq!(server.sent().len(), 2, "送信中のまま止まったメールも送り直す");
+        assert_eq!(server.requests(), vec!["sendBatchMail", "getDeliveryStatus", "sendBatchMail"]);
+    }
+
+    #[test]
+    fn test_message_still_in_flight_is_retried_later() {
+        let server = MockGasServer::start();
+        server.inject(Fault::CrashAfterClaim);
+        let client = mock_client(&server);
+
+        let items = [
+            item("m-a", "a@example.com"),
+            item("m-b", "b@example.com"),
+        ];
+        let report = client.send_batch_mail(&items).unwrap();
+
+        // 送信中の記録は送信済みとして扱わず、後で再送する
+        assert!(!report.results[0].success);
+        assert!(report.results[0].retryable);
+        assert!(report.results[1].success);
+        assert_eq!(server.sent().len(), 1);
+    }
+
+    #[test]
+    fn test_resent_message_id_is_not_delivered_twice() {
+        let server = MockGasServer::start();
+        let client = mock_client(&server);
+        let items = [
+            item("m-a", "a@example.com"),
+        ];
+
+        client.send_batch_mail(&items).unwrap();
+        // 送信待ちから同じIDで再送した場合もサーバー側で弾く
+        let report = client.send_batch_mail(&items).unwrap();
+
+        assert!(report.is_all_success());
+        assert_eq!(server.sent().len(), 1);
+    }
+
+    #[test]
+    fn test_malformed_json_is_not_retried() {
+        let server = MockGasServer::start();
+        server.inject(Fault::MalformedJson);
+        let client = mock_client(&server);
+
+        assert!(matches!(client.get_linkings(), Err(ApiError::ParseError(_))));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_max() {
+        let config = RetryConfig { max_attempts: 10, initial_delay_ms: 500, max_delay_ms: 3000 };
+        let delays: Vec<u64> = (1..=5).map(|n| config.delay_for_attempt(n).as_millis() as u64).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+    }
+
+    #[test]
+    fn test_empty_url_is_not_retried() {
+        let client = GasClient::new("  ".to_string());
+        assert!(matches!(client.get_templates(), Err(ApiError::UrlNotSet)));
+    }
+
+    #[test]
+    fn test_canonical_params_escape_separators_like_encode_uri_component() {
+        assert_eq!(canonical_params(&[("q", "a&b=c 日本")]), "q=a%26b%3Dc%20%E6%97%A5%E6%9C%AC");
+        assert_ne!(canonical_params(&[("a", "1&b=2")]), canonical_params(&[("a", "1"), ("b", "2")]));
+        assert_eq!(canonical_params(&[("action", "getTemplates"), ("x", "-_.!~*'()")]), "action=getTemplates&x=-_.!~*'()");
+    }
+
+    #[test]
+    fn test_unsigned_tampered_and_replayed_requests_are_rejected() {
+        let server = MockGasServer::start_with(sample_sheets());
+
+        // 共有シークレットが違えば拒否され、再試行もしない
+        let wrong = mock_client(&server).with_shared_secret("違う値".to_string());
+        assert!(matches!(wrong.get_templates(), Err(ApiError::Unauthorized(_))));
+        assert_eq!(server.requests().len(), 1);
+
+        // 共有シークレットがなければ送らない
+        let unset = GasClient::new(server.url());
+        assert!(matches!(unset.get_templates(), Err(ApiError::ConfigError(_))));
+        assert_eq!(server.requests().len(), 1);
+
+        let http = Client::new();
+        let get = |query: &[(&str, String)]| -> serde_json::Value {
+            http.get(server.url()).query(query).send().unwrap().json().unwrap()
+        };
+        assert_eq!(get(&[("action", "getTemplates".to_string())])["unauthorized"], true, "署名なし");
+
+        let now = now_unix_secs();
+        let signed = |ts: u64, nonce: &str| {
+            let sig = request_signature(MOCK_SHARED_SECRET, "GET", ts, nonce, "action=getTemplates");
+            [("action", "getTemplates".to_string()), ("ts", ts.to_string()), ("nonce", nonce.to_string()), ("sig", sig)]
+        };
+        let request = signed(now, "0123456789abcdef01");
+        assert_eq!(get(&request)["templates"].as_array().map(Vec::len), Some(1));
+        let replayed = get(&request);
+        assert_eq!(replayed["unauthorized"], true, "同じ nonce の再送");
+        assert_eq!(replayed["success"], false);
+        assert_eq!(replayed["error"], "同じリクエストが再送されました");
+        assert_eq!(get(&signed(now - 3600, "0123456789abcdef02"))["error"], "署名の有効期限が切れています。PCの時計を確認してください");
+        assert_eq!(get(&signed(now, "nonce-1"))["error"], "署名が正しくありません", "Code.gs と同じ形式の nonce だけ受け付ける");
+        assert_eq!(get(&signed(now, "0123456789ABCDEF03"))["unauthorized"], true);
+
+        // 署名した後に本文を書き換えた POST
+        let body = r#"{"action":"deleteRecipient","id":"2"}"#;
+        let sig = request_signature(MOCK_SHARED_SECRET, "POST", now, "0123456789abcdef04", body);
+        let response: serde_json::Value = http.post(server.url())
+            .query(&[("ts", now.to_string()), ("nonce", "0123456789abcdef04".to_string()), ("sig", sig)])
+            .body(body.replace("\"2\"", "\"3\""))
+            .send().unwrap()
+            .json().unwrap();
+        assert_eq!(response["unauthorized"], true);
+        assert_eq!(server.sheets().recipients.len(), sample_sheets().recipients.len());
+    }
+}
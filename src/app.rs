@@ -1,18 +1,38 @@
 use eframe::egui;
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::auth::{self, LoginThrottle, Role, UserStore};
+use crate::config::{self, AppConfig};
+use crate::drafts::{self, Autosave, DraftStore, RecoveredDrafts, SavedDraft};
 use crate::models::{AppState, Tab, StartupPhase};
 use crate::ui;
+use crate::calendar::{BusinessCalendar, HOLIDAYS_FILE_NAME};
+use crate::outbox::Outbox;
+use crate::schedule::Schedule;
+use crate::signature;
+use crate::storage;
+use crate::utils::now_unix_secs;
+use crate::worker::{self, Job, JobEvent};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 /// セッションファイルのパス（TEMPディレクトリに保存、PC再起動で消える）
 fn get_session_file_path() -> std::path::PathBuf {
     std::env::temp_dir().join("auto_mail_pilot_session.txt")
 }
 
-/// セッションを保存（ログイン成功時）
-fn save_session() {
-    let session_path = get_session_file_path();
-    let _ = std::fs::write(&session_path, "authenticated");
+fn session_key() -> Result<Vec<u8>, String> {
+    auth::load_or_create_session_key(&storage::data_dir().join(auth::SESSION_KEY_FILE_NAME))
+}
+
+/// セッションを保存（ログイン成功時）。有効期限を返す
+fn save_session(username: &str) -> Option<u64> {
+    let key = session_key().ok()?;
+    let token = auth::issue_session(&key, username, now_unix_secs());
+    std::fs::write(get_session_file_path(), &token).ok()?;
+    auth::verify_session(&key, &token, now_unix_secs()).map(|(_, expires_at)| expires_at)
 }
 
 /// セッションを削除（ログアウト時）
@@ -21,15 +41,40 @@ fn clear_session() {
     let _ = std::fs::remove_file(&session_path);
 }
 
-/// セッションが有効かチェック
-fn check_session() -> bool {
-    let session_path = get_session_file_path();
-    session_path.exists()
+/// 署名が正しく期限内のセッションなら、ユーザー名と有効期限を返す
+fn check_session() -> Option<(String, u64)> {
+    let token = std::fs::read_to_string(get_session_file_path()).ok()?;
+    auth::verify_session(&session_key().ok()?, &token, now_unix_secs())
+}
+
+/// ログアウトしてログイン画面に戻る
+fn logout(state: &mut AppState, reason: Option<String>) {
+    audit::record(state, AuditAction::Logout, reason.clone().unwrap_or_default());
+    // 誰の下書きか分かるように、ユーザー名を消す前に残す
+    ui::mail_panel::set_aside_draft_for_logout(state);
+    state.is_authenticated = false;
+    state.auth_username.clear();
+    state.auth_password.clear();
+    state.auth_role = Role::Sender;
+    state.session_expires_at = None;
+    state.auth_error = reason;
+    state.user_admin = Default::default();
+    // まだ始めていない処理は、次にログインしたユーザーの権限で実行しない
+    state.job_queue.clear();
+    clear_session();
 }
 
 pub struct MailApp {
     state: Arc<Mutex<AppState>>,
-    loading_started: bool,
+    // 最後に保存した設定（変わったら保存する）
+    saved_config: AppConfig,
+    // 作成中の下書きの自動保存
+    autosave: Autosave,
+    last_autosave: Instant,
+    // バックグラウンド処理の結果受け取り
+    job_tx: Sender<JobEvent>,
+    job_rx: Receiver<JobEvent>,
+    job_cancel: Arc<AtomicBool>,
 }
 
 impl MailApp {
@@ -98,42 +143,236 @@ impl MailApp {
 
         cc.egui_ctx.set_style(style);
 
-        let mut state = AppState::default();
+        // 前回送れなかったメールと予約送信を読み込む
+        let data_dir = storage::data_dir();
+        let mut state = AppState {
+            data_dir: data_dir.clone(),
+            ..Default::default()
+        };
+        match Outbox::load(data_dir.join("outbox.json")) {
+            Ok(outbox) => state.outbox = outbox,
+            Err(e) => state.status_message = format!("⚠ {}", e),
+        }
+        match Schedule::load(data_dir.join("schedule.json")) {
+            Ok(schedule) => state.schedule = schedule,
+            Err(e) => state.status_message = format!("⚠ {}", e),
+        }
+        match AppConfig::load(storage::config_dir().join(config::CONFIG_FILE_NAME)) {
+            Ok(config) => state.config = config,
+            Err(e) => state.status_message = format!("⚠ {}", e),
+        }
+        cc.egui_ctx.set_zoom_factor(state.config.preferences.zoom_factor);
+
+        // 自動保存した下書きが残っていれば、前回は正常に終了していない
+        match DraftStore::load(data_dir.join(drafts::DRAFTS_FILE_NAME)) {
+            Ok(drafts) => state.drafts = drafts,
+            Err(e) => state.status_message = format!("⚠ {}", e),
+        }
+        // 前回の自動保存は別のファイルに移し、復元するか決めるまでの間も作成中の下書きを自動保存する
+        // （移せなければ前回の自動保存を上書きしないように、今回は自動保存しない）
+        let autosave_path = data_dir.join(drafts::AUTOSAVE_FILE_NAME);
+        let autosave = match RecoveredDrafts::load(data_dir.join(drafts::RECOVERED_FILE_NAME))
+            .and_then(|mut recovered| recovered.take_autosave(&autosave_path).map(|_| recovered))
+        {
+            Ok(recovered) => {
+                state.recovered_drafts = recovered;
+                Autosave::new(autosave_path)
+            }
+            Err(e) => {
+                state.status_message = format!("⚠ {}（前回の自動保存を残すため、今回は自動保存しません）", e);
+                Autosave::default()
+            }
+        };
+        match BusinessCalendar::load(&data_dir.join(HOLIDAYS_FILE_NAME)) {
+            Ok(calendar) => state.calendar = calendar,
+            Err(e) => state.status_message = format!("⚠ {}", e),
+        }
 
-        // セッションが有効なら自動ログイン
-        if check_session() {
-            state.is_authenticated = true;
+        // セッションが有効で、ユーザーがまだいれば自動ログイン（権限はユーザーの一覧から取り直す）
+        match UserStore::load(data_dir.join(auth::USERS_FILE_NAME)) {
+            Ok(store) => state.user_store = store,
+            Err(e) => state.user_store_error = Some(e),
+        }
+        state.login_throttle = LoginThrottle::load(data_dir.join(auth::LOGIN_THROTTLE_FILE_NAME));
+        let audit_key = session_key().unwrap_or_else(|e| {
+            state.status_message = format!("⚠ {}（監査ログを検証できません）", e);
+            Vec::new()
+        });
+        state.audit_log = AuditLog::load(data_dir.join(audit::AUDIT_FILE_NAME), audit_key);
+        if let Some((username, expires_at)) = check_session() {
+            if let Some(role) = state.user_store.role_of(&username) {
+                state.is_authenticated = true;
+                state.auth_username = username;
+                state.auth_role = role;
+                state.session_expires_at = Some(expires_at);
+                audit::record(&mut state, AuditAction::Login, "セッション復元");
+            }
         }
 
+        let (job_tx, job_rx) = mpsc::channel();
+
         Self {
+            saved_config: state.config.clone(),
+            autosave,
+            last_autosave: Instant::now(),
             state: Arc::new(Mutex::new(state)),
-            loading_started: false,
+            job_tx,
+            job_rx,
+            job_cancel: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    /// バックグラウンドでデータをロード
-    fn start_loading(&mut self, ctx: egui::Context) {
-        if self.loading_started {
+    /// バックグラウンド処理の進捗・結果を反映し、待機中の処理があれば開始する
+    fn pump_jobs(&self, ctx: &egui::Context, state: &mut AppState) {
+        while let Ok(event) = self.job_rx.try_recv() {
+            match event {
+                JobEvent::Progress { done, total, detail } => {
+                    if let Some(progress) = state.job_progress.as_mut() {
+                        progress.done = done;
+                        progress.total = total;
+                        progress.detail = detail;
+                    }
+                }
+                JobEvent::Finished(outcome) => {
+                    let started_by = state.job_progress.take().map(|p| p.started_by).unwrap_or_default();
+                    worker::apply_outcome(state, *outcome, &started_by);
+                }
+            }
+        }
+
+        if let Some(progress) = state.job_progress.as_ref() {
+            if progress.cancel_requested {
+                self.job_cancel.store(true, Ordering::SeqCst);
+            }
             return;
         }
-        self.loading_started = true;
 
+        // 取り消し猶予が過ぎた送信を確定する
+        if let Some(held) = &state.held_send {
+            let remaining = held.release_at.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                ui::mail_panel::release_held_send(state);
+            } else {
+                ctx.request_repaint_after(remaining.min(Duration::from_secs(1)));
+            }
+        }
+
+        // 送信日時を過ぎた予約は送信待ちに移す
+        let now = now_unix_secs();
+        let due_scheduled = state.schedule.take_due(now);
+        if !due_scheduled.is_empty() {
+            for scheduled in &due_scheduled {
+                state.outbox.enqueue(&scheduled.pending, now);
+            }
+            state.status_message = format!("⏰ 予約送信 {}件を送信します", due_scheduled.len());
+            if let Err(e) = state.outbox.save().and_then(|_| state.schedule.save()) {
+                state.status_message = format!("❌ {}", e);
+            }
+        }
+
+        // 再送時刻を過ぎた送信待ちがあれば送信する（取り消し猶予中の分は release_held_send で送る）
+        if state.job_queue.is_empty() && state.is_authenticated {
+            let mut due = state.outbox.due_items(now);
+            if let Some(held) = &state.held_send {
+                due.retain(|i| !held.item_ids.contains(&i.id));
+            }
+            if !due.is_empty() {
+                state.job_queue.push(Job::SendOutbox { items: due, pending: None, held_draft: None });
+            }
+        }
+        if !state.outbox.is_empty() || !state.schedule.items().is_empty() {
+            ctx.request_repaint_after(Duration::from_secs(5));
+        }
+
+        if !state.job_queue.is_empty() {
+            let job = state.job_queue.remove(0);
+            if !job.permitted(&state.auth_username, state.auth_role) {
+                state.status_message = format!("❌ {}の権限がありません", job.title());
+                ctx.request_repaint();
+                return;
+            }
+            if let Job::SendOutbox { items, .. } = &job {
+                let ids: Vec<String> = items.iter().map(|i| i.id.clone()).collect();
+                state.outbox.mark_sending(&ids);
+                if let Err(e) = state.outbox.save() {
+                    state.status_message = format!("❌ {}", e);
+                }
+            }
+            state.job_progress = Some(worker::initial_progress(&job, &state.auth_username));
+            self.job_cancel.store(false, Ordering::SeqCst);
+            worker::spawn_job(
+                job,
+                state.config.backend_config(),
+                Arc::clone(&self.job_cancel),
+                self.job_tx.clone(),
+                ctx.clone(),
+            );
+        }
+    }
+
+    /// 設定が変わっていれば保存する（欄の大きさはドラッグし終えてから）
+    fn persist_config(&mut self, ctx: &egui::Context, state: &mut AppState) {
+        state.config.preferences.zoom_factor = ctx.zoom_factor();
+        if state.config == self.saved_config || ctx.input(|i| i.pointer.any_down()) {
+            return;
+        }
+        if let Err(e) = state.config.save() {
+            state.status_message = format!("❌ {}", e);
+        }
+        self.saved_config = state.config.clone();
+    }
+
+    /// 作成中の下書きを定期的に保存する
+    fn persist_draft(&mut self, state: &mut AppState) {
+        if self.last_autosave.elapsed() < Duration::from_secs(drafts::AUTOSAVE_INTERVAL_SECS) {
+            return;
+        }
+        self.last_autosave = Instant::now();
+
+        // 送信取り消しの猶予中は、取り消したときに戻る下書きを保存する
+        let (draft, active_recipient_index) = match &state.held_send {
+            Some(held) => (&held.draft, held.active_recipient_index),
+            None => (&state.mail_draft, state.active_recipient_index),
+        };
+        let saved = SavedDraft::capture("", draft, active_recipient_index, &state.auth_username, now_unix_secs());
+        if let Err(e) = self.autosave.write(&saved) {
+            state.status_message = format!("❌ {}", e);
+        }
+    }
+
+    /// ウィンドウを閉じるとき、作成中の下書きがあれば確認する
+    /// 正常に終了するので自動保存は消す（復元するか決めていない下書きは別のファイルに残る）
+    fn handle_close_request(&mut self, ctx: &egui::Context, state: &mut AppState) {
+        if !ctx.input(|i| i.viewport().close_requested()) {
+            return;
+        }
+        if state.is_authenticated && !state.exit_confirmed && !state.mail_draft.is_blank() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            state.show_exit_confirmation = true;
+            return;
+        }
+        // 取り消し猶予中の送信は送信待ちに保存してあるので、次に起動したときに送信する
+        self.autosave.clear();
+    }
+
+    /// バックグラウンドでデータをロード（プロファイルを切り替えたときも呼ぶ）
+    fn start_loading(&mut self, ctx: egui::Context) {
         let state_clone = Arc::clone(&self.state);
 
         thread::spawn(move || {
-            let gas_url = {
+            let backend_config = {
                 let state = state_clone.lock().unwrap();
-                state.gas_url.clone()
+                state.config.backend_config()
             };
 
-            if gas_url.is_empty() {
+            if backend_config.gas_url.is_empty() {
                 let mut state = state_clone.lock().unwrap();
                 state.startup_phase = StartupPhase::Ready;
                 ctx.request_repaint();
                 return;
             }
 
-            let client = crate::api::GasClient::new(gas_url);
+            let client = crate::backend::create_backend(&backend_config);
 
             // ロード進捗を更新するヘルパー
             let update_message = |msg: &str| {
@@ -149,11 +388,15 @@ impl MailApp {
             update_message("設定を読み込み中...");
             if let Ok(settings) = client.get_settings() {
                 if let Ok(mut state) = state_clone.lock() {
-                    if let Some(selected_signature_idx) = settings.get("selected_signature_index") {
+                    if let Some(selected_signature_idx) = settings.get(signature::LEGACY_SIGNATURE_SETTING) {
                         if let Ok(idx) = selected_signature_idx.parse::<usize>() {
                             state.selected_signature_index = Some(idx);
                         }
                     }
+                    state.user_profiles = signature::user_profiles(&settings);
+                    if let Some(secs) = settings.get("undo_send_seconds").and_then(|s| s.parse().ok()) {
+                        state.undo_send_secs = ui::mail_panel::clamp_undo_send_secs(secs);
+                    }
                 }
             }
 
@@ -184,11 +427,9 @@ impl MailApp {
             match client.get_signatures() {
                 Ok(signatures) => {
                     if let Ok(mut state) = state_clone.lock() {
-                        state.signatures = signatures.clone();
-                        // デフォルト署名を選択
-                        if state.selected_signature_index.is_none() && !signatures.is_empty() {
-                            state.selected_signature_index = Some(0);
-                        }
+                        state.signatures = signatures;
+                        // ログイン中（前回のセッション）のユーザーの既定の署名を選択
+                        signature::apply_user_defaults(&mut state);
                     }
                 }
                 Err(e) => errors.push(format!("署名: {}", e)),
@@ -215,8 +456,13 @@ impl MailApp {
 
             // 完了
             if let Ok(mut state) = state_clone.lock() {
+                // 起動時にファイルを読み込めなかった警告は消さない
                 if errors.is_empty() {
-                    state.status_message = "起動完了".to_string();
+                    if !state.status_message.starts_with('⚠') {
+                        state.status_message = "起動完了".to_string();
+                    }
+                } else if state.status_message.starts_with('⚠') {
+                    state.status_message = format!("{} / 一部データ取得失敗: {}", state.status_message, errors.join(", "));
                 } else {
                     state.status_message = format!("⚠ 一部データ取得失敗: {}", errors.join(", "));
                 }
@@ -250,26 +496,57 @@ impl eframe::App for MailApp {
             return;
         }
 
-        let mut state = self.state.lock().unwrap();
+        let state_arc = Arc::clone(&self.state);
+        let mut state = state_arc.lock().unwrap();
+
+        self.pump_jobs(ctx, &mut state);
+        self.persist_config(ctx, &mut state);
+        self.persist_draft(&mut state);
+        self.handle_close_request(ctx, &mut state);
 
         // 認証されていない場合はログイン画面を表示
         if !state.is_authenticated {
             ui::login_panel::show(ctx, &mut state);
 
-            // ログイン成功時にセッションを保存
+            // ログイン成功時にセッションを保存し、そのユーザーの既定の署名を選ぶ
             if state.is_authenticated {
-                save_session();
+                state.session_expires_at = save_session(&state.auth_username);
+                if state.session_expires_at.is_none() {
+                    state.status_message = "⚠ セッションを保存できませんでした（次回もログインが必要です）".to_string();
+                }
+                signature::apply_user_defaults(&mut state);
             }
             return;
         }
 
+        if state.session_expires_at.is_some_and(|expires_at| now_unix_secs() >= expires_at) {
+            logout(&mut state, Some("セッションの有効期限が切れました。もう一度ログインしてください".to_string()));
+            return;
+        }
+        // 権限はユーザーの一覧に合わせる（自分の権限を変えた・削除したときもすぐ反映する）
+        match state.user_store.role_of(&state.auth_username) {
+            Some(role) => state.auth_role = role,
+            None => {
+                logout(&mut state, Some("ユーザーが削除されたためログアウトしました".to_string()));
+                return;
+            }
+        }
+        if state.tab.required_role() > state.auth_role {
+            state.tab = Tab::Main;
+        }
+
         // Top tab bar (system tabs style)
         egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
             ui.add_space(4.0);
             ui.horizontal(|ui| {
                 ui.add_space(8.0);
 
+                let role = state.auth_role;
                 let tab_button = |ui: &mut egui::Ui, current: &mut Tab, target: Tab, label: &str| {
+                    // 権限のないタブは表示しない
+                    if target.required_role() > role {
+                        return;
+                    }
                     let is_selected = *current == target;
                     let text = if is_selected {
                         egui::RichText::new(label).strong()
@@ -279,23 +556,29 @@ impl eframe::App for MailApp {
                     if ui.selectable_label(is_selected, text).clicked() {
                         *current = target;
                     }
+                    ui.add_space(16.0);
                 };
 
                 tab_button(ui, &mut state.tab, Tab::Main, "✉ メール作成");
-                ui.add_space(16.0);
+                tab_button(ui, &mut state.tab, Tab::Merge, "📑 差し込み");
+                tab_button(ui, &mut state.tab, Tab::Templates, "📝 テンプレート");
+                tab_button(ui, &mut state.tab, Tab::Recipients, "👥 宛先");
+                tab_button(ui, &mut state.tab, Tab::Linkings, "🔗 紐付け");
+                tab_button(ui, &mut state.tab, Tab::Signatures, "✍ 署名");
                 tab_button(ui, &mut state.tab, Tab::History, "📜 送信履歴");
-                ui.add_space(16.0);
+                let outbox_label = format!("📤 送信待ち ({})", state.outbox.items().len());
+                tab_button(ui, &mut state.tab, Tab::Outbox, &outbox_label);
+                let scheduled_label = format!("⏰ 予約送信 ({})", state.schedule.items().len());
+                tab_button(ui, &mut state.tab, Tab::Scheduled, &scheduled_label);
                 tab_button(ui, &mut state.tab, Tab::Settings, "⚙ 設定");
+                tab_button(ui, &mut state.tab, Tab::Audit, "🛡 監査ログ");
 
                 // ログアウトボタン（右寄せ）
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui.small_button("🚪 ログアウト").clicked() {
-                        state.is_authenticated = false;
-                        state.auth_username.clear();
-                        state.auth_password.clear();
-                        state.auth_error = None;
-                        clear_session();  // セッションを削除
+                        logout(&mut state, None);
                     }
+                    ui.weak(format!("👤 {}（{}）", state.auth_username, state.auth_role.label()));
                 });
             });
             ui.add_space(4.0);
@@ -322,15 +605,52 @@ impl eframe::App for MailApp {
                 ui.label(icon);
                 ui.label(egui::RichText::new(msg).color(color));
             });
+
+            // バックグラウンド処理の進捗
+            let mut cancel_clicked = false;
+            if let Some(progress) = &state.job_progress {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("{} ({}/{})", progress.title, progress.done, progress.total));
+                    let fraction = if progress.total == 0 {
+                        0.0
+                    } else {
+                        progress.done as f32 / progress.total as f32
+                    };
+                    ui.add(egui::ProgressBar::new(fraction).desired_width(200.0).text(&progress.detail));
+                    if progress.cancel_requested {
+                        ui.weak("キャンセル中...");
+                    } else if ui.small_button("キャンセル").clicked() {
+                        cancel_clicked = true;
+                    }
+                });
+            }
+            if cancel_clicked {
+                if let Some(progress) = state.job_progress.as_mut() {
+                    progress.cancel_requested = true;
+                }
+            }
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             match state.tab {
                 Tab::Main => ui::mail_panel::show(ui, &mut state),
+                Tab::Merge => ui::merge_panel::show(ui, &mut state),
+                Tab::Templates => ui::template_panel::show(ui, &mut state),
+                Tab::Recipients => ui::recipient_panel::show(ui, &mut state),
+                Tab::Linkings => ui::linking_panel::show(ui, &mut state),
+                Tab::Signatures => ui::signature_panel::show(ui, &mut state),
                 Tab::History => ui::history_panel::show(ui, &mut state),
+                Tab::Outbox => ui::outbox_panel::show(ui, &mut state),
+                Tab::Scheduled => ui::schedule_panel::show(ui, &mut state),
                 Tab::Settings => ui::settings_panel::show(ui, &mut state),
+                Tab::Audit => ui::audit_panel::show(ui, &mut state),
             }
         });
+
+        if state.show_exit_confirmation {
+            ui::mail_panel::show_exit_confirmation(ctx, &mut state);
+        }
     }
 }
 
@@ -0,0 +1,246 @@
+//! 操作の監査ログ
+//!
+//! データフォルダの audit.log に1行1件の JSON で追記だけする。各行は直前の行のハッシュを含めた
+//! HMAC-SHA256（鍵はセッションと同じ session.key）を持つので、鍵を持たない人が途中の行を書き換えたり
+//! 消したりすると verify で検出できる。鍵を読める人はハッシュを計算し直せるので、その書き換えは検出できない。
+//! また末尾の行をまとめて消した（切り詰めた）場合は、残った行の連鎖は正しいままなので検出できない。
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::Write;
+use std::path::PathBuf;
+use crate::models::AppState;
+use crate::utils::now_unix_secs;
+
+pub const AUDIT_FILE_NAME: &str = "audit.log";
+
+/// 最初の行の prev_hash
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// 記録する操作の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Lockout,
+    Logout,
+    Send,
+    Template,
+    Recipient,
+    Linking,
+    Signature,
+    UserAdmin,
+}
+
+impl AuditAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            AuditAction::Login => "ログイン",
+            AuditAction::LoginFailed => "ログイン失敗",
+            AuditAction::Lockout => "ロック",
+            AuditAction::Logout => "ログアウト",
+            AuditAction::Send => "送信",
+            AuditAction::Template => "テンプレート",
+            AuditAction::Recipient => "宛先",
+            AuditAction::Linking => "紐付け",
+            AuditAction::Signature => "署名",
+            AuditAction::UserAdmin => "ユーザー管理",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    /// UNIX秒
+    pub at: u64,
+    /// 操作したユーザー（ログイン失敗では入力されたユーザー名）
+    pub user: String,
+    pub action: AuditAction,
+    pub detail: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self, key: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC は任意の長さの鍵を受け付ける");
+        let action = serde_json::to_string(&self.action).unwrap_or_default();
+        for field in [&self.seq.to_string(), &self.at.to_string(), &self.user, &action, &self.detail, &self.prev_hash] {
+            // 区切りを入れて、項目の境目をずらした改ざんも別のハッシュになるようにする
+            mac.update(&field.len().to_le_bytes());
+            mac.update(field.as_bytes());
+        }
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    /// ハッシュの鍵
+    key: Vec<u8>,
+    entries: Vec<AuditEntry>,
+    /// 読めなかった行の行番号（1始まり）
+    unreadable_lines: Vec<usize>,
+}
+
+impl AuditLog {
+    /// すべての行を読み、読めない行は行番号を残して検証で報告する
+    /// 追記は読めた最後の行に続けるので、壊れた行があっても記録は止めない
+    pub fn load(path: PathBuf, key: Vec<u8>) -> Self {
+        let mut entries = Vec::new();
+        let mut unreadable_lines = Vec::new();
+        let content = std::fs::read_to_string(&path).unwrap_or_default();
+        for (i, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) => unreadable_lines.push(i + 1),
+            }
+        }
+        Self { path: Some(path), key, entries, unreadable_lines }
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    pub fn unreadable_lines(&self) -> &[usize] {
+        &self.unreadable_lines
+    }
+
+    /// 1件追記する（ファイルへは追記モードで書くだけで、既存の行は書き換えない）
+    pub fn append(&mut self, user: &str, action: AuditAction, detail: impl Into<String>, now: u64) -> Result<(), String> {
+        let (seq, prev_hash) = match self.entries.last() {
+            Some(last) => (last.seq + 1, last.hash.clone()),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            seq,
+            at: now,
+            user: user.to_string(),
+            action,
+            detail: detail.into(),
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash(&self.key);
+
+        if let Some(path) = &self.path {
+            let line = serde_json::to_string(&entry)
+                .map_err(|e| format!("監査ログを書き込めません: {}", e))?;
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .map_err(|e| format!("監査ログを書き込めません: {}", e))?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// 読めない行とハッシュの連鎖を確かめ、最初に不整合のあった行の番号（1始まり）を返す
+    /// 末尾の行を切り詰めた場合は検出できない
+    pub fn verify(&self) -> Result<(), usize> {
+        if let Some(&line) = self.unreadable_lines.first() {
+            return Err(line);
+        }
+        let mut prev_hash = GENESIS_HASH;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry.seq != i as u64 + 1 || entry.prev_hash != prev_hash || entry.hash != entry.compute_hash(&self.key) {
+                return Err(i + 1);
+            }
+            prev_hash = &entry.hash;
+        }
+        Ok(())
+    }
+}
+
+/// ログイン中のユーザーの操作として記録する（書き込めなければステータスバーに出す）
+pub fn record(state: &mut AppState, action: AuditAction, detail: impl Into<String>) {
+    let user = state.auth_username.clone();
+    record_as(state, &user, action, detail);
+}
+
+/// ユーザーを指定して記録する（ログイン失敗や、予約した人の名前で残す送信）
+pub fn record_as(state: &mut AppState, user: &str, action: AuditAction, detail: impl Into<String>) {
+    if let Err(e) = state.audit_log.append(user, action, detail, now_unix_secs()) {
+        state.status_message = format!("❌ {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> AuditLog {
+        let mut log = AuditLog { key: b"audit key".to_vec(), ..Default::default() };
+        log.append("tanaka", AuditAction::Login, "", 100).unwrap();
+        log.append("tanaka", AuditAction::Send, "a@example.com: ご請求書", 110).unwrap();
+        log.append("tanaka", AuditAction::Logout, "", 120).unwrap();
+        log
+    }
+
+    #[test]
+    fn test_chain_detects_tampering() {
+        let log = sample_log();
+        assert_eq!(log.verify(), Ok(()));
+        assert_eq!(log.entries()[1].prev_hash, log.entries()[0].hash);
+
+        let mut edited = log.clone();
+        edited.entries[1].detail = "b@example.com: ご請求書".to_string();
+        assert_eq!(edited.verify(), Err(2), "内容の書き換え");
+
+        let mut removed = log.clone();
+        removed.entries.remove(1);
+        assert_eq!(removed.verify(), Err(2), "行の削除");
+
+        let mut rehashed = log.clone();
+        rehashed.entries[0].user = "suzuki".to_string();
+        rehashed.entries[0].hash = rehashed.entries[0].compute_hash(b"audit key");
+        assert_eq!(rehashed.verify(), Err(2), "ハッシュを計算し直しても次の行でわかる");
+
+        // 鍵を持たない人が連鎖をすべて計算し直しても、最初の行でわかる
+        let mut forged = log.clone();
+        forged.entries[1].detail = "b@example.com: ご請求書".to_string();
+        let mut prev_hash = GENESIS_HASH.to_string();
+        for entry in &mut forged.entries {
+            entry.prev_hash = prev_hash;
+            entry.hash = entry.compute_hash(b"guessed key");
+            prev_hash = entry.hash.clone();
+        }
+        assert_eq!(forged.verify(), Err(1), "鍵がなければ計算し直せない");
+    }
+
+    #[test]
+    fn test_appends_survive_reload() {
+        let path = std::env::temp_dir().join(format!("audit_test_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut log = AuditLog::load(path.clone(), b"audit key".to_vec());
+        log.append("tanaka", AuditAction::Login, "", 100).unwrap();
+        let mut reloaded = AuditLog::load(path.clone(), b"audit key".to_vec());
+        reloaded.append("tanaka", AuditAction::Logout, "", 200).unwrap();
+
+        let reloaded = AuditLog::load(path.clone(), b"audit key".to_vec());
+        assert_eq!(reloaded.entries().len(), 2);
+        assert_eq!(reloaded.verify(), Ok(()));
+
+        // 壊れた行は報告し、その後の行も読む。追記も続ける
+        let mut content = std::fs::read_to_string(&path).unwrap();
+        content = content.replacen("\"seq\":1", "\"seq\":\"x\"", 1);
+        std::fs::write(&path, content).unwrap();
+        let mut broken = AuditLog::load(path.clone(), b"audit key".to_vec());
+        assert_eq!(broken.verify(), Err(1));
+        assert_eq!(broken.unreadable_lines(), &[1]);
+        assert_eq!(broken.entries().len(), 1);
+        broken.append("tanaka", AuditAction::Login, "", 300).unwrap();
+        let reloaded = AuditLog::load(path.clone(), b"audit key".to_vec());
+        assert_eq!(reloaded.entries().len(), 2);
+        assert_eq!(reloaded.unreadable_lines(), &[1]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
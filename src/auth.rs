@@ -0,0 +1,404 @@
+//! ログインユーザーと権限、署名付きセッション
+//!
+//! ユーザーはデータフォルダの users.json に Argon2 のハッシュ（ソルト付き）で保存する。
+//! セッションは「ユーザー名・有効期限・HMAC-SHA256」で、鍵はデータフォルダの session.key。
+//! 鍵を持たない人がセッションファイルを作っても自動ログインできない。
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::storage::{read_json, write_atomic};
+
+pub const USERS_FILE_NAME: &str = "users.json";
+pub const SESSION_KEY_FILE_NAME: &str = "session.key";
+/// セッションの有効期間（秒）
+pub const SESSION_TTL_SECS: u64 = 8 * 60 * 60;
+pub const MIN_PASSWORD_CHARS: usize = 8;
+pub const LOGIN_THROTTLE_FILE_NAME: &str = "login_failures.json";
+/// この回数続けて失敗したらロックする
+pub const MAX_LOGIN_FAILURES: u32 = 5;
+pub const LOCKOUT_SECS: u64 = 15 * 60;
+
+const LOGIN_FAILED: &str = "ユーザー名またはパスワードが正しくありません";
+
+/// 権限。上位の権限は下位の権限でできることをすべてできる
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// メールの作成・送信、自分の送信者名と既定の署名の設定
+    #[default]
+    Sender,
+    /// テンプレート・宛先マスター・紐付け・署名の編集
+    TemplateEditor,
+    /// 接続設定とユーザー管理
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Sender, Role::TemplateEditor, Role::Admin];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Role::Sender => "送信者",
+            Role::TemplateEditor => "テンプレート編集者",
+            Role::Admin => "管理者",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UserAccount {
+    pub username: String,
+    /// Argon2 の PHC 形式（ソルトを含む）
+    pub password_hash: String,
+    pub role: Role,
+}
+
+/// ユーザーの一覧（変更したら save で保存する）
+#[derive(Clone, Debug, Default)]
+pub struct UserStore {
+    path: Option<PathBuf>,
+    users: Vec<UserAccount>,
+}
+
+impl UserStore {
+    /// ファイルがないときだけ「ユーザーがまだいない」とする
+    /// 読めない・壊れているときはエラーにし、最初の管理者を作らせない（既存のユーザーを上書きしない）
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let users = read_json(&path)?.unwrap_or_default();
+        Ok(Self { path: Some(path), users })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(&self.users)
+            .map_err(|e| format!("ユーザーの保存に失敗しました: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    pub fn users(&self) -> &[UserAccount] {
+        &self.users
+    }
+
+    /// ユーザーがいなければ、最初の管理者を作るまでログインできない
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn role_of(&self, username: &str) -> Option<Role> {
+        self.find(username).map(|u| u.role)
+    }
+
+    /// パスワードを確かめて権限を返す
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<Role, String> {
+        let user = self.find(username).ok_or(LOGIN_FAILED)?;
+        if verify_password(password, &user.password_hash) {
+            Ok(user.role)
+        } else {
+            Err(LOGIN_FAILED.to_string())
+        }
+    }
+
+    pub fn add_user(&mut self, username: &str, password: &str, role: Role) -> Result<(), String> {
+        let username = username.trim();
+        validate_username(username)?;
+        if self.find(username).is_some() {
+            return Err(format!("ユーザー「{}」はすでにあります", username));
+        }
+        let password_hash = hash_password(password)?;
+        self.users.push(UserAccount { username: username.to_string(), password_hash, role });
+        Ok(())
+    }
+
+    pub fn set_password(&mut self, username: &str, password: &str) -> Result<(), String> {
+        let password_hash = hash_password(password)?;
+        self.find_mut(username)?.password_hash = password_hash;
+        Ok(())
+    }
+
+    pub fn set_role(&mut self, username: &str, role: Role) -> Result<(), String> {
+        if role != Role::Admin && self.is_last_admin(username) {
+            return Err("管理者が1人もいなくなるため変更できません".to_string());
+        }
+        self.find_mut(username)?.role = role;
+        Ok(())
+    }
+
+    pub fn remove_user(&mut self, username: &str) -> Result<(), String> {
+        if self.is_last_admin(username) {
+            return Err("最後の管理者は削除できません".to_string());
+        }
+        self.find_mut(username)?;
+        self.users.retain(|u| u.username != username);
+        Ok(())
+    }
+
+    fn find(&self, username: &str) -> Option<&UserAccount> {
+        self.users.iter().find(|u| u.username == username)
+    }
+
+    fn find_mut(&mut self, username: &str) -> Result<&mut UserAccount, String> {
+        self.users.iter_mut()
+            .find(|u| u.username == username)
+            .ok_or_else(|| format!("ユーザー「{}」が見つかりません", username))
+    }
+
+    fn is_last_admin(&self, username: &str) -> bool {
+        self.role_of(username) == Some(Role::Admin)
+            && self.users.iter().filter(|u| u.role == Role::Admin).count() == 1
+    }
+}
+
+/// ログインに失敗した回数と、次に試せる時刻（UNIX秒）
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoginFailures {
+    pub count: u32,
+    pub retry_at: u64,
+}
+
+/// ログイン失敗の記録（アプリを再起動しても続くよう保存する）
+///
+/// 2回目の失敗から 2, 4, 8 秒と待ち時間を延ばし、MAX_LOGIN_FAILURES 回で LOCKOUT_SECS の間ロックする。
+/// 存在しないユーザー名も同じように数える。
+#[derive(Clone, Debug, Default)]
+pub struct LoginThrottle {
+    path: Option<PathBuf>,
+    failures: HashMap<String, LoginFailures>,
+}
+
+impl LoginThrottle {
+    pub fn load(path: PathBuf) -> Self {
+        let failures = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path: Some(path), failures }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(&self.failures)
+            .map_err(|e| format!("ログイン失敗の記録を保存できません: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    /// いまログインを試せるか（待ち時間中・ロック中ならその旨のエラー）
+    pub fn check(&self, username: &str, now: u64) -> Result<(), String> {
+        let Some(failures) = self.failures.get(username).filter(|f| f.retry_at > now) else {
+            return Ok(());
+        };
+        let wait = failures.retry_at - now;
+        if failures.count >= MAX_LOGIN_FAILURES {
+            Err(format!("ログインに{}回失敗したためロックしています。{}分後に再度お試しいただくか、管理者に解除を依頼してください", failures.count, wait.div_ceil(60)))
+        } else {
+            Err(format!("{}秒待ってから再度お試しください", wait))
+        }
+    }
+
+    /// 失敗を記録し、ロックしたら true（ロックが明けた後は1回目から数え直す）
+    pub fn record_failure(&mut self, username: &str, now: u64) -> bool {
+        let failures = self.failures.entry(username.to_string()).or_default();
+        if failures.count >= MAX_LOGIN_FAILURES && failures.retry_at <= now {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        let wait = if failures.count >= MAX_LOGIN_FAILURES {
+            LOCKOUT_SECS
+        } else if failures.count >= 2 {
+            1 << (failures.count - 1)
+        } else {
+            0
+        };
+        failures.retry_at = now + wait;
+        failures.count >= MAX_LOGIN_FAILURES
+    }
+
+    /// ログインできたら、またはパスワードを再設定したら記録を消す
+    pub fn clear(&mut self, username: &str) {
+        self.failures.remove(username);
+    }
+
+    /// ロック中なら解除される時刻
+    pub fn locked_until(&self, username: &str, now: u64) -> Option<u64> {
+        self.failures.get(username)
+            .filter(|f| f.count >= MAX_LOGIN_FAILURES && f.retry_at > now)
+            .map(|f| f.retry_at)
+    }
+}
+
+/// ユーザー名はセッションや設定のキーに使うので、空白と制御文字を含めない
+fn validate_username(username: &str) -> Result<(), String> {
+    if username.is_empty() {
+        return Err("ユーザー名を入力してください".to_string());
+    }
+    if username.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("ユーザー名に空白は使えません".to_string());
+    }
+    Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String, String> {
+    if password.chars().count() < MIN_PASSWORD_CHARS {
+        return Err(format!("パスワードは{}文字以上にしてください", MIN_PASSWORD_CHARS));
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("パスワードを保存できません: {}", e))
+}
+
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+/// セッションの署名鍵を読み込む（なければ作って保存する）
+pub fn load_or_create_session_key(path: &Path) -> Result<Vec<u8>, String> {
+    if let Some(key) = std::fs::read_to_string(path).ok().and_then(|hex_key| hex::decode(hex_key.trim()).ok()) {
+        if key.len() == 32 {
+            return Ok(key);
+        }
+    }
+    let mut key = vec![0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    write_atomic(path, &hex::encode(&key))?;
+    Ok(key)
+}
+
+fn session_mac(key: &[u8], username: &str, expires_at: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC は任意の長さの鍵を使える");
+    mac.update(format!("{}\n{}", username, expires_at).as_bytes());
+    mac
+}
+
+/// ログインしたユーザーのセッションを作る（有効期限は now から SESSION_TTL_SECS）
+pub fn issue_session(key: &[u8], username: &str, now: u64) -> String {
+    let expires_at = now + SESSION_TTL_SECS;
+    let signature = session_mac(key, username, expires_at).finalize().into_bytes();
+    format!("{}\n{}\n{}", username, expires_at, hex::encode(signature))
+}
+
+/// 署名が正しく期限内ならユーザー名と有効期限を返す
+pub fn verify_session(key: &[u8], token: &str, now: u64) -> Option<(String, u64)> {
+    let mut lines = token.trim().lines();
+    let username = lines.next()?;
+    let expires_at: u64 = lines.next()?.parse().ok()?;
+    let signature = hex::decode(lines.next()?).ok()?;
+    session_mac(key, username, expires_at).verify_slice(&signature).ok()?;
+    (now < expires_at).then(|| (username.to_string(), expires_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passwords_are_salted_and_checked() {
+        let mut store = UserStore::default();
+        store.add_user("tanaka", "correct horse", Role::Sender).unwrap();
+        store.add_user("suzuki", "correct horse", Role::Admin).unwrap();
+
+        let hashes: Vec<&str> = store.users().iter().map(|u| u.password_hash.as_str()).collect();
+        assert!(hashes[0].starts_with("$argon2"));
+        assert_ne!(hashes[0], hashes[1], "同じパスワードでもソルトが違う");
+        assert!(!hashes[0].contains("correct horse"));
+
+        assert_eq!(store.authenticate("tanaka", "correct horse"), Ok(Role::Sender));
+        assert_eq!(store.authenticate("tanaka", "wrong horse!"), Err(LOGIN_FAILED.to_string()));
+        assert_eq!(store.authenticate("sato", "correct horse"), Err(LOGIN_FAILED.to_string()), "存在しないユーザーも同じエラー");
+
+        assert!(store.add_user("ito", "short", Role::Sender).is_err());
+        assert!(store.add_user("ito 2", "long enough", Role::Sender).is_err());
+        assert!(store.add_user("tanaka", "long enough", Role::Sender).is_err());
+    }
+
+    #[test]
+    fn test_only_a_missing_users_file_means_no_users() {
+        let path = std::env::temp_dir().join(format!("amp_users_test_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(UserStore::load(path.clone()).unwrap().is_empty());
+
+        std::fs::write(&path, r#"[{"username":"suzuki","#).unwrap();
+        assert!(UserStore::load(path.clone()).is_err(), "壊れたファイルを空として読まない");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"[{"username":"suzuki","#, "ファイルはそのまま残す");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_last_admin_cannot_be_removed_or_demoted() {
+        let admin = |name: &str| UserAccount { username: name.to_string(), password_hash: String::new(), role: Role::Admin };
+        let mut store = UserStore { path: None, users: vec![admin("suzuki")] };
+
+        assert!(store.set_role("suzuki", Role::Sender).is_err());
+        assert!(store.remove_user("suzuki").is_err());
+
+        store.users.push(admin("sato"));
+        store.set_role("suzuki", Role::TemplateEditor).unwrap();
+        assert!(store.remove_user("sato").is_err(), "残る管理者は sato だけ");
+        store.remove_user("suzuki").unwrap();
+        assert_eq!(store.users().len(), 1);
+    }
+
+    #[test]
+    fn test_failures_delay_then_lock_out() {
+        let mut throttle = LoginThrottle::default();
+        assert!(!throttle.record_failure("tanaka", 100));
+        assert!(throttle.check("tanaka", 100).is_ok(), "1回目はすぐ再入力できる");
+
+        assert!(!throttle.record_failure("tanaka", 100));
+        assert!(throttle.check("tanaka", 101).is_err(), "2回目の後は2秒待つ");
+        assert!(throttle.check("tanaka", 102).is_ok());
+        assert!(throttle.check("suzuki", 101).is_ok(), "他のユーザーには影響しない");
+
+        for _ in 3..MAX_LOGIN_FAILURES {
+            assert!(!throttle.record_failure("tanaka", 200));
+        }
+        assert!(throttle.record_failure("tanaka", 200));
+        assert_eq!(throttle.locked_until("tanaka", 200), Some(200 + LOCKOUT_SECS));
+        assert!(throttle.check("tanaka", 200 + LOCKOUT_SECS - 1).unwrap_err().contains("ロック"));
+        assert!(throttle.check("tanaka", 200 + LOCKOUT_SECS).is_ok());
+
+        throttle.clear("tanaka");
+        assert_eq!(throttle.locked_until("tanaka", 200), None);
+    }
+
+    #[test]
+    fn test_lockout_expiry_starts_counting_again() {
+        let mut throttle = LoginThrottle::default();
+        for _ in 0..MAX_LOGIN_FAILURES {
+            throttle.record_failure("tanaka", 100);
+        }
+        let unlocked_at = 100 + LOCKOUT_SECS;
+
+        // ロックが明けた後の1回の入力ミスではロックしない
+        assert!(!throttle.record_failure("tanaka", unlocked_at));
+        assert!(throttle.check("tanaka", unlocked_at).is_ok());
+        assert_eq!(throttle.locked_until("tanaka", unlocked_at), None);
+    }
+
+    #[test]
+    fn test_session_is_signed_and_expires() {
+        let key = [7u8; 32];
+        let token = issue_session(&key, "tanaka", 1_000);
+
+        assert_eq!(verify_session(&key, &token, 1_001), Some(("tanaka".to_string(), 1_000 + SESSION_TTL_SECS)));
+        assert_eq!(verify_session(&key, &token, 1_000 + SESSION_TTL_SECS), None, "期限切れ");
+        assert_eq!(verify_session(&[8u8; 32], &token, 1_001), None, "別の鍵");
+
+        let forged = token.replacen("tanaka", "suzuki", 1);
+        assert_eq!(verify_session(&key, &forged, 1_001), None, "ユーザー名の書き換え");
+        let extended = token.replacen(&(1_000 + SESSION_TTL_SECS).to_string(), "99999999999", 1);
+        assert_eq!(verify_session(&key, &extended, 1_001), None, "期限の書き換え");
+        assert_eq!(verify_session(&key, "authenticated", 1_001), None, "以前の形式");
+    }
+}
@@ -0,0 +1,161 @@
+use super::{build_message, send_each, MailBackend};
+use crate::api::{ApiError, BatchMailItem, BatchSendReport, GasClient};
+use crate::models::{HistoryItem, LinkingData, RecipientData, Signature, Template};
+use lettre::{FileTransport, Transport};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// 送信する代わりに .eml ファイルをフォルダへ書き出すバックエンド
+/// 送信前の確認や、別のメールソフトからの送信に使う。マスターデータは GAS から取得する
+pub struct FileDropBackend {
+    master: GasClient,
+    dir: String,
+    from: String,
+}
+
+impl FileDropBackend {
+    pub fn new(master: GasClient, dir: String, from: String) -> Self {
+        Self { master, dir, from }
+    }
+
+    /// 出力先フォルダを確認（なければ作成）
+    fn ensure_dir(&self) -> Result<PathBuf, ApiError> {
+        let dir = self.dir.trim();
+        if dir.is_empty() {
+            return Err(ApiError::ConfigError("出力先フォルダが設定されていません".to_string()));
+        }
+        let path = PathBuf::from(dir);
+        std::fs::create_dir_all(&path)
+            .map_err(|e| ApiError::ConfigError(format!("出力先フォルダを作成できません: {}", e)))?;
+        Ok(path)
+    }
+}
+
+impl MailBackend for FileDropBackend {
+    fn get_templates(&self) -> Result<Vec<Template>, ApiError> {
+        self.master.get_templates()
+    }
+
+    fn save_template(&self, template: &Template, original_name: Option<&str>) -> Result<String, ApiError> {
+        self.master.save_template(template, original_name)
+    }
+
+    fn delete_template(&self, name: &str, updated_at: &str) -> Result<(), ApiError> {
+        self.master.delete_template(name, updated_at)
+    }
+
+    fn get_recipients(&self) -> Result<Vec<RecipientData>, ApiError> {
+        self.master.get_recipients()
+    }
+
+    fn save_recipient(&self, recipient: &RecipientData) -> Result<(), ApiError> {
+        self.master.save_recipient(recipient)
+    }
+
+    fn update_recipient(&self, recipient: &RecipientData) -> Result<(), ApiError> {
+        self.master.update_recipient(recipient)
+    }
+
+    fn delete_recipient(&self, id: &str) -> Result<(), ApiError> {
+        self.master.delete_recipient(id)
+    }
+
+    fn get_signatures(&self) -> Result<Vec<Signature>, ApiError> {
+        self.master.get_signatures()
+    }
+
+    fn save_signature(&self, signature: &Signature, original_name: Option<&str>) -> Result<(), ApiError> {
+        self.master.save_signature(signature, original_name)
+    }
+
+    fn delete_signature(&self, name: &str) -> Result<(), ApiError> {
+        self.master.delete_signature(name)
+    }
+
+    fn get_linkings(&self) -> Result<Vec<LinkingData>, ApiError> {
+        self.master.get_linkings()
+    }
+
+    fn save_linking(&self, linking: &LinkingData) -> Result<(), ApiError> {
+        self.master.save_linking(linking)
+    }
+
+    fn delete_linking(&self, linking: &LinkingData) -> Result<(), ApiError> {
+        self.master.delete_linking(linking)
+    }
+
+    fn get_settings(&self) -> Result<HashMap<String, String>, ApiError> {
+        self.master.get_settings()
+    }
+
+    fn save_settings(&self, settings: &HashMap<String, String>) -> Result<(), ApiError> {
+        self.master.save_settings(settings)
+    }
+
+    fn get_history(&self) -> Result<Vec<HistoryItem>, ApiError> {
+        self.master.get_history()
+    }
+
+    fn send_batch_mail(&self, items: &[BatchMailItem]) -> Result<BatchSendReport, ApiError> {
+        let transport = FileTransport::new(self.ensure_dir()?);
+        Ok(send_each(items, |item| {
+            let message = build_message(&self.from, item)?;
+            transport.send(&message)
+                .map(|_| ())
+                .map_err(|e| ApiError::NetworkError(format!(".eml 書き出しエラー: {}", e)))
+        }))
+    }
+
+    fn test_connection(&self) -> Result<(), ApiError> {
+        self.ensure_dir().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Attachment;
+
+    #[test]
+    fn test_writes_one_eml_per_recipient_with_own_attachment() {
+        let dir = std::env::temp_dir().join(format!("amp_file_drop_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let backend = FileDropBackend::new(
+            GasClient::new(String::new()),
+            dir.to_string_lossy().to_string(),
+            "sender@example.com".to_string(),
+        );
+        let invoice = vec![Attachment {
+            file_name: "請求書_A社.pdf".to_string(),
+            mime_type: "application/pdf".to_string(),
+            data: "JVBERi0=".to_string(),
+            ..Default::default()
+        }];
+        let cc = vec!["sales@example.com".to_string()];
+        let items = [
+            BatchMailItem { message_id: "m-a", to: "a@example.com", cc: &cc, bcc: &[], reply_to: "", subject: "ご請求書", body: "A社様", html_body: Some("<p>A社様</p>"), attachments: &invoice },
+            BatchMailItem { message_id: "m-b", to: "b@example.com", cc: &[], bcc: &[], reply_to: "", subject: "ご案内", body: "B社様", html_body: None, attachments: &[] },
+        ];
+
+        let report = backend.send_batch_mail(&items).unwrap();
+        assert!(report.is_all_success());
+
+        let emls: Vec<String> = std::fs::read_dir(&dir).unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "eml"))
+            .map(|e| std::fs::read_to_string(e.path()).unwrap())
+            .collect();
+        assert_eq!(emls.len(), 2);
+        let to_a = emls.iter().find(|m| m.contains("To: a@example.com")).unwrap();
+        let to_b = emls.iter().find(|m| m.contains("To: b@example.com")).unwrap();
+        assert!(to_a.contains("application/pdf"));
+        assert!(to_a.contains("Cc: sales@example.com"));
+        assert!(to_a.contains("multipart/alternative"));
+        assert!(to_a.contains("text/html"));
+        assert!(!to_b.contains("text/html"));
+        assert!(!to_b.contains("application/pdf"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,225 @@
+//! 送信・マスターデータ取得のバックエンド
+//!
+//! GAS (Gmail) 経由の送信に加えて、SMTP 直接送信と .eml ファイル出力を切り替えられる。
+//! SMTP・ファイル出力のバックエンドも、テンプレートや宛先などのマスターデータは
+//! 従来どおりスプレッドシート（GAS）から取得する。
+
+pub mod file_drop;
+pub mod smtp;
+
+use crate::api::{ApiError, BatchMailItem, BatchSendReport, GasClient, RecipientSendResult, RetryConfig};
+use crate::models::{HistoryItem, LinkingData, RecipientData, Signature, Template};
+use base64::{engine::general_purpose, Engine as _};
+use lettre::message::{header::ContentType, Attachment as MimeAttachment, Mailbox, Message, MultiPart, SinglePart};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+pub use file_drop::FileDropBackend;
+pub use smtp::SmtpBackend;
+
+/// メール送信とマスターデータ操作の共通インターフェース
+pub trait MailBackend: Send + Sync {
+    fn get_templates(&self) -> Result<Vec<Template>, ApiError>;
+    /// original_name はシート上の元の名前（新規作成なら None）。保存後の版（updatedAt）を返す
+    fn save_template(&self, template: &Template, original_name: Option<&str>) -> Result<String, ApiError>;
+    /// updated_at は読み込んだ版。他のユーザーが更新していれば Conflict
+    fn delete_template(&self, name: &str, updated_at: &str) -> Result<(), ApiError>;
+
+    fn get_recipients(&self) -> Result<Vec<RecipientData>, ApiError>;
+    fn save_recipient(&self, recipient: &RecipientData) -> Result<(), ApiError>;
+    /// ID で更新する（メールアドレスも変更できる）
+    fn update_recipient(&self, recipient: &RecipientData) -> Result<(), ApiError>;
+    fn delete_recipient(&self, id: &str) -> Result<(), ApiError>;
+
+    fn get_signatures(&self) -> Result<Vec<Signature>, ApiError>;
+    /// original_name はシート上の元の名前（新規作成なら None）
+    fn save_signature(&self, signature: &Signature, original_name: Option<&str>) -> Result<(), ApiError>;
+    fn delete_signature(&self, name: &str) -> Result<(), ApiError>;
+
+    fn get_linkings(&self) -> Result<Vec<LinkingData>, ApiError>;
+    /// 宛先とキーワードの組ごとに1件（同じ組があればテンプレートを差し替える）
+    fn save_linking(&self, linking: &LinkingData) -> Result<(), ApiError>;
+    fn delete_linking(&self, linking: &LinkingData) -> Result<(), ApiError>;
+
+    fn get_settings(&self) -> Result<HashMap<String, String>, ApiError>;
+    fn save_settings(&self, settings: &HashMap<String, String>) -> Result<(), ApiError>;
+
+    fn get_history(&self) -> Result<Vec<HistoryItem>, ApiError>;
+
+    /// 宛先ごとの本文・添付ファイルで一括送信し、宛先ごとの結果を返す
+    fn send_batch_mail(&self, items: &[BatchMailItem]) -> Result<BatchSendReport, ApiError>;
+
+    /// 接続テスト（設定画面用）
+    fn test_connection(&self) -> Result<(), ApiError> {
+        self.get_templates().map(|_| ())
+    }
+}
+
+/// バックエンドの種類
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Gas,
+    Smtp,
+    FileDrop,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 3] = [BackendKind::Gas, BackendKind::Smtp, BackendKind::FileDrop];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BackendKind::Gas => "GAS (Gmail)",
+            BackendKind::Smtp => "SMTP 直接送信",
+            BackendKind::FileDrop => "フォルダに .eml 出力",
+        }
+    }
+}
+
+/// SMTP サーバーの接続設定
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            username: String::new(),
+            password: String::new(),
+        }
+    }
+}
+
+/// GAS へのリクエストに署名する共有シークレットの保存先（データフォルダ内）
+pub const SHARED_SECRET_FILE_NAME: &str = "gas_secret.txt";
+
+/// 以前の共有シークレット（設定ファイルに移す前の保存先）
+pub fn load_shared_secret(path: &Path) -> String {
+    std::fs::read_to_string(path).map(|s| s.trim().to_string()).unwrap_or_default()
+}
+
+/// バックエンドの設定一式（設定ファイルのプロファイルごとに保存する）
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    pub gas_url: String,
+    /// Code.gs のスクリプトプロパティ SHARED_SECRET と同じ値。GAS へのリクエストすべてに署名する
+    pub gas_secret: String,
+    pub smtp: SmtpConfig,
+    pub drop_dir: String,
+    pub from_address: String,  // SMTP・ファイル出力時の差出人
+    /// GAS への通信の再試行
+    pub retry: RetryConfig,
+    pub timeout_secs: u64,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: BackendKind::Gas,
+            gas_url: String::new(),
+            gas_secret: String::new(),
+            smtp: SmtpConfig::default(),
+            drop_dir: String::new(),
+            from_address: String::new(),
+            retry: RetryConfig::default(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// 設定に応じたバックエンドを作成
+pub fn create_backend(config: &BackendConfig) -> Box<dyn MailBackend> {
+    let master = GasClient::new(config.gas_url.clone())
+        .with_shared_secret(config.gas_secret.clone())
+        .with_retry_config(config.retry.clone())
+        .with_timeout(Duration::from_secs(config.timeout_secs));
+    match config.kind {
+        BackendKind::Gas => Box::new(master),
+        BackendKind::Smtp => Box::new(SmtpBackend::new(master, config.smtp.clone(), config.from_address.clone())),
+        BackendKind::FileDrop => Box::new(FileDropBackend::new(master, config.drop_dir.clone(), config.from_address.clone())),
+    }
+}
+
+/// BatchMailItem から MIME メッセージを組み立てる（SMTP・ファイル出力で共用）
+pub(crate) fn build_message(from: &str, item: &BatchMailItem) -> Result<Message, ApiError> {
+    let from: Mailbox = from.trim().parse()
+        .map_err(|e| ApiError::ConfigError(format!("差出人アドレス「{}」が不正です: {}", from, e)))?;
+    let to: Mailbox = item.to.trim().parse()
+        .map_err(|e| ApiError::ApiResponseError(format!("宛先アドレス「{}」が不正です: {}", item.to, e)))?;
+
+    let parse_address = |address: &str| -> Result<Mailbox, ApiError> {
+        address.trim().parse()
+            .map_err(|e| ApiError::ApiResponseError(format!("アドレス「{}」が不正です: {}", address, e)))
+    };
+
+    let mut builder = Message::builder()
+        .from(from)
+        .to(to);
+    for cc in item.cc {
+        builder = builder.cc(parse_address(cc)?);
+    }
+    for bcc in item.bcc {
+        builder = builder.bcc(parse_address(bcc)?);
+    }
+    if !item.reply_to.is_empty() {
+        builder = builder.reply_to(parse_address(item.reply_to)?);
+    }
+
+    let builder = builder
+        .subject(item.subject)
+        // 送信待ちのIDを Message-ID にして、再送したメールを受信側で識別できるようにする
+        .message_id(Some(format!("<{}@auto-mail-pilot>", item.message_id)));
+
+    let message = match (item.html_body, item.attachments.is_empty()) {
+        (None, true) => builder.singlepart(SinglePart::plain(item.body.to_string())),
+        (Some(html), true) => builder.multipart(MultiPart::alternative_plain_html(item.body.to_string(), html.to_string())),
+        (html_body, false) => {
+            let mut multipart = match html_body {
+                Some(html) => MultiPart::mixed()
+                    .multipart(MultiPart::alternative_plain_html(item.body.to_string(), html.to_string())),
+                None => MultiPart::mixed().singlepart(SinglePart::plain(item.body.to_string())),
+            };
+            for att in item.attachments {
+                let data = general_purpose::STANDARD.decode(&att.data)
+                    .map_err(|e| ApiError::ParseError(format!("添付ファイル「{}」のデコードに失敗: {}", att.file_name, e)))?;
+                let content_type = ContentType::parse(&att.mime_type)
+                    .unwrap_or_else(|_| ContentType::parse("application/octet-stream").expect("valid mime type"));
+                multipart = multipart.singlepart(MimeAttachment::new(att.file_name.clone()).body(data, content_type));
+            }
+            builder.multipart(multipart)
+        }
+    };
+
+    message.map_err(|e| ApiError::ApiResponseError(format!("メール作成エラー: {}", e)))
+}
+
+/// 1通ずつ送信する関数で BatchSendReport を作る（SMTP・ファイル出力で共用）
+pub(crate) fn send_each<F>(items: &[BatchMailItem], mut send_one: F) -> BatchSendReport
+where
+    F: FnMut(&BatchMailItem) -> Result<(), ApiError>,
+{
+    let results = items.iter()
+        .map(|item| match send_one(item) {
+            Ok(()) => RecipientSendResult { to: item.to.to_string(), success: true, error: None, retryable: false },
+            Err(e) => RecipientSendResult {
+                to: item.to.to_string(),
+                success: false,
+                error: Some(e.to_string()),
+                retryable: e.is_retryable(),
+            },
+        })
+        .collect();
+    BatchSendReport { results }
+}
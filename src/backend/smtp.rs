@@ -0,0 +1,130 @@
+use super::{build_message, send_each, MailBackend, SmtpConfig};
+use crate::api::{ApiError, BatchMailItem, BatchSendReport, GasClient};
+use crate::models::{HistoryItem, LinkingData, RecipientData, Signature, Template};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// SMTP サーバーへ直接送信するバックエンド
+/// マスターデータは GAS から取得する（送信履歴も GAS 経由の送信分のみ）
+pub struct SmtpBackend {
+    master: GasClient,
+    config: SmtpConfig,
+    from: String,
+}
+
+impl SmtpBackend {
+    pub fn new(master: GasClient, config: SmtpConfig, from: String) -> Self {
+        Self { master, config, from }
+    }
+
+    /// SMTP トランスポートを作成（465 は SMTPS、それ以外は STARTTLS）
+    fn transport(&self) -> Result<SmtpTransport, ApiError> {
+        let host = self.config.host.trim();
+        if host.is_empty() {
+            return Err(ApiError::ConfigError("SMTPサーバーが設定されていません".to_string()));
+        }
+
+        let builder = if self.config.port == 465 {
+            SmtpTransport::relay(host)
+        } else {
+            SmtpTransport::starttls_relay(host)
+        }
+        .map_err(|e| ApiError::ConfigError(format!("SMTPサーバー設定エラー: {}", e)))?
+        .port(self.config.port)
+        .timeout(Some(Duration::from_secs(30)));
+
+        let builder = if self.config.username.is_empty() {
+            builder
+        } else {
+            builder.credentials(Credentials::new(self.config.username.clone(), self.config.password.clone()))
+        };
+
+        Ok(builder.build())
+    }
+}
+
+impl MailBackend for SmtpBackend {
+    fn get_templates(&self) -> Result<Vec<Template>, ApiError> {
+        self.master.get_templates()
+    }
+
+    fn save_template(&self, template: &Template, original_name: Option<&str>) -> Result<String, ApiError> {
+        self.master.save_template(template, original_name)
+    }
+
+    fn delete_template(&self, name: &str, updated_at: &str) -> Result<(), ApiError> {
+        self.master.delete_template(name, updated_at)
+    }
+
+    fn get_recipients(&self) -> Result<Vec<RecipientData>, ApiError> {
+        self.master.get_recipients()
+    }
+
+    fn save_recipient(&self, recipient: &RecipientData) -> Result<(), ApiError> {
+        self.master.save_recipient(recipient)
+    }
+
+    fn update_recipient(&self, recipient: &RecipientData) -> Result<(), ApiError> {
+        self.master.update_recipient(recipient)
+    }
+
+    fn delete_recipient(&self, id: &str) -> Result<(), ApiError> {
+        self.master.delete_recipient(id)
+    }
+
+    fn get_signatures(&self) -> Result<Vec<Signature>, ApiError> {
+        self.master.get_signatures()
+    }
+
+    fn save_signature(&self, signature: &Signature, original_name: Option<&str>) -> Result<(), ApiError> {
+        self.master.save_signature(signature, original_name)
+    }
+
+    fn delete_signature(&self, name: &str) -> Result<(), ApiError> {
+        self.master.delete_signature(name)
+    }
+
+    fn get_linkings(&self) -> Result<Vec<LinkingData>, ApiError> {
+        self.master.get_linkings()
+    }
+
+    fn save_linking(&self, linking: &LinkingData) -> Result<(), ApiError> {
+        self.master.save_linking(linking)
+    }
+
+    fn delete_linking(&self, linking: &LinkingData) -> Result<(), ApiError> {
+        self.master.delete_linking(linking)
+    }
+
+    fn get_settings(&self) -> Result<HashMap<String, String>, ApiError> {
+        self.master.get_settings()
+    }
+
+    fn save_settings(&self, settings: &HashMap<String, String>) -> Result<(), ApiError> {
+        self.master.save_settings(settings)
+    }
+
+    fn get_history(&self) -> Result<Vec<HistoryItem>, ApiError> {
+        self.master.get_history()
+    }
+
+    fn send_batch_mail(&self, items: &[BatchMailItem]) -> Result<BatchSendReport, ApiError> {
+        let transport = self.transport()?;
+        Ok(send_each(items, |item| {
+            let message = build_message(&self.from, item)?;
+            transport.send(&message)
+                .map(|_| ())
+                .map_err(|e| ApiError::NetworkError(format!("SMTP送信エラー: {}", e)))
+        }))
+    }
+
+    fn test_connection(&self) -> Result<(), ApiError> {
+        match self.transport()?.test_connection() {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ApiError::NetworkError("SMTPサーバーに接続できません".to_string())),
+            Err(e) => Err(ApiError::NetworkError(format!("SMTP接続エラー: {}", e))),
+        }
+    }
+}
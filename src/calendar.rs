@@ -0,0 +1,97 @@
+//! 営業日カレンダー
+//!
+//! 土日と、祝日ファイルに書かれた日を休業日として扱う。
+//! 祝日ファイルは1行1日で `2026-01-01 元日` のように日付の後に任意で名前を書く。
+//! `#` 以降はコメント。会社独自の休業日（年末年始など）も同じファイルに追加できる。
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// データフォルダ内の祝日ファイル名
+pub const HOLIDAYS_FILE_NAME: &str = "holidays.txt";
+
+#[derive(Clone, Debug, Default)]
+pub struct BusinessCalendar {
+    holidays: BTreeMap<NaiveDate, String>,
+}
+
+impl BusinessCalendar {
+    /// 祝日ファイルを読み込む。ファイルがなければ土日のみ休業日とする
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("祝日ファイル {} を読み込めません: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut holidays = BTreeMap::new();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (date, name) = line.split_once([' ', '\t', ','])
+                .map(|(d, n)| (d, n.trim()))
+                .unwrap_or((line, ""));
+            let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("祝日ファイル {}行目: 日付「{}」を読み取れません（例: 2026-01-01）", line_no + 1, date))?;
+            holidays.insert(date, name.to_string());
+        }
+        Ok(Self { holidays })
+    }
+
+    pub fn holiday_count(&self) -> usize {
+        self.holidays.len()
+    }
+
+    pub fn holiday_name(&self, date: NaiveDate) -> Option<&str> {
+        self.holidays.get(&date).map(String::as_str)
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains_key(&date)
+    }
+
+    /// date の翌日以降で最初の営業日
+    pub fn next_business_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut next = date + Duration::days(1);
+        while !self.is_business_day(next) {
+            next += Duration::days(1);
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_next_business_day_skips_weekends_and_holidays() {
+        let calendar = BusinessCalendar::parse(
+            "# 2026年\n2026-05-04 みどりの日\n2026-05-05,こどもの日\n2026-05-06 振替休日\n"
+        ).unwrap();
+
+        assert_eq!(calendar.holiday_count(), 3);
+        assert_eq!(calendar.holiday_name(date("2026-05-05")), Some("こどもの日"));
+
+        // 金曜の翌営業日は月曜
+        assert_eq!(calendar.next_business_day(date("2026-10-16")), date("2026-10-19"));
+        // 5/1(金) → 土日・5/4〜5/6 の祝日を飛ばして 5/7(木)
+        assert_eq!(calendar.next_business_day(date("2026-05-01")), date("2026-05-07"));
+    }
+
+    #[test]
+    fn test_invalid_line_reports_line_number() {
+        let err = BusinessCalendar::parse("2026-01-01 元日\n1月12日 成人の日\n").unwrap_err();
+        assert!(err.contains("2行目"));
+    }
+}
@@ -0,0 +1,244 @@
+//! ローカルの設定ファイル（接続先のプロファイル・通信設定・画面レイアウト）
+//!
+//! ユーザーごとの設定フォルダの config.json に保存する。接続先（GAS の URL など）は名前付きの
+//! プロファイルとして複数持てるので、本番と検証用のスプレッドシートを実行中に切り替えられる。
+
+use crate::backend::{self, BackendConfig};
+use crate::storage::{self, write_atomic};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+/// 以前の設定ファイル（作業フォルダに置いた gas_url だけのもの）
+const LEGACY_SETTINGS_FILE_NAME: &str = "settings.json";
+
+const DEFAULT_PROFILE_NAME: &str = "本番";
+
+pub const MIN_ZOOM_FACTOR: f32 = 0.5;
+pub const MAX_ZOOM_FACTOR: f32 = 3.0;
+
+/// 名前付きの接続先
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(flatten)]
+    pub backend: BackendConfig,
+}
+
+/// リサイズできる欄の大きさ（ドラッグし終えたら保存する）
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub col_recipients_width: f32,
+    pub col_templates_width: f32,
+    pub col_signatures_width: f32,
+    pub body_editor_height: f32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            col_recipients_width: 220.0,
+            col_templates_width: 220.0,
+            col_signatures_width: 150.0,
+            body_editor_height: 100.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// 表示倍率（Ctrl + / Ctrl - で変えた値を覚えておく）
+    pub zoom_factor: f32,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self { zoom_factor: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    pub active_profile: String,
+    pub profiles: Vec<Profile>,
+    pub layout: LayoutConfig,
+    pub preferences: Preferences,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            active_profile: DEFAULT_PROFILE_NAME.to_string(),
+            profiles: vec![Profile { name: DEFAULT_PROFILE_NAME.to_string(), backend: BackendConfig::default() }],
+            layout: LayoutConfig::default(),
+            preferences: Preferences::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// 設定を読み込む。まだなければ以前の settings.json と共有シークレットから作る
+    /// 読めない場合はエラーにして、壊れたファイルを既定値で上書きしない
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("{} を読み込めません: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::migrated(),
+            Err(e) => return Err(format!("{} を読み込めません: {}", path.display(), e)),
+        };
+        config.path = Some(path);
+        config.normalize();
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("設定を保存できません: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// 以前は作業フォルダの settings.json に URL だけ、データフォルダに共有シークレットを置いていた
+    fn migrated() -> Self {
+        let mut config = Self::default();
+        let backend = &mut config.profiles[0].backend;
+        if let Some(url) = std::fs::read_to_string(LEGACY_SETTINGS_FILE_NAME)
+            .ok()
+            .and_then(|content| legacy_gas_url(&content))
+        {
+            backend.gas_url = url;
+        }
+        backend.gas_secret = backend::load_shared_secret(&storage::data_dir().join(backend::SHARED_SECRET_FILE_NAME));
+        config
+    }
+
+    /// プロファイルが1つもない・選択中のプロファイルがない設定を直す
+    fn normalize(&mut self) {
+        if self.profiles.is_empty() {
+            self.profiles = Self::default().profiles;
+        }
+        if !self.profiles.iter().any(|p| p.name == self.active_profile) {
+            self.active_profile = self.profiles[0].name.clone();
+        }
+        self.preferences.zoom_factor = self.preferences.zoom_factor.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR);
+    }
+
+    fn active_index(&self) -> usize {
+        self.profiles.iter().position(|p| p.name == self.active_profile).unwrap_or(0)
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active_index()]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Profile {
+        let index = self.active_index();
+        &mut self.profiles[index]
+    }
+
+    /// いま使う接続先
+    pub fn backend_config(&self) -> BackendConfig {
+        self.active().backend.clone()
+    }
+
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), String> {
+        if !self.profiles.iter().any(|p| p.name == name) {
+            return Err(format!("プロファイル「{}」がありません", name));
+        }
+        self.active_profile = name.to_string();
+        Ok(())
+    }
+
+    /// 選択中のプロファイルを複製して追加する（切り替えはしない）
+    pub fn add_profile(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("プロファイル名を入力してください".to_string());
+        }
+        if self.profiles.iter().any(|p| p.name == name) {
+            return Err(format!("プロファイル「{}」はすでにあります", name));
+        }
+        let backend = self.active().backend.clone();
+        self.profiles.push(Profile { name: name.to_string(), backend });
+        Ok(())
+    }
+
+    /// 選択中のプロファイルは削除できない（先に切り替える）
+    pub fn remove_profile(&mut self, name: &str) -> Result<(), String> {
+        if name == self.active_profile {
+            return Err("使用中のプロファイルは削除できません".to_string());
+        }
+        let before = self.profiles.len();
+        self.profiles.retain(|p| p.name != name);
+        if self.profiles.len() == before {
+            return Err(format!("プロファイル「{}」がありません", name));
+        }
+        Ok(())
+    }
+}
+
+fn legacy_gas_url(content: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct LegacySettings {
+        gas_url: String,
+    }
+    serde_json::from_str::<LegacySettings>(content)
+        .ok()
+        .map(|s| s.gas_url.trim().to_string())
+        .filter(|url| !url.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendKind;
+
+    #[test]
+    fn test_profiles_round_trip_and_switch() {
+        let mut config = AppConfig::default();
+        config.active_mut().backend.gas_url = "https://example.com/prod/exec".to_string();
+        config.add_profile(" 検証 ").unwrap();
+        assert!(config.add_profile("検証").is_err(), "同じ名前");
+
+        config.switch_profile("検証").unwrap();
+        config.active_mut().backend.gas_url = "https://example.com/test/exec".to_string();
+        config.active_mut().backend.kind = BackendKind::FileDrop;
+        config.layout.body_editor_height = 240.0;
+
+        let json = serde_json::to_string(&config).unwrap();
+        let mut loaded: AppConfig = serde_json::from_str(&json).unwrap();
+        loaded.normalize();
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.backend_config().gas_url, "https://example.com/test/exec");
+
+        assert!(loaded.remove_profile("検証").is_err(), "使用中");
+        loaded.switch_profile(DEFAULT_PROFILE_NAME).unwrap();
+        loaded.remove_profile("検証").unwrap();
+        assert_eq!(loaded.backend_config().gas_url, "https://example.com/prod/exec");
+    }
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let mut config: AppConfig = serde_json::from_str(r#"{"active_profile": "なし", "profiles": [{"name": "検証", "gas_url": "https://example.com/exec"}]}"#).unwrap();
+        config.normalize();
+        assert_eq!(config.active().name, "検証", "選択中のプロファイルがなければ先頭");
+        assert_eq!(config.backend_config().timeout_secs, BackendConfig::default().timeout_secs);
+        assert_eq!(config.layout, LayoutConfig::default());
+
+        assert_eq!(legacy_gas_url(r#"{"gas_url": " https://example.com/exec "}"#).as_deref(), Some("https://example.com/exec"));
+        assert_eq!(legacy_gas_url("{}"), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! 下書きの自動保存と名前付きの下書き
+//!
+//! 作成中のメール（宛先ごとの本文・ロック・添付ファイル）をデータフォルダの draft_autosave.json に
+//! 定期的に保存し、正常に終了したときに消す。起動時に残っていれば前回は異常終了しているので、
+//! draft_recovered.json に移して、保存したユーザーがログインしたときに復元するか尋ねる。
+//! 添付ファイルは中身を保存せず場所だけ持ち、復元するときに読み直す。
+
+use crate::file_utils::encode_file_to_base64;
+use crate::models::{MailDraft, RecipientInfo};
+use crate::storage::{read_json_or_set_aside, write_atomic};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const AUTOSAVE_FILE_NAME: &str = "draft_autosave.json";
+pub const RECOVERED_FILE_NAME: &str = "draft_recovered.json";
+pub const DRAFTS_FILE_NAME: &str = "drafts.json";
+
+/// 自動保存の間隔
+pub const AUTOSAVE_INTERVAL_SECS: u64 = 5;
+
+/// 保存した下書き1件
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedDraft {
+    pub name: String,
+    /// UNIX秒
+    pub saved_at: u64,
+    /// 保存したユーザー
+    #[serde(default)]
+    pub saved_by: String,
+    pub draft: MailDraft,
+    #[serde(default)]
+    pub active_recipient_index: usize,
+}
+
+impl SavedDraft {
+    /// 添付ファイルの中身（Base64）は除いて保存する
+    pub fn capture(name: &str, draft: &MailDraft, active_recipient_index: usize, saved_by: &str, now: u64) -> Self {
+        let mut draft = draft.clone();
+        for att in &mut draft.attachments {
+            att.data.clear();
+        }
+        Self {
+            name: name.to_string(),
+            saved_at: now,
+            saved_by: saved_by.to_string(),
+            draft,
+            active_recipient_index,
+        }
+    }
+
+    /// 添付ファイルを読み直した下書きと、読めずに外したファイル名を返す
+    pub fn restore(&self) -> (MailDraft, Vec<String>) {
+        let mut draft = self.draft.clone();
+        let mut missing = Vec::new();
+        draft.attachments.retain_mut(|att| match encode_file_to_base64(&att.file_path) {
+            Ok(data) => {
+                att.data = data;
+                true
+            }
+            Err(_) => {
+                missing.push(att.file_name.clone());
+                false
+            }
+        });
+        if draft.recipients.is_empty() {
+            draft.recipients.push(RecipientInfo::default());
+        }
+        (draft, missing)
+    }
+}
+
+/// 名前を付けて保存した下書き
+#[derive(Default)]
+pub struct DraftStore {
+    path: Option<PathBuf>,
+    drafts: Vec<SavedDraft>,
+}
+
+impl DraftStore {
+    /// 壊れたファイルは空にせず退避してエラーを返す
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let drafts = read_json_or_set_aside(&path)?.unwrap_or_default();
+        Ok(Self { path: Some(path), drafts })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(&self.drafts)
+            .map_err(|e| format!("下書きの保存に失敗しました: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    /// ユーザーの下書き（新しい順）
+    pub fn drafts_of(&self, user: &str) -> Vec<&SavedDraft> {
+        let mut drafts: Vec<&SavedDraft> = self.drafts.iter().filter(|d| d.saved_by == user).collect();
+        drafts.sort_by_key(|d| std::cmp::Reverse(d.saved_at));
+        drafts
+    }
+
+    /// 同じユーザーの同じ名前の下書きは上書きする
+    pub fn put(&mut self, saved: SavedDraft) {
+        self.drafts.retain(|d| !(d.saved_by == saved.saved_by && d.name == saved.name));
+        self.drafts.push(saved);
+    }
+
+    pub fn remove(&mut self, user: &str, name: &str) -> Option<SavedDraft> {
+        let pos = self.drafts.iter().position(|d| d.saved_by == user && d.name == name)?;
+        Some(self.drafts.remove(pos))
+    }
+}
+
+/// 復元するか決めていない下書き（異常終了したときの自動保存と、ログアウトしたときの下書き）
+/// 保存したユーザーが復元するか破棄するまで残す
+#[derive(Default)]
+pub struct RecoveredDrafts {
+    path: Option<PathBuf>,
+    drafts: Vec<SavedDraft>,
+}
+
+impl RecoveredDrafts {
+    /// 壊れたファイルは空にせず退避してエラーを返す
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let drafts = read_json_or_set_aside(&path)?.unwrap_or_default();
+        Ok(Self { path: Some(path), drafts })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(&self.drafts)
+            .map_err(|e| format!("下書きの保存に失敗しました: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    /// 自動保存のファイルが残っていれば取り込んで消す（この後の自動保存で上書きしないようにする）
+    pub fn take_autosave(&mut self, autosave_path: &Path) -> Result<(), String> {
+        let Some(saved) = std::fs::read_to_string(autosave_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<SavedDraft>(&content).ok())
+            .filter(|saved| !saved.draft.is_blank())
+        else {
+            return Ok(());
+        };
+        self.drafts.push(saved);
+        self.save()?;
+        let _ = std::fs::remove_file(autosave_path);
+        Ok(())
+    }
+
+    /// ユーザーの下書きのうち最新のもの
+    pub fn latest_of(&self, user: &str) -> Option<&SavedDraft> {
+        self.drafts.iter().filter(|d| d.saved_by == user).max_by_key(|d| d.saved_at)
+    }
+
+    pub fn push(&mut self, saved: SavedDraft) {
+        if !saved.draft.is_blank() {
+            self.drafts.push(saved);
+        }
+    }
+
+    pub fn remove(&mut self, user: &str, saved_at: u64) -> Option<SavedDraft> {
+        let pos = self.drafts.iter().position(|d| d.saved_by == user && d.saved_at == saved_at)?;
+        Some(self.drafts.remove(pos))
+    }
+}
+
+/// 作成中の下書きの自動保存
+#[derive(Default)]
+pub struct Autosave {
+    path: Option<PathBuf>,
+    // 最後に書き込んだ内容（変わっていなければ書かない）
+    last_written: Option<String>,
+}
+
+impl Autosave {
+    /// 前回の自動保存は先に RecoveredDrafts::take_autosave で取り込んでおく
+    pub fn new(path: PathBuf) -> Self {
+        Self { path: Some(path), last_written: None }
+    }
+
+    /// 内容が変わっていれば書き込む。何も入力していない下書きならファイルを消す
+    pub fn write(&mut self, saved: &SavedDraft) -> Result<(), String> {
+        if saved.draft.is_blank() {
+            self.clear();
+            return Ok(());
+        }
+        // 保存日時は比べない（内容が変わったときだけ書く）
+        let json = serde_json::to_string(&saved.draft)
+            .map_err(|e| format!("下書きの自動保存に失敗しました: {}", e))?;
+        if self.last_written.as_ref() == Some(&json) {
+            return Ok(());
+        }
+        if let Some(path) = &self.path {
+            let content = serde_json::to_string(saved)
+                .map_err(|e| format!("下書きの自動保存に失敗しました: {}", e))?;
+            write_atomic(path, &content)?;
+        }
+        self.last_written = Some(json);
+        Ok(())
+    }
+
+    /// 正常に終了するとき・下書きを破棄したときに消す
+    pub fn clear(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = std::fs::remove_file(path);
+        }
+        self.last_written = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Attachment;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("drafts_test_{}_{}", std::process::id(), name))
+    }
+
+    fn sample_draft(attachment_path: &str) -> MailDraft {
+        MailDraft {
+            recipients: vec![
+                RecipientInfo {
+                    email: "a@example.com".to_string(),
+                    body: "請求書をお送りします".to_string(),
+                    locked_recipient_id: Some("1".to_string()),
+                    locked_company: Some("株式会社A".to_string()),
+                    ..Default::default()
+                },
+                RecipientInfo::default(),
+            ],
+            attachments: vec![
+                Attachment {
+                    file_path: attachment_path.to_string(),
+                    file_name: "請求書_A.pdf".to_string(),
+                    data: "ZGF0YQ==".to_string(),
+                    linked_recipient_index: Some(0),
+                    ..Default::default()
+                },
+                Attachment {
+                    file_path: temp_path("missing.pdf").to_string_lossy().to_string(),
+                    file_name: "missing.pdf".to_string(),
+                    ..Default::default()
+                },
+            ],
+            use_markdown: true,
+        }
+    }
+
+    #[test]
+    fn test_autosave_recovers_locks_and_reloads_attachments() {
+        let attachment = temp_path("invoice.pdf");
+        std::fs::write(&attachment, b"data").unwrap();
+        let path = temp_path(AUTOSAVE_FILE_NAME);
+        let recovered_path = temp_path(RECOVERED_FILE_NAME);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&recovered_path);
+
+        let mut autosave = Autosave::new(path.clone());
+        let draft = sample_draft(&attachment.to_string_lossy());
+        autosave.write(&SavedDraft::capture("", &draft, 1, "tanaka", 100)).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("ZGF0YQ=="), "添付ファイルの中身は保存しない");
+
+        // 終了せずに起動し直すと残っている。取り込んだ後の自動保存では上書きしない
+        let mut recovered_drafts = RecoveredDrafts::load(recovered_path.clone()).unwrap();
+        recovered_drafts.take_autosave(&path).unwrap();
+        assert!(!path.exists());
+        let mut autosave = Autosave::new(path.clone());
+        autosave.write(&SavedDraft::capture("", &sample_draft(""), 0, "tanaka", 150)).unwrap();
+        let recovered_drafts = RecoveredDrafts::load(recovered_path.clone()).unwrap();
+        assert!(recovered_drafts.latest_of("suzuki").is_none(), "保存したユーザーにだけ見せる");
+        let recovered = recovered_drafts.latest_of("tanaka").expect("異常終了後は復元できる");
+        assert_eq!(recovered.saved_at, 100);
+        assert_eq!(recovered.active_recipient_index, 1);
+        let (restored, missing) = recovered.restore();
+        assert_eq!(restored.recipients[0].locked_recipient_id.as_deref(), Some("1"));
+        assert_eq!(restored.recipients[0].locked_company.as_deref(), Some("株式会社A"));
+        assert!(restored.use_markdown);
+        assert_eq!(restored.attachments.len(), 1);
+        assert_eq!(restored.attachments[0].data, "ZGF0YQ==");
+        assert_eq!(restored.attachments[0].linked_recipient_index, Some(0));
+        assert_eq!(missing, vec!["missing.pdf".to_string()]);
+
+        // 空の下書き・正常終了ではファイルを残さない
+        autosave.write(&SavedDraft::capture("", &MailDraft::default(), 0, "tanaka", 200)).unwrap();
+        assert!(!path.exists());
+        autosave.write(&SavedDraft::capture("", &draft, 0, "tanaka", 300)).unwrap();
+        autosave.clear();
+        assert!(!path.exists());
+        let _ = std::fs::remove_file(&attachment);
+        let _ = std::fs::remove_file(&recovered_path);
+    }
+
+    #[test]
+    fn test_named_drafts_per_user() {
+        let path = temp_path(DRAFTS_FILE_NAME);
+        let _ = std::fs::remove_file(&path);
+        let draft = sample_draft("");
+
+        let mut store = DraftStore::load(path.clone()).unwrap();
+        store.put(SavedDraft::capture("月末請求", &draft, 0, "tanaka", 100));
+        store.put(SavedDraft::capture("見積", &draft, 0, "tanaka", 200));
+        store.put(SavedDraft::capture("月末請求", &MailDraft::default(), 0, "suzuki", 300));
+        store.put(SavedDraft::capture("月末請求", &draft, 1, "tanaka", 400));
+        store.save().unwrap();
+
+        let store = DraftStore::load(path.clone()).unwrap();
+        let names: Vec<&str> = store.drafts_of("tanaka").iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["月末請求", "見積"], "同じ名前は上書きして新しい順");
+        assert_eq!(store.drafts_of("tanaka")[0].active_recipient_index, 1);
+        assert_eq!(store.drafts_of("suzuki").len(), 1);
+
+        let mut store = store;
+        assert!(store.remove("suzuki", "見積").is_none());
+        assert!(store.remove("tanaka", "見積").is_some());
+        assert_eq!(store.drafts_of("tanaka").len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
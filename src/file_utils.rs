@@ -16,7 +16,7 @@ pub fn encode_file_to_base64(file_path: &str) -> Result<String, String> {
 
 /// Get MIME type from file extension
 pub fn get_mime_type(file_name: &str) -> String {
-    let extension = file_name.split('.').last().unwrap_or("").to_lowercase();
+    let extension = file_name.rsplit('.').next().unwrap_or("").to_lowercase();
     
     match extension.as_str() {
         "pdf" => "application/pdf",
@@ -52,7 +52,7 @@ pub fn check_file_size(file_path: &str) -> Result<u64, String> {
     Ok(size)
 }
 
-/// 書類タイプのキーワード（これらが最初にある場合、2番目のパーツが会社名）
+/// 書類タイプのキーワード（会社名の候補から除外する）
 const DOCUMENT_TYPE_KEYWORDS: &[&str] = &[
     "請求書", "納品書", "見積書", "発注書", "注文書", "領収書",
     "契約書", "報告書", "提案書", "仕様書", "明細書", "通知書",
@@ -69,28 +69,27 @@ pub fn extract_company_name_from_path(path: &str) -> Option<String> {
         .to_str()?;
 
     // Split by common delimiters
-    let delimiters = ['_', ' ', '(', '（', '【', '['];
-    let parts: Vec<&str> = file_name
+    // スペースは "Example Corp" のように会社名の一部になり得るため、区切り文字にせず
+    // 各パーツ内の書類タイプキーワードだけを取り除く
+    let delimiters = ['_', '(', '（', '【', '['];
+    file_name
         .split(&delimiters[..])
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    if parts.is_empty() {
-        return None;
-    }
+        .map(strip_document_type_words)
+        .find(|s| !s.is_empty())
+}
 
-    // 最初のパーツが書類タイプキーワードかチェック
-    let first_part_lower = parts[0].to_lowercase();
-    let is_first_part_document_type = DOCUMENT_TYPE_KEYWORDS.iter()
-        .any(|keyword| first_part_lower == keyword.to_lowercase());
+fn is_document_type(word: &str) -> bool {
+    let lower = word.to_lowercase();
+    DOCUMENT_TYPE_KEYWORDS.iter().any(|keyword| lower == keyword.to_lowercase())
+}
 
-    // 書類タイプが先頭なら2番目のパーツを返す、そうでなければ最初のパーツを返す
-    if is_first_part_document_type && parts.len() > 1 {
-        Some(parts[1].to_string())
-    } else {
-        Some(parts[0].to_string())
-    }
+/// スペース区切りの単語から書類タイプキーワードを除く
+/// 例: "テスト商事 報告書" -> "テスト商事", "Example Corp" -> "Example Corp"
+fn strip_document_type_words(part: &str) -> String {
+    part.split_whitespace()
+        .filter(|word| !is_document_type(word))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 /// Extract all parts from filename for template matching.
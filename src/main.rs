@@ -2,11 +2,27 @@
 #![windows_subsystem = "windows"]
 
 mod models;
+mod auth;
+mod audit;
 mod api;
+mod backend;
 mod app;
 mod ui;
 mod utils;
 mod file_utils;
+mod worker;
+mod storage;
+mod outbox;
+mod calendar;
+mod schedule;
+mod markdown;
+mod merge;
+mod template_engine;
+mod signature;
+mod config;
+mod drafts;
+#[cfg(test)]
+mod mock_gas;
 
 use app::MailApp;
 
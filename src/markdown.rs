@@ -0,0 +1,306 @@
+//! 本文用の Markdown サブセット
+//!
+//! 対応する記法は **太字**、箇条書き（`- ` `* ` `1. `）、[リンク](https://...)、表（`| a | b |`）のみ。
+//! ビジネスメールは改行の位置が大切なので、段落内の改行はそのまま `<br>` にする。
+//! HTML パートとプレーンテキストパートの両方を同じ解析結果から作る。
+
+/// 行内の要素
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(String),
+    Link { text: String, url: String },
+}
+
+/// ブロック要素
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    /// 段落（1要素が1行）
+    Paragraph(Vec<Vec<Inline>>),
+    List { ordered: bool, items: Vec<Vec<Inline>> },
+    Table { header: Vec<Vec<Inline>>, rows: Vec<Vec<Vec<Inline>>> },
+}
+
+pub fn parse(md: &str) -> Vec<Block> {
+    let lines: Vec<&str> = md.lines().collect();
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<Vec<Inline>> = Vec::new();
+    let mut i = 0;
+
+    let flush = |paragraph: &mut Vec<Vec<Inline>>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(std::mem::take(paragraph)));
+        }
+    };
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+            i += 1;
+            continue;
+        }
+
+        // 表: 見出し行の次が区切り行（|---|---|）
+        if is_table_row(trimmed) && lines.get(i + 1).is_some_and(|next| is_table_separator(next.trim())) {
+            flush(&mut paragraph, &mut blocks);
+            let header = split_table_row(trimmed);
+            i += 2;
+            let mut rows = Vec::new();
+            while let Some(row) = lines.get(i).map(|l| l.trim()).filter(|l| is_table_row(l)) {
+                rows.push(split_table_row(row));
+                i += 1;
+            }
+            blocks.push(Block::Table { header, rows });
+            continue;
+        }
+
+        if let Some((ordered, _)) = list_item(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            let mut items = Vec::new();
+            while let Some((item_ordered, content)) = lines.get(i).and_then(|l| list_item(l.trim())) {
+                if item_ordered != ordered {
+                    break;
+                }
+                items.push(parse_inline(content));
+                i += 1;
+            }
+            blocks.push(Block::List { ordered, items });
+            continue;
+        }
+
+        paragraph.push(parse_inline(line.trim_end()));
+        i += 1;
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.len() > 1 && line.starts_with('|') && line.ends_with('|')
+}
+
+fn is_table_separator(line: &str) -> bool {
+    is_table_row(line)
+        && line.trim_matches('|').split('|').all(|cell| {
+            let cell = cell.trim();
+            !cell.is_empty() && cell.chars().all(|c| c == '-' || c == ':')
+        })
+}
+
+fn split_table_row(line: &str) -> Vec<Vec<Inline>> {
+    line[1..line.len() - 1].split('|')
+        .map(|cell| parse_inline(cell.trim()))
+        .collect()
+}
+
+/// 箇条書きの行なら (番号付きか, 本文)
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    if let Some(rest) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+        return Some((false, rest));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 {
+        if let Some(rest) = line[digits..].strip_prefix(". ") {
+            return Some((true, rest));
+        }
+    }
+    None
+}
+
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("**") {
+            if let Some(end) = after.find("**").filter(|&end| end > 0) {
+                push_text(&mut inlines, &mut plain);
+                inlines.push(Inline::Bold(after[..end].to_string()));
+                rest = &after[end + 2..];
+                continue;
+            }
+        }
+        if let Some(after) = rest.strip_prefix('[') {
+            if let Some((link_text, url, consumed)) = parse_link(after) {
+                push_text(&mut inlines, &mut plain);
+                inlines.push(Inline::Link { text: link_text.to_string(), url: url.to_string() });
+                rest = &after[consumed..];
+                continue;
+            }
+        }
+        let ch = rest.chars().next().unwrap_or_default();
+        plain.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    push_text(&mut inlines, &mut plain);
+    inlines
+}
+
+/// `text](url)` を解析して (text, url, 消費したバイト数)
+fn parse_link(s: &str) -> Option<(&str, &str, usize)> {
+    let close = s.find("](")?;
+    let url_start = close + 2;
+    let url_len = s[url_start..].find(')')?;
+    let url = &s[url_start..url_start + url_len];
+    if close == 0 || url.is_empty() || url.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((&s[..close], url, url_start + url_len + 1))
+}
+
+fn push_text(inlines: &mut Vec<Inline>, plain: &mut String) {
+    if !plain.is_empty() {
+        inlines.push(Inline::Text(std::mem::take(plain)));
+    }
+}
+
+/// リンクとして出力してよい URL か（javascript: などを除外）
+pub fn is_safe_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.starts_with("https://") || lower.starts_with("http://") || lower.starts_with("mailto:")
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn inline_html(inlines: &[Inline]) -> String {
+    inlines.iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => escape_html(text),
+            Inline::Bold(text) => format!("<strong>{}</strong>", escape_html(text)),
+            Inline::Link { text, url } if is_safe_url(url) => {
+                format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(text))
+            }
+            Inline::Link { text, url } => format!("{} ({})", escape_html(text), escape_html(url)),
+        })
+        .collect()
+}
+
+fn inline_plain(inlines: &[Inline]) -> String {
+    inlines.iter()
+        .map(|inline| match inline {
+            Inline::Text(text) | Inline::Bold(text) => text.clone(),
+            Inline::Link { text, url } if text == url => url.clone(),
+            Inline::Link { text, url } => format!("{} ({})", text, url),
+        })
+        .collect()
+}
+
+const CELL_STYLE: &str = "border:1px solid #ccc;padding:4px 8px";
+
+/// HTML パート
+pub fn to_html(md: &str) -> String {
+    let mut html = String::from("<div style=\"font-family:sans-serif;line-height:1.6\">\n");
+    for block in parse(md) {
+        match block {
+            Block::Paragraph(lines) => {
+                let lines: Vec<String> = lines.iter().map(|l| inline_html(l)).collect();
+                html.push_str(&format!("<p>{}</p>\n", lines.join("<br>\n")));
+            }
+            Block::List { ordered, items } => {
+                let tag = if ordered { "ol" } else { "ul" };
+                html.push_str(&format!("<{}>\n", tag));
+                for item in items {
+                    html.push_str(&format!("<li>{}</li>\n", inline_html(&item)));
+                }
+                html.push_str(&format!("</{}>\n", tag));
+            }
+            Block::Table { header, rows } => {
+                html.push_str("<table style=\"border-collapse:collapse\">\n<tr>");
+                for cell in &header {
+                    html.push_str(&format!("<th style=\"{};background:#f3f3f3\">{}</th>", CELL_STYLE, inline_html(cell)));
+                }
+                html.push_str("</tr>\n");
+                for row in rows {
+                    html.push_str("<tr>");
+                    for cell in &row {
+                        html.push_str(&format!("<td style=\"{}\">{}</td>", CELL_STYLE, inline_html(cell)));
+                    }
+                    html.push_str("</tr>\n");
+                }
+                html.push_str("</table>\n");
+            }
+        }
+    }
+    html.push_str("</div>");
+    html
+}
+
+/// プレーンテキストパート（記号を取り除き、リンクは URL を併記）
+pub fn to_plain_text(md: &str) -> String {
+    let blocks: Vec<String> = parse(md).into_iter()
+        .map(|block| match block {
+            Block::Paragraph(lines) => lines.iter().map(|l| inline_plain(l)).collect::<Vec<_>>().join("\n"),
+            Block::List { ordered, items } => items.iter().enumerate()
+                .map(|(i, item)| if ordered {
+                    format!("{}. {}", i + 1, inline_plain(item))
+                } else {
+                    format!("・{}", inline_plain(item))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Block::Table { header, rows } => std::iter::once(header).chain(rows)
+                .map(|row| row.iter().map(|cell| inline_plain(cell)).collect::<Vec<_>>().join(" | "))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+        .collect();
+    blocks.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "A社 **田中様**\n\nいつもお世話になっております。\n請求書を送付します。\n\n- 請求書\n- 明細書\n\n| 品目 | 金額 |\n|---|---:|\n| 保守 | 10,000円 |\n\n詳細は[こちら](https://example.com/a?b=1&c=2)";
+
+    #[test]
+    fn test_to_html() {
+        let html = to_html(SAMPLE);
+        assert!(html.contains("<p>A社 <strong>田中様</strong></p>"));
+        assert!(html.contains("<p>いつもお世話になっております。<br>\n請求書を送付します。</p>"));
+        assert!(html.contains("<ul>\n<li>請求書</li>\n<li>明細書</li>\n</ul>"));
+        assert!(html.contains(">品目</th>"));
+        assert!(html.contains(">10,000円</td>"));
+        assert!(html.contains("<a href=\"https://example.com/a?b=1&amp;c=2\">こちら</a>"));
+    }
+
+    #[test]
+    fn test_to_plain_text() {
+        assert_eq!(
+            to_plain_text(SAMPLE),
+            "A社 田中様\n\nいつもお世話になっております。\n請求書を送付します。\n\n・請求書\n・明細書\n\n品目 | 金額\n保守 | 10,000円\n\n詳細はこちら (https://example.com/a?b=1&c=2)"
+        );
+    }
+
+    #[test]
+    fn test_escapes_html_and_unsafe_links() {
+        let html = to_html("<script>alert(1)</script> [押して](javascript:alert(1)) 1**2");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("href=\"javascript"));
+        assert!(html.contains("1**2"), "閉じていない ** はそのまま");
+    }
+
+    #[test]
+    fn test_ordered_list() {
+        assert_eq!(
+            parse("1. 一\n2. **二**"),
+            vec![Block::List {
+                ordered: true,
+                items: vec![
+                    vec![Inline::Text("一".to_string())],
+                    vec![Inline::Bold("二".to_string())],
+                ],
+            }]
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! 差し込み送信
+//!
+//! 1行が1宛先のデータシート（CSV / Excel）とテンプレートから、宛先ごとに本文を差し込んだ下書きを作る。
+//! 宛先マスターには取り込まず、そのまま送信の確認ダイアログ（validate_send_safety）に回す。
+//! 列名はそのまま `{{列名}}` として本文・件名に差し込める（例: `{{金額}}` `{{支払期日}}`）。
+
+use crate::models::{RecipientData, RecipientInfo, Template};
+use crate::template_engine;
+use crate::utils::{find_placeholders, is_valid_email, recipient_context};
+use calamine::{open_workbook_auto, Data, Reader};
+use std::path::{Path, PathBuf};
+
+/// 読み込んだデータシート（1行目が見出し）
+#[derive(Clone, Debug, Default)]
+pub struct DataSheet {
+    pub path: PathBuf,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl DataSheet {
+    /// 拡張子で CSV と Excel（xlsx / xls）を切り替えて読み込む
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let mut sheet = match extension.as_str() {
+            "csv" => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("{} を読み込めません: {}", path.display(), e))?;
+                Self::parse_csv(&content)?
+            }
+            "xlsx" | "xlsm" | "xls" => Self::load_excel(path)?,
+            _ => return Err(format!("CSV または Excel ファイルを指定してください: {}", path.display())),
+        };
+        sheet.path = path.to_path_buf();
+        Ok(sheet)
+    }
+
+    /// 引用符で囲まれた値（カンマ・改行を含む）にも対応
+    pub fn parse_csv(content: &str) -> Result<Self, String> {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);  // Excel の BOM
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(content.as_bytes());
+
+        let mut records = Vec::new();
+        for (line, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| format!("CSV {}行目を読み取れません: {}", line + 1, e))?;
+            records.push(record.iter().map(|v| v.trim().to_string()).collect());
+        }
+        Self::from_records(records)
+    }
+
+    fn load_excel(path: &Path) -> Result<Self, String> {
+        let mut workbook = open_workbook_auto(path)
+            .map_err(|e| format!("{} を開けません: {}", path.display(), e))?;
+        let range = workbook.worksheet_range_at(0)
+            .ok_or_else(|| "シートがありません".to_string())?
+            .map_err(|e| format!("シートを読み込めません: {}", e))?;
+
+        let records = range.rows()
+            .map(|row| row.iter().map(cell_to_string).collect())
+            .collect();
+        Self::from_records(records)
+    }
+
+    fn from_records(mut records: Vec<Vec<String>>) -> Result<Self, String> {
+        // 空行は読み飛ばす
+        records.retain(|r| r.iter().any(|v| !v.is_empty()));
+        if records.is_empty() {
+            return Err("データシートが空です".to_string());
+        }
+        let headers = records.remove(0);
+        Ok(Self { path: PathBuf::new(), headers, rows: records })
+    }
+
+    /// 行の値を列名とともに取り出す（列数が足りない行は空文字）
+    pub fn variables(&self, row: usize) -> Vec<(&str, &str)> {
+        let values = &self.rows[row];
+        self.headers.iter()
+            .enumerate()
+            .filter(|(_, h)| !h.is_empty())
+            .map(|(col, h)| (h.as_str(), values.get(col).map(String::as_str).unwrap_or("")))
+            .collect()
+    }
+}
+
+/// Excel のセルを文字列に（整数の金額は小数点なし、日付は YYYY-MM-DD）
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", *f as i64),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(dt) if dt.time() == chrono::NaiveTime::MIN => dt.format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%d %H:%M").to_string(),
+            None => cell.to_string(),
+        },
+        _ => cell.to_string().trim().to_string(),
+    }
+}
+
+/// 見出しから既知の列を探した結果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnMap {
+    pub email: Option<usize>,
+    pub company: Option<usize>,
+    pub name: Option<usize>,
+    pub cc: Option<usize>,
+    pub bcc: Option<usize>,
+    pub reply_to: Option<usize>,
+    pub attachment: Option<usize>,
+}
+
+impl ColumnMap {
+    pub fn detect(headers: &[String]) -> Self {
+        let find = |names: &[&str]| {
+            headers.iter().position(|h| {
+                let h = h.trim().to_lowercase();
+                names.contains(&h.as_str())
+            })
+        };
+        Self {
+            email: find(&["email", "e-mail", "mail", "メール", "メールアドレス", "宛先"]),
+            company: find(&["company", "会社名", "会社", "企業名"]),
+            name: find(&["name", "氏名", "名前", "担当者"]),
+            cc: find(&["cc"]),
+            bcc: find(&["bcc"]),
+            reply_to: find(&["reply_to", "replyto", "reply-to", "返信先"]),
+            attachment: find(&["file", "filename", "attachment", "ファイル名", "添付", "添付ファイル"]),
+        }
+    }
+}
+
+/// データシートの1行から作った宛先
+#[derive(Clone, Debug, Default)]
+pub struct MergeRow {
+    pub line: usize,  // シート上の行番号（見出しが1行目）
+    pub recipient: RecipientInfo,
+    pub company: String,
+    pub name: String,
+    pub attachment: Option<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+/// 差し込み結果
+#[derive(Clone, Debug, Default)]
+pub struct MergeBatch {
+    pub rows: Vec<MergeRow>,
+    /// 行によらないエラー（列が足りないなど）
+    pub errors: Vec<String>,
+}
+
+impl MergeBatch {
+    pub fn error_count(&self) -> usize {
+        self.errors.len() + self.rows.iter().map(|r| r.errors.len()).sum::<usize>()
+    }
+}
+
+/// 宛先の変数（{{name}} など）に行の値 `{{列名}}` を重ねて差し込む（同名なら行の値が優先）
+pub fn apply_row_variables(text: &str, variables: &[(&str, &str)], recipient: &RecipientData) -> String {
+    let mut ctx = recipient_context(recipient);
+    for (header, value) in variables {
+        ctx.set(header, value);
+    }
+    template_engine::render(text, &ctx)
+}
+
+/// テンプレートとデータシートから宛先ごとの下書きを作る
+/// 宛先マスターにメールアドレスが一致する宛先があればロックし、送信前の照合チェックを効かせる
+pub fn build_batch(sheet: &DataSheet, template: &Template, recipients_master: &[RecipientData]) -> MergeBatch {
+    let columns = ColumnMap::detect(&sheet.headers);
+    let mut batch = MergeBatch::default();
+
+    let Some(email_col) = columns.email else {
+        batch.errors.push("メールアドレスの列（email / メールアドレス）が見つかりません".to_string());
+        return batch;
+    };
+    let base_dir = sheet.path.parent().unwrap_or(Path::new(""));
+
+    for (row_idx, values) in sheet.rows.iter().enumerate() {
+        let value = |col: Option<usize>| col.and_then(|c| values.get(c)).cloned().unwrap_or_default();
+        let email = value(Some(email_col));
+        let master = recipients_master.iter().find(|r| !email.is_empty() && r.email.eq_ignore_ascii_case(&email));
+
+        // 行の値を優先し、空なら宛先マスターの値を使う
+        let or_master = |col: Option<usize>, field: fn(&RecipientData) -> &String| {
+            let v = value(col);
+            if v.is_empty() { master.map(|m| field(m).clone()).unwrap_or_default() } else { v }
+        };
+        let data = RecipientData {
+            id: master.map(|m| m.id.clone()).unwrap_or_default(),
+            company: or_master(columns.company, |m| &m.company),
+            name: or_master(columns.name, |m| &m.name),
+            email: email.clone(),
+            cc: or_master(columns.cc, |m| &m.cc),
+            bcc: or_master(columns.bcc, |m| &m.bcc),
+            reply_to: or_master(columns.reply_to, |m| &m.reply_to),
+            custom_fields: master.map(|m| m.custom_fields.clone()).unwrap_or_default(),
+        };
+
+        let variables = sheet.variables(row_idx);
+        let mut row = MergeRow {
+            line: row_idx + 2,
+            recipient: RecipientInfo {
+                email: email.clone(),
+                subject: apply_row_variables(&template.subject, &variables, &data),
+                subject_template: Some(template.subject.clone()),
+                cc: data.cc.clone(),
+                bcc: data.bcc.clone(),
+                reply_to: data.reply_to.clone(),
+                body: apply_row_variables(&template.body, &variables, &data),
+                locked_recipient_id: master.map(|m| m.id.clone()),
+                locked_company: master.map(|m| m.company.clone()),
+            },
+            company: data.company.clone(),
+            name: data.name.clone(),
+            ..Default::default()
+        };
+        for (label, text) in [("件名", &row.recipient.subject), ("本文", &row.recipient.body)] {
+            for placeholder in find_placeholders(text) {
+                row.errors.push(format!("{}の {} に対応する列がありません", label, placeholder));
+            }
+        }
+        if email.is_empty() {
+            row.errors.push("メールアドレスが空です".to_string());
+        } else if !is_valid_email(&email) {
+            row.errors.push(format!("「{}」はメールアドレスの形式ではありません", email));
+        }
+
+        let file_name = value(columns.attachment);
+        if !file_name.is_empty() {
+            // 相対パスはデータシートと同じフォルダから探す
+            let path = base_dir.join(&file_name);
+            if path.is_file() {
+                row.attachment = Some(path);
+            } else {
+                row.errors.push(format!("添付ファイル「{}」が見つかりません", path.display()));
+            }
+        }
+        batch.rows.push(row);
+    }
+
+    if batch.rows.is_empty() {
+        batch.errors.push("データ行がありません".to_string());
+    }
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_with_quotes() {
+        let sheet = DataSheet::parse_csv(
+            "\u{feff}会社名,メールアドレス,金額,備考\nA社,a@example.com,\"10,000\",\"1行目\n2行目\"\n\nB社,b@example.com,5000\n"
+        ).unwrap();
+
+        assert_eq!(sheet.headers, vec!["会社名", "メールアドレス", "金額", "備考"]);
+        assert_eq!(sheet.rows.len(), 2);
+        assert_eq!(sheet.rows[0][2], "10,000");
+        assert_eq!(sheet.rows[0][3], "1行目\n2行目");
+        assert_eq!(sheet.variables(1)[3], ("備考", ""), "足りない列は空文字");
+    }
+
+    #[test]
+    fn test_build_batch() {
+        let dir = std::env::temp_dir().join(format!("merge_test_{}", crate::utils::generate_id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("請求書_A社.pdf"), b"%PDF").unwrap();
+
+        let mut sheet = DataSheet::parse_csv(
+            "email,会社名,金額,ファイル名\na@example.com,,\"12,000\",請求書_A社.pdf\nb@example,B社,3000,ない.pdf\n"
+        ).unwrap();
+        sheet.path = dir.join("data.csv");
+        let template = Template {
+            subject: "ご請求書の送付".to_string(),
+            body: "{{company}} {{name}}様\nご請求金額: {{金額}}円".to_string(),
+            ..Default::default()
+        };
+        let master = vec![RecipientData {
+            id: "7".to_string(),
+            company: "A社".to_string(),
+            name: "田中".to_string(),
+            email: "A@example.com".to_string(),
+            ..Default::default()
+        }];
+
+        let batch = build_batch(&sheet, &template, &master);
+
+        assert!(batch.errors.is_empty());
+        let a = &batch.rows[0];
+        assert_eq!(a.recipient.subject, "ご請求書の送付");
+        assert_eq!(a.recipient.body, "A社 田中様\nご請求金額: 12,000円", "空の列は宛先マスターで補う");
+        assert_eq!(a.recipient.locked_recipient_id.as_deref(), Some("7"));
+        assert_eq!(a.attachment, Some(dir.join("請求書_A社.pdf")));
+        assert!(a.errors.is_empty());
+
+        let b = &batch.rows[1];
+        assert_eq!(b.line, 3);
+        assert_eq!(b.recipient.locked_recipient_id, None);
+        assert_eq!(b.errors.len(), 2, "{:?}", b.errors);
+        assert_eq!(batch.error_count(), 2);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_subject_is_rendered_per_row() {
+        let sheet = DataSheet::parse_csv("email,番号\na@example.com,1\nb@example.com,2\n").unwrap();
+        let template = Template { subject: "請求書 No.{{番号}} {{部署}}".to_string(), ..Default::default() };
+        let batch = build_batch(&sheet, &template, &[]);
+        assert_eq!(batch.rows[1].recipient.subject, "請求書 No.2 {{部署}}");
+        assert!(batch.rows[1].errors[0].contains("件名の {{部署}}"));
+    }
+}
@@ -0,0 +1,647 @@
+//! テスト用のローカル GAS モックサーバー
+//!
+//! gas/Code.gs の `action=` プロトコルをメモリ上のシートで再現する。
+//! 実際の Apps Script にアクセスせずに GasClient とリトライ処理をテストするために使う。
+//! タイムアウト・HTTP 500・不正な JSON を注入できる。
+//! Code.gs と同じく messageId を送信前に「送信中」、送信後に「送信済み」として記録し、二重送信を防ぐ。
+//! リクエストの署名も Code.gs と同じく検証し、署名のないもの・再送されたものは拒否する。
+
+use crate::api::{canonical_params, request_signature};
+use crate::models::{HistoryItem, LinkingData, RecipientData, Signature};
+use crate::utils::{generate_id, now_unix_secs};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Code.gs の CLAIM_TTL_MS と同じ
+const CLAIM_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// モックサーバーが署名の検証に使う共有シークレット
+pub const MOCK_SHARED_SECRET: &str = "mock-shared-secret";
+
+/// Code.gs の SIGNATURE_MAX_AGE_SECONDS と同じ
+const SIGNATURE_MAX_AGE_SECS: u64 = 300;
+
+/// 次のリクエストに注入する障害
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// 指定時間待ってから応答する（リクエストは処理しない）
+    Timeout(Duration),
+    /// リクエストを処理した後、指定時間待ってから 504 を返す（送信済みなのにクライアントはタイムアウト）
+    TimeoutAfterProcessing(Duration),
+    /// HTTP 500 を返す
+    Http500,
+    /// JSON として解析できないレスポンスを返す
+    MalformedJson,
+    /// sendBatchMail の最初のメールの messageId を送信中にした後、送信せずに実行が止まる（500 を返す）
+    CrashAfterClaim,
+}
+
+/// テンプレートシートの1行
+#[derive(Clone, Debug)]
+pub struct TemplateRow {
+    /// ID 列（行を削除しても変わらない）
+    pub id: String,
+    pub name: String,
+    pub subject: String,
+    pub body: String,
+    pub updated_at: String,
+}
+
+/// メモリ上のスプレッドシート
+#[derive(Clone, Debug, Default)]
+pub struct MockSheets {
+    pub templates: Vec<TemplateRow>,
+    pub recipients: Vec<RecipientData>,
+    pub signatures: Vec<Signature>,
+    pub linkings: Vec<LinkingData>,
+    pub settings: BTreeMap<String, String>,
+    pub logs: Vec<HistoryItem>,
+}
+
+#[derive(Default)]
+struct MockState {
+    sheets: MockSheets,
+    faults: VecDeque<Fault>,
+    /// 受け付けたリクエストの action（障害を注入したものも含む）
+    requests: Vec<String>,
+    /// GmailApp.sendEmail に渡されたメール
+    sent: Vec<Value>,
+    /// GmailApp.sendEmail が例外を投げる宛先
+    rejected_addresses: Vec<String>,
+    /// 送信中・送信済みの messageId（Code.gs ではスクリプトプロパティ）
+    claimed_ids: HashMap<String, Instant>,
+    delivered_ids: HashSet<String>,
+    claim_ttl: Duration,
+    /// テンプレートを保存した回数（updatedAt の生成に使う）
+    template_versions: u32,
+    /// 最後に振った宛先 ID（Code.gs ではスクリプトプロパティ）
+    last_recipient_id: u64,
+    /// 使用済みの nonce（Code.gs では CacheService）
+    used_nonces: HashSet<String>,
+}
+
+pub struct MockGasServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockGasServer {
+    /// 空いているポートでサーバーを起動
+    pub fn start() -> Self {
+        Self::start_with(MockSheets::default())
+    }
+
+    /// シートの初期データを指定して起動
+    pub fn start_with(sheets: MockSheets) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("mock server bind");
+        let addr = listener.local_addr().expect("mock server addr");
+        let state = Arc::new(Mutex::new(MockState { sheets, claim_ttl: CLAIM_TTL, ..Default::default() }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = Arc::clone(&state);
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = Arc::clone(&state);
+                        // タイムアウト注入中も次のリクエストを受け付けられるよう接続ごとにスレッドを分ける
+                        thread::spawn(move || handle_connection(stream, &state));
+                    }
+                }
+            })
+        };
+
+        Self { addr, state, shutdown, handle: Some(handle) }
+    }
+
+    /// GAS ウェブアプリの URL に相当するアドレス
+    pub fn url(&self) -> String {
+        format!("http://{}/macros/s/mock/exec", self.addr)
+    }
+
+    /// 次のリクエストに障害を注入する（複数回呼ぶと順に消費される）
+    pub fn inject(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back(fault);
+    }
+
+    /// 指定した宛先への送信を失敗させる
+    pub fn reject_address(&self, address: &str) {
+        self.state.lock().unwrap().rejected_addresses.push(address.to_string());
+    }
+
+    /// 送信中の記録の有効期間を変える（止まった実行の後に送り直すテスト用）
+    pub fn set_claim_ttl(&self, ttl: Duration) {
+        self.state.lock().unwrap().claim_ttl = ttl;
+    }
+
+    /// 受け付けたリクエストの action 一覧
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// 送信されたメール（sendMail / sendBatchMail の各要素）
+    pub fn sent(&self) -> Vec<Value> {
+        self.state.lock().unwrap().sent.clone()
+    }
+
+    /// 現在のシート内容
+    pub fn sheets(&self) -> MockSheets {
+        self.state.lock().unwrap().sheets.clone()
+    }
+}
+
+impl Drop for MockGasServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // accept() のブロックを解除する
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    query: BTreeMap<String, String>,
+    body: String,
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).ok()?;
+
+    let query = target.split_once('?')
+        .map(|(_, q)| parse_query(q))
+        .unwrap_or_default();
+
+    Some(Request { method, query, body: String::from_utf8_lossy(&body).to_string() })
+}
+
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (percent_decode(k), percent_decode(v)))
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        out.push(b);
+                        i += 3;
+                    }
+                    Err(_) => {
+                        out.push(b'%');
+                        i += 1;
+                    }
+                }
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Mutex<MockState>) {
+    let Some(request) = read_request(&mut stream) else {
+        return;
+    };
+
+    let payload: Option<Value> = if request.method == "POST" {
+        serde_json::from_str(&request.body).ok()
+    } else {
+        None
+    };
+    let action = match &payload {
+        Some(p) => p["action"].as_str().unwrap_or_default().to_string(),
+        None => request.query.get("action").cloned().unwrap_or_else(|| "getTemplates".to_string()),
+    };
+
+    let fault = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(action.clone());
+        state.faults.pop_front()
+    };
+
+    match fault {
+        Some(Fault::Timeout(delay)) => {
+            thread::sleep(delay);
+            write_response(&mut stream, "504 Gateway Timeout", "{}");
+            return;
+        }
+        Some(Fault::Http500) => {
+            write_response(&mut stream, "500 Internal Server Error", "<html>Internal Error</html>");
+            return;
+        }
+        Some(Fault::MalformedJson) => {
+            write_response(&mut stream, "200 OK", "<!DOCTYPE html><html>not json");
+            return;
+        }
+        Some(Fault::CrashAfterClaim) => {
+            let mut state = state.lock().unwrap();
+            let first_id = payload.as_ref()
+                .and_then(|p| p["emails"][0]["messageId"].as_str())
+                .filter(|_| verify_request(&request, &mut state.used_nonces, now_unix_secs()).is_ok());
+            if let Some(id) = first_id {
+                claim_message_id(&mut state, id);
+            }
+            drop(state);
+            write_response(&mut stream, "500 Internal Server Error", "<html>Exceeded maximum execution time</html>");
+            return;
+        }
+        Some(Fault::TimeoutAfterProcessing(_)) | None => {}
+    }
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        if let Err(e) = verify_request(&request, &mut state.used_nonces, now_unix_secs()) {
+            json!({ "success": false, "unauthorized": true, "error": e })
+        } else if request.method == "POST" {
+            match payload {
+                Some(payload) => do_post(&mut state, &action, &payload),
+                None => json!({ "success": false, "error": "Invalid JSON" }),
+            }
+        } else {
+            do_get(&state, &action)
+        }
+    };
+
+    if let Some(Fault::TimeoutAfterProcessing(delay)) = fault {
+        thread::sleep(delay);
+        write_response(&mut stream, "504 Gateway Timeout", "{}");
+        return;
+    }
+    write_response(&mut stream, "200 OK", &response.to_string());
+}
+
+enum Claim {
+    Claimed,
+    Sent,
+    InFlight,
+}
+
+/// Code.gs の claimMessageId と同じ（期限を過ぎた送信中の記録は止まった実行のものとして取り直す）
+fn claim_message_id(state: &mut MockState, message_id: &str) -> Claim {
+    if state.delivered_ids.contains(message_id) {
+        return Claim::Sent;
+    }
+    if state.claimed_ids.get(message_id).is_some_and(|at| at.elapsed() < state.claim_ttl) {
+        return Claim::InFlight;
+    }
+    state.claimed_ids.insert(message_id.to_string(), Instant::now());
+    Claim::Claimed
+}
+
+/// Code.gs の verifyRequest と同じ検証
+fn verify_request(request: &Request, used_nonces: &mut HashSet<String>, now: u64) -> Result<(), String> {
+    let (Some(ts), Some(nonce), Some(sig)) = (request.query.get("ts"), request.query.get("nonce"), request.query.get("sig")) else {
+        return Err("署名がありません".to_string());
+    };
+    // 拒否の理由は Code.gs の verifyRequest と同じ文言にする
+    let expired = || "署名の有効期限が切れています。PCの時計を確認してください".to_string();
+    let timestamp: u64 = ts.parse().map_err(|_| expired())?;
+    if timestamp.abs_diff(now) > SIGNATURE_MAX_AGE_SECS {
+        return Err(expired());
+    }
+    let valid_nonce = (16..=64).contains(&nonce.len()) && nonce.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if !valid_nonce {
+        return Err("署名が正しくありません".to_string());
+    }
+    let content = if request.method == "POST" {
+        request.body.clone()
+    } else {
+        let params: Vec<(&String, &String)> = request.query.iter()
+            .filter(|(k, _)| !matches!(k.as_str(), "ts" | "nonce" | "sig"))
+            .collect();
+        canonical_params(&params)
+    };
+    if request_signature(MOCK_SHARED_SECRET, &request.method, timestamp, nonce, &content) != *sig {
+        return Err("署名が正しくありません".to_string());
+    }
+    if !used_nonces.insert(nonce.clone()) {
+        return Err("同じリクエストが再送されました".to_string());
+    }
+    Ok(())
+}
+
+fn do_get(state: &MockState, action: &str) -> Value {
+    let sheets = &state.sheets;
+    match action {
+        "getTemplates" => {
+            let templates: Vec<Value> = sheets.templates.iter()
+                .map(|t| json!({
+                    "id": t.id,
+                    "name": t.name,
+                    "subject": t.subject,
+                    "body": t.body,
+                    "updatedAt": t.updated_at,
+                }))
+                .collect();
+            json!({ "templates": templates })
+        }
+        "getRecipients" => json!({ "recipients": sheets.recipients }),
+        "getSignatures" => json!({ "signatures": sheets.signatures }),
+        "getLinkings" => json!({ "linkings": sheets.linkings }),
+        "getSettings" => json!({ "settings": sheets.settings }),
+        "getLogs" => {
+            let logs: Vec<&HistoryItem> = sheets.logs.iter().rev().take(50).collect();
+            json!({ "logs": logs })
+        }
+        "test" => json!({ "success": true, "message": "接続成功" }),
+        _ => json!({ "error": "Unknown action" }),
+    }
+}
+
+fn do_post(state: &mut MockState, action: &str, payload: &Value) -> Value {
+    match action {
+        "sendMail" => match send_one(state, payload) {
+            Ok(()) => json!({ "success": true }),
+            Err(e) => json!({ "success": false, "error": e }),
+        },
+        "sendBatchMail" => {
+            let Some(emails) = payload["emails"].as_array() else {
+                return json!({ "success": false, "error": "Emails should be an array" });
+            };
+            let results: Vec<Value> = emails.iter().enumerate()
+                .map(|(index, email)| {
+                    let to = email["to"].as_str().unwrap_or_default();
+                    let complete = [&email["to"], &email["subject"], &email["body"]].iter()
+                        .all(|v| v.as_str().is_some_and(|s| !s.is_empty()));
+                    if !complete {
+                        return json!({ "index": index, "to": to, "success": false, "error": "宛先・件名・本文のいずれかが空です" });
+                    }
+                    let message_id = email["messageId"].as_str().unwrap_or_default().to_string();
+                    if !message_id.is_empty() {
+                        match claim_message_id(state, &message_id) {
+                            Claim::Sent => return json!({ "index": index, "to": to, "success": true, "alreadySent": true }),
+                            Claim::InFlight => return json!({ "index": index, "to": to, "success": false, "inFlight": true, "error": "同じメールを別の処理が送信中です" }),
+                            Claim::Claimed => {}
+                        }
+                    }
+                    let result = send_one(state, email);
+                    if !message_id.is_empty() {
+                        state.claimed_ids.remove(&message_id);
+                        if result.is_ok() {
+                            state.delivered_ids.insert(message_id);
+                        }
+                    }
+                    match result {
+                        Ok(()) => json!({ "index": index, "to": to, "success": true }),
+                        Err(e) => json!({ "index": index, "to": to, "success": false, "error": e }),
+                    }
+                })
+                .collect();
+            json!({ "success": true, "results": results })
+        }
+        "getDeliveryStatus" => {
+            let delivered: Vec<&str> = payload["messageIds"].as_array().into_iter().flatten()
+                .filter_map(Value::as_str)
+                .filter(|id| state.delivered_ids.contains(*id))
+                .collect();
+            json!({ "success": true, "delivered": delivered })
+        }
+        "saveSettings" => {
+            if let Some(settings) = payload["settings"].as_object() {
+                for (key, value) in settings {
+                    let value = value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string());
+                    state.sheets.settings.insert(key.clone(), value);
+                }
+            }
+            json!({ "success": true })
+        }
+        "saveTemplate" => {
+            // Code.gs と同じく、読み込んだ版（updatedAt）が古ければ競合として保存しない
+            let template = &payload["template"];
+            let text = |key: &str| template[key].as_str().unwrap_or_default().to_string();
+            state.template_versions += 1;
+            let row = TemplateRow {
+                // Code.gs では Utilities.getUuid()
+                id: generate_id(),
+                name: text("name"),
+                subject: text("subject"),
+                body: text("body"),
+                updated_at: format!("v{}", state.template_versions + 1),
+            };
+            let conflict = |message: String| json!({ "success": false, "conflict": true, "error": message });
+            let templates = &mut state.sheets.templates;
+            match payload["originalName"].as_str() {
+                None => {
+                    if templates.iter().any(|t| t.name == row.name) {
+                        return conflict(format!("同じ名前のテンプレート「{}」がすでにあります", row.name));
+                    }
+                    let updated_at = row.updated_at.clone();
+                    templates.push(row);
+                    json!({ "success": true, "updatedAt": updated_at })
+                }
+                Some(original) => {
+                    let Some(idx) = templates.iter().position(|t| t.name == original) else {
+                        return conflict(format!("テンプレート「{}」は他のユーザーが削除しました", original));
+                    };
+                    let existing = &templates[idx];
+                    if (&existing.name, &existing.subject, &existing.body) == (&row.name, &row.subject, &row.body) {
+                        return json!({ "success": true, "updatedAt": existing.updated_at });
+                    }
+                    if existing.updated_at != text("updatedAt") {
+                        return conflict(format!("テンプレート「{}」は他のユーザーが更新しました", original));
+                    }
+                    if row.name != original && templates.iter().any(|t| t.name == row.name) {
+                        return conflict(format!("同じ名前のテンプレート「{}」がすでにあります", row.name));
+                    }
+                    let updated_at = row.updated_at.clone();
+                    templates[idx] = TemplateRow { id: existing.id.clone(), ..row };
+                    json!({ "success": true, "updatedAt": updated_at })
+                }
+            }
+        }
+        "deleteTemplate" => {
+            let name = payload["name"].as_str().unwrap_or_default();
+            let stale = payload["updatedAt"].as_str()
+                .is_some_and(|v| state.sheets.templates.iter().any(|t| t.name == name && t.updated_at != v));
+            if stale {
+                return json!({ "success": false, "conflict": true, "error": format!("テンプレート「{}」は他のユーザーが更新しました", name) });
+            }
+            // 削除したテンプレートへの紐付けも消す
+            while let Some(pos) = state.sheets.templates.iter().position(|t| t.name == name) {
+                let deleted = state.sheets.templates.remove(pos);
+                state.sheets.linkings.retain(|l| l.template_id != deleted.id);
+            }
+            json!({ "success": true })
+        }
+        "saveSignature" => {
+            let Ok(signature) = serde_json::from_value::<Signature>(payload["signature"].clone()) else {
+                return json!({ "success": false, "error": "Invalid signature" });
+            };
+            // 新規作成と名前の変更では、同じ名前の署名を上書きしない
+            let signatures = &mut state.sheets.signatures;
+            let original = payload["originalName"].as_str();
+            if original != Some(signature.name.as_str()) && signatures.iter().any(|s| s.name == signature.name) {
+                return json!({ "success": false, "error": format!("同じ名前の署名「{}」がすでにあります", signature.name) });
+            }
+            match signatures.iter_mut().find(|s| Some(s.name.as_str()) == original) {
+                Some(existing) => *existing = signature,
+                None => signatures.push(signature),
+            }
+            json!({ "success": true })
+        }
+        "deleteSignature" => {
+            let name = payload["name"].as_str().unwrap_or_default();
+            state.sheets.signatures.retain(|s| s.name != name);
+            json!({ "success": true })
+        }
+        "saveLinking" => {
+            let Ok(link) = serde_json::from_value::<LinkingData>(payload["linking"].clone()) else {
+                return json!({ "success": false, "error": "Invalid linking" });
+            };
+            let linkings = &mut state.sheets.linkings;
+            match linkings.iter_mut().find(|l| l.recipient_id == link.recipient_id && l.keyword == link.keyword) {
+                Some(existing) => existing.template_id = link.template_id,
+                None => linkings.push(link),
+            }
+            json!({ "success": true })
+        }
+        "deleteLinking" => {
+            let Ok(link) = serde_json::from_value::<LinkingData>(payload["linking"].clone()) else {
+                return json!({ "success": false, "error": "Invalid linking" });
+            };
+            state.sheets.linkings.retain(|l| !(l.recipient_id == link.recipient_id && l.keyword == link.keyword));
+            json!({ "success": true })
+        }
+        "saveRecipient" => {
+            let Ok(mut rec) = serde_json::from_value::<RecipientData>(payload["recipient"].clone()) else {
+                return json!({ "success": false, "error": "Invalid recipient" });
+            };
+            let existing = state.sheets.recipients.iter_mut()
+                .find(|r| r.id == rec.id || (!rec.email.is_empty() && r.email == rec.email));
+            match existing {
+                Some(existing) => *existing = rec,
+                None => {
+                    if rec.id.is_empty() {
+                        rec.id = next_recipient_id(state);
+                    }
+                    state.sheets.recipients.push(rec);
+                }
+            }
+            json!({ "success": true })
+        }
+        "updateRecipient" => {
+            let Ok(rec) = serde_json::from_value::<RecipientData>(payload["recipient"].clone()) else {
+                return json!({ "success": false, "error": "Invalid recipient" });
+            };
+            let recipients = &mut state.sheets.recipients;
+            if let Some(other) = recipients.iter().find(|r| r.id != rec.id && r.email.eq_ignore_ascii_case(&rec.email)) {
+                return json!({ "success": false, "error": format!("メールアドレス {} は他の宛先（ID: {}）で使われています", rec.email, other.id) });
+            }
+            match recipients.iter_mut().find(|r| r.id == rec.id) {
+                Some(existing) => {
+                    *existing = rec;
+                    json!({ "success": true })
+                }
+                None => json!({ "success": false, "error": format!("宛先（ID: {}）が見つかりません。他のユーザーが削除した可能性があります", rec.id) }),
+            }
+        }
+        "deleteRecipient" => {
+            let id = payload["id"].as_str().unwrap_or_default();
+            state.sheets.recipients.retain(|r| r.id != id);
+            state.sheets.linkings.retain(|l| l.recipient_id != id);
+            json!({ "success": true })
+        }
+        _ => json!({ "error": "Unknown action" }),
+    }
+}
+
+/// Code.gs の nextRecipientId と同じく、最後に振った ID とシート上の最大の ID の大きい方 + 1
+fn next_recipient_id(state: &mut MockState) -> String {
+    let max = state.sheets.recipients.iter()
+        .filter_map(|r| r.id.parse::<u64>().ok())
+        .fold(state.last_recipient_id, u64::max);
+    state.last_recipient_id = max + 1;
+    state.last_recipient_id.to_string()
+}
+
+/// GmailApp.sendEmail と logSentMail に相当する処理
+fn send_one(state: &mut MockState, email: &Value) -> Result<(), String> {
+    let to = email["to"].as_str().unwrap_or_default().to_string();
+    let subject = email["subject"].as_str().unwrap_or_default().to_string();
+    let body = email["body"].as_str().unwrap_or_default().to_string();
+
+    let result = if state.rejected_addresses.contains(&to) {
+        Err(format!("Exception: Invalid email: {}", to))
+    } else {
+        state.sent.push(email.clone());
+        Ok(())
+    };
+
+    state.sheets.logs.push(HistoryItem {
+        date: format!("mock-{}", state.sheets.logs.len() + 1),
+        to,
+        subject,
+        body,
+        status: match &result {
+            Ok(()) => "Success".to_string(),
+            Err(e) => format!("Error: {}", e),
+        },
+    });
+
+    result
+}
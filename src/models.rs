@@ -1,11 +1,25 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use crate::audit::AuditLog;
+use crate::auth::{LoginThrottle, Role, UserStore};
+use crate::config::AppConfig;
+use crate::drafts::{DraftStore, RecoveredDrafts};
+use crate::calendar::BusinessCalendar;
+use crate::merge::{DataSheet, MergeBatch};
+use crate::outbox::Outbox;
+use crate::schedule::Schedule;
+use crate::worker::{Job, JobProgress};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Template {
     pub id: String,
     pub name: String,
     pub subject: String,
     pub body: String,
+    /// シート上の版（保存時の競合検出に使う）
+    #[serde(default, rename = "updatedAt")]
+    pub updated_at: String,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, Default)]
@@ -14,31 +28,50 @@ pub struct RecipientData {
     pub company: String,
     pub name: String,
     pub email: String,
+    // 会社ごとの既定の CC・BCC・返信先（カンマ区切り）
+    #[serde(default)]
+    pub cc: String,
+    #[serde(default)]
+    pub bcc: String,
+    #[serde(default, rename = "replyTo")]
+    pub reply_to: String,
+    // 宛先リストの追加列（部署・役職・顧客コードなど）。列名で {{列名}} として差し込める
+    #[serde(default, rename = "customFields")]
+    pub custom_fields: BTreeMap<String, String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Signature {
     pub name: String,
     pub content: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct LinkingData {
     pub recipient_id: String,
     pub template_id: String,
+    // 添付ファイル名に含まれていればこのテンプレートを使う語（空なら宛先の既定）
+    #[serde(default)]
+    pub keyword: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MailDraft {
     pub recipients: Vec<RecipientInfo>,
-    pub subject: String,
     pub attachments: Vec<Attachment>,
-    pub signature: String,
+    pub use_markdown: bool,  // 本文を Markdown として HTML メールで送る
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RecipientInfo {
     pub email: String,
+    pub subject: String,   // 宛先ごとに変数を差し込んだ件名
+    pub subject_template: Option<String>,  // 変数を差し込む前の件名（全宛先に適用するときに宛先ごとに差し込み直す）
+    pub cc: String,        // カンマ区切り
+    pub bcc: String,       // カンマ区切り
+    pub reply_to: String,
     pub body: String,
     pub locked_recipient_id: Option<String>,  // 紐付けられた宛先マスターのID
     pub locked_company: Option<String>,       // ロック時の会社名（照合用）
@@ -84,7 +117,160 @@ impl Default for Attachment {
 pub enum Tab {
     Main,
     History,
+    Merge,
+    Templates,
+    Recipients,
+    Linkings,
+    Signatures,
+    Outbox,
+    Scheduled,
     Settings,
+    Audit,
+}
+
+impl Tab {
+    /// タブを開くのに必要な権限（設定タブの中はさらに項目ごとに分ける）
+    pub fn required_role(&self) -> Role {
+        match self {
+            Tab::Templates | Tab::Recipients | Tab::Linkings => Role::TemplateEditor,
+            Tab::Audit => Role::Admin,
+            _ => Role::Sender,
+        }
+    }
+}
+
+/// 設定タブのユーザー管理とパスワード変更の入力欄
+#[derive(Clone, Debug, Default)]
+pub struct UserAdminForm {
+    pub new_username: String,
+    pub new_password: String,
+    pub new_role: Role,
+    /// パスワードを再設定中のユーザーと新しいパスワード
+    pub reset: Option<(String, String)>,
+    /// 削除を確認中のユーザー
+    pub confirm_delete: Option<String>,
+    pub current_password: String,
+    pub changed_password: String,
+    pub message: Option<String>,
+}
+
+/// テンプレート編集タブの状態
+#[derive(Clone, Debug, Default)]
+pub struct TemplateEditor {
+    /// 編集中の内容（None なら未選択）
+    pub draft: Option<Template>,
+    /// シート上の元の名前（新規作成なら None）
+    pub original_name: Option<String>,
+    /// 未保存の変更を破棄するか確認中の、次に開くテンプレートと元の名前
+    pub pending_open: Option<(Template, Option<String>)>,
+    /// プレビューに使う宛先（recipients_master 上の位置）
+    pub preview_recipient_index: Option<usize>,
+    /// 保存・削除が他のユーザーの変更と競合したときのメッセージ
+    pub conflict: Option<String>,
+    /// 本文のカーソル位置（文字数）。変数パレットはここに挿入する
+    pub body_cursor: Option<usize>,
+    pub confirm_delete: bool,
+}
+
+impl TemplateEditor {
+    /// シート上の内容から変更されているか（新規作成は常に未保存）
+    pub fn is_dirty(&self, templates: &[Template]) -> bool {
+        let Some(draft) = &self.draft else {
+            return false;
+        };
+        let saved = self.original_name.as_ref()
+            .and_then(|name| templates.iter().find(|t| &t.name == name));
+        match saved {
+            Some(saved) => (&saved.name, &saved.subject, &saved.body) != (&draft.name, &draft.subject, &draft.body),
+            None => true,
+        }
+    }
+
+    pub fn open(&mut self, template: Template, original_name: Option<String>) {
+        self.draft = Some(template);
+        self.original_name = original_name;
+        self.pending_open = None;
+        self.conflict = None;
+        self.body_cursor = None;
+        self.confirm_delete = false;
+    }
+}
+
+/// 宛先タブの並べ替えの基準
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RecipientSort {
+    #[default]
+    Id,
+    Company,
+    Name,
+    Email,
+}
+
+/// 宛先タブの状態
+#[derive(Clone, Debug, Default)]
+pub struct RecipientEditor {
+    pub search: String,
+    pub sort: RecipientSort,
+    pub descending: bool,
+    /// 編集中の行（新規追加は id が空）
+    pub editing: Option<RecipientData>,
+    /// 編集中の行に追加する列の名前
+    pub new_field: String,
+    /// 削除を確認中の宛先 ID
+    pub confirm_delete: Option<String>,
+}
+
+/// 紐付けタブの入力欄
+#[derive(Clone, Debug, Default)]
+pub struct LinkingEditor {
+    /// 一覧の絞り込み（会社名・氏名・キーワード）
+    pub search: String,
+    pub recipient_id: Option<String>,
+    pub template_id: Option<String>,
+    pub keyword: String,
+}
+
+/// 署名タブの状態
+#[derive(Clone, Debug, Default)]
+pub struct SignatureEditor {
+    /// 編集中の内容（None なら未選択）
+    pub draft: Option<Signature>,
+    /// シート上の元の名前（新規作成なら None）
+    pub original_name: Option<String>,
+    /// 内容のカーソル位置（文字数）。変数ボタンはここに挿入する
+    pub content_cursor: Option<usize>,
+    pub confirm_delete: bool,
+}
+
+/// ログインユーザーごとの設定（設定シートの user.<ユーザー名>.* に保存する）
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserProfile {
+    /// 既定の署名の名前
+    pub signature: Option<String>,
+    /// 署名の {{sender_name}} に入る送信者名
+    pub sender_name: String,
+}
+
+/// 送信取り消し待ちの送信（取り消すと draft などを下書きに戻す）
+/// 送信内容は猶予が過ぎる時刻を送信時刻にして送信待ちに保存してあるので、猶予中に終了しても失われない
+#[derive(Clone, Debug)]
+pub struct HeldSend {
+    pub pending: PendingSendData,
+    /// 送信待ちに保存した分
+    pub item_ids: Vec<String>,
+    pub release_at: std::time::Instant,
+    pub draft: MailDraft,
+    pub active_recipient_index: usize,
+    pub selected_recipient_index: Option<usize>,
+    pub selected_template_index: Option<usize>,
+}
+
+/// 確認ダイアログで選ぶ送信タイミング
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SendTiming {
+    Now,
+    NextBusinessDay,  // 翌営業日の指定時刻
+    At,               // 日時を指定
 }
 
 /// アプリの起動フェーズ
@@ -102,16 +288,35 @@ pub struct AppState {
     pub recipients_master: Vec<RecipientData>,
     pub selected_recipient_index: Option<usize>,
     pub recipient_search: String,
-    pub active_recipient_index: usize, // 0, 1, or 2
+    pub active_recipient_index: usize, // mail_draft.recipients 上の位置
     pub signatures: Vec<Signature>,
     pub selected_signature_index: Option<usize>,
     pub linkings_master: Vec<LinkingData>,
     pub mail_draft: MailDraft,
+    // 名前を付けて保存した下書きと、復元するか決めていない下書き（異常終了・ログアウトしたとき）
+    pub drafts: DraftStore,
+    pub draft_name_input: String,
+    pub recovered_drafts: RecoveredDrafts,
+    // 下書きがあるときの終了確認
+    pub show_exit_confirmation: bool,
+    pub exit_confirmed: bool,
     pub history: Vec<HistoryItem>,
     pub tab: Tab,
-    pub gas_url: String,
+    // 設定ファイル（接続先のプロファイル・通信設定・レイアウト）
+    pub config: AppConfig,
+    pub profile_name_input: String,
+    // データフォルダ（起動時に決めて、画面の描画中にファイルシステムを触らない）
+    pub data_dir: PathBuf,
     pub status_message: String,
     pub is_loading: bool,
+    // バックグラウンド処理（MailApp が順番に実行する）
+    pub job_queue: Vec<Job>,
+    pub job_progress: Option<JobProgress>,
+    // 送信待ち（ディスクに保存し、通信障害時は自動で再送する）
+    pub outbox: Outbox,
+    // 予約送信と営業日カレンダー
+    pub schedule: Schedule,
+    pub calendar: BusinessCalendar,
     // 起動フェーズ
     pub startup_phase: StartupPhase,
     pub loading_message: String,
@@ -121,43 +326,193 @@ pub struct AppState {
     pub confirmation_checked: bool,
     pub validation_errors: Vec<String>,
     pub pending_send_data: Option<PendingSendData>,
-    // Basic認証
+    pub send_timing: SendTiming,
+    // 送信取り消しの猶予（0なら即送信）
+    pub undo_send_secs: u32,
+    pub held_send: Option<HeldSend>,
+    pub schedule_date_input: String,  // YYYY-MM-DD
+    pub schedule_hour: u32,
+    pub schedule_minute: u32,
+    // 差し込み送信
+    pub merge_path_input: String,
+    pub merge_sheet: Option<DataSheet>,
+    pub merge_template_index: Option<usize>,
+    pub merge_batch: Option<MergeBatch>,
+    // テンプレート編集
+    pub template_editor: TemplateEditor,
+    // 宛先マスターの編集
+    pub recipient_editor: RecipientEditor,
+    pub linking_editor: LinkingEditor,
+    pub signature_editor: SignatureEditor,
+    // ログインユーザーごとの既定の署名と送信者名
+    pub user_profiles: HashMap<String, UserProfile>,
+    // 送信失敗した宛先（再送のため下書きに残す）
+    pub send_failures: Vec<String>,
+    // ログイン（ユーザーと権限は user_store、セッションの期限を過ぎたらログアウト）
     pub is_authenticated: bool,
     pub auth_username: String,
     pub auth_password: String,
+    pub auth_password_confirm: String,  // 最初の管理者の作成時のみ
     pub auth_error: Option<String>,
-    pub expected_username: String,  // 正しいユーザー名（設定で変更可能）
-    pub expected_password: String,  // 正しいパスワード（設定で変更可能）
-    // カラム幅（リサイズ可能）
-    pub col_recipients_width: f32,
-    pub col_templates_width: f32,
-    pub col_signatures_width: f32,
-    // 本文エディタの高さ（リサイズ可能）
-    pub body_editor_height: f32,
+    pub auth_role: Role,
+    pub session_expires_at: Option<u64>,
+    pub user_store: UserStore,
+    // users.json を読み込めなかったとき（ログインも最初の管理者の作成もさせない）
+    pub user_store_error: Option<String>,
+    pub user_admin: UserAdminForm,
+    pub login_throttle: LoginThrottle,
+    // 監査ログ（管理者だけが見られる）
+    pub audit_log: AuditLog,
+    pub audit_search: String,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct PendingSendData {
     pub recipients: Vec<PendingRecipient>,
-    pub subject: String,
+    // 件名が宛先ごとになる前に保存された予約送信の共通件名（読み込み時に各宛先へ移す）
+    #[serde(default, rename = "subject", skip_serializing)]
+    pub legacy_subject: String,
+    /// 送信したユーザー（監査ログ用。予約送信や再送は後で別のユーザーのログイン中に送られることがある）
+    #[serde(default)]
+    pub sent_by: String,
 }
 
-#[derive(Clone, Debug, Default)]
+impl PendingSendData {
+    /// 旧形式の共通件名を、件名が空の宛先に移す
+    pub fn migrate_legacy_subject(&mut self) {
+        let subject = std::mem::take(&mut self.legacy_subject);
+        if subject.is_empty() {
+            return;
+        }
+        for rec in self.recipients.iter_mut().filter(|r| r.subject.is_empty()) {
+            rec.subject = subject.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+impl PendingSendData {
+    /// テスト用: 件名「件名」・本文「本文」で宛先だけ変えた送信内容（draft_index は並び順）
+    pub fn sample(emails: &[&str]) -> Self {
+        Self {
+            recipients: emails.iter().enumerate()
+                .map(|(i, email)| PendingRecipient {
+                    draft_index: i,
+                    email: email.to_string(),
+                    subject: "件名".to_string(),
+                    body: "本文".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct PendingRecipient {
+    pub draft_index: usize,  // mail_draft.recipients 上の位置
     pub email: String,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    #[serde(default)]
+    pub reply_to: String,
     pub company: String,
     pub name: String,
-    pub body: String,
-    pub attachments: Vec<String>,  // ファイル名のリスト
+    #[serde(default)]
+    pub subject: String,
+    pub body: String,  // プレーンテキスト（署名を含む）
+    #[serde(default)]
+    pub html_body: Option<String>,  // Markdown から生成した HTML
+    pub attachments: Vec<Attachment>,  // この宛先にだけ添付するファイル
+}
+
+impl MailDraft {
+    /// 何も入力していない（保存・復元するものがない）
+    pub fn is_blank(&self) -> bool {
+        self.attachments.is_empty()
+            && self.recipients.iter().all(|r| r.email.is_empty()
+                && r.subject.is_empty()
+                && r.cc.is_empty()
+                && r.bcc.is_empty()
+                && r.reply_to.is_empty()
+                && r.body.is_empty()
+                && r.locked_recipient_id.is_none())
+    }
 }
 
 impl Default for MailDraft {
     fn default() -> Self {
         Self {
-            recipients: vec![RecipientInfo::default(); 3],
-            subject: String::new(),
+            recipients: vec![RecipientInfo::default()],
             attachments: Vec::new(),
-            signature: String::new(),
+            use_markdown: false,
+        }
+    }
+}
+
+impl MailDraft {
+    /// 空の宛先行を末尾に追加し、その位置を返す
+    pub fn add_recipient(&mut self) -> usize {
+        self.recipients.push(RecipientInfo::default());
+        self.recipients.len() - 1
+    }
+
+    /// 宛先行を削除し、その宛先に紐付いていた添付ファイルも取り除いて返す
+    /// 後ろの宛先に紐付いた添付ファイルの位置は詰める。最後の1行は空にするだけで残す
+    pub fn remove_recipient(&mut self, idx: usize) -> Vec<Attachment> {
+        if idx >= self.recipients.len() {
+            return Vec::new();
+        }
+
+        let (removed, kept): (Vec<Attachment>, Vec<Attachment>) = std::mem::take(&mut self.attachments)
+            .into_iter()
+            .partition(|att| att.linked_recipient_index == Some(idx));
+        self.attachments = kept;
+        for att in &mut self.attachments {
+            if let Some(linked) = att.linked_recipient_index.as_mut() {
+                if *linked > idx {
+                    *linked -= 1;
+                }
+            }
+        }
+
+        self.recipients.remove(idx);
+        if self.recipients.is_empty() {
+            self.recipients.push(RecipientInfo::default());
+        }
+        removed
+    }
+
+    /// 宛先行を入れ替える（添付ファイルの紐付けも追従する）
+    pub fn swap_recipients(&mut self, a: usize, b: usize) {
+        if a >= self.recipients.len() || b >= self.recipients.len() {
+            return;
+        }
+        self.recipients.swap(a, b);
+        for att in &mut self.attachments {
+            if att.linked_recipient_index == Some(a) {
+                att.linked_recipient_index = Some(b);
+            } else if att.linked_recipient_index == Some(b) {
+                att.linked_recipient_index = Some(a);
+            }
+        }
+    }
+
+    /// 別の下書きの宛先行を、その行の添付ファイルと全員宛ての添付ファイルごと末尾に追加する
+    /// 全員宛ての添付ファイルは追加した行に紐付け直す（この下書きの他の宛先には付けない）
+    pub fn append_rows_from(&mut self, other: &MailDraft, rows: &[usize]) {
+        for &row in rows {
+            let Some(recipient) = other.recipients.get(row) else {
+                continue;
+            };
+            self.recipients.push(recipient.clone());
+            let new_idx = self.recipients.len() - 1;
+            for att in other.attachments.iter().filter(|a| a.linked_recipient_index.is_none_or(|i| i == row)) {
+                self.attachments.push(Attachment { linked_recipient_index: Some(new_idx), ..att.clone() });
+            }
         }
     }
 }
@@ -176,11 +531,23 @@ impl Default for AppState {
             selected_signature_index: None,
             linkings_master: Vec::new(),
             mail_draft: MailDraft::default(),
+            drafts: DraftStore::default(),
+            draft_name_input: String::new(),
+            recovered_drafts: RecoveredDrafts::default(),
+            show_exit_confirmation: false,
+            exit_confirmed: false,
             history: Vec::new(),
             tab: Tab::Main,
-            gas_url: "https://script.google.com/macros/s/AKfycbwUAgPH2nh3Mn7JYbsRUWadfXHlCPkPKMm1OOqzbFg1mjjDvVS76ZKuM8sNB1NwP2wE/exec".to_string(),
+            config: AppConfig::default(),
+            profile_name_input: String::new(),
+            data_dir: PathBuf::new(),
             status_message: "準備完了".to_string(),
             is_loading: false,
+            job_queue: Vec::new(),
+            job_progress: None,
+            outbox: Outbox::default(),
+            schedule: Schedule::default(),
+            calendar: BusinessCalendar::default(),
             startup_phase: StartupPhase::Splash,
             loading_message: "起動中...".to_string(),
             show_send_confirmation: false,
@@ -188,19 +555,121 @@ impl Default for AppState {
             confirmation_checked: false,
             validation_errors: Vec::new(),
             pending_send_data: None,
-            // Basic認証（デフォルト: admin/password）
+            send_timing: SendTiming::Now,
+            undo_send_secs: 10,
+            held_send: None,
+            merge_path_input: String::new(),
+            merge_sheet: None,
+            merge_template_index: None,
+            merge_batch: None,
+            template_editor: TemplateEditor::default(),
+            recipient_editor: RecipientEditor::default(),
+            linking_editor: LinkingEditor::default(),
+            signature_editor: SignatureEditor::default(),
+            user_profiles: HashMap::new(),
+            schedule_date_input: String::new(),
+            schedule_hour: 9,
+            schedule_minute: 0,
+            send_failures: Vec::new(),
             is_authenticated: false,
             auth_username: String::new(),
             auth_password: String::new(),
+            auth_password_confirm: String::new(),
             auth_error: None,
-            expected_username: "nikko".to_string(),
-            expected_password: "nikko".to_string(),
-            // カラム幅のデフォルト値
-            col_recipients_width: 220.0,
-            col_templates_width: 220.0,
-            col_signatures_width: 150.0,
-            // 本文エディタの高さ
-            body_editor_height: 100.0,
+            auth_role: Role::Sender,
+            session_expires_at: None,
+            user_store: UserStore::default(),
+            user_store_error: None,
+            user_admin: UserAdminForm::default(),
+            login_throttle: LoginThrottle::default(),
+            audit_log: AuditLog::default(),
+            audit_search: String::new(),
         }
     }
 }
+
+impl AppState {
+    /// 接続先を切り替えるとき、前のスプレッドシートから読み込んだデータと編集中の内容を捨てる
+    pub fn clear_master_data(&mut self) {
+        self.templates.clear();
+        self.selected_template_index = None;
+        self.recipients_master.clear();
+        self.selected_recipient_index = None;
+        self.signatures.clear();
+        self.selected_signature_index = None;
+        self.linkings_master.clear();
+        self.history.clear();
+        self.user_profiles.clear();
+        self.template_editor = Default::default();
+        self.recipient_editor = Default::default();
+        self.linking_editor = Default::default();
+        self.signature_editor = Default::default();
+    }
+
+    /// 実行中または待機中の送信があるか
+    pub fn is_sending(&self) -> bool {
+        self.job_queue.iter().any(|job| matches!(job, Job::SendOutbox { pending: Some(_), .. }))
+            || self.job_progress.as_ref().is_some_and(|p| p.is_send)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draft(emails: &[&str]) -> MailDraft {
+        let mut draft = MailDraft {
+            recipients: emails.iter()
+                .map(|email| RecipientInfo { email: email.to_string(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        for (i, email) in emails.iter().enumerate() {
+            draft.attachments.push(Attachment {
+                file_name: format!("{}.pdf", email),
+                linked_recipient_index: Some(i),
+                ..Default::default()
+            });
+        }
+        draft
+    }
+
+    fn linked_files(draft: &MailDraft) -> Vec<(String, Option<usize>)> {
+        draft.attachments.iter()
+            .map(|a| (a.file_name.clone(), a.linked_recipient_index))
+            .collect()
+    }
+
+    #[test]
+    fn test_remove_recipient_shifts_attachment_links() {
+        let mut draft = draft(&["a", "b", "c"]);
+
+        let removed = draft.remove_recipient(1);
+
+        assert_eq!(removed[0].file_name, "b.pdf");
+        assert_eq!(draft.recipients.len(), 2);
+        assert_eq!(draft.recipients[1].email, "c");
+        assert_eq!(linked_files(&draft), vec![("a.pdf".to_string(), Some(0)), ("c.pdf".to_string(), Some(1))]);
+    }
+
+    #[test]
+    fn test_last_row_is_kept_empty() {
+        let mut draft = draft(&["a"]);
+        draft.remove_recipient(0);
+        assert_eq!(draft.recipients.len(), 1);
+        assert!(draft.recipients[0].email.is_empty());
+        assert!(draft.attachments.is_empty());
+    }
+
+    #[test]
+    fn test_swap_recipients_moves_attachments() {
+        let mut draft = draft(&["a", "b", "c"]);
+        draft.swap_recipients(0, 2);
+
+        assert_eq!(draft.recipients[0].email, "c");
+        assert_eq!(
+            linked_files(&draft),
+            vec![("a.pdf".to_string(), Some(2)), ("b.pdf".to_string(), Some(1)), ("c.pdf".to_string(), Some(0))]
+        );
+    }
+}
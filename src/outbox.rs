@@ -0,0 +1,320 @@
+//! 送信待ちキュー（アウトボックス）
+//!
+//! 確認ダイアログで確定したメールを、宛先・件名・署名入りの本文・添付ファイルごと
+//! ディスクに保存してから送信する。通信障害で送れなかったものはアプリを再起動しても残り、
+//! RetryConfig と同じ指数バックオフで自動的に再送する。
+
+use crate::api::{BatchMailItem, RecipientSendResult, RetryConfig};
+use crate::models::{Attachment, PendingSendData};
+use crate::storage::{read_json_or_set_aside, write_atomic};
+use crate::utils::generate_id;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OutboxStatus {
+    Queued,   // 送信待ち（next_attempt_at 以降に送信）
+    Sending,  // 送信中
+    Failed,   // 自動再送をやめたもの（手動で再送・削除する）
+}
+
+impl OutboxStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutboxStatus::Queued => "送信待ち",
+            OutboxStatus::Sending => "送信中",
+            OutboxStatus::Failed => "失敗",
+        }
+    }
+}
+
+/// 送信する内容をすべて含んだ1通分
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub id: String,
+    pub to: String,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    #[serde(default)]
+    pub reply_to: String,
+    pub company: String,
+    pub name: String,
+    pub subject: String,
+    pub body: String,  // 署名を含む
+    #[serde(default)]
+    pub html_body: Option<String>,
+    pub attachments: Vec<Attachment>,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub next_attempt_at: u64,  // UNIX秒
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    /// 送信を指示したユーザー
+    #[serde(default)]
+    pub queued_by: String,
+}
+
+impl OutboxItem {
+    pub fn as_batch_item(&self) -> BatchMailItem<'_> {
+        BatchMailItem {
+            message_id: &self.id,
+            to: &self.to,
+            cc: &self.cc,
+            bcc: &self.bcc,
+            reply_to: &self.reply_to,
+            subject: &self.subject,
+            body: &self.body,
+            html_body: self.html_body.as_deref(),
+            attachments: &self.attachments,
+        }
+    }
+}
+
+/// 送信結果を反映した後の扱い
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Disposition {
+    Sent,
+    RetryLater,
+    Failed,
+}
+
+pub struct Outbox {
+    path: Option<PathBuf>,
+    items: Vec<OutboxItem>,
+    retry: RetryConfig,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            path: None,
+            items: Vec::new(),
+            retry: Self::default_retry_config(),
+        }
+    }
+}
+
+impl Outbox {
+    /// 送信待ちの再送間隔（30秒から倍々で最大30分、10回まで）
+    pub fn default_retry_config() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 10,
+            initial_delay_ms: 30_000,
+            max_delay_ms: 30 * 60_000,
+        }
+    }
+
+    /// ファイルから読み込む。送信中のまま終了していたものは送信待ちに戻す
+    /// 壊れたファイルは空にせず退避してエラーを返す（送れていないメールを黙って失わない）
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let mut items: Vec<OutboxItem> = read_json_or_set_aside(&path)?.unwrap_or_default();
+
+        for item in items.iter_mut().filter(|i| i.status == OutboxStatus::Sending) {
+            item.status = OutboxStatus::Queued;
+        }
+
+        Ok(Self { path: Some(path), items, ..Default::default() })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(&self.items)
+            .map_err(|e| format!("送信待ちの保存に失敗しました: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    pub fn items(&self) -> &[OutboxItem] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 確定した送信内容を送信待ちに追加し、追加した分を返す
+    pub fn enqueue(&mut self, pending: &PendingSendData, now: u64) -> Vec<OutboxItem> {
+        let new_items: Vec<OutboxItem> = pending.recipients.iter()
+            .map(|rec| OutboxItem {
+                id: generate_id(),
+                to: rec.email.clone(),
+                cc: rec.cc.clone(),
+                bcc: rec.bcc.clone(),
+                reply_to: rec.reply_to.clone(),
+                company: rec.company.clone(),
+                name: rec.name.clone(),
+                subject: rec.subject.clone(),
+                body: rec.body.clone(),
+                html_body: rec.html_body.clone(),
+                attachments: rec.attachments.clone(),
+                status: OutboxStatus::Queued,
+                attempts: 0,
+                next_attempt_at: now,
+                last_error: None,
+                created_at: now,
+                queued_by: pending.sent_by.clone(),
+            })
+            .collect();
+        self.items.extend(new_items.iter().cloned());
+        new_items
+    }
+
+    /// 送信時刻を過ぎた送信待ち
+    pub fn due_items(&self, now: u64) -> Vec<OutboxItem> {
+        self.items.iter()
+            .filter(|i| i.status == OutboxStatus::Queued && i.next_attempt_at <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// 指定した時刻まで送信しない（送信取り消しの猶予中）
+    pub fn hold_until(&mut self, ids: &[String], until: u64) {
+        for item in self.items.iter_mut().filter(|i| ids.contains(&i.id)) {
+            item.next_attempt_at = until;
+        }
+    }
+
+    pub fn mark_sending(&mut self, ids: &[String]) {
+        for item in self.items.iter_mut().filter(|i| ids.contains(&i.id)) {
+            item.status = OutboxStatus::Sending;
+        }
+    }
+
+    /// 送信結果を反映する。送信済みは削除、通信障害はバックオフして再送待ち、それ以外は失敗
+    pub fn record_result(&mut self, id: &str, result: &RecipientSendResult, now: u64) -> Disposition {
+        let Some(pos) = self.items.iter().position(|i| i.id == id) else {
+            return Disposition::Failed;
+        };
+
+        if result.success {
+            self.items.remove(pos);
+            return Disposition::Sent;
+        }
+
+        let item = &mut self.items[pos];
+        item.attempts += 1;
+        item.last_error = result.error.clone();
+
+        if result.retryable && item.attempts < self.retry.max_attempts {
+            item.status = OutboxStatus::Queued;
+            item.next_attempt_at = now + self.retry.delay_for_attempt(item.attempts).as_secs();
+            Disposition::RetryLater
+        } else {
+            item.status = OutboxStatus::Failed;
+            Disposition::Failed
+        }
+    }
+
+    /// すぐに再送する（失敗したものも送信待ちに戻す）
+    pub fn retry_now(&mut self, id: &str, now: u64) {
+        if let Some(item) = self.items.iter_mut().find(|i| i.id == id && i.status != OutboxStatus::Sending) {
+            item.status = OutboxStatus::Queued;
+            item.next_attempt_at = now;
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<OutboxItem> {
+        let pos = self.items.iter().position(|i| i.id == id)?;
+        Some(self.items.remove(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 署名と添付ファイルの付いた1通
+    fn pending() -> PendingSendData {
+        let mut pending = PendingSendData::sample(&["a@example.com"]);
+        pending.recipients[0].body = "本文\n\n--\n署名".to_string();
+        pending.recipients[0].attachments = vec![Attachment { file_name: "請求書.pdf".to_string(), ..Default::default() }];
+        pending
+    }
+
+    fn result(success: bool, retryable: bool) -> RecipientSendResult {
+        RecipientSendResult {
+            to: "a@example.com".to_string(),
+            success,
+            error: (!success).then(|| "接続できません".to_string()),
+            retryable,
+        }
+    }
+
+    fn failure(retryable: bool) -> RecipientSendResult {
+        result(false, retryable)
+    }
+
+    #[test]
+    fn test_survives_restart_and_resumes_interrupted_sends() {
+        let path = std::env::temp_dir().join(format!("amp_outbox_test_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut outbox = Outbox::load(path.clone()).unwrap();
+        let items = outbox.enqueue(&pending(), 1000);
+        outbox.mark_sending(&[items[0].id.clone()]);
+        outbox.save().unwrap();
+
+        // 送信中に終了した想定で読み直す
+        let reloaded = Outbox::load(path.clone()).unwrap();
+        let item = &reloaded.items()[0];
+        assert_eq!(item.status, OutboxStatus::Queued);
+        assert_eq!(item.body, "本文\n\n--\n署名");
+        assert_eq!(item.attachments[0].file_name, "請求書.pdf");
+        assert_eq!(reloaded.due_items(1000).len(), 1);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_corrupt_file_is_set_aside_instead_of_emptied() {
+        let dir = std::env::temp_dir().join(format!("amp_outbox_corrupt_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("outbox.json");
+        std::fs::write(&path, "[{\"id\":").unwrap();
+
+        let error = Outbox::load(path.clone()).err().expect("壊れたファイルは空として読まない");
+        assert!(error.contains("outbox.json"), "{}", error);
+        assert!(!path.exists());
+        let aside: Vec<String> = std::fs::read_dir(&dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert!(aside[0].starts_with("outbox.json.corrupt-"), "{:?}", aside);
+        assert_eq!(std::fs::read_to_string(dir.join(&aside[0])).unwrap(), "[{\"id\":");
+
+        // ファイルがないだけなら空の送信待ち
+        assert!(Outbox::load(path).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_backoff_then_give_up() {
+        let mut outbox = Outbox::default();
+        let id = outbox.enqueue(&pending(), 1000)[0].id.clone();
+
+        assert_eq!(outbox.record_result(&id, &failure(true), 1000), Disposition::RetryLater);
+        assert_eq!(outbox.items()[0].next_attempt_at, 1030);
+        assert!(outbox.due_items(1029).is_empty());
+
+        assert_eq!(outbox.record_result(&id, &failure(true), 1030), Disposition::RetryLater);
+        assert_eq!(outbox.items()[0].next_attempt_at, 1090);
+
+        // 宛先不正など再送しても直らない失敗はすぐに失敗扱い
+        assert_eq!(outbox.record_result(&id, &failure(false), 1090), Disposition::Failed);
+        assert!(outbox.due_items(u64::MAX).is_empty());
+
+        outbox.retry_now(&id, 2000);
+        assert_eq!(outbox.due_items(2000).len(), 1);
+    }
+
+    #[test]
+    fn test_sent_items_are_removed() {
+        let mut outbox = Outbox::default();
+        let id = outbox.enqueue(&pending(), 0)[0].id.clone();
+        assert_eq!(outbox.record_result(&id, &result(true, false), 0), Disposition::Sent);
+        assert!(outbox.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! 予約送信
+//!
+//! 確認ダイアログで確定した PendingSendData を送信日時とともにディスクに保存する。
+//! 送信日時を過ぎたものは送信待ち（outbox）に移し、通常の送信と同じく再送付きで送る。
+
+use crate::calendar::BusinessCalendar;
+use crate::models::PendingSendData;
+use crate::storage::{read_json_or_set_aside, write_atomic};
+use crate::utils::generate_id;
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScheduledSend {
+    pub id: String,
+    pub send_at: u64,  // UNIX秒
+    pub pending: PendingSendData,
+    pub created_at: u64,
+}
+
+#[derive(Default)]
+pub struct Schedule {
+    path: Option<PathBuf>,
+    items: Vec<ScheduledSend>,
+}
+
+impl Schedule {
+    /// 壊れたファイルは空にせず退避してエラーを返す
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let mut items: Vec<ScheduledSend> = read_json_or_set_aside(&path)?.unwrap_or_default();
+        for item in &mut items {
+            item.pending.migrate_legacy_subject();
+        }
+        Ok(Self { path: Some(path), items })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(&self.items)
+            .map_err(|e| format!("予約送信の保存に失敗しました: {}", e))?;
+        write_atomic(path, &json)
+    }
+
+    /// 送信日時の早い順
+    pub fn items(&self) -> &[ScheduledSend] {
+        &self.items
+    }
+
+    pub fn add(&mut self, pending: PendingSendData, send_at: u64, now: u64) -> String {
+        let id = generate_id();
+        self.items.push(ScheduledSend { id: id.clone(), send_at, pending, created_at: now });
+        self.items.sort_by_key(|item| item.send_at);
+        id
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Option<ScheduledSend> {
+        let pos = self.items.iter().position(|item| item.id == id)?;
+        Some(self.items.remove(pos))
+    }
+
+    /// 送信日時を過ぎたものを取り出す
+    pub fn take_due(&mut self, now: u64) -> Vec<ScheduledSend> {
+        let (due, rest) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|item| item.send_at <= now);
+        self.items = rest;
+        due
+    }
+}
+
+/// ローカル時刻の日付・時・分を UNIX秒に変換
+pub fn local_timestamp(date: NaiveDate, hour: u32, minute: u32) -> Option<u64> {
+    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+    Local.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|dt| dt.timestamp().max(0) as u64)
+}
+
+/// UNIX秒をローカル時刻で表示（例: 2026-10-19(月) 09:00）
+pub fn format_local(ts: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["月", "火", "水", "木", "金", "土", "日"];
+    match Local.timestamp_opt(ts as i64, 0).single() {
+        Some(dt) => {
+            format!("{}({}) {}", dt.format("%Y-%m-%d"), WEEKDAYS[dt.weekday().num_days_from_monday() as usize], dt.format("%H:%M"))
+        }
+        None => ts.to_string(),
+    }
+}
+
+/// 翌営業日の指定時刻
+pub fn next_business_day_at(calendar: &BusinessCalendar, now: u64, hour: u32, minute: u32) -> Option<u64> {
+    let today = Local.timestamp_opt(now as i64, 0).single()?.date_naive();
+    local_timestamp(calendar.next_business_day(today), hour, minute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PendingRecipient;
+
+    #[test]
+    fn test_take_due_keeps_future_items() {
+        let mut schedule = Schedule::default();
+        let pending = |subject: &str| PendingSendData {
+            recipients: vec![PendingRecipient { subject: subject.to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        schedule.add(pending("明日"), 2000, 0);
+        let today = schedule.add(pending("今日"), 1000, 0);
+
+        assert_eq!(schedule.items()[0].id, today, "送信日時順に並ぶ");
+        assert!(schedule.take_due(999).is_empty());
+
+        let due = schedule.take_due(1000);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].pending.recipients[0].subject, "今日");
+        assert_eq!(schedule.items().len(), 1);
+    }
+
+    #[test]
+    fn test_load_moves_legacy_subject_to_recipients() {
+        let path = std::env::temp_dir().join(format!("schedule_test_{}.json", generate_id()));
+        std::fs::write(&path, r#"[{"id":"1","send_at":1,"created_at":0,"pending":{"subject":"旧件名","recipients":[
+            {"draft_index":0,"email":"a@example.com","company":"","name":"","body":"","attachments":[]}]}}]"#).unwrap();
+
+        let schedule = Schedule::load(path.clone()).unwrap();
+        assert_eq!(schedule.items()[0].pending.recipients[0].subject, "旧件名");
+
+        schedule.save().unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("\"subject\":\"旧件名\",\"recipients\""), "共通件名は書き出さない");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_next_business_day_at_nine() {
+        let calendar = BusinessCalendar::parse("2026-10-19 テスト休業日").unwrap();
+        // 2026-10-16(金) 18:00 に予約 → 月曜が休業日なので 10/20(火) 9:00
+        let friday_evening = local_timestamp(NaiveDate::from_ymd_opt(2026, 10, 16).unwrap(), 18, 0).unwrap();
+        let send_at = next_business_day_at(&calendar, friday_evening, 9, 0).unwrap();
+        assert_eq!(format_local(send_at), "2026-10-20(火) 09:00");
+    }
+}
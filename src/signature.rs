@@ -0,0 +1,157 @@
+//! 署名の差し込みとログインユーザーごとの既定の署名
+//!
+//! 署名では `{{sender_name}}`（送信者名）とテンプレートと同じ日付の組み込み変数が使える。
+//! 既定の署名と送信者名は設定シートに `user.<ユーザー名>.signature` / `user.<ユーザー名>.sender_name`
+//! として保存し、同じ GAS を使う他のユーザーの選択とは分ける。
+
+use chrono::{Local, NaiveDate};
+use std::collections::HashMap;
+use crate::auth::Role;
+use crate::models::{AppState, Signature, UserProfile};
+use crate::template_engine::{self, Context};
+use crate::worker::Job;
+
+const SETTING_PREFIX: &str = "user.";
+const SIGNATURE_KEY: &str = "signature";
+const SENDER_NAME_KEY: &str = "sender_name";
+
+/// 以前の全員共通の署名の設定（ユーザーごとの既定がないときに使う）
+pub const LEGACY_SIGNATURE_SETTING: &str = "selected_signature_index";
+
+/// 署名に送信者名と日付を差し込む
+pub fn render(content: &str, sender_name: &str) -> String {
+    render_on(content, sender_name, Local::now().date_naive())
+}
+
+fn render_on(content: &str, sender_name: &str, today: NaiveDate) -> String {
+    let mut ctx = Context::new(today);
+    // 空なら {{sender_name}} を残して送信前チェックで検出する
+    if !sender_name.trim().is_empty() {
+        ctx.set("sender_name", sender_name.trim());
+    }
+    template_engine::render(content, &ctx)
+}
+
+/// 設定シートの値からユーザーごとの設定を読み出す
+pub fn user_profiles(settings: &HashMap<String, String>) -> HashMap<String, UserProfile> {
+    let mut profiles: HashMap<String, UserProfile> = HashMap::new();
+    for (key, value) in settings {
+        let Some((user, field)) = key.strip_prefix(SETTING_PREFIX).and_then(|k| k.rsplit_once('.')) else {
+            continue;
+        };
+        let profile = profiles.entry(user.to_string()).or_default();
+        match field {
+            SIGNATURE_KEY => profile.signature = Some(value.clone()).filter(|v| !v.is_empty()),
+            SENDER_NAME_KEY => profile.sender_name = value.clone(),
+            _ => {}
+        }
+    }
+    profiles
+}
+
+/// ユーザーの設定を設定シートに保存する形にする
+pub fn profile_settings(username: &str, profile: &UserProfile) -> HashMap<String, String> {
+    let key = |field: &str| format!("{}{}.{}", SETTING_PREFIX, username, field);
+    HashMap::from([
+        (key(SIGNATURE_KEY), profile.signature.clone().unwrap_or_default()),
+        (key(SENDER_NAME_KEY), profile.sender_name.clone()),
+    ])
+}
+
+/// 設定シートの値を保存してよいか。自分の設定は誰でも、署名の名前変更に伴う
+/// 他のユーザーの既定の署名の書き換えはテンプレート編集者から、それ以外は管理者だけ
+pub fn may_save_setting(key: &str, username: &str, role: Role) -> bool {
+    match key.strip_prefix(SETTING_PREFIX).and_then(|k| k.rsplit_once('.')) {
+        Some((user, _)) if !username.is_empty() && user == username => true,
+        Some((_, SIGNATURE_KEY)) => role >= Role::TemplateEditor,
+        _ => role >= Role::Admin,
+    }
+}
+
+/// 他のユーザーの既定の署名だけを書き換える設定
+pub fn signature_setting(username: &str, name: &str) -> HashMap<String, String> {
+    HashMap::from([(format!("{}{}.{}", SETTING_PREFIX, username, SIGNATURE_KEY), name.to_string())])
+}
+
+/// 既定の署名の位置。ユーザーの既定 → 以前の共通の選択 → 先頭 の順に選ぶ
+pub fn default_signature_index(signatures: &[Signature], profile: Option<&UserProfile>, legacy: Option<usize>) -> Option<usize> {
+    profile
+        .and_then(|p| p.signature.as_ref())
+        .and_then(|name| signatures.iter().position(|s| &s.name == name))
+        .or(legacy.filter(|i| *i < signatures.len()))
+        .or(if signatures.is_empty() { None } else { Some(0) })
+}
+
+/// ログイン中のユーザーの既定の署名を選ぶ（ログイン時と起動時に呼ぶ）
+pub fn apply_user_defaults(state: &mut AppState) {
+    let profile = state.user_profiles.get(&state.auth_username);
+    state.selected_signature_index = default_signature_index(&state.signatures, profile, state.selected_signature_index);
+}
+
+/// 署名を選び、ログイン中のユーザーの既定として設定シートに保存する
+pub fn remember_default(state: &mut AppState, index: usize) {
+    let Some(name) = state.signatures.get(index).map(|s| s.name.clone()) else {
+        return;
+    };
+    state.selected_signature_index = Some(index);
+    save_profile(state, |profile| profile.signature = Some(name));
+}
+
+/// ログイン中のユーザーの設定を書き換えて保存する（ユーザー名が分からなければ保存しない）
+pub fn save_profile(state: &mut AppState, change: impl FnOnce(&mut UserProfile)) {
+    if state.auth_username.is_empty() {
+        return;
+    }
+    let profile = state.user_profiles.entry(state.auth_username.clone()).or_default();
+    change(profile);
+    let settings = profile_settings(&state.auth_username, profile);
+    state.job_queue.push(Job::SaveSettings(settings));
+}
+
+/// ログイン中のユーザーの送信者名
+pub fn sender_name(state: &AppState) -> &str {
+    state.user_profiles.get(&state.auth_username)
+        .map(|p| p.sender_name.as_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(name: &str) -> Signature {
+        Signature { name: name.to_string(), content: format!("--\n{}", name) }
+    }
+
+    #[test]
+    fn test_render_sender_name_and_date() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap();
+        let content = "--\n{{sender_name}}\n{{today|和暦}}";
+        assert_eq!(render_on(content, " 田中 太郎 ", today), "--\n田中 太郎\n令和8年10月17日");
+        assert_eq!(render_on(content, "", today), "--\n{{sender_name}}\n令和8年10月17日", "送信者名が空なら変数を残す");
+    }
+
+    #[test]
+    fn test_profiles_round_trip_through_settings() {
+        let profile = UserProfile { signature: Some("営業用".to_string()), sender_name: "田中".to_string() };
+        let mut settings = profile_settings("tanaka.t", &profile);
+        settings.insert(LEGACY_SIGNATURE_SETTING.to_string(), "1".to_string());
+        settings.insert("undo_send_seconds".to_string(), "10".to_string());
+
+        let profiles = user_profiles(&settings);
+        assert_eq!(profiles.len(), 1, "ユーザーの設定以外は読まない");
+        assert_eq!(profiles.get("tanaka.t"), Some(&profile), "ユーザー名に . を含んでもよい");
+    }
+
+    #[test]
+    fn test_default_signature_per_user() {
+        let signatures = vec![signature("デフォルト"), signature("営業用")];
+        let mine = UserProfile { signature: Some("営業用".to_string()), ..Default::default() };
+        let deleted = UserProfile { signature: Some("削除済み".to_string()), ..Default::default() };
+
+        assert_eq!(default_signature_index(&signatures, Some(&mine), Some(0)), Some(1));
+        assert_eq!(default_signature_index(&signatures, Some(&deleted), Some(0)), Some(0), "見つからなければ共通の選択");
+        assert_eq!(default_signature_index(&signatures, None, Some(5)), Some(0), "範囲外なら先頭");
+        assert_eq!(default_signature_index(&[], Some(&mine), None), None);
+    }
+}
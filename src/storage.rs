@@ -0,0 +1,72 @@
+//! ローカル保存先（送信待ちキュー・設定ファイルなど）
+
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+
+const APP_DIR_NAME: &str = "auto-mail-pilot";
+
+/// ユーザーごとのデータフォルダ（なければ作成）
+/// フォルダを作るので起動時に呼び、画面からは AppState::data_dir を使う
+/// Windows: %LOCALAPPDATA%\auto-mail-pilot, macOS: ~/Library/Application Support/auto-mail-pilot
+pub fn data_dir() -> PathBuf {
+    let dir = dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR_NAME);
+    let _ = std::fs::create_dir_all(&dir);
+    dir
+}
+
+/// ユーザーごとの設定フォルダ（なければ作成）
+/// Windows: %APPDATA%\auto-mail-pilot, macOS: ~/Library/Application Support/auto-mail-pilot
+pub fn config_dir() -> PathBuf {
+    let dir = dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR_NAME);
+    let _ = std::fs::create_dir_all(&dir);
+    dir
+}
+
+/// 一時ファイルに書いてから置き換える（書き込み途中で落ちても元のファイルを壊さない）
+pub fn write_atomic(path: &std::path::Path, contents: &str) -> Result<(), String> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)
+        .map_err(|e| format!("{} に書き込めません: {}", tmp_path.display(), e))?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| format!("{} を保存できません: {}", path.display(), e))
+}
+
+/// ファイルの中身（ファイルがなければ None。ほかの理由で読めなければエラー）
+fn read_if_exists(path: &Path) -> Result<Option<String>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{} を読み込めません: {}", path.display(), e)),
+    }
+}
+
+/// JSON のファイルを読み込む（ファイルがなければ None）
+/// 読めない・中身が壊れているときは空として扱わずエラーにする（次の保存で上書きしないため）
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let Some(content) = read_if_exists(path)? else {
+        return Ok(None);
+    };
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("{} が壊れています: {}", path.display(), e))
+}
+
+/// read_json と同じだが、壊れたファイルは <ファイル名>.corrupt-<UNIX秒> に移して残す
+pub fn read_json_or_set_aside<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let Some(content) = read_if_exists(path)? else {
+        return Ok(None);
+    };
+    serde_json::from_str(&content).map(Some).map_err(|e| {
+        let mut aside = path.as_os_str().to_owned();
+        aside.push(format!(".corrupt-{}", crate::utils::now_unix_secs()));
+        let aside = PathBuf::from(aside);
+        match std::fs::rename(path, &aside) {
+            Ok(()) => format!("{} が壊れていたため {} に移しました: {}", path.display(), aside.display(), e),
+            Err(rename_err) => format!("{} が壊れています（退避できません: {}）: {}", path.display(), rename_err, e),
+        }
+    })
+}
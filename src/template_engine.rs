@@ -0,0 +1,330 @@
+//! テンプレートエンジン
+//!
+//! 対応する書式:
+//! - `{{変数}}` 置換。値のない変数はそのまま残し、送信前チェックで検出する
+//! - `{{変数|既定値}}` 値が空なら既定値（例: `{{title|様}}`）
+//! - `{{変数|フィルタ}}` 和暦（`{{today|和暦}}` → 令和8年10月17日）、yen（`{{amount|yen}}` → 12,000円）
+//! - `{{#if 変数}}…{{else}}…{{/if}}` 値が空でなければ前半、空なら後半（入れ子可）
+//!
+//! 組み込み変数は `today` と `next_month_end`（翌月末日）。日付は「2026年10月17日」の形式。
+
+use chrono::{Datelike, Local, NaiveDate};
+use std::collections::HashMap;
+
+/// 解析結果の要素
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Var { name: String, pipes: Vec<String>, raw: String },
+    If { name: String, then: Vec<Node>, otherwise: Vec<Node> },
+}
+
+/// 書式エラー（行番号は1始まり）
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}行目: {}", self.line, self.message)
+    }
+}
+
+/// 差し込みに使う値
+#[derive(Clone, Debug)]
+pub struct Context {
+    values: HashMap<String, String>,
+}
+
+impl Context {
+    /// 組み込みの日付変数を today 基準で設定する
+    pub fn new(today: NaiveDate) -> Self {
+        let mut values = HashMap::new();
+        values.insert("today".to_string(), format_date(today));
+        values.insert("next_month_end".to_string(), format_date(next_month_end(today)));
+        Self { values }
+    }
+
+    pub fn today() -> Self {
+        Self::new(Local::now().date_naive())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.values.insert(name.to_string(), value.to_string());
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// 書式をチェックする（テンプレート保存時）
+pub fn validate(src: &str) -> Result<(), ParseError> {
+    parse(src).map(|_| ())
+}
+
+/// テンプレートに値を差し込む。書式エラーがあれば元の文字列をそのまま返す
+pub fn render(src: &str, ctx: &Context) -> String {
+    match parse(src) {
+        Ok(nodes) => {
+            let mut out = String::new();
+            render_nodes(&nodes, ctx, &mut out);
+            out
+        }
+        Err(_) => src.to_string(),
+    }
+}
+
+fn render_nodes(nodes: &[Node], ctx: &Context, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var { name, pipes, raw } => match render_var(ctx.get(name), pipes) {
+                Some(value) => out.push_str(&value),
+                // 値がない・フィルタを適用できない場合は残して送信前に気づけるようにする
+                None => out.push_str(raw),
+            },
+            Node::If { name, then, otherwise } => {
+                let truthy = ctx.get(name).is_some_and(|v| !v.trim().is_empty());
+                render_nodes(if truthy { then } else { otherwise }, ctx, out);
+            }
+        }
+    }
+}
+
+fn render_var(value: Option<&str>, pipes: &[String]) -> Option<String> {
+    let mut value = value.map(str::to_string);
+    for pipe in pipes {
+        value = match (pipe.as_str(), value) {
+            ("和暦", Some(v)) if !v.is_empty() => Some(to_wareki(parse_date(&v)?)?),
+            ("yen", Some(v)) if !v.is_empty() => Some(format_yen(&v)?),
+            ("和暦" | "yen", v) => v,
+            // フィルタ名でなければ既定値
+            (default, v) => match v {
+                Some(v) if !v.is_empty() => Some(v),
+                _ => Some(default.to_string()),
+            },
+        };
+    }
+    value
+}
+
+/// 解析中のブロック（最下段はテンプレート全体）
+struct Frame {
+    name: String,
+    then: Vec<Node>,
+    otherwise: Vec<Node>,
+    in_else: bool,
+    line: usize,
+}
+
+impl Frame {
+    fn new(name: &str, line: usize) -> Self {
+        Self { name: name.to_string(), then: Vec::new(), otherwise: Vec::new(), in_else: false, line }
+    }
+
+    fn push(&mut self, node: Node) {
+        if self.in_else {
+            self.otherwise.push(node);
+        } else {
+            self.then.push(node);
+        }
+    }
+}
+
+fn parse(src: &str) -> Result<Vec<Node>, ParseError> {
+    let mut stack = vec![Frame::new("", 1)];
+    let mut rest = src;
+    let mut consumed = 0;
+
+    while let Some(start) = rest.find("{{") {
+        let line = src[..consumed + start].matches('\n').count() + 1;
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            return Err(ParseError { line, message: "「{{」が「}}」で閉じられていません".to_string() });
+        };
+        let tag = after[..end].trim();
+        let raw = &rest[start..start + 2 + end + 2];
+        let error = |message: String| Err(ParseError { line, message });
+
+        if start > 0 {
+            stack.last_mut().unwrap().push(Node::Text(rest[..start].to_string()));
+        }
+
+        if let Some(cond) = tag.strip_prefix("#if") {
+            let name = cond.trim();
+            if name.is_empty() || !cond.starts_with(char::is_whitespace) {
+                return error(format!("{} に変数名がありません", raw));
+            }
+            stack.push(Frame::new(name, line));
+        } else if tag == "else" {
+            if stack.len() == 1 {
+                return error("{{#if}} の外に {{else}} があります".to_string());
+            }
+            let frame = stack.last_mut().unwrap();
+            if frame.in_else {
+                return error("{{else}} が重複しています".to_string());
+            }
+            frame.in_else = true;
+        } else if tag == "/if" {
+            if stack.len() == 1 {
+                return error("対応する {{#if}} のない {{/if}} があります".to_string());
+            }
+            let frame = stack.pop().unwrap();
+            let node = Node::If { name: frame.name, then: frame.then, otherwise: frame.otherwise };
+            stack.last_mut().unwrap().push(node);
+        } else if tag.starts_with('#') || tag.starts_with('/') {
+            return error(format!("{} は使えない書式です（使えるのは {{{{#if}}}} のみ）", raw));
+        } else {
+            let mut parts = tag.split('|').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            if name.is_empty() {
+                return error(format!("{} に変数名がありません", raw));
+            }
+            let node = Node::Var { name: name.to_string(), pipes: parts.map(str::to_string).collect(), raw: raw.to_string() };
+            stack.last_mut().unwrap().push(node);
+        }
+
+        let advanced = start + 2 + end + 2;
+        consumed += advanced;
+        rest = &rest[advanced..];
+    }
+
+    if stack.len() > 1 {
+        let frame = stack.last().unwrap();
+        return Err(ParseError { line: frame.line, message: format!("{{{{#if {}}}}} が {{{{/if}}}} で閉じられていません", frame.name) });
+    }
+    let mut root = stack.pop().unwrap();
+    if !rest.is_empty() {
+        root.push(Node::Text(rest.to_string()));
+    }
+    Ok(root.then)
+}
+
+/// 2026年10月17日
+pub fn format_date(date: NaiveDate) -> String {
+    format!("{}年{}月{}日", date.year(), date.month(), date.day())
+}
+
+/// 翌月の末日
+pub fn next_month_end(today: NaiveDate) -> NaiveDate {
+    let (year, month) = if today.month() >= 11 {
+        (today.year() + 1, today.month() - 10)
+    } else {
+        (today.year(), today.month() + 2)
+    };
+    // 翌々月1日の前日
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|d| d.pred_opt())
+        .unwrap_or(today)
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    let s = s.trim();
+    ["%Y-%m-%d", "%Y/%m/%d", "%Y年%m月%d日"].iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(s, fmt).ok())
+}
+
+/// 令和8年10月17日（元年は「元年」と表記）
+pub fn to_wareki(date: NaiveDate) -> Option<String> {
+    const ERAS: [(&str, i32, u32, u32); 4] = [
+        ("令和", 2019, 5, 1),
+        ("平成", 1989, 1, 8),
+        ("昭和", 1926, 12, 25),
+        ("大正", 1912, 7, 30),
+    ];
+    let (era, start_year, _, _) = ERAS.iter()
+        .find(|(_, y, m, d)| NaiveDate::from_ymd_opt(*y, *m, *d).is_some_and(|start| date >= start))?;
+    let year = date.year() - start_year + 1;
+    let year = if year == 1 { "元".to_string() } else { year.to_string() };
+    Some(format!("{}{}年{}月{}日", era, year, date.month(), date.day()))
+}
+
+/// 12000 → 12,000円（カンマ・円記号・「円」が付いていても可）
+/// 符号・数字・小数点だけを受け付ける（NaN・inf・指数表記や、誤差の出る桁数は数値とみなさない）
+pub fn format_yen(s: &str) -> Option<String> {
+    let cleaned: String = s.trim()
+        .trim_start_matches(['¥', '￥'])
+        .trim_end_matches('円')
+        .chars()
+        .filter(|c| *c != ',')
+        .collect();
+    let unsigned = cleaned.strip_prefix(['-', '+']).unwrap_or(&cleaned);
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if int_part.is_empty() || int_part.len() > 15 || !is_digits(int_part) || !is_digits(frac_part) {
+        return None;
+    }
+    let amount: f64 = cleaned.parse().ok()?;
+    let amount = amount.round() as i64;
+
+    let digits = amount.unsigned_abs().to_string();
+    let mut grouped = String::new();
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+    let sign = if amount < 0 { "-" } else { "" };
+    Some(format!("{}{}円", sign, grouped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> Context {
+        let mut ctx = Context::new(NaiveDate::from_ymd_opt(2026, 10, 17).unwrap());
+        ctx.set("company", "A社");
+        ctx.set("department", "経理部");
+        ctx.set("title", "");
+        ctx.set("amount", "1234567");
+        ctx
+    }
+
+    #[test]
+    fn test_variables_defaults_and_filters() {
+        assert_eq!(
+            render("{{company}} {{title|様}} {{amount|yen}} {{today|和暦}} {{next_month_end}}", &ctx()),
+            "A社 様 1,234,567円 令和8年10月17日 2026年11月30日"
+        );
+        assert_eq!(render("{{unknown}} {{unknown|なし}}", &ctx()), "{{unknown}} なし");
+        assert_eq!(render("{{company|yen}}", &ctx()), "{{company|yen}}", "数値でなければ残す");
+    }
+
+    #[test]
+    fn test_if_else_nested() {
+        let src = "{{#if department}}{{department}}{{#if title}} {{title}}{{else}} ご担当者{{/if}}様{{else}}ご担当者様{{/if}}";
+        assert_eq!(render(src, &ctx()), "経理部 ご担当者様");
+        assert_eq!(render("{{#if title}}x{{else}}y{{/if}}{{#if missing}}z{{/if}}", &ctx()), "y");
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        assert_eq!(validate("本文\n{{#if department}}\n…").unwrap_err().line, 2);
+        assert!(validate("{{/if}}").unwrap_err().message.contains("{{/if}}"));
+        assert!(validate("{{else}}").is_err());
+        assert!(validate("{{#each items}}{{/each}}").is_err());
+        assert!(validate("{{company").is_err());
+        assert!(validate("{{ }}").is_err());
+        assert!(validate("{{#if a}}{{else}}{{else}}{{/if}}").is_err());
+        assert!(validate("{{#if a}}{{b|様}}{{/if}}").is_ok());
+    }
+
+    #[test]
+    fn test_dates() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(next_month_end(date(2026, 12, 15)), date(2027, 1, 31));
+        assert_eq!(next_month_end(date(2027, 1, 31)), date(2027, 2, 28));
+        assert_eq!(to_wareki(date(2019, 5, 1)).unwrap(), "令和元年5月1日");
+        assert_eq!(to_wareki(date(2019, 4, 30)).unwrap(), "平成31年4月30日");
+        assert_eq!(render("{{d|和暦}}", &{ let mut c = ctx(); c.set("d", "2026/4/1"); c }), "令和8年4月1日");
+        assert_eq!(format_yen("¥-1,000").unwrap(), "-1,000円");
+        for not_amount in ["NaN", "inf", "-infinity", "1e20", ".5", "1.2.3", "100000000000000000000"] {
+            assert_eq!(format_yen(not_amount), None, "{}", not_amount);
+        }
+    }
+}
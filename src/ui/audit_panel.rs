@@ -0,0 +1,73 @@
+use eframe::egui;
+use crate::audit::AuditEntry;
+use crate::models::AppState;
+use crate::schedule;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 150, 150);
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("監査ログ");
+    ui.separator();
+    ui.weak("ログイン・ログアウト・送信・テンプレートや宛先の変更を、操作したユーザーとともにこのPCに記録しています。");
+
+    ui.add_space(6.0);
+    match state.audit_log.verify() {
+        Ok(()) => {
+            ui.colored_label(egui::Color32::GREEN, format!("✓ {}件、改ざんは見つかりません", state.audit_log.entries().len()));
+        }
+        Err(line) => {
+            ui.colored_label(ERROR_COLOR, format!("⚠ {}行目以降の記録が書き換えられているか、壊れています", line));
+        }
+    }
+    let unreadable = state.audit_log.unreadable_lines();
+    if !unreadable.is_empty() {
+        let lines: Vec<String> = unreadable.iter().map(|l| l.to_string()).collect();
+        ui.colored_label(ERROR_COLOR, format!("⚠ 読めない行があります（{}行目）。この行は一覧に表示していません", lines.join(", ")));
+    }
+
+    ui.add_space(6.0);
+    ui.add(egui::TextEdit::singleline(&mut state.audit_search)
+        .hint_text("🔍 ユーザー・操作・内容で絞り込み")
+        .desired_width(300.0));
+    ui.add_space(6.0);
+
+    let rows = visible_entries(state.audit_log.entries(), &state.audit_search);
+    egui::ScrollArea::vertical()
+        .id_salt("audit_table")
+        .show(ui, |ui| {
+            egui::Grid::new("audit_grid")
+                .num_columns(4)
+                .spacing([16.0, 6.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("日時");
+                    ui.strong("ユーザー");
+                    ui.strong("操作");
+                    ui.strong("内容");
+                    ui.end_row();
+
+                    for entry in rows {
+                        ui.label(schedule::format_local(entry.at));
+                        ui.label(&entry.user);
+                        ui.label(entry.action.label());
+                        ui.label(&entry.detail);
+                        ui.end_row();
+                    }
+                });
+            if state.audit_log.entries().is_empty() {
+                ui.weak("記録はありません");
+            }
+        });
+}
+
+/// 絞り込んで新しい順に並べた記録
+fn visible_entries<'a>(entries: &'a [AuditEntry], search: &str) -> Vec<&'a AuditEntry> {
+    let search = search.trim().to_lowercase();
+    entries.iter()
+        .rev()
+        .filter(|e| search.is_empty()
+            || e.user.to_lowercase().contains(&search)
+            || e.action.label().contains(&search)
+            || e.detail.to_lowercase().contains(&search))
+        .collect()
+}
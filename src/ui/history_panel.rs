@@ -1,24 +1,15 @@
 use eframe::egui;
 use crate::models::AppState;
-use crate::api::GasClient;
+use crate::worker::Job;
 
 pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
     ui.heading("送信履歴 (直近50件)");
     ui.separator();
 
-    if ui.button("🔄 履歴を更新").clicked() {
-        let client = GasClient::new(state.gas_url.clone());
+    if ui.add_enabled(!state.is_loading, egui::Button::new("🔄 履歴を更新")).clicked() {
         state.is_loading = true;
         state.status_message = "履歴を取得中...".to_string();
-        
-        match client.get_history() {
-            Ok(logs) => {
-                state.history = logs;
-                state.status_message = "履歴を更新しました".to_string();
-            }
-            Err(e) => state.status_message = format!("履歴取得エラー: {}", e),
-        }
-        state.is_loading = false;
+        state.job_queue.push(Job::RefreshHistory);
     }
 
     ui.add_space(10.0);
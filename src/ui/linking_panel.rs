@@ -0,0 +1,170 @@
+use eframe::egui;
+use crate::models::{AppState, LinkingData, RecipientData, Template};
+use crate::worker::Job;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 150, 150);
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("宛先とテンプレートの紐付け");
+    ui.separator();
+    ui.weak("宛先を選んだとき、紐付けたテンプレートを自動で適用します。");
+    ui.weak("キーワードを入れると、添付ファイル名にその語を含むときだけ使います（例: 請求書 → 請求書のテンプレート）。キーワードが空欄の紐付けはその宛先の既定です。");
+
+    ui.add_space(10.0);
+    show_form(ui, state);
+    ui.add_space(10.0);
+    ui.separator();
+
+    ui.add(egui::TextEdit::singleline(&mut state.linking_editor.search)
+        .hint_text("🔍 会社名・氏名・キーワードで絞り込み")
+        .desired_width(300.0));
+    ui.add_space(6.0);
+
+    let rows = visible_linkings(state);
+    let mut delete = None;
+    let mut edit = None;
+    egui::ScrollArea::vertical()
+        .id_salt("linking_table")
+        .show(ui, |ui| {
+            egui::Grid::new("linking_grid")
+                .num_columns(4)
+                .spacing([16.0, 6.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("宛先");
+                    ui.strong("キーワード");
+                    ui.strong("テンプレート");
+                    ui.label("");
+                    ui.end_row();
+
+                    for link in rows {
+                        match find_recipient(&state.recipients_master, &link.recipient_id) {
+                            Some(rec) => ui.label(format!("{} {}", rec.company, rec.name)),
+                            None => ui.colored_label(ERROR_COLOR, format!("⚠ 宛先 ID {} が見つかりません", link.recipient_id)),
+                        };
+                        if link.keyword.trim().is_empty() {
+                            ui.weak("（既定）");
+                        } else {
+                            ui.label(&link.keyword);
+                        }
+                        match find_template(&state.templates, &link.template_id) {
+                            Some(template) => ui.label(&template.name),
+                            None => ui.colored_label(ERROR_COLOR, format!("⚠ テンプレート ID {} が見つかりません", link.template_id)),
+                        };
+                        ui.horizontal(|ui| {
+                            if ui.small_button("✏").on_hover_text("入力欄に読み込む").clicked() {
+                                edit = Some(link.clone());
+                            }
+                            if ui.small_button("🗑").on_hover_text("削除").clicked() {
+                                delete = Some(link.clone());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            if state.linkings_master.is_empty() {
+                ui.weak("紐付けはありません");
+            }
+        });
+
+    if let Some(link) = edit {
+        let editor = &mut state.linking_editor;
+        editor.recipient_id = Some(link.recipient_id);
+        editor.template_id = Some(link.template_id);
+        editor.keyword = link.keyword;
+    }
+    if let Some(link) = delete {
+        state.job_queue.push(Job::DeleteLinking(link));
+    }
+}
+
+/// 追加・差し替えの入力欄
+fn show_form(ui: &mut egui::Ui, state: &mut AppState) {
+    let editor = &mut state.linking_editor;
+    ui.horizontal(|ui| {
+        ui.label("宛先:");
+        let selected = editor.recipient_id.as_ref()
+            .and_then(|id| find_recipient(&state.recipients_master, id))
+            .map(|r| format!("{} {}", r.company, r.name))
+            .unwrap_or_else(|| "選択してください".to_string());
+        egui::ComboBox::from_id_salt("linking_recipient")
+            .selected_text(selected)
+            .width(200.0)
+            .show_ui(ui, |ui| {
+                for rec in &state.recipients_master {
+                    ui.selectable_value(&mut editor.recipient_id, Some(rec.id.clone()), format!("{} {}", rec.company, rec.name));
+                }
+            });
+
+        ui.label("キーワード:");
+        ui.add(egui::TextEdit::singleline(&mut editor.keyword)
+            .hint_text("空欄なら既定")
+            .desired_width(120.0));
+
+        ui.label("テンプレート:");
+        let selected = editor.template_id.as_ref()
+            .and_then(|id| find_template(&state.templates, id))
+            .map(|t| t.name.clone())
+            .unwrap_or_else(|| "選択してください".to_string());
+        egui::ComboBox::from_id_salt("linking_template")
+            .selected_text(selected)
+            .width(180.0)
+            .show_ui(ui, |ui| {
+                for template in &state.templates {
+                    ui.selectable_value(&mut editor.template_id, Some(template.id.clone()), &template.name);
+                }
+            });
+    });
+
+    let Some((recipient_id, template_id)) = editor.recipient_id.clone().zip(editor.template_id.clone()) else {
+        return;
+    };
+    let linking = LinkingData { recipient_id, template_id, keyword: editor.keyword.trim().to_string() };
+    let existing = state.linkings_master.iter()
+        .find(|l| l.recipient_id == linking.recipient_id && l.keyword == linking.keyword);
+
+    ui.horizontal(|ui| {
+        let label = if existing.is_some() { "🔁 差し替え" } else { "➕ 追加" };
+        if ui.add_enabled(existing != Some(&linking), egui::Button::new(label)).clicked() {
+            state.job_queue.push(Job::SaveLinking(linking.clone()));
+            editor.keyword.clear();
+        }
+        match existing {
+            Some(existing) if *existing == linking => {
+                ui.weak("同じ紐付けがすでにあります");
+            }
+            Some(existing) => {
+                let current = find_template(&state.templates, &existing.template_id)
+                    .map(|t| t.name.as_str())
+                    .unwrap_or("不明なテンプレート");
+                ui.weak(format!("この宛先・キーワードの紐付け（{}）を置き換えます", current));
+            }
+            None => {}
+        }
+    });
+}
+
+/// 絞り込んで宛先・キーワード順に並べた紐付け
+fn visible_linkings(state: &AppState) -> Vec<LinkingData> {
+    let search = state.linking_editor.search.trim().to_lowercase();
+    let recipient_label = |id: &str| find_recipient(&state.recipients_master, id)
+        .map(|r| format!("{} {}", r.company, r.name))
+        .unwrap_or_default();
+
+    let mut rows: Vec<LinkingData> = state.linkings_master.iter()
+        .filter(|l| search.is_empty()
+            || recipient_label(&l.recipient_id).to_lowercase().contains(&search)
+            || l.keyword.to_lowercase().contains(&search))
+        .cloned()
+        .collect();
+    rows.sort_by_cached_key(|l| (recipient_label(&l.recipient_id), l.recipient_id.clone(), l.keyword.clone()));
+    rows
+}
+
+fn find_recipient<'a>(master: &'a [RecipientData], id: &str) -> Option<&'a RecipientData> {
+    master.iter().find(|r| r.id == id)
+}
+
+fn find_template<'a>(templates: &'a [Template], id: &str) -> Option<&'a Template> {
+    templates.iter().find(|t| t.id == id)
+}
@@ -1,5 +1,8 @@
 use eframe::egui;
+use crate::audit::{self, AuditAction};
+use crate::auth::{Role, MIN_PASSWORD_CHARS};
 use crate::models::AppState;
+use crate::utils::now_unix_secs;
 
 /// ログイン画面を表示
 pub fn show(ctx: &egui::Context, state: &mut AppState) {
@@ -12,8 +15,22 @@ pub fn show(ctx: &egui::Context, state: &mut AppState) {
                 .size(32.0)
                 .strong());
 
+            // ユーザーの一覧を読み込めなければ、ログインも最初の管理者の作成もさせない
+            if let Some(error) = &state.user_store_error {
+                ui.add_space(40.0);
+                ui.label(egui::RichText::new(format!("❌ {}", error))
+                    .color(egui::Color32::from_rgb(255, 100, 100)));
+                ui.add_space(8.0);
+                ui.label("ファイルを元に戻してから起動し直してください");
+                return;
+            }
+
+            // ユーザーがまだいなければ、最初の管理者を作る
+            let setup = state.user_store.is_empty();
+
             ui.add_space(8.0);
-            ui.label(egui::RichText::new("ログインしてください")
+            let subtitle = if setup { "最初の管理者アカウントを作成してください" } else { "ログインしてください" };
+            ui.label(egui::RichText::new(subtitle)
                 .size(16.0)
                 .color(egui::Color32::from_rgb(180, 180, 180)));
 
@@ -68,6 +85,29 @@ pub fn show(ctx: &egui::Context, state: &mut AppState) {
                                 .desired_width(f32::INFINITY));
                         });
 
+                    if setup {
+                        ui.add_space(16.0);
+                        ui.label("パスワード（確認）");
+                        ui.add_space(4.0);
+                        egui::Frame::none()
+                            .fill(egui::Color32::from_rgb(40, 50, 70))
+                            .stroke(egui::Stroke::new(1.5, egui::Color32::from_rgb(80, 120, 170)))
+                            .inner_margin(8.0)
+                            .rounding(4.0)
+                            .show(ui, |ui| {
+                                ui.visuals_mut().selection.stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 180, 0));
+                                ui.visuals_mut().text_cursor.stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 180, 0));
+                                ui.add(egui::TextEdit::singleline(&mut state.auth_password_confirm)
+                                    .hint_text("もう一度入力...")
+                                    .password(true)
+                                    .text_color(egui::Color32::WHITE)
+                                    .frame(false)
+                                    .desired_width(f32::INFINITY));
+                            });
+                        ui.add_space(4.0);
+                        ui.weak(format!("パスワードは{}文字以上", MIN_PASSWORD_CHARS));
+                    }
+
                     ui.add_space(24.0);
 
                     // エラーメッセージ
@@ -81,8 +121,9 @@ pub fn show(ctx: &egui::Context, state: &mut AppState) {
                     let can_login = !state.auth_username.is_empty()
                         && !state.auth_password.is_empty();
 
+                    let label = if setup { "作成してログイン" } else { "ログイン" };
                     let button = egui::Button::new(
-                        egui::RichText::new("ログイン").size(16.0)
+                        egui::RichText::new(label).size(16.0)
                     )
                     .min_size(egui::vec2(f32::INFINITY, 40.0))
                     .fill(if can_login {
@@ -91,30 +132,67 @@ pub fn show(ctx: &egui::Context, state: &mut AppState) {
                         egui::Color32::from_rgb(60, 60, 60)
                     });
 
-                    if ui.add_enabled(can_login, button).clicked() {
-                        // 認証チェック
-                        if state.auth_username == state.expected_username
-                            && state.auth_password == state.expected_password {
-                            state.is_authenticated = true;
-                            state.auth_error = None;
-                            state.status_message = "ログイン成功！".to_string();
-                        } else {
-                            state.auth_error = Some("ユーザー名またはパスワードが正しくありません".to_string());
-                        }
-                    }
-
                     // Enterキーでもログイン可能に
-                    if can_login && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                        if state.auth_username == state.expected_username
-                            && state.auth_password == state.expected_password {
-                            state.is_authenticated = true;
-                            state.auth_error = None;
-                            state.status_message = "ログイン成功！".to_string();
-                        } else {
-                            state.auth_error = Some("ユーザー名またはパスワードが正しくありません".to_string());
+                    let submitted = ui.add_enabled(can_login, button).clicked()
+                        || (can_login && ui.input(|i| i.key_pressed(egui::Key::Enter)));
+                    if submitted {
+                        let result = if setup { create_first_admin(state) } else { log_in(state) };
+                        match result {
+                            Ok(()) => {
+                                state.auth_password.clear();
+                                state.auth_password_confirm.clear();
+                                state.auth_error = None;
+                            }
+                            Err(e) => state.auth_error = Some(e),
                         }
                     }
                 });
         });
     });
 }
+
+/// ユーザーの一覧でパスワードを確かめる（失敗が続くと待ち時間を置き、やがてロックする）
+fn log_in(state: &mut AppState) -> Result<(), String> {
+    let username = state.auth_username.trim().to_string();
+    let now = now_unix_secs();
+    state.login_throttle.check(&username, now)?;
+
+    let role = match state.user_store.authenticate(&username, &state.auth_password) {
+        Ok(role) => role,
+        Err(e) => {
+            let locked = state.login_throttle.record_failure(&username, now);
+            let saved = state.login_throttle.save();
+            audit::record_as(state, &username, AuditAction::LoginFailed, "");
+            if locked {
+                audit::record_as(state, &username, AuditAction::Lockout, "");
+            }
+            saved?;
+            // ロックした直後はロックの案内を出す
+            return Err(state.login_throttle.check(&username, now).err().unwrap_or(e));
+        }
+    };
+    state.status_message = "ログイン成功！".to_string();
+    state.login_throttle.clear(&username);
+    if let Err(e) = state.login_throttle.save() {
+        state.status_message = format!("⚠ {}", e);
+    }
+    state.auth_username = username;
+    state.auth_role = role;
+    state.is_authenticated = true;
+    audit::record(state, AuditAction::Login, "");
+    Ok(())
+}
+
+/// 最初の管理者を作ってログインする
+fn create_first_admin(state: &mut AppState) -> Result<(), String> {
+    if state.auth_password != state.auth_password_confirm {
+        return Err("確認用のパスワードが一致しません".to_string());
+    }
+    let mut store = state.user_store.clone();
+    store.add_user(&state.auth_username, &state.auth_password, Role::Admin)?;
+    store.save()?;
+    state.user_store = store;
+    let username = state.auth_username.trim().to_string();
+    audit::record_as(state, &username, AuditAction::UserAdmin, format!("最初の管理者 {} を作成", username));
+    log_in(state)
+}
@@ -1,8 +1,13 @@
 use eframe::egui;
-use crate::models::{AppState, PendingSendData, PendingRecipient};
-use crate::api::GasClient;
-use crate::utils::{apply_variables, validate_send_safety};
-use crate::file_utils::{extract_company_name_from_path, extract_filename_parts, encode_file_to_base64, get_mime_type};
+use crate::auth::Role;
+use crate::drafts::SavedDraft;
+use crate::models::{AppState, Attachment, HeldSend, MailDraft, PendingSendData, PendingRecipient, RecipientInfo, SendTiming};
+use crate::markdown::{self, Block, Inline};
+use crate::signature;
+use crate::schedule::{format_local, local_timestamp, next_business_day_at};
+use crate::worker::{Job, SendOutcome};
+use crate::utils::{apply_variables, find_linking, find_placeholders, now_unix_secs, parse_address_list, validate_send_safety};
+use crate::file_utils::{extract_company_name_from_path, extract_filename_parts, encode_file_to_base64, get_mime_type, check_file_size};
 
 /// 宛先を選択し、ロック状態を設定する
 /// force_unlock: trueの場合、既存のロックを解除して新しい宛先を設定
@@ -22,24 +27,34 @@ pub fn select_recipient(state: &mut AppState, index: usize, force_unlock: bool)
     if let Some(rec) = state.recipients_master.get(index) {
         if let Some(draft_rec) = state.mail_draft.recipients.get_mut(active_idx) {
             draft_rec.email = rec.email.clone();
+            // 会社ごとの既定の CC・BCC・返信先
+            draft_rec.cc = rec.cc.clone();
+            draft_rec.bcc = rec.bcc.clone();
+            draft_rec.reply_to = rec.reply_to.clone();
             // 宛先をロック
             draft_rec.locked_recipient_id = Some(rec.id.clone());
             draft_rec.locked_company = Some(rec.company.clone());
 
-            // Auto-apply linked template if exists
-            let linked_template = state.linkings_master.iter()
-                .find(|link| link.recipient_id == rec.id)
+            // Auto-apply linked template if exists（この行の添付ファイル名のキーワードで選ぶ）
+            let file_names: Vec<String> = state.mail_draft.attachments.iter()
+                .filter(|a| a.linked_recipient_index == Some(active_idx))
+                .map(|a| a.file_name.clone())
+                .collect();
+            let linked_template = find_linking(&state.linkings_master, &rec.id, &file_names)
                 .and_then(|link| state.templates.iter()
                     .position(|t| t.id == link.template_id));
 
             if let Some(template_idx) = linked_template {
                 state.selected_template_index = Some(template_idx);
                 if let Some(template) = state.templates.get(template_idx) {
-                    state.mail_draft.subject = template.subject.clone();
+                    draft_rec.subject = apply_variables(template.subject.clone(), rec);
+                    draft_rec.subject_template = Some(template.subject.clone());
                     draft_rec.body = apply_variables(template.body.clone(), rec);
                 }
             } else if let Some(t_idx) = state.selected_template_index {
                 if let Some(template) = state.templates.get(t_idx) {
+                    draft_rec.subject = apply_variables(template.subject.clone(), rec);
+                    draft_rec.subject_template = Some(template.subject.clone());
                     draft_rec.body = apply_variables(template.body.clone(), rec);
                 }
             }
@@ -47,6 +62,31 @@ pub fn select_recipient(state: &mut AppState, index: usize, force_unlock: bool)
     }
 }
 
+/// 宛先の件名を全宛先に適用する
+/// テンプレートの件名から書き換えていなければ、変数を差し込む前の件名を宛先ごとに差し込み直す
+fn apply_subject_to_all(state: &mut AppState, source_idx: usize) {
+    let master = &state.recipients_master;
+    let locked = |rec: &RecipientInfo| rec.locked_recipient_id.as_ref()
+        .and_then(|id| master.iter().find(|r| &r.id == id));
+    let render = |template: &str, rec: &RecipientInfo| match locked(rec) {
+        Some(data) => apply_variables(template.to_string(), data),
+        None => template.to_string(),
+    };
+
+    let Some(source) = state.mail_draft.recipients.get(source_idx) else {
+        return;
+    };
+    let template = source.subject_template.clone()
+        .filter(|template| render(template, source) == source.subject)
+        .unwrap_or_else(|| source.subject.clone());
+
+    let subjects: Vec<String> = state.mail_draft.recipients.iter().map(|rec| render(&template, rec)).collect();
+    for (rec, subject) in state.mail_draft.recipients.iter_mut().zip(subjects) {
+        rec.subject = subject;
+        rec.subject_template = Some(template.clone());
+    }
+}
+
 /// 現在アクティブな宛先のロックを解除
 pub fn unlock_recipient(state: &mut AppState) {
     let active_idx = state.active_recipient_index;
@@ -59,18 +99,24 @@ pub fn unlock_recipient(state: &mut AppState) {
 
 fn apply_template(state: &mut AppState, template_idx: usize) {
     if let Some(template) = state.templates.get(template_idx) {
-        state.mail_draft.subject = template.subject.clone();
         let active_idx = state.active_recipient_index;
 
-        let recipient_data = state.selected_recipient_index
-            .and_then(|r_idx| state.recipients_master.get(r_idx).cloned());
+        // アクティブな宛先行にロックされた宛先の値で差し込む
+        let recipient_data = state.mail_draft.recipients.get(active_idx)
+            .and_then(|r| r.locked_recipient_id.as_ref())
+            .and_then(|id| state.recipients_master.iter().find(|r| &r.id == id))
+            .or_else(|| state.selected_recipient_index.and_then(|r_idx| state.recipients_master.get(r_idx)))
+            .cloned();
 
         if let Some(draft_rec) = state.mail_draft.recipients.get_mut(active_idx) {
             if let Some(ref rec) = recipient_data {
+                draft_rec.subject = apply_variables(template.subject.clone(), rec);
                 draft_rec.body = apply_variables(template.body.clone(), rec);
             } else {
+                draft_rec.subject = template.subject.clone();
                 draft_rec.body = template.body.clone();
             }
+            draft_rec.subject_template = Some(template.subject.clone());
         }
         state.status_message = format!("テンプレート「{}」を適用しました", template.name);
     }
@@ -84,141 +130,185 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
             .collect::<Vec<_>>()
     });
 
+    // テンプレート・宛先マスターの編集はテンプレート編集者から
+    let can_edit_master = state.auth_role >= Role::TemplateEditor;
+
     for path in dropped_files {
         let path_str = path.to_string_lossy();
         let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
 
-        if extension == "csv" {
-            // CSV Import Logic with error handling
+        if extension == "csv" && !can_edit_master {
+            state.status_message = "❌ 宛先マスターへのインポートはテンプレート編集者以上の権限が必要です".to_string();
+        } else if extension == "csv" {
+            // CSVを読み込み、マスターへの保存はバックグラウンドで行う
             match std::fs::read_to_string(&path) {
                 Ok(content) => {
-                    let client = GasClient::new(state.gas_url.clone());
-                    let mut imported_count = 0;
-                    let mut failed_count = 0;
-
-                    for line in content.lines().skip(1) { // Skip header
-                        let parts: Vec<&str> = line.split(',').collect();
-                        if parts.len() >= 3 {
-                            let rec = crate::models::RecipientData {
-                                id: (state.recipients_master.len() + 1).to_string(),
+                    // 削除で ID が飛んでいることがあるので、最大の ID の次から振る
+                    let first_id = state.recipients_master.iter()
+                        .filter_map(|r| r.id.parse::<usize>().ok())
+                        .max()
+                        .unwrap_or(0) + 1;
+                    // 7列目以降は見出しを名前にした追加項目（部署・役職など）
+                    let headers: Vec<&str> = content.lines().next()
+                        .map(|line| line.trim_start_matches('\u{feff}').split(',').map(str::trim).collect())
+                        .unwrap_or_default();
+                    let recipients: Vec<crate::models::RecipientData> = content.lines()
+                        .skip(1) // Skip header
+                        .map(|line| line.split(',').collect::<Vec<&str>>())
+                        .filter(|parts| parts.len() >= 3)
+                        .enumerate()
+                        .map(|(i, parts)| {
+                            // 4列目以降は任意（CC, BCC, 返信先）
+                            let optional = |col: usize| parts.get(col).map(|s| s.trim().to_string()).unwrap_or_default();
+                            crate::models::RecipientData {
+                                id: (first_id + i).to_string(),
                                 company: parts[0].trim().to_string(),
                                 name: parts[1].trim().to_string(),
                                 email: parts[2].trim().to_string(),
-                            };
-                            match client.save_recipient(&rec) {
-                                Ok(_) => {
-                                    state.recipients_master.push(rec);
-                                    imported_count += 1;
-                                }
-                                Err(_) => failed_count += 1,
+                                cc: optional(3),
+                                bcc: optional(4),
+                                reply_to: optional(5),
+                                custom_fields: headers.iter()
+                                    .enumerate()
+                                    .skip(6)
+                                    .filter(|(_, h)| !h.is_empty())
+                                    .map(|(col, h)| (h.to_string(), optional(col)))
+                                    .collect(),
                             }
-                        }
-                    }
+                        })
+                        .collect();
 
-                    if failed_count == 0 {
-                        state.status_message = format!("✅ CSVから{}件の宛先をインポートしました", imported_count);
-                    } else {
-                        state.status_message = format!("⚠ CSVインポート: {}件成功, {}件失敗", imported_count, failed_count);
-                    }
+                    state.status_message = format!("CSVの宛先{}件をインポート中...", recipients.len());
+                    state.job_queue.push(Job::ImportRecipients(recipients));
                 }
                 Err(e) => {
                     state.status_message = format!("❌ CSVファイル読み込みエラー: {}", e);
                 }
             }
-        } else {
-            if let (Ok(data), name) = (encode_file_to_base64(&path_str), path.file_name()) {
-                let file_name = name.map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "unknown".to_string());
-                let mime_type = get_mime_type(&file_name);
-
-                // ファイル名から全パーツを抽出（会社名・人名マッチング用）
-                let filename_parts = extract_filename_parts(&path_str);
-                let linked_company = extract_company_name_from_path(&path_str);
-                let active_idx = state.active_recipient_index;
-
-                state.mail_draft.attachments.push(crate::models::Attachment {
-                    file_path: path_str.to_string(),
-                    file_name: file_name.clone(),
-                    enabled: true,
-                    data,
-                    mime_type,
-                    linked_company: linked_company.clone(),
-                    linked_recipient_index: Some(active_idx),  // 現在アクティブな宛先に紐付け
-                });
+        } else if let Err(e) = check_file_size(&path_str) {
+            state.status_message = format!("❌ 添付できません: {}", e);
+        } else if let (Ok(data), name) = (encode_file_to_base64(&path_str), path.file_name()) {
+            let file_name = name.map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "unknown".to_string());
+            let mime_type = get_mime_type(&file_name);
+
+            // ファイル名から全パーツを抽出（会社名・人名マッチング用）
+            let filename_parts = extract_filename_parts(&path_str);
+            let linked_company = extract_company_name_from_path(&path_str);
+            let active_idx = state.active_recipient_index;
 
-                // ファイル名の全パーツを使って宛先をマッチング
-                if !filename_parts.is_empty() {
-                    // 除外すべき一般的な単語（法人格名、書類タイプなど）
-                    let exclude_words = [
-                        "株式会社", "有限会社", "合同会社", "合資会社", "一般社団法人", "公益社団法人",
-                        "(株)", "（株）", "(有)", "（有）", "(合)", "（合）",
-                        "co", "ltd", "inc", "corp", "corporation", "company",
-                        "納品書", "請求書", "見積書", "発注書", "注文書", "領収書",
-                        "契約書", "報告書", "提案書", "仕様書", "明細書", "通知書",
-                    ];
-
-                    // 各パーツを正規化し、除外単語をフィルタ
-                    let parts_normalized: Vec<String> = filename_parts.iter()
-                        .map(|p| p.replace(" ", "").replace("　", "").to_lowercase())
-                        .filter(|p| p.len() >= 2)  // 2文字以上のパーツのみ
-                        .filter(|p| !exclude_words.iter().any(|ex| ex.to_lowercase() == *p))  // 除外単語を除く
-                        .filter(|p| !p.chars().all(|c| c.is_ascii_digit()))  // 数字のみのパーツを除外
-                        .collect();
+            state.mail_draft.attachments.push(crate::models::Attachment {
+                file_path: path_str.to_string(),
+                file_name: file_name.clone(),
+                enabled: true,
+                data,
+                mime_type,
+                linked_company: linked_company.clone(),
+                linked_recipient_index: Some(active_idx),  // 現在アクティブな宛先に紐付け
+            });
 
-                    // Auto-select recipient from filename (全パーツでマッチング)
-                    if let Some(pos) = state.recipients_master.iter()
-                        .position(|r| {
-                            let company_norm = r.company.replace(" ", "").replace("　", "").to_lowercase();
-                            let name_norm = r.name.replace(" ", "").replace("　", "").to_lowercase();
-
-                            // いずれかのパーツがマッチするか確認
-                            parts_normalized.iter().any(|part| {
-                                // 完全一致または部分一致（両方向）
-                                company_norm == *part
-                                    || name_norm == *part
-                                    || (part.len() >= 3 && company_norm.contains(part))
-                                    || (part.len() >= 3 && part.contains(&company_norm) && !company_norm.is_empty())
-                                    || (part.len() >= 3 && name_norm.contains(part))
-                                    || (part.len() >= 3 && part.contains(&name_norm) && !name_norm.is_empty())
-                            })
+            // ファイル名の全パーツを使って宛先をマッチング
+            if !filename_parts.is_empty() {
+                // 除外すべき一般的な単語（法人格名、書類タイプなど）
+                let exclude_words = [
+                    "株式会社", "有限会社", "合同会社", "合資会社", "一般社団法人", "公益社団法人",
+                    "(株)", "（株）", "(有)", "（有）", "(合)", "（合）",
+                    "co", "ltd", "inc", "corp", "corporation", "company",
+                    "納品書", "請求書", "見積書", "発注書", "注文書", "領収書",
+                    "契約書", "報告書", "提案書", "仕様書", "明細書", "通知書",
+                ];
+
+                // 各パーツを正規化し、除外単語をフィルタ
+                let parts_normalized: Vec<String> = filename_parts.iter()
+                    .map(|p| p.replace(" ", "").replace("　", "").to_lowercase())
+                    .filter(|p| p.len() >= 2)  // 2文字以上のパーツのみ
+                    .filter(|p| !exclude_words.iter().any(|ex| ex.to_lowercase() == *p))  // 除外単語を除く
+                    .filter(|p| !p.chars().all(|c| c.is_ascii_digit()))  // 数字のみのパーツを除外
+                    .collect();
+
+                // Auto-select recipient from filename (全パーツでマッチング)
+                if let Some(pos) = state.recipients_master.iter()
+                    .position(|r| {
+                        let company_norm = r.company.replace(" ", "").replace("　", "").to_lowercase();
+                        let name_norm = r.name.replace(" ", "").replace("　", "").to_lowercase();
+
+                        // いずれかのパーツがマッチするか確認
+                        parts_normalized.iter().any(|part| {
+                            // 完全一致または部分一致（両方向）
+                            company_norm == *part
+                                || name_norm == *part
+                                || (part.len() >= 3 && company_norm.contains(part))
+                                || (part.len() >= 3 && part.contains(&company_norm) && !company_norm.is_empty())
+                                || (part.len() >= 3 && name_norm.contains(part))
+                                || (part.len() >= 3 && part.contains(&name_norm) && !name_norm.is_empty())
                         })
-                    {
-                        select_recipient(state, pos, true);  // force_unlock = true for auto-selection
-                        let rec = &state.recipients_master[pos];
-                        let display_name = if rec.company.is_empty() {
-                            rec.name.clone()
-                        } else {
-                            format!("{} ({})", rec.name, rec.company)
-                        };
-                        state.status_message = format!("🔒 ファイル名から宛先を自動選択＆ロック: {}", display_name);
-
-                        // 添付ファイルの紐付けを更新
-                        if let Some(att) = state.mail_draft.attachments.last_mut() {
-                            att.linked_recipient_index = Some(state.active_recipient_index);
+                    })
+                {
+                    // 同じ宛先の行があればそこに、なければ空いている行か新しい行に入れる
+                    let master_id = state.recipients_master[pos].id.clone();
+                    let existing_row = state.mail_draft.recipients.iter()
+                        .position(|r| r.locked_recipient_id.as_deref() == Some(master_id.as_str()));
+                    match existing_row {
+                        Some(row) => state.active_recipient_index = row,
+                        None => {
+                            let active_is_empty = state.mail_draft.recipients.get(state.active_recipient_index)
+                                .is_some_and(|r| r.email.is_empty());
+                            if !active_is_empty {
+                                state.active_recipient_index = state.mail_draft.add_recipient();
+                            }
+                            select_recipient(state, pos, true);  // force_unlock = true for auto-selection
                         }
                     }
+                    let rec = &state.recipients_master[pos];
+                    let display_name = if rec.company.is_empty() {
+                        rec.name.clone()
+                    } else {
+                        format!("{} ({})", rec.name, rec.company)
+                    };
+                    state.status_message = format!("🔒 ファイル名から宛先を自動選択＆ロック: {}", display_name);
+
+                    // 添付ファイルの紐付けを更新
+                    if let Some(att) = state.mail_draft.attachments.last_mut() {
+                        att.linked_recipient_index = Some(state.active_recipient_index);
+                    }
                 }
+            }
 
-                // Auto-select template from filename (use all filename parts)
-                let filename_parts = extract_filename_parts(&path_str);
-                if let Some(template_pos) = state.templates.iter()
-                    .position(|t| {
-                        let template_name_norm = t.name.replace(" ", "").replace("　", "");
-                        filename_parts.iter().any(|part| {
-                            let part_norm = part.replace(" ", "").replace("　", "");
-                            template_name_norm.contains(&part_norm)
-                                || part_norm.contains(&template_name_norm)
-                        })
+            // 宛先の紐付けにファイル名のキーワードがあればそのテンプレート
+            let keyword_template = state.mail_draft.recipients.get(state.active_recipient_index)
+                .and_then(|r| r.locked_recipient_id.as_ref())
+                .and_then(|id| find_linking(&state.linkings_master, id, std::slice::from_ref(&file_name)))
+                .filter(|link| !link.keyword.trim().is_empty())
+                .and_then(|link| state.templates.iter().position(|t| t.id == link.template_id));
+            if let Some(template_pos) = keyword_template {
+                state.selected_template_index = Some(template_pos);
+                apply_template(state, template_pos);
+                let template_name = &state.templates[template_pos].name;
+                state.status_message = format!("紐付けのキーワードからテンプレートを自動選択: {}", template_name);
+                continue;
+            }
+
+            // Auto-select template from filename (use all filename parts)
+            let filename_parts = extract_filename_parts(&path_str);
+            if let Some(template_pos) = state.templates.iter()
+                .position(|t| {
+                    let template_name_norm = t.name.replace(" ", "").replace("　", "");
+                    filename_parts.iter().any(|part| {
+                        let part_norm = part.replace(" ", "").replace("　", "");
+                        template_name_norm.contains(&part_norm)
+                            || part_norm.contains(&template_name_norm)
                     })
-                {
-                    state.selected_template_index = Some(template_pos);
-                    apply_template(state, template_pos);
-                    let template_name = &state.templates[template_pos].name;
-                    state.status_message = format!("ファイル名からテンプレートを自動選択: {}", template_name);
-                }
+                })
+            {
+                state.selected_template_index = Some(template_pos);
+                apply_template(state, template_pos);
+                let template_name = &state.templates[template_pos].name;
+                state.status_message = format!("ファイル名からテンプレートを自動選択: {}", template_name);
             }
         }
     }
 
+    show_recovered_draft_banner(ui, state);
+
     // ========== TOP SECTION: Recipients & Templates (dropdowns) ==========
     // 高さを固定して内部スクロール
     let top_section_height = 120.0;
@@ -233,7 +323,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
             .outer_margin(2.0)
             .rounding(4.0)
             .show(ui, |ui| {
-                ui.set_width(state.col_recipients_width);
+                ui.set_width(state.config.layout.col_recipients_width);
                 ui.set_height(top_section_height - 8.0);
                 ui.vertical(|ui| {
                     // ロック状態を確認
@@ -266,14 +356,8 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                                     .frame(false)
                                     .desired_width(90.0));
                             });
-                        if ui.small_button("➕").on_hover_text("新規宛先").clicked() {
-                            let new_rec = crate::models::RecipientData {
-                                id: (state.recipients_master.len() + 1).to_string(),
-                                company: "新規会社".to_string(),
-                                name: "氏名".to_string(),
-                                email: "".to_string(),
-                            };
-                            state.recipients_master.push(new_rec);
+                        if can_edit_master && ui.small_button("➕").on_hover_text("新規宛先").clicked() {
+                            crate::ui::recipient_panel::open_new(state);
                         }
                     });
 
@@ -316,16 +400,28 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                         .max_height(100.0)
                         .show(ui, |ui| {
                             let mut clicked_idx = None;
+                            let mut edit_idx = None;
                             for (i, label) in &filtered_recipients {
                                 let is_selected = state.selected_recipient_index == Some(*i);
                                 let response = ui.selectable_label(is_selected, label);
                                 if response.clicked() {
                                     clicked_idx = Some(*i);
                                 }
+                                if can_edit_master {
+                                    response.context_menu(|ui| {
+                                        if ui.button("✏ 編集").clicked() {
+                                            edit_idx = Some(*i);
+                                            ui.close_menu();
+                                        }
+                                    });
+                                }
                             }
                             if let Some(i) = clicked_idx {
                                 select_recipient(state, i, false);  // force_unlock = false
                             }
+                            if let Some(i) = edit_idx {
+                                crate::ui::recipient_panel::open_existing(state, i);
+                            }
                             if filtered_recipients.is_empty() {
                                 ui.weak("宛先なし");
                             }
@@ -336,7 +432,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
         // リサイズハンドル（宛先カラム）
         let resize_response = ui.add(egui::Separator::default().vertical().spacing(4.0));
         if resize_response.dragged() {
-            state.col_recipients_width = (state.col_recipients_width + resize_response.drag_delta().x).max(100.0).min(500.0);
+            state.config.layout.col_recipients_width = (state.config.layout.col_recipients_width + resize_response.drag_delta().x).clamp(100.0, 500.0);
         }
         if resize_response.hovered() {
             ui.ctx().set_cursor_icon(egui::CursorIcon::ResizeHorizontal);
@@ -349,7 +445,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
             .outer_margin(2.0)
             .rounding(4.0)
             .show(ui, |ui| {
-                ui.set_width(state.col_templates_width);
+                ui.set_width(state.config.layout.col_templates_width);
                 ui.set_height(top_section_height - 8.0);
                 ui.vertical(|ui| {
                     ui.horizontal(|ui| {
@@ -367,14 +463,8 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                                     .frame(false)
                                     .desired_width(70.0));
                             });
-                        if ui.small_button("➕").on_hover_text("新規テンプレート").clicked() {
-                            let new_temp = crate::models::Template {
-                                id: (state.templates.len() + 1).to_string(),
-                                name: "新しいテンプレート".to_string(),
-                                subject: "".to_string(),
-                                body: "".to_string(),
-                            };
-                            state.templates.push(new_temp);
+                        if can_edit_master && ui.small_button("➕").on_hover_text("新規テンプレート").clicked() {
+                            crate::ui::template_panel::open_new(state);
                         }
                     });
 
@@ -396,16 +486,29 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                         .max_height(100.0)
                         .show(ui, |ui| {
                             let mut apply_idx = None;
+                            let mut edit_idx = None;
                             for (i, label) in &filtered_templates {
                                 let is_selected = state.selected_template_index == Some(*i);
-                                if ui.selectable_label(is_selected, label).clicked() {
+                                let response = ui.selectable_label(is_selected, label);
+                                if response.clicked() {
                                     state.selected_template_index = Some(*i);
                                     apply_idx = Some(*i);
                                 }
+                                if can_edit_master {
+                                    response.context_menu(|ui| {
+                                        if ui.button("✏ 編集").clicked() {
+                                            edit_idx = Some(*i);
+                                            ui.close_menu();
+                                        }
+                                    });
+                                }
                             }
                             if let Some(i) = apply_idx {
                                 apply_template(state, i);
                             }
+                            if let Some(i) = edit_idx {
+                                crate::ui::template_panel::open_existing(state, i);
+                            }
                             if filtered_templates.is_empty() {
                                 ui.weak("テンプレートなし");
                             }
@@ -416,7 +519,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
         // リサイズハンドル（テンプレートカラム）
         let resize_response = ui.add(egui::Separator::default().vertical().spacing(4.0));
         if resize_response.dragged() {
-            state.col_templates_width = (state.col_templates_width + resize_response.drag_delta().x).max(100.0).min(500.0);
+            state.config.layout.col_templates_width = (state.config.layout.col_templates_width + resize_response.drag_delta().x).clamp(100.0, 500.0);
         }
         if resize_response.hovered() {
             ui.ctx().set_cursor_icon(egui::CursorIcon::ResizeHorizontal);
@@ -429,7 +532,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
             .outer_margin(2.0)
             .rounding(4.0)
             .show(ui, |ui| {
-                ui.set_width(state.col_signatures_width);
+                ui.set_width(state.config.layout.col_signatures_width);
                 ui.set_height(top_section_height - 8.0);
                 ui.vertical(|ui| {
                     ui.strong("✍ 署名");
@@ -438,17 +541,16 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                         .id_salt("signatures_dropdown")
                         .max_height(100.0)
                         .show(ui, |ui| {
-                            let mut sel_sig_idx = state.selected_signature_index;
+                            // 選んだ署名はログイン中のユーザーの既定として保存する
+                            let mut clicked = None;
                             for (i, sig) in state.signatures.iter().enumerate() {
-                                if ui.selectable_label(sel_sig_idx == Some(i), &sig.name).clicked() {
-                                    sel_sig_idx = Some(i);
-                                    let client = GasClient::new(state.gas_url.clone());
-                                    let mut settings = std::collections::HashMap::new();
-                                    settings.insert("selected_signature_index".to_string(), i.to_string());
-                                    let _ = client.save_settings(&settings);
+                                if ui.selectable_label(state.selected_signature_index == Some(i), &sig.name).clicked() {
+                                    clicked = Some(i);
                                 }
                             }
-                            state.selected_signature_index = sel_sig_idx;
+                            if let Some(i) = clicked.filter(|i| state.selected_signature_index != Some(*i)) {
+                                signature::remember_default(state, i);
+                            }
                             if state.signatures.is_empty() {
                                 ui.weak("署名なし");
                             }
@@ -471,26 +573,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                 ui.strong("📧 メール編集");
                 ui.add_space(16.0);
 
-                // Recipient Tabs
-                for i in 0..3 {
-                    let has_email = state.mail_draft.recipients.get(i)
-                        .map(|r| !r.email.is_empty())
-                        .unwrap_or(false);
-                    let is_active = state.active_recipient_index == i;
-
-                    let label = if has_email {
-                        format!("宛先{} ✓", i + 1)
-                    } else {
-                        format!("宛先{}", i + 1)
-                    };
-
-                    let button = egui::Button::new(label)
-                        .selected(is_active);
-
-                    if ui.add(button).clicked() {
-                        state.active_recipient_index = i;
-                    }
-                }
+                show_recipient_row_controls(ui, state);
             });
 
             ui.add_space(8.0);
@@ -511,6 +594,8 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                 })
                 .unwrap_or_default();
 
+            let row_count = state.mail_draft.recipients.len();
+            let mut subject_for_all = false;
             if let Some(recipient) = state.mail_draft.recipients.get_mut(active_idx) {
                 // To field
                 ui.horizontal(|ui| {
@@ -536,12 +621,33 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                     }
                 });
 
+                // CC / BCC / 返信先
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    for (label, value, hint) in [
+                        ("CC:", &mut recipient.cc, "カンマ区切り"),
+                        ("BCC:", &mut recipient.bcc, "カンマ区切り"),
+                        ("返信先:", &mut recipient.reply_to, "Reply-To"),
+                    ] {
+                        ui.label(label);
+                        ui.add(egui::TextEdit::singleline(value)
+                            .hint_text(hint)
+                            .desired_width(180.0));
+                        ui.add_space(8.0);
+                    }
+                });
+
                 ui.add_space(8.0);
 
-                // Subject field
+                // Subject field（宛先ごとの件名）
                 ui.horizontal(|ui| {
                     ui.label(egui::RichText::new("件名:").strong());
                     ui.add_space(12.0);
+                    if row_count > 1
+                        && ui.small_button("全宛先に適用").on_hover_text("この件名を他の宛先にも使います（差し込み変数は宛先ごとに置き換えます）").clicked()
+                    {
+                        subject_for_all = true;
+                    }
                     egui::Frame::none()
                         .fill(egui::Color32::from_rgb(50, 80, 120))
                         .stroke(egui::Stroke::new(1.5, egui::Color32::from_rgb(80, 120, 170)))
@@ -549,7 +655,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                         .rounding(4.0)
                         .show(ui, |ui| {
                             ui.visuals_mut().text_cursor.stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 180, 0));
-                            ui.add(egui::TextEdit::singleline(&mut state.mail_draft.subject)
+                            ui.add(egui::TextEdit::singleline(&mut recipient.subject)
                                 .hint_text("件名を入力")
                                 .text_color(egui::Color32::WHITE)
                                 .frame(false)
@@ -560,7 +666,12 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                 ui.add_space(8.0);
 
                 // Body field
-                ui.label(egui::RichText::new("本文:").strong());
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("本文:").strong());
+                    ui.add_space(12.0);
+                    ui.checkbox(&mut state.mail_draft.use_markdown, "HTMLメール（Markdown）")
+                        .on_hover_text("**太字**、- 箇条書き、[リンク](https://...)、| 表 | が使えます");
+                });
                 ui.add_space(4.0);
                 egui::Frame::none()
                     .fill(egui::Color32::from_rgb(50, 80, 120))
@@ -571,7 +682,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                         ui.visuals_mut().text_cursor.stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 180, 0));
                         egui::ScrollArea::vertical()
                             .id_salt("body_editor")
-                            .max_height(state.body_editor_height)
+                            .max_height(state.config.layout.body_editor_height)
                             .show(ui, |ui| {
                                 ui.add(egui::TextEdit::multiline(&mut recipient.body)
                                     .hint_text("本文を入力...")
@@ -585,12 +696,22 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                 // 本文エディタのリサイズハンドル
                 let resize_response = ui.add(egui::Separator::default().horizontal().spacing(4.0));
                 if resize_response.dragged() {
-                    state.body_editor_height = (state.body_editor_height + resize_response.drag_delta().y).max(50.0).min(400.0);
+                    state.config.layout.body_editor_height = (state.config.layout.body_editor_height + resize_response.drag_delta().y).clamp(50.0, 400.0);
                 }
                 if resize_response.hovered() {
                     ui.ctx().set_cursor_icon(egui::CursorIcon::ResizeVertical);
                 }
 
+                if state.mail_draft.use_markdown {
+                    ui.collapsing("👁 プレビュー", |ui| {
+                        egui::Frame::none()
+                            .fill(ui.visuals().extreme_bg_color)
+                            .inner_margin(8.0)
+                            .rounding(4.0)
+                            .show(ui, |ui| show_markdown_preview(ui, &recipient.body));
+                    });
+                }
+
                 // Signature preview
                 if let Some(sig_idx) = state.selected_signature_index {
                     if let Some(sig) = state.signatures.get(sig_idx) {
@@ -601,12 +722,15 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                                 .inner_margin(8.0)
                                 .rounding(4.0)
                                 .show(ui, |ui| {
-                                    ui.label(&sig.content);
+                                    ui.label(signature::render(&sig.content, signature::sender_name(state)));
                                 });
                         });
                     }
                 }
             }
+            if subject_for_all {
+                apply_subject_to_all(state, active_idx);
+            }
         });
 
     ui.add_space(8.0);
@@ -641,6 +765,9 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                                                 ui.horizontal(|ui| {
                                                     ui.checkbox(&mut att.enabled, "");
                                                     ui.label(&att.file_name);
+                                                    if let Some(linked_idx) = att.linked_recipient_index {
+                                                        ui.weak(format!("→宛先{}", linked_idx + 1));
+                                                    }
                                                     if ui.small_button("✕").on_hover_text("削除").clicked() {
                                                         to_remove = Some(i);
                                                     }
@@ -671,13 +798,22 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
             let button = egui::Button::new(egui::RichText::new(send_label).size(16.0))
                 .min_size(egui::vec2(100.0, 36.0));
 
-            if ui.add_enabled(valid_count > 0, button).clicked() {
+            let can_send = valid_count > 0 && !state.is_sending();
+            if ui.add_enabled(can_send, button).clicked() {
                 // 送信前検証を実行
-                let errors = validate_send_safety(
+                let mut errors = validate_send_safety(
                     &state.mail_draft.recipients,
                     &state.recipients_master,
                     &state.mail_draft.attachments,
                 );
+                let signature = state.selected_signature_index
+                    .and_then(|idx| state.signatures.get(idx))
+                    .map(|sig| signature::render(&sig.content, signature::sender_name(state)))
+                    .unwrap_or_default();
+                let unresolved = find_placeholders(&signature);
+                if !unresolved.is_empty() {
+                    errors.push(format!("署名に値のない変数があります: {}（送信者名は「✍ 署名」タブで設定）", unresolved.join(", ")));
+                }
 
                 if !errors.is_empty() {
                     // 検証エラーがある場合
@@ -686,10 +822,7 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                 } else {
                     // 検証OK → 確認ダイアログを表示
                     // PendingSendDataを作成
-                    let signature = state.selected_signature_index
-                        .and_then(|idx| state.signatures.get(idx))
-                        .map(|sig| format!("\n\n{}", sig.content))
-                        .unwrap_or_default();
+                    let signature = if signature.is_empty() { signature } else { format!("\n\n{}", signature) };
 
                     let pending_recipients: Vec<PendingRecipient> = state.mail_draft.recipients.iter()
                         .enumerate()
@@ -698,16 +831,30 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
                             let recipient_data = rec.locked_recipient_id.as_ref()
                                 .and_then(|id| state.recipients_master.iter().find(|r| &r.id == id));
 
-                            let attachments: Vec<String> = state.mail_draft.attachments.iter()
+                            // 確認ダイアログで表示した添付ファイルをそのまま送信する
+                            let attachments: Vec<Attachment> = state.mail_draft.attachments.iter()
                                 .filter(|a| a.enabled && a.linked_recipient_index == Some(idx))
-                                .map(|a| a.file_name.clone())
+                                .cloned()
                                 .collect();
 
+                            let full_body = format!("{}{}", rec.body, signature);
+                            let (body, html_body) = if state.mail_draft.use_markdown {
+                                (markdown::to_plain_text(&full_body), Some(markdown::to_html(&full_body)))
+                            } else {
+                                (full_body, None)
+                            };
+
                             PendingRecipient {
+                                draft_index: idx,
                                 email: rec.email.clone(),
+                                cc: parse_address_list(&rec.cc),
+                                bcc: parse_address_list(&rec.bcc),
+                                reply_to: rec.reply_to.trim().to_string(),
                                 company: recipient_data.map(|r| r.company.clone()).unwrap_or_default(),
                                 name: recipient_data.map(|r| r.name.clone()).unwrap_or_default(),
-                                body: format!("{}{}", rec.body, signature),
+                                subject: rec.subject.trim().to_string(),
+                                body,
+                                html_body,
                                 attachments,
                             }
                         })
@@ -715,18 +862,27 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
 
                     state.pending_send_data = Some(PendingSendData {
                         recipients: pending_recipients,
-                        subject: state.mail_draft.subject.clone(),
+                        sent_by: state.auth_username.clone(),
+                        ..Default::default()
                     });
 
                     state.show_send_confirmation = true;
                     state.confirmation_company_input = String::new();
                     state.confirmation_checked = false;
+                    state.send_timing = SendTiming::Now;
+                    if state.schedule_date_input.is_empty() {
+                        let today = chrono::Local::now().date_naive();
+                        state.schedule_date_input = state.calendar.next_business_day(today).to_string();
+                    }
                     state.validation_errors.clear();
                 }
             }
         });
     });
 
+    ui.add_space(4.0);
+    show_draft_controls(ui, state);
+
     // 検証エラー表示
     if !state.validation_errors.is_empty() {
         ui.add_space(8.0);
@@ -748,6 +904,31 @@ pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
             });
     }
 
+    show_held_send_banner(ui, state);
+
+    // 送信失敗した宛先の表示
+    if !state.send_failures.is_empty() {
+        ui.add_space(8.0);
+        egui::Frame::none()
+            .fill(egui::Color32::from_rgb(80, 30, 30))
+            .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(200, 80, 80)))
+            .inner_margin(12.0)
+            .rounding(6.0)
+            .show(ui, |ui| {
+                ui.label(egui::RichText::new("❌ 送信できなかった宛先").strong().color(egui::Color32::from_rgb(255, 150, 150)));
+                ui.label(egui::RichText::new("下書きに残しています。内容を確認して再送してください。")
+                    .color(egui::Color32::from_rgb(255, 200, 200)));
+                ui.add_space(8.0);
+                for failure in &state.send_failures {
+                    ui.label(egui::RichText::new(failure).color(egui::Color32::from_rgb(255, 200, 200)));
+                }
+                ui.add_space(8.0);
+                if ui.button("閉じる").clicked() {
+                    state.send_failures.clear();
+                }
+            });
+    }
+
     // 送信前確認ダイアログ
     if state.show_send_confirmation {
         show_send_confirmation_dialog(ui, state);
@@ -777,36 +958,60 @@ fn show_send_confirmation_dialog(ui: &mut egui::Ui, state: &mut AppState) {
                 ui.separator();
                 ui.add_space(8.0);
 
-                // 各宛先の情報を表示
-                for (i, recipient) in pending.recipients.iter().enumerate() {
-                    egui::Frame::none()
-                        .fill(ui.visuals().extreme_bg_color)
-                        .inner_margin(8.0)
-                        .rounding(4.0)
-                        .show(ui, |ui| {
-                            ui.label(egui::RichText::new(format!("【宛先{}】", i + 1)).strong());
-                            ui.horizontal(|ui| {
-                                ui.label("会社名:");
-                                ui.label(egui::RichText::new(&recipient.company)
-                                    .color(egui::Color32::from_rgb(100, 200, 255)));
-                            });
-                            ui.horizontal(|ui| {
-                                ui.label("氏名:");
-                                ui.label(&recipient.name);
-                            });
-                            ui.horizontal(|ui| {
-                                ui.label("メール:");
-                                ui.label(&recipient.email);
-                            });
-                            if !recipient.attachments.is_empty() {
-                                ui.horizontal(|ui| {
-                                    ui.label("添付:");
-                                    ui.label(recipient.attachments.join(", "));
+                // 各宛先の情報を表示（宛先が多い場合はスクロール）
+                egui::ScrollArea::vertical()
+                    .id_salt("confirm_recipients")
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        for (i, recipient) in pending.recipients.iter().enumerate() {
+                            egui::Frame::none()
+                                .fill(ui.visuals().extreme_bg_color)
+                                .inner_margin(8.0)
+                                .rounding(4.0)
+                                .show(ui, |ui| {
+                                    ui.label(egui::RichText::new(format!("【宛先{}】", i + 1)).strong());
+                                    ui.horizontal(|ui| {
+                                        ui.label("会社名:");
+                                        ui.label(egui::RichText::new(&recipient.company)
+                                            .color(egui::Color32::from_rgb(100, 200, 255)));
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.label("氏名:");
+                                        ui.label(&recipient.name);
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.label("メール:");
+                                        ui.label(&recipient.email);
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.label("件名:");
+                                        ui.label(egui::RichText::new(&recipient.subject).strong());
+                                    });
+                                    for (label, addresses) in [("CC:", recipient.cc.join(", ")), ("BCC:", recipient.bcc.join(", ")), ("返信先:", recipient.reply_to.clone())] {
+                                        if !addresses.is_empty() {
+                                            ui.horizontal(|ui| {
+                                                ui.label(label);
+                                                ui.label(egui::RichText::new(addresses).color(egui::Color32::from_rgb(255, 200, 100)));
+                                            });
+                                        }
+                                    }
+                                    if !recipient.attachments.is_empty() {
+                                        ui.horizontal(|ui| {
+                                            ui.label("添付:");
+                                            let names: Vec<&str> = recipient.attachments.iter()
+                                                .map(|a| a.file_name.as_str())
+                                                .collect();
+                                            ui.label(names.join(", "));
+                                        });
+                                    }
                                 });
-                            }
-                        });
-                    ui.add_space(4.0);
-                }
+                            ui.add_space(4.0);
+                        }
+                    });
+
+                ui.add_space(12.0);
+
+                show_send_timing(ui, state);
 
                 ui.add_space(12.0);
 
@@ -826,8 +1031,13 @@ fn show_send_confirmation_dialog(ui: &mut egui::Ui, state: &mut AppState) {
 
                     let can_send = state.confirmation_checked;
 
+                    let send_label = if state.send_timing == SendTiming::Now {
+                        "📧 送信する"
+                    } else {
+                        "⏰ 予約する"
+                    };
                     let send_button = egui::Button::new(
-                        egui::RichText::new("📧 送信する").size(14.0)
+                        egui::RichText::new(send_label).size(14.0)
                     ).fill(if can_send {
                         egui::Color32::from_rgb(50, 120, 50)
                     } else {
@@ -849,31 +1059,74 @@ fn show_send_confirmation_dialog(ui: &mut egui::Ui, state: &mut AppState) {
         state.confirmation_checked = false;
     }
 
-    // 送信処理
-    if should_send {
-        if let Some(ref pending) = state.pending_send_data {
-            let client = GasClient::new(state.gas_url.clone());
-
-            let items: Vec<(String, String, String)> = pending.recipients.iter()
-                .map(|rec| (
-                    rec.email.clone(),
-                    pending.subject.clone(),
-                    rec.body.clone()
-                ))
-                .collect();
-
-            let items_ref: Vec<(&str, &str, &str)> = items.iter()
-                .map(|(to, sub, body)| (to.as_str(), sub.as_str(), body.as_str()))
-                .collect();
-
-            match client.send_batch_mail(items_ref, &state.mail_draft.attachments) {
-                Ok(_) => {
-                    state.status_message = "✅ すべて送信完了しました！".to_string();
-                    // 送信成功後、画面をリセットして次の送信に備える
+    // 予約送信（送信日時に送信待ちへ移される）
+    if should_send && state.send_timing != SendTiming::Now {
+        let now = now_unix_secs();
+        match resolve_send_at(state, now) {
+            Ok(send_at) => {
+                if let Some(pending) = state.pending_send_data.take() {
+                    let count = pending.recipients.len();
+                    state.schedule.add(pending, send_at, now);
+                    state.status_message = match state.schedule.save() {
+                        Ok(()) => format!("✅ {} に{}件の送信を予約しました", format_local(send_at), count),
+                        Err(e) => format!("❌ {}", e),
+                    };
+                    state.send_failures.clear();
                     reset_mail_draft(state);
                 }
-                Err(e) => state.status_message = format!("❌ 送信エラー: {}", e),
+                state.show_send_confirmation = false;
+                state.pending_send_data = None;
+                state.confirmation_company_input.clear();
+                state.confirmation_checked = false;
             }
+            Err(e) => state.status_message = format!("⚠️ {}", e),
+        }
+        return;
+    }
+
+    // 取り消し猶予つきの送信（猶予が過ぎる時刻で送信待ちに保存し、猶予が過ぎたら release_held_send で送信する）
+    if should_send && state.undo_send_secs > 0 {
+        if let Some(pending) = state.pending_send_data.take() {
+            // 前の送信がまだ猶予中なら先に確定する
+            release_held_send(state);
+
+            let now = now_unix_secs();
+            let item_ids: Vec<String> = state.outbox.enqueue(&pending, now).into_iter().map(|i| i.id).collect();
+            state.outbox.hold_until(&item_ids, now + u64::from(state.undo_send_secs));
+            state.status_message = match state.outbox.save() {
+                Ok(()) => format!("{}秒後に{}件を送信します", state.undo_send_secs, pending.recipients.len()),
+                Err(e) => format!("⚠ {}（送信は続行します）", e),
+            };
+            state.send_failures.clear();
+            state.held_send = Some(HeldSend {
+                pending,
+                item_ids,
+                release_at: std::time::Instant::now() + std::time::Duration::from_secs(state.undo_send_secs.into()),
+                draft: state.mail_draft.clone(),
+                active_recipient_index: state.active_recipient_index,
+                selected_recipient_index: state.selected_recipient_index,
+                selected_template_index: state.selected_template_index,
+            });
+            reset_mail_draft(state);
+        }
+
+        state.show_send_confirmation = false;
+        state.pending_send_data = None;
+        state.confirmation_company_input.clear();
+        state.confirmation_checked = false;
+        return;
+    }
+
+    // 送信処理（送信待ちに保存してからバックグラウンドで送信し、結果は apply_send_outcomes で反映する）
+    if should_send {
+        if let Some(pending) = state.pending_send_data.take() {
+            let items = state.outbox.enqueue(&pending, now_unix_secs());
+            state.status_message = match state.outbox.save() {
+                Ok(()) => format!("{}件の送信を開始します...", pending.recipients.len()),
+                Err(e) => format!("⚠ {}（送信は続行します）", e),
+            };
+            state.send_failures.clear();
+            state.job_queue.push(Job::SendOutbox { items, pending: Some(pending), held_draft: None });
         }
 
         // ダイアログを閉じる
@@ -884,18 +1137,566 @@ fn show_send_confirmation_dialog(ui: &mut egui::Ui, state: &mut AppState) {
     }
 }
 
-/// 送信後にメール作成画面をリセット
-fn reset_mail_draft(state: &mut AppState) {
-    // 宛先をクリア
-    for recipient in &mut state.mail_draft.recipients {
-        recipient.email.clear();
-        recipient.body.clear();
-        recipient.locked_recipient_id = None;
-        recipient.locked_company = None;
+/// 宛先行の一覧（横スクロール）と追加・並べ替え・削除ボタン
+fn show_recipient_row_controls(ui: &mut egui::Ui, state: &mut AppState) {
+    let row_count = state.mail_draft.recipients.len();
+    let active_idx = state.active_recipient_index;
+
+    let mut add = false;
+    let mut move_to = None;
+    let mut remove = false;
+
+    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+        if ui.add_enabled(row_count > 1, egui::Button::new("🗑").small()).on_hover_text("この宛先を削除").clicked() {
+            remove = true;
+        }
+        if ui.add_enabled(active_idx + 1 < row_count, egui::Button::new("▶").small()).on_hover_text("後ろへ移動").clicked() {
+            move_to = Some(active_idx + 1);
+        }
+        if ui.add_enabled(active_idx > 0, egui::Button::new("◀").small()).on_hover_text("前へ移動").clicked() {
+            move_to = Some(active_idx - 1);
+        }
+        if ui.small_button("➕").on_hover_text("宛先を追加").clicked() {
+            add = true;
+        }
+        ui.weak(format!("{}件", row_count));
+
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
+            egui::ScrollArea::horizontal()
+                .id_salt("recipient_rows")
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for (i, rec) in state.mail_draft.recipients.iter().enumerate() {
+                            let label = match (&rec.locked_company, rec.email.is_empty()) {
+                                (Some(company), _) if !company.is_empty() => format!("{}. {} ✓", i + 1, company),
+                                (_, false) => format!("{}. {} ✓", i + 1, rec.email),
+                                _ => format!("宛先{}", i + 1),
+                            };
+                            let response = ui.add(egui::Button::new(label).selected(active_idx == i));
+                            if response.clicked() {
+                                state.active_recipient_index = i;
+                            }
+                            if i == active_idx && (add || move_to.is_some()) {
+                                response.scroll_to_me(None);
+                            }
+                        }
+                    });
+                });
+        });
+    });
+
+    if add {
+        state.active_recipient_index = state.mail_draft.add_recipient();
+    } else if let Some(target) = move_to {
+        state.mail_draft.swap_recipients(active_idx, target);
+        state.active_recipient_index = target;
+    } else if remove {
+        let removed = state.mail_draft.remove_recipient(active_idx);
+        state.active_recipient_index = active_idx.min(state.mail_draft.recipients.len() - 1);
+        state.status_message = if removed.is_empty() {
+            format!("宛先{}を削除しました", active_idx + 1)
+        } else {
+            format!("宛先{}と添付ファイル{}件を削除しました", active_idx + 1, removed.len())
+        };
     }
+}
 
-    // 件名をクリア
-    state.mail_draft.subject.clear();
+/// Markdown 本文のプレビュー（送信される HTML と同じ解析結果を表示する）
+fn show_markdown_preview(ui: &mut egui::Ui, md: &str) {
+    fn inline_row(ui: &mut egui::Ui, inlines: &[Inline]) {
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            for inline in inlines {
+                match inline {
+                    Inline::Text(text) => { ui.label(text); }
+                    Inline::Bold(text) => { ui.label(egui::RichText::new(text).strong()); }
+                    Inline::Link { text, url } if markdown::is_safe_url(url) => { ui.hyperlink_to(text, url); }
+                    Inline::Link { text, url } => { ui.label(format!("{} ({})", text, url)); }
+                }
+            }
+        });
+    }
+
+    for (i, block) in markdown::parse(md).iter().enumerate() {
+        match block {
+            Block::Paragraph(lines) => {
+                for line in lines {
+                    inline_row(ui, line);
+                }
+            }
+            Block::List { ordered, items } => {
+                for (n, item) in items.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(if *ordered { format!("{}.", n + 1) } else { "•".to_string() });
+                        inline_row(ui, item);
+                    });
+                }
+            }
+            Block::Table { header, rows } => {
+                egui::Grid::new(("markdown_table", i))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for cell in header {
+                            ui.strong(cell_text(cell));
+                        }
+                        ui.end_row();
+                        for row in rows {
+                            for cell in row {
+                                inline_row(ui, cell);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+        }
+        ui.add_space(6.0);
+    }
+}
+
+/// 表の見出しセルを文字列に戻す
+fn cell_text(cell: &[Inline]) -> String {
+    cell.iter()
+        .map(|inline| match inline {
+            Inline::Text(text) | Inline::Bold(text) => text.clone(),
+            Inline::Link { text, .. } => text.clone(),
+        })
+        .collect()
+}
+
+/// 送信取り消しの猶予の上限（秒）
+pub const MAX_UNDO_SEND_SECS: u32 = 120;
+
+pub fn clamp_undo_send_secs(secs: u32) -> u32 {
+    secs.min(MAX_UNDO_SEND_SECS)
+}
+
+/// 猶予中の送信を確定して送信する
+/// 下書きはすでにリセット済みなので、送信時の下書きを渡して失敗した宛先を下書きに戻せるようにする
+pub fn release_held_send(state: &mut AppState) {
+    let Some(held) = state.held_send.take() else {
+        return;
+    };
+    let now = now_unix_secs();
+    for id in &held.item_ids {
+        state.outbox.retry_now(id, now);
+    }
+    // 送信待ちタブから削除された分は送らない
+    let items: Vec<_> = state.outbox.items().iter()
+        .filter(|i| held.item_ids.contains(&i.id))
+        .cloned()
+        .collect();
+    if items.is_empty() {
+        return;
+    }
+    state.status_message = format!("{}件の送信を開始します...", items.len());
+    state.job_queue.push(Job::SendOutbox { items, pending: Some(held.pending), held_draft: Some(held.draft) });
+}
+
+/// 猶予中の送信を取り消し、送信前の下書き（ロックした宛先・添付ファイルを含む）に戻す
+pub fn undo_held_send(state: &mut AppState) {
+    let Some(held) = state.held_send.take() else {
+        return;
+    };
+    for id in &held.item_ids {
+        state.outbox.remove(id);
+    }
+    state.mail_draft = held.draft;
+    state.active_recipient_index = held.active_recipient_index;
+    state.selected_recipient_index = held.selected_recipient_index;
+    state.selected_template_index = held.selected_template_index;
+    state.status_message = match state.outbox.save() {
+        Ok(()) => "送信を取り消しました。下書きに戻しています".to_string(),
+        Err(e) => format!("❌ {}", e),
+    };
+}
+
+/// ログアウトするとき、作成中の下書きを次にログインしたユーザーに見せず、同じユーザーが復元できるように残す
+/// 取り消し猶予中の送信は送らずに取り消し、その下書きも残す
+pub fn set_aside_draft_for_logout(state: &mut AppState) {
+    let now = now_unix_secs();
+    let current = SavedDraft::capture("", &state.mail_draft, state.active_recipient_index, &state.auth_username, now);
+    state.recovered_drafts.push(current);
+    if state.held_send.is_some() {
+        undo_held_send(state);
+        // 取り消した送信の下書きを先に出す
+        let held = SavedDraft::capture("", &state.mail_draft, state.active_recipient_index, &state.auth_username, now + 1);
+        state.recovered_drafts.push(held);
+        state.status_message = "ログアウトしたため送信を取り消しました。次にログインしたときに下書きを復元できます".to_string();
+    }
+    reset_mail_draft(state);
+    if let Err(e) = state.recovered_drafts.save() {
+        state.status_message = format!("❌ {}", e);
+    }
+}
+
+/// 送信取り消しのカウントダウン表示
+fn show_held_send_banner(ui: &mut egui::Ui, state: &mut AppState) {
+    let Some(held) = &state.held_send else {
+        return;
+    };
+    let remaining = held.release_at.saturating_duration_since(std::time::Instant::now());
+    let recipients: Vec<String> = held.pending.recipients.iter()
+        .map(|r| format!("{}（{}）", r.subject, r.email))
+        .collect();
+
+    let mut undo = false;
+    let mut send_now = false;
+    ui.add_space(8.0);
+    egui::Frame::none()
+        .fill(egui::Color32::from_rgb(30, 50, 80))
+        .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(100, 150, 220)))
+        .inner_margin(12.0)
+        .rounding(6.0)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("⏳ {}秒後に送信します", remaining.as_secs() + 1))
+                    .strong()
+                    .color(egui::Color32::from_rgb(150, 200, 255)));
+                ui.label(recipients.join(", "));
+            });
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                if ui.button("↩ 送信を取り消して下書きに戻す").clicked() {
+                    undo = true;
+                }
+                if ui.button("今すぐ送信").clicked() {
+                    send_now = true;
+                }
+            });
+        });
+
+    if undo {
+        undo_held_send(state);
+    } else if send_now {
+        release_held_send(state);
+    }
+}
+
+/// 送信タイミングの選択（今すぐ・翌営業日・日時指定）
+fn show_send_timing(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.horizontal(|ui| {
+        ui.label("送信タイミング:");
+        ui.radio_value(&mut state.send_timing, SendTiming::Now, "今すぐ");
+        ui.radio_value(&mut state.send_timing, SendTiming::NextBusinessDay, "翌営業日");
+        ui.radio_value(&mut state.send_timing, SendTiming::At, "日時を指定");
+    });
+
+    if state.send_timing == SendTiming::Now {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        if state.send_timing == SendTiming::At {
+            ui.label("日付:");
+            ui.add(egui::TextEdit::singleline(&mut state.schedule_date_input)
+                .hint_text("2026-01-05")
+                .desired_width(90.0));
+        }
+        ui.label("時刻:");
+        ui.add(egui::DragValue::new(&mut state.schedule_hour).range(0..=23));
+        ui.label(":");
+        ui.add(egui::DragValue::new(&mut state.schedule_minute).range(0..=59).custom_formatter(|v, _| format!("{:02}", v)));
+    });
+
+    match resolve_send_at(state, now_unix_secs()) {
+        Ok(send_at) => ui.label(egui::RichText::new(format!("→ {} に送信します", format_local(send_at)))
+            .color(egui::Color32::from_rgb(100, 200, 255))),
+        Err(e) => ui.label(egui::RichText::new(e).color(egui::Color32::from_rgb(255, 150, 150))),
+    };
+}
+
+/// 選択した送信タイミングの送信日時（UNIX秒）
+fn resolve_send_at(state: &AppState, now: u64) -> Result<u64, String> {
+    let send_at = match state.send_timing {
+        SendTiming::Now => return Ok(now),
+        SendTiming::NextBusinessDay => {
+            next_business_day_at(&state.calendar, now, state.schedule_hour, state.schedule_minute)
+        }
+        SendTiming::At => {
+            let date = chrono::NaiveDate::parse_from_str(state.schedule_date_input.trim(), "%Y-%m-%d")
+                .map_err(|_| "日付を YYYY-MM-DD の形式で入力してください".to_string())?;
+            if !state.calendar.is_business_day(date) {
+                let reason = state.calendar.holiday_name(date).filter(|n| !n.is_empty()).unwrap_or("休業日");
+                return Err(format!("{} は{}です", date, reason));
+            }
+            local_timestamp(date, state.schedule_hour, state.schedule_minute)
+        }
+    };
+
+    match send_at {
+        Some(send_at) if send_at > now => Ok(send_at),
+        Some(_) => Err("過去の日時は指定できません".to_string()),
+        None => Err("日時を解釈できません".to_string()),
+    }
+}
+
+/// 送信結果を下書きに反映する
+/// 送信できた宛先と送信待ちに残した宛先はクリアし、失敗・キャンセルした宛先は再送できるように残す
+/// held_draft（取り消し猶予を経た送信の、送信時の下書き）があれば、失敗した宛先をそこから下書きに戻す
+pub fn apply_send_outcomes(state: &mut AppState, pending: &PendingSendData, outcomes: &[SendOutcome], held_draft: Option<&MailDraft>) {
+    let queued = outcomes.iter().filter(|o| **o == SendOutcome::Queued).count();
+    let sent_count = outcomes.iter().filter(|o| **o == SendOutcome::Sent).count();
+
+    if outcomes.iter().all(|o| matches!(o, SendOutcome::Sent | SendOutcome::Queued)) {
+        state.status_message = if queued == 0 {
+            "✅ すべて送信完了しました！".to_string()
+        } else {
+            format!("⚠ {}件送信, {}件は通信障害のため送信待ちに入れました。自動で再送します", sent_count, queued)
+        };
+        state.send_failures.clear();
+        // 送信成功後、画面をリセットして次の送信に備える（猶予中に書き始めた下書きはそのまま）
+        if held_draft.is_none() {
+            reset_mail_draft(state);
+        }
+        return;
+    }
+
+    let mut cleared = Vec::new();
+    let mut unsent_rows = Vec::new();
+    let mut failures = Vec::new();
+    let mut cancelled = false;
+    for (rec, outcome) in pending.recipients.iter().zip(outcomes) {
+        match outcome {
+            SendOutcome::Sent | SendOutcome::Queued => cleared.push((rec.draft_index, rec.email.as_str())),
+            // 送信済みの行を詰めると番号がずれるので、宛先アドレスで示す
+            SendOutcome::Failed(error) => {
+                unsent_rows.push(rec.draft_index);
+                failures.push(format!("{}: {}", rec.email, error));
+            }
+            SendOutcome::Cancelled => {
+                cancelled = true;
+                unsent_rows.push(rec.draft_index);
+                failures.push(format!("{}: キャンセルしたため未送信", rec.email));
+            }
+        }
+    }
+
+    let mut summary = format!("{}件送信成功, {}件未送信。未送信の宛先は下書きに残しています", sent_count, failures.len());
+    if queued > 0 {
+        summary.push_str(&format!("（{}件は送信待ちで自動再送）", queued));
+    }
+    state.status_message = if cancelled {
+        format!("⚠ 送信をキャンセルしました: {}", summary)
+    } else {
+        format!("⚠ {}", summary)
+    };
+    state.send_failures = failures;
+    match held_draft {
+        Some(held) => restore_unsent_recipients(state, held, &unsent_rows),
+        None => clear_sent_recipients(state, &cleared),
+    }
+}
+
+/// 取り消し猶予を経た送信で送れなかった宛先を、送信時の下書きから戻す
+/// 猶予中に次のメールを書き始めていれば、その後ろに追加する
+fn restore_unsent_recipients(state: &mut AppState, held: &MailDraft, rows: &[usize]) {
+    if state.mail_draft.is_blank() {
+        state.mail_draft = MailDraft { recipients: Vec::new(), attachments: Vec::new(), use_markdown: held.use_markdown };
+    }
+    let first_restored = state.mail_draft.recipients.len();
+    state.mail_draft.append_rows_from(held, rows);
+    if state.mail_draft.recipients.is_empty() {
+        state.mail_draft.recipients.push(RecipientInfo::default());
+    }
+    state.active_recipient_index = first_restored.min(state.mail_draft.recipients.len() - 1);
+}
+
+/// 送信できた宛先の行とその添付ファイルだけを下書きから取り除く
+/// 送信中に宛先が書き換えられていた場合は、その宛先には触れない
+fn clear_sent_recipients(state: &mut AppState, sent: &[(usize, &str)]) {
+    let mut sent_rows: Vec<usize> = sent.iter()
+        .filter(|&&(idx, email)| state.mail_draft.recipients.get(idx).is_some_and(|r| r.email == email))
+        .map(|&(idx, _)| idx)
+        .collect();
+
+    // 後ろから削除して、前の行の位置をずらさない
+    sent_rows.sort_unstable_by(|a, b| b.cmp(a));
+    for idx in sent_rows {
+        state.mail_draft.remove_recipient(idx);
+    }
+
+    // 残った宛先のうち最初のものをアクティブにする
+    state.active_recipient_index = state.mail_draft.recipients.iter()
+        .position(|r| !r.email.is_empty())
+        .unwrap_or(0);
+}
+
+/// 保存した下書きを開く（作成中の内容は置き換わる）
+pub fn open_draft(state: &mut AppState, saved: &SavedDraft) {
+    let (draft, missing) = saved.restore();
+    state.mail_draft = draft;
+    state.active_recipient_index = saved.active_recipient_index.min(state.mail_draft.recipients.len() - 1);
+    state.selected_recipient_index = state.mail_draft.recipients[state.active_recipient_index]
+        .locked_recipient_id.as_ref()
+        .and_then(|id| state.recipients_master.iter().position(|r| &r.id == id));
+    state.selected_template_index = None;
+    state.status_message = if missing.is_empty() {
+        format!("下書きを開きました（{}保存）", format_local(saved.saved_at))
+    } else {
+        format!("⚠ 下書きを開きましたが、見つからない添付ファイルを外しました: {}", missing.join(", "))
+    };
+}
+
+/// 作成中の下書きに名前を付けて保存する
+pub fn save_named_draft(state: &mut AppState, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("下書きの名前を入力してください".to_string());
+    }
+    let saved = SavedDraft::capture(name, &state.mail_draft, state.active_recipient_index, &state.auth_username, now_unix_secs());
+    state.drafts.put(saved);
+    state.drafts.save()
+}
+
+/// 作成中の下書きがあるときの終了確認
+pub fn show_exit_confirmation(ctx: &egui::Context, state: &mut AppState) {
+    let mut save_and_exit = false;
+    let mut exit = false;
+    let mut cancel = false;
+    egui::Window::new("終了の確認")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("作成中のメールがあります。終了すると入力した内容は失われます。");
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                save_and_exit = ui.button("💾 下書きに保存して終了").clicked();
+                exit = ui.button("保存せずに終了").clicked();
+                cancel = ui.button("キャンセル").clicked();
+            });
+        });
+
+    if save_and_exit {
+        let name = match state.draft_name_input.trim() {
+            "" => format!("終了時の下書き {}", format_local(now_unix_secs())),
+            name => name.to_string(),
+        };
+        if let Err(e) = save_named_draft(state, &name) {
+            state.status_message = format!("❌ {}", e);
+            state.show_exit_confirmation = false;
+            return;
+        }
+        exit = true;
+    }
+    if exit {
+        state.exit_confirmed = true;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+    }
+    if cancel {
+        state.show_exit_confirmation = false;
+    }
+}
+
+/// 復元するか決めていない下書き（ログインしているユーザーが保存したものだけ見せる）
+fn show_recovered_draft_banner(ui: &mut egui::Ui, state: &mut AppState) {
+    let Some(recovered) = state.recovered_drafts.latest_of(&state.auth_username) else {
+        return;
+    };
+    let saved_at = recovered.saved_at;
+    let recipient_count = recovered.draft.recipients.iter().filter(|r| !r.email.is_empty()).count();
+
+    let mut restore = false;
+    let mut discard = false;
+    egui::Frame::none()
+        .fill(egui::Color32::from_rgb(30, 50, 80))
+        .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(100, 150, 220)))
+        .inner_margin(12.0)
+        .rounding(6.0)
+        .show(ui, |ui| {
+            ui.label(egui::RichText::new("💾 復元していない下書きがあります")
+                .strong()
+                .color(egui::Color32::from_rgb(150, 200, 255)));
+            ui.label(format!(
+                "{} に自動保存した下書き（宛先{}件・添付ファイル{}件）があります。前回正常に終了しなかったか、ログアウトしたときの下書きです。",
+                format_local(saved_at),
+                recipient_count,
+                recovered.draft.attachments.len(),
+            ));
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                restore = ui.button("↩ 復元する").on_hover_text("作成中の内容は置き換わります").clicked();
+                discard = ui.button("破棄").clicked();
+            });
+        });
+    ui.add_space(8.0);
+
+    if !restore && !discard {
+        return;
+    }
+    let username = state.auth_username.clone();
+    let Some(recovered) = state.recovered_drafts.remove(&username, saved_at) else {
+        return;
+    };
+    if restore {
+        open_draft(state, &recovered);
+    } else {
+        state.status_message = "自動保存した下書きを破棄しました".to_string();
+    }
+    if let Err(e) = state.recovered_drafts.save() {
+        state.status_message = format!("❌ {}", e);
+    }
+}
+
+/// 名前を付けた下書きの保存・読み込み
+fn show_draft_controls(ui: &mut egui::Ui, state: &mut AppState) {
+    let mut save = false;
+    let mut open = None;
+    let mut remove = None;
+    ui.horizontal(|ui| {
+        ui.label("💾 下書き:");
+        ui.add(egui::TextEdit::singleline(&mut state.draft_name_input)
+            .hint_text("下書きの名前")
+            .desired_width(160.0));
+        save = ui.add_enabled(!state.mail_draft.is_blank(), egui::Button::new("保存"))
+            .on_hover_text("同じ名前の下書きは上書きします")
+            .clicked();
+
+        let drafts = state.drafts.drafts_of(&state.auth_username);
+        ui.add_enabled_ui(!drafts.is_empty(), |ui| {
+            ui.menu_button(format!("📂 開く ({})", drafts.len()), |ui| {
+                for saved in &drafts {
+                    ui.horizontal(|ui| {
+                        if ui.button(&saved.name).on_hover_text("作成中の内容は置き換わります").clicked() {
+                            open = Some(saved.name.clone());
+                            ui.close_menu();
+                        }
+                        ui.weak(format_local(saved.saved_at));
+                        if ui.small_button("🗑").on_hover_text("この下書きを削除").clicked() {
+                            remove = Some(saved.name.clone());
+                        }
+                    });
+                }
+            });
+        });
+    });
+
+    let user = state.auth_username.clone();
+    if save {
+        let name = state.draft_name_input.clone();
+        state.status_message = match save_named_draft(state, &name) {
+            Ok(()) => format!("✅ 下書き「{}」を保存しました", name.trim()),
+            Err(e) => format!("❌ {}", e),
+        };
+    } else if let Some(name) = open {
+        let saved = state.drafts.drafts_of(&user).into_iter().find(|d| d.name == name).cloned();
+        if let Some(saved) = saved {
+            open_draft(state, &saved);
+            state.draft_name_input = saved.name;
+        }
+    } else if let Some(name) = remove {
+        state.drafts.remove(&user, &name);
+        state.status_message = match state.drafts.save() {
+            Ok(()) => format!("下書き「{}」を削除しました", name),
+            Err(e) => format!("❌ {}", e),
+        };
+    }
+}
+
+/// 送信後にメール作成画面をリセット
+fn reset_mail_draft(state: &mut AppState) {
+    // 宛先をクリア（空の1行に戻す）
+    state.mail_draft.recipients = vec![RecipientInfo::default()];
 
     // 添付ファイルをクリア
     state.mail_draft.attachments.clear();
@@ -909,3 +1710,174 @@ fn reset_mail_draft(state: &mut AppState) {
     state.recipient_search.clear();
     state.template_search.clear();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{RecipientData, Template};
+    use std::time::{Duration, Instant};
+
+    fn hold_current_draft(state: &mut AppState) {
+        let pending = PendingSendData {
+            recipients: vec![PendingRecipient {
+                draft_index: 1,
+                email: "a@example.com".to_string(),
+                subject: "ご請求書".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let item_ids: Vec<String> = state.outbox.enqueue(&pending, 0).into_iter().map(|i| i.id).collect();
+        state.outbox.hold_until(&item_ids, u64::MAX);
+        state.held_send = Some(HeldSend {
+            pending,
+            item_ids,
+            release_at: Instant::now() + Duration::from_secs(10),
+            draft: state.mail_draft.clone(),
+            active_recipient_index: state.active_recipient_index,
+            selected_recipient_index: state.selected_recipient_index,
+            selected_template_index: state.selected_template_index,
+        });
+        reset_mail_draft(state);
+    }
+
+    fn locked_draft_with_attachment(state: &mut AppState) {
+        state.mail_draft.add_recipient();
+        state.mail_draft.recipients[1].email = "a@example.com".to_string();
+        state.mail_draft.recipients[1].subject = "ご請求書".to_string();
+        state.mail_draft.recipients[1].locked_recipient_id = Some("7".to_string());
+        state.mail_draft.attachments.push(Attachment {
+            file_name: "請求書_A社.pdf".to_string(),
+            linked_recipient_index: Some(1),
+            ..Default::default()
+        });
+        state.active_recipient_index = 1;
+    }
+
+    #[test]
+    fn test_undo_restores_locked_recipient_and_attachments() {
+        let mut state = AppState::default();
+        locked_draft_with_attachment(&mut state);
+
+        hold_current_draft(&mut state);
+        assert!(state.mail_draft.attachments.is_empty());
+        assert_eq!(state.outbox.items().len(), 1, "猶予中も送信待ちに保存しておく");
+        assert!(state.outbox.due_items(now_unix_secs()).is_empty());
+
+        undo_held_send(&mut state);
+
+        assert!(state.held_send.is_none());
+        assert_eq!(state.mail_draft.recipients[1].subject, "ご請求書");
+        assert_eq!(state.mail_draft.recipients[1].locked_recipient_id.as_deref(), Some("7"));
+        assert_eq!(state.mail_draft.attachments[0].file_name, "請求書_A社.pdf");
+        assert_eq!(state.active_recipient_index, 1);
+        assert!(state.outbox.is_empty(), "取り消した送信は送信待ちに入れない");
+    }
+
+    #[test]
+    fn test_logout_sets_draft_aside_for_the_same_user_only() {
+        let mut state = AppState { auth_username: "sato".to_string(), ..Default::default() };
+        locked_draft_with_attachment(&mut state);
+        hold_current_draft(&mut state);
+        state.mail_draft.recipients[0].body = "次のメール".to_string();
+
+        set_aside_draft_for_logout(&mut state);
+        state.auth_username.clear();
+
+        assert!(state.held_send.is_none(), "次のユーザーに取り消しのカウントダウンを見せない");
+        assert!(state.outbox.is_empty(), "取り消した送信は送らない");
+        assert!(state.mail_draft.is_blank());
+
+        state.auth_username = "suzuki".to_string();
+        assert!(state.recovered_drafts.latest_of("suzuki").is_none());
+
+        // 同じユーザーがログインし直すと、取り消した送信の下書きから復元できる
+        state.auth_username = "sato".to_string();
+        let held = state.recovered_drafts.latest_of("sato").cloned().expect("ログアウトした下書きを復元できる");
+        assert_eq!(held.draft.recipients[1].locked_recipient_id.as_deref(), Some("7"));
+        assert_eq!(held.draft.attachments[0].file_name, "請求書_A社.pdf");
+        state.recovered_drafts.remove("sato", held.saved_at);
+        let current = state.recovered_drafts.latest_of("sato").expect("作成中だった下書きも残す");
+        assert_eq!(current.draft.recipients[0].body, "次のメール");
+    }
+
+    #[test]
+    fn test_released_send_returns_failed_recipient_to_draft() {
+        let mut state = AppState::default();
+        locked_draft_with_attachment(&mut state);
+        hold_current_draft(&mut state);
+
+        release_held_send(&mut state);
+
+        assert!(state.held_send.is_none());
+        assert_eq!(state.outbox.due_items(now_unix_secs()).len(), 1);
+        let Some(Job::SendOutbox { pending: Some(pending), held_draft: Some(held_draft), .. }) = state.job_queue.pop() else {
+            panic!("送信時の下書きを持った送信ジョブを期待");
+        };
+        assert_eq!(pending.recipients[0].subject, "ご請求書");
+
+        apply_send_outcomes(&mut state, &pending, &[SendOutcome::Failed("宛先不明".to_string())], Some(&held_draft));
+
+        assert_eq!(state.mail_draft.recipients.len(), 1);
+        assert_eq!(state.mail_draft.recipients[0].email, "a@example.com");
+        assert_eq!(state.mail_draft.recipients[0].locked_recipient_id.as_deref(), Some("7"));
+        assert_eq!(state.mail_draft.attachments[0].linked_recipient_index, Some(0));
+        assert_eq!(state.send_failures.len(), 1);
+    }
+
+    #[test]
+    fn test_subject_applied_to_all_is_rendered_per_recipient() {
+        let mut state = AppState {
+            recipients_master: ["A社", "B社"].iter().enumerate()
+                .map(|(i, company)| RecipientData { id: i.to_string(), company: company.to_string(), ..Default::default() })
+                .collect(),
+            templates: vec![Template { id: "1".to_string(), subject: "{{company}} 御中 ご請求書".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        state.mail_draft.add_recipient();
+        for (i, rec) in state.mail_draft.recipients.iter_mut().enumerate() {
+            rec.email = format!("{}@example.com", i);
+            rec.locked_recipient_id = Some(i.to_string());
+        }
+        apply_template(&mut state, 0);
+        assert_eq!(state.mail_draft.recipients[0].subject, "A社 御中 ご請求書");
+
+        apply_subject_to_all(&mut state, 0);
+        assert_eq!(state.mail_draft.recipients[1].subject, "B社 御中 ご請求書");
+
+        // 書き換えた件名はそのまま使い、他の宛先の会社名が残れば送信前の確認で止める
+        state.mail_draft.recipients[0].subject = "A社 御中 ご請求書（再送）".to_string();
+        apply_subject_to_all(&mut state, 0);
+        assert_eq!(state.mail_draft.recipients[1].subject, "A社 御中 ご請求書（再送）");
+        let errors = validate_send_safety(&state.mail_draft.recipients, &state.recipients_master, &[]);
+        assert!(errors.iter().any(|e| e.starts_with("[宛先2]") && e.contains("A社")));
+    }
+
+    #[test]
+    fn test_sent_rows_are_removed_and_failed_rows_kept() {
+        let mut state = AppState::default();
+        for (i, email) in ["a@example.com", "b@example.com", "c@example.com"].iter().enumerate() {
+            if i > 0 {
+                state.mail_draft.add_recipient();
+            }
+            state.mail_draft.recipients[i].email = email.to_string();
+            state.mail_draft.attachments.push(Attachment {
+                file_name: format!("{}.pdf", i),
+                linked_recipient_index: Some(i),
+                ..Default::default()
+            });
+        }
+        let emails: Vec<&str> = state.mail_draft.recipients.iter().map(|r| r.email.as_str()).collect();
+        let pending = PendingSendData::sample(&emails);
+
+        let outcomes = [SendOutcome::Sent, SendOutcome::Failed("エラー".to_string()), SendOutcome::Sent];
+        apply_send_outcomes(&mut state, &pending, &outcomes, None);
+
+        assert_eq!(state.mail_draft.recipients.len(), 1);
+        assert_eq!(state.mail_draft.recipients[0].email, "b@example.com");
+        assert_eq!(state.mail_draft.attachments.len(), 1);
+        assert_eq!(state.mail_draft.attachments[0].file_name, "1.pdf");
+        assert_eq!(state.mail_draft.attachments[0].linked_recipient_index, Some(0));
+        assert_eq!(state.active_recipient_index, 0);
+    }
+}
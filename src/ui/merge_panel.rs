@@ -0,0 +1,185 @@
+use eframe::egui;
+use crate::file_utils::{check_file_size, encode_file_to_base64, get_mime_type};
+use crate::merge::{build_batch, DataSheet, MergeBatch};
+use crate::models::{AppState, Attachment, MailDraft, Tab};
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    // CSV / Excel のドロップで読み込む（宛先マスターへの取り込みはメール作成画面で行う）
+    let dropped = ui.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+    if let Some(path) = dropped {
+        state.merge_path_input = path.to_string_lossy().to_string();
+        load_sheet(state);
+    }
+
+    ui.heading("差し込み送信");
+    ui.separator();
+    ui.weak("1行1宛先のデータシート（CSV / Excel）から、宛先ごとに本文を差し込んだ下書きを作ります。列名は {{列名}} で件名・本文に差し込めます。");
+    ui.weak("email（メールアドレス）列は必須です。ファイル名列があれば、データシートと同じフォルダのファイルをその宛先に添付します。");
+
+    ui.add_space(10.0);
+
+    ui.horizontal(|ui| {
+        ui.label("データシート:");
+        ui.add(egui::TextEdit::singleline(&mut state.merge_path_input)
+            .hint_text("ファイルをドロップするかパスを入力")
+            .desired_width(400.0));
+        if ui.button("読み込み").clicked() {
+            load_sheet(state);
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("テンプレート:");
+        let selected_name = state.merge_template_index
+            .and_then(|i| state.templates.get(i))
+            .map(|t| t.name.clone())
+            .unwrap_or_else(|| "選択してください".to_string());
+        let before = state.merge_template_index;
+        egui::ComboBox::from_id_salt("merge_template")
+            .selected_text(selected_name)
+            .show_ui(ui, |ui| {
+                for (i, template) in state.templates.iter().enumerate() {
+                    ui.selectable_value(&mut state.merge_template_index, Some(i), &template.name);
+                }
+            });
+        if before != state.merge_template_index || ui.button("🔄 再チェック").clicked() {
+            rebuild_batch(state);
+        }
+    });
+
+    let Some(sheet) = &state.merge_sheet else {
+        return;
+    };
+    ui.label(format!("{}行 / 列: {}", sheet.rows.len(), sheet.headers.join(", ")));
+
+    let Some(batch) = state.merge_batch.clone() else {
+        return;
+    };
+
+    ui.add_space(10.0);
+
+    for error in &batch.errors {
+        ui.colored_label(egui::Color32::from_rgb(255, 150, 150), format!("⚠ {}", error));
+    }
+
+    egui::ScrollArea::vertical()
+        .max_height(ui.available_height() - 60.0)
+        .show(ui, |ui| {
+            egui::Grid::new("merge_grid")
+                .num_columns(5)
+                .spacing([10.0, 6.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("行");
+                    ui.label("宛先");
+                    ui.label("会社名 / 氏名");
+                    ui.label("添付");
+                    ui.label("状態");
+                    ui.end_row();
+
+                    for row in &batch.rows {
+                        ui.label(row.line.to_string());
+                        ui.label(&row.recipient.email)
+                            .on_hover_text(format!("件名: {}\n\n{}", row.recipient.subject, row.recipient.body));
+                        ui.label(format!("{} {}", row.company, row.name));
+                        ui.label(row.attachment.as_ref()
+                            .and_then(|p| p.file_name())
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default());
+                        if row.errors.is_empty() {
+                            let status = if row.recipient.locked_recipient_id.is_some() { "✓ マスター照合済み" } else { "✓" };
+                            ui.colored_label(egui::Color32::from_rgb(100, 200, 100), status);
+                        } else {
+                            ui.colored_label(egui::Color32::from_rgb(255, 150, 150), row.errors.join("\n"));
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+    ui.add_space(10.0);
+    ui.separator();
+
+    let draft_in_use = state.mail_draft.recipients.iter().any(|r| !r.email.is_empty());
+    ui.horizontal(|ui| {
+        let ready = batch.error_count() == 0 && !draft_in_use;
+        let button = egui::Button::new(format!("✉ {}件を下書きに展開", batch.rows.len()));
+        if ui.add_enabled(ready, button).clicked() {
+            match expand_into_draft(state, &batch) {
+                Ok(count) => {
+                    state.status_message = format!("差し込みで{}件の宛先を作成しました。内容を確認して送信してください", count);
+                    state.tab = Tab::Main;
+                }
+                Err(e) => state.status_message = format!("❌ {}", e),
+            }
+        }
+        if draft_in_use {
+            ui.weak("作成中の下書きがあります。送信するかクリアしてから展開してください");
+        } else if batch.error_count() > 0 {
+            ui.weak(format!("{}件のエラーを直してから展開してください", batch.error_count()));
+        }
+    });
+}
+
+fn load_sheet(state: &mut AppState) {
+    let path = std::path::PathBuf::from(state.merge_path_input.trim());
+    match DataSheet::load(&path) {
+        Ok(sheet) => {
+            state.status_message = format!("データシートを読み込みました（{}行）", sheet.rows.len());
+            state.merge_sheet = Some(sheet);
+            rebuild_batch(state);
+        }
+        Err(e) => {
+            state.status_message = format!("❌ {}", e);
+            state.merge_sheet = None;
+            state.merge_batch = None;
+        }
+    }
+}
+
+fn rebuild_batch(state: &mut AppState) {
+    let template = state.merge_template_index.and_then(|i| state.templates.get(i));
+    state.merge_batch = match (&state.merge_sheet, template) {
+        (Some(sheet), Some(template)) => Some(build_batch(sheet, template, &state.recipients_master)),
+        _ => None,
+    };
+}
+
+/// 差し込み結果を下書きに展開する（添付ファイルはここで読み込む）
+/// 展開後は通常の送信と同じく確認ダイアログの照合チェックを通る
+fn expand_into_draft(state: &mut AppState, batch: &MergeBatch) -> Result<usize, String> {
+    let mut draft = MailDraft {
+        recipients: Vec::new(),
+        ..Default::default()
+    };
+
+    for (idx, row) in batch.rows.iter().enumerate() {
+        draft.recipients.push(row.recipient.clone());
+
+        let Some(path) = &row.attachment else {
+            continue;
+        };
+        let path_str = path.to_string_lossy();
+        check_file_size(&path_str).map_err(|e| format!("{}行目の添付ファイル: {}", row.line, e))?;
+        let data = encode_file_to_base64(&path_str).map_err(|e| format!("{}行目の添付ファイル: {}", row.line, e))?;
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        draft.attachments.push(Attachment {
+            file_path: path_str.to_string(),
+            mime_type: get_mime_type(&file_name),
+            file_name,
+            data,
+            // データシートで宛先を明示しているので、ファイル名からの会社名推定はしない
+            linked_company: None,
+            linked_recipient_index: Some(idx),
+            ..Default::default()
+        });
+    }
+
+    let count = draft.recipients.len();
+    state.mail_draft = draft;
+    state.active_recipient_index = 0;
+    state.selected_recipient_index = None;
+    state.selected_template_index = state.merge_template_index;
+    state.send_failures.clear();
+    Ok(count)
+}
@@ -1,4 +1,12 @@
 pub mod mail_panel;
 pub mod settings_panel;
 pub mod history_panel;
+pub mod outbox_panel;
+pub mod schedule_panel;
+pub mod merge_panel;
+pub mod template_panel;
+pub mod recipient_panel;
+pub mod linking_panel;
+pub mod signature_panel;
 pub mod login_panel;
+pub mod audit_panel;
@@ -0,0 +1,84 @@
+use eframe::egui;
+use crate::models::AppState;
+use crate::outbox::OutboxStatus;
+use crate::utils::now_unix_secs;
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("送信待ち");
+    ui.separator();
+    ui.weak("通信障害などで送れなかったメールです。アプリを終了しても残り、自動で再送します。");
+
+    ui.add_space(10.0);
+
+    let now = now_unix_secs();
+    let mut retry_id = None;
+    let mut remove_id = None;
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        if state.outbox.is_empty() {
+            ui.label("送信待ちのメールはありません");
+        } else {
+            egui::Grid::new("outbox_grid")
+                .num_columns(6)
+                .spacing([10.0, 10.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("宛先");
+                    ui.label("件名");
+                    ui.label("ステータス");
+                    ui.label("次回送信");
+                    ui.label("エラー");
+                    ui.label("操作");
+                    ui.end_row();
+
+                    for item in state.outbox.items() {
+                        ui.label(&item.to);
+                        ui.label(&item.subject);
+
+                        let status = format!("{} ({}回試行)", item.status.label(), item.attempts);
+                        match item.status {
+                            OutboxStatus::Failed => ui.colored_label(egui::Color32::RED, status),
+                            _ => ui.colored_label(egui::Color32::YELLOW, status),
+                        };
+
+                        if item.status == OutboxStatus::Queued {
+                            let wait = item.next_attempt_at.saturating_sub(now);
+                            if wait == 0 {
+                                ui.label("まもなく");
+                            } else {
+                                ui.label(format!("{}秒後", wait));
+                            }
+                        } else {
+                            ui.label("-");
+                        }
+
+                        ui.label(item.last_error.as_deref().unwrap_or(""));
+
+                        ui.horizontal(|ui| {
+                            let idle = item.status != OutboxStatus::Sending;
+                            if ui.add_enabled(idle, egui::Button::new("今すぐ再送")).clicked() {
+                                retry_id = Some(item.id.clone());
+                            }
+                            if ui.add_enabled(idle, egui::Button::new("削除")).clicked() {
+                                remove_id = Some(item.id.clone());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+        }
+    });
+
+    if retry_id.is_none() && remove_id.is_none() {
+        return;
+    }
+    if let Some(id) = retry_id {
+        state.outbox.retry_now(&id, now);
+    }
+    if let Some(id) = remove_id {
+        state.outbox.remove(&id);
+    }
+    if let Err(e) = state.outbox.save() {
+        state.status_message = format!("❌ {}", e);
+    }
+}
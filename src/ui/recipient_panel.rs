@@ -0,0 +1,404 @@
+use eframe::egui;
+use crate::api::ApiError;
+use crate::models::{AppState, RecipientData, RecipientSort, Tab};
+use crate::utils::{is_valid_email, validate_recipient};
+use crate::worker::Job;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 150, 150);
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("宛先マスター");
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut state.recipient_editor.search)
+            .hint_text("🔍 会社名・氏名・メールアドレスで検索")
+            .desired_width(300.0));
+        if ui.add_enabled(state.recipient_editor.editing.is_none(), egui::Button::new("➕ 追加")).clicked() {
+            open_new(state);
+        }
+        ui.weak(format!("{}件", state.recipients_master.len()));
+    });
+
+    show_delete_confirmation(ui, state);
+    ui.add_space(6.0);
+
+    let fields = custom_field_names(state);
+    let rows = visible_rows(state);
+
+    egui::ScrollArea::both()
+        .id_salt("recipient_table")
+        .max_height(ui.available_height() - 80.0)
+        .show(ui, |ui| {
+            egui::Grid::new("recipient_grid")
+                .num_columns(8 + fields.len())
+                .spacing([10.0, 6.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    let editor = &mut state.recipient_editor;
+                    for (label, key) in [
+                        ("ID", RecipientSort::Id),
+                        ("会社名", RecipientSort::Company),
+                        ("氏名", RecipientSort::Name),
+                        ("メールアドレス", RecipientSort::Email),
+                    ] {
+                        let arrow = match (editor.sort == key, editor.descending) {
+                            (true, false) => " ▲",
+                            (true, true) => " ▼",
+                            _ => "",
+                        };
+                        if ui.button(format!("{}{}", label, arrow)).clicked() {
+                            editor.descending = editor.sort == key && !editor.descending;
+                            editor.sort = key;
+                        }
+                    }
+                    ui.strong("CC");
+                    ui.strong("BCC");
+                    ui.strong("返信先");
+                    for field in &fields {
+                        ui.strong(field);
+                    }
+                    ui.label("");
+                    ui.end_row();
+
+                    // 新規追加の行は先頭に表示する
+                    if state.recipient_editor.editing.as_ref().is_some_and(|r| r.id.is_empty()) {
+                        show_edit_row(ui, state, &fields);
+                    }
+                    for index in rows {
+                        let id = &state.recipients_master[index].id;
+                        if state.recipient_editor.editing.as_ref().is_some_and(|r| &r.id == id) {
+                            show_edit_row(ui, state, &fields);
+                        } else {
+                            show_row(ui, state, index, &fields);
+                        }
+                    }
+                });
+        });
+
+    show_edit_errors(ui, state);
+}
+
+/// 新しい宛先の入力を始める（メール作成画面の ➕ からも呼ぶ）
+pub fn open_new(state: &mut AppState) {
+    state.tab = Tab::Recipients;
+    state.recipient_editor.editing = Some(RecipientData::default());
+    state.recipient_editor.confirm_delete = None;
+}
+
+/// 宛先マスターの ID で編集を始める
+pub fn open_existing(state: &mut AppState, index: usize) {
+    if let Some(recipient) = state.recipients_master.get(index).cloned() {
+        state.tab = Tab::Recipients;
+        state.recipient_editor.editing = Some(recipient);
+        state.recipient_editor.confirm_delete = None;
+    }
+}
+
+/// 検索で絞り込み、並べ替えた recipients_master 上の位置
+fn visible_rows(state: &AppState) -> Vec<usize> {
+    let editor = &state.recipient_editor;
+    let search = editor.search.trim().to_lowercase();
+    let mut rows: Vec<usize> = state.recipients_master.iter().enumerate()
+        .filter(|(_, r)| {
+            search.is_empty()
+                || [&r.id, &r.company, &r.name, &r.email].iter().any(|v| v.to_lowercase().contains(&search))
+                || r.custom_fields.values().any(|v| v.to_lowercase().contains(&search))
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let master = &state.recipients_master;
+    rows.sort_by(|&a, &b| {
+        let (a, b) = (&master[a], &master[b]);
+        let ordering = match editor.sort {
+            RecipientSort::Id => compare_ids(&a.id, &b.id),
+            RecipientSort::Company => a.company.cmp(&b.company),
+            RecipientSort::Name => a.name.cmp(&b.name),
+            RecipientSort::Email => a.email.to_lowercase().cmp(&b.email.to_lowercase()),
+        };
+        if editor.descending { ordering.reverse() } else { ordering }
+    });
+    rows
+}
+
+/// ID は数値として比べる（数値でない ID は後ろ）
+fn compare_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+fn show_row(ui: &mut egui::Ui, state: &mut AppState, index: usize, fields: &[String]) {
+    let rec = &state.recipients_master[index];
+    ui.label(&rec.id);
+    ui.label(&rec.company);
+    ui.label(&rec.name);
+    ui.label(&rec.email);
+    ui.label(&rec.cc);
+    ui.label(&rec.bcc);
+    ui.label(&rec.reply_to);
+    for field in fields {
+        ui.label(rec.custom_fields.get(field).map(String::as_str).unwrap_or_default());
+    }
+
+    let id = rec.id.clone();
+    let idle = state.recipient_editor.editing.is_none();
+    ui.horizontal(|ui| {
+        if ui.add_enabled(idle, egui::Button::new("✏").small()).on_hover_text("編集").clicked() {
+            open_existing(state, index);
+        }
+        if ui.add_enabled(idle, egui::Button::new("🗑").small()).on_hover_text("削除").clicked() {
+            state.recipient_editor.confirm_delete = Some(id);
+        }
+    });
+    ui.end_row();
+}
+
+fn show_edit_row(ui: &mut egui::Ui, state: &mut AppState, fields: &[String]) {
+    let errors = state.recipient_editor.editing.as_ref()
+        .map(|r| validate_recipient(r, &state.recipients_master))
+        .unwrap_or_default();
+    let editor = &mut state.recipient_editor;
+    let Some(rec) = editor.editing.as_mut() else {
+        return;
+    };
+
+    ui.label(if rec.id.is_empty() { "新規" } else { rec.id.as_str() });
+    // 入力エラーのある項目は赤字にする
+    let field = |ui: &mut egui::Ui, text: &mut String, width: f32, bad: bool| {
+        let mut edit = egui::TextEdit::singleline(text).desired_width(width);
+        if bad {
+            edit = edit.text_color(ERROR_COLOR);
+        }
+        ui.add(edit);
+    };
+    let company_bad = rec.company.trim().is_empty();
+    let email = rec.email.trim().to_string();
+    let email_bad = !is_valid_email(&email) || errors.iter().any(|e| e.contains("重複"));
+    field(ui, &mut rec.company, 120.0, company_bad);
+    field(ui, &mut rec.name, 100.0, false);
+    field(ui, &mut rec.email, 180.0, email_bad);
+    ui.add(egui::TextEdit::singleline(&mut rec.cc).desired_width(120.0));
+    ui.add(egui::TextEdit::singleline(&mut rec.bcc).desired_width(120.0));
+    ui.add(egui::TextEdit::singleline(&mut rec.reply_to).desired_width(120.0));
+    for field in fields {
+        ui.add(egui::TextEdit::singleline(rec.custom_fields.entry(field.clone()).or_default()).desired_width(100.0));
+    }
+
+    let mut save = false;
+    let mut cancel = false;
+    ui.horizontal(|ui| {
+        save = ui.add_enabled(errors.is_empty(), egui::Button::new("💾").small()).on_hover_text("保存").clicked();
+        cancel = ui.small_button("✖").on_hover_text("キャンセル").clicked();
+    });
+    ui.end_row();
+
+    if save {
+        let mut recipient = rec.clone();
+        recipient.email = recipient.email.trim().to_string();
+        // 入力しなかった追加項目は、列の並びのために作った空の値なので送らない
+        recipient.custom_fields.retain(|_, v| !v.is_empty());
+        state.job_queue.push(Job::SaveRecipient(recipient));
+        editor.editing = None;
+    } else if cancel {
+        editor.editing = None;
+    }
+}
+
+/// 編集中の行のエラーと、追加項目の列の追加
+fn show_edit_errors(ui: &mut egui::Ui, state: &mut AppState) {
+    let Some(rec) = &state.recipient_editor.editing else {
+        return;
+    };
+    for error in validate_recipient(rec, &state.recipients_master) {
+        ui.colored_label(ERROR_COLOR, error);
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("追加項目:");
+        let editor = &mut state.recipient_editor;
+        ui.add(egui::TextEdit::singleline(&mut editor.new_field)
+            .hint_text("列名（例: 部署）")
+            .desired_width(150.0));
+        let name = editor.new_field.trim().to_string();
+        if ui.add_enabled(!name.is_empty(), egui::Button::new("列を追加")).clicked() {
+            if let Some(rec) = editor.editing.as_mut() {
+                rec.custom_fields.entry(name).or_default();
+            }
+            editor.new_field.clear();
+        }
+        ui.weak("追加した列は {{列名}} でテンプレートに差し込めます");
+    });
+}
+
+/// 削除の確認（テンプレートの紐付けで使われていれば警告する）
+fn show_delete_confirmation(ui: &mut egui::Ui, state: &mut AppState) {
+    let Some(id) = state.recipient_editor.confirm_delete.clone() else {
+        return;
+    };
+    let Some(rec) = state.recipients_master.iter().find(|r| r.id == id).cloned() else {
+        state.recipient_editor.confirm_delete = None;
+        return;
+    };
+
+    ui.add_space(6.0);
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.colored_label(ERROR_COLOR, format!("「{} {}（{}）」を宛先マスターから削除しますか？", rec.company, rec.name, rec.email));
+        let linked = linked_template_names(state, &id);
+        if !linked.is_empty() {
+            ui.colored_label(ERROR_COLOR, format!(
+                "⚠ この宛先は{}件のテンプレート紐付け（{}）で使われています。削除すると紐付けも削除されます",
+                linked.len(),
+                linked.join(", "),
+            ));
+        }
+        ui.horizontal(|ui| {
+            if ui.button("削除する").clicked() {
+                state.job_queue.push(Job::DeleteRecipient(rec.clone()));
+                state.recipient_editor.confirm_delete = None;
+            }
+            if ui.button("やめる").clicked() {
+                state.recipient_editor.confirm_delete = None;
+            }
+        });
+    });
+}
+
+/// recipient_id に紐付いたテンプレートの名前（見つからないテンプレートは ID で表示）
+fn linked_template_names(state: &AppState, recipient_id: &str) -> Vec<String> {
+    state.linkings_master.iter()
+        .filter(|l| l.recipient_id == recipient_id)
+        .map(|l| state.templates.iter()
+            .find(|t| t.id == l.template_id)
+            .map(|t| t.name.clone())
+            .unwrap_or_else(|| format!("ID: {}", l.template_id)))
+        .collect()
+}
+
+/// 宛先マスターにある追加列の名前（重複なし・名前順）
+fn custom_field_names(state: &AppState) -> Vec<String> {
+    let mut names: BTreeSet<String> = state.recipients_master.iter()
+        .flat_map(|r| r.custom_fields.keys().cloned())
+        .collect();
+    if let Some(rec) = &state.recipient_editor.editing {
+        names.extend(rec.custom_fields.keys().cloned());
+    }
+    names.into_iter().collect()
+}
+
+/// 取り直した宛先マスターに差し替え、選択中の宛先を ID で選び直す
+fn replace_recipients(state: &mut AppState, recipients: Vec<RecipientData>) {
+    let id_of = |index: Option<usize>, master: &[RecipientData]| {
+        index.and_then(|i| master.get(i)).map(|r| r.id.clone())
+    };
+    let selected = id_of(state.selected_recipient_index, &state.recipients_master);
+    let preview = id_of(state.template_editor.preview_recipient_index, &state.recipients_master);
+    let position = |id: Option<String>| id.and_then(|id| recipients.iter().position(|r| r.id == id));
+    state.selected_recipient_index = position(selected);
+    state.template_editor.preview_recipient_index = position(preview);
+    state.recipients_master = recipients;
+}
+
+/// 保存の結果を反映する
+pub fn apply_saved(
+    state: &mut AppState,
+    recipient: RecipientData,
+    result: Result<(), ApiError>,
+    recipients: Option<Vec<RecipientData>>,
+) {
+    let label = format!("{} {}", recipient.company, recipient.name);
+    if let Err(e) = result {
+        if let Some(recipients) = recipients {
+            replace_recipients(state, recipients);
+        }
+        // 入力し直さなくて済むよう編集中に戻す
+        if state.recipient_editor.editing.is_none() {
+            state.recipient_editor.editing = Some(recipient);
+        }
+        state.status_message = format!("❌ 宛先「{}」の保存エラー: {}", label, e);
+        return;
+    }
+
+    match recipients {
+        Some(recipients) => replace_recipients(state, recipients),
+        None => {
+            // 一覧を取り直せなかった場合は手元で反映する（新規は ID がわからないので再読み込みを待つ）
+            if let Some(existing) = state.recipients_master.iter_mut().find(|r| r.id == recipient.id && !r.id.is_empty()) {
+                *existing = recipient;
+            }
+        }
+    }
+    state.status_message = format!("✅ 宛先「{}」を保存しました", label);
+}
+
+/// 削除の結果を反映する
+pub fn apply_deleted(
+    state: &mut AppState,
+    recipient: RecipientData,
+    result: Result<(), ApiError>,
+    recipients: Option<Vec<RecipientData>>,
+) {
+    let label = format!("{} {}", recipient.company, recipient.name);
+    match result {
+        Ok(()) => {
+            let recipients = recipients.unwrap_or_else(|| {
+                state.recipients_master.iter().filter(|r| r.id != recipient.id).cloned().collect()
+            });
+            replace_recipients(state, recipients);
+            state.status_message = format!("🗑 宛先「{}」を削除しました", label);
+        }
+        Err(e) => {
+            if let Some(recipients) = recipients {
+                replace_recipients(state, recipients);
+            }
+            state.status_message = format!("❌ 宛先「{}」の削除エラー: {}", label, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient(id: &str, company: &str) -> RecipientData {
+        RecipientData { id: id.to_string(), company: company.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_search_and_sort() {
+        let mut state = AppState {
+            recipients_master: vec![recipient("10", "B社"), recipient("9", "A社"), recipient("x", "C社")],
+            ..Default::default()
+        };
+        state.recipients_master[0].custom_fields.insert("部署".to_string(), "経理部".to_string());
+
+        assert_eq!(visible_rows(&state), vec![1, 0, 2], "ID は数値順");
+        state.recipient_editor.sort = RecipientSort::Company;
+        state.recipient_editor.descending = true;
+        assert_eq!(visible_rows(&state), vec![2, 0, 1]);
+        state.recipient_editor.search = "経理".to_string();
+        assert_eq!(visible_rows(&state), vec![0], "追加項目も検索する");
+    }
+
+    #[test]
+    fn test_delete_keeps_selection_by_id() {
+        let mut state = AppState {
+            recipients_master: vec![recipient("1", "A社"), recipient("2", "B社"), recipient("3", "C社")],
+            selected_recipient_index: Some(2),
+            ..Default::default()
+        };
+        state.template_editor.preview_recipient_index = Some(0);
+
+        apply_deleted(&mut state, recipient("1", "A社"), Ok(()), None);
+
+        assert_eq!(state.recipients_master.len(), 2);
+        assert_eq!(state.selected_recipient_index, Some(1), "C社を選んだまま");
+        assert_eq!(state.template_editor.preview_recipient_index, None, "削除した宛先の選択は外す");
+    }
+}
@@ -0,0 +1,82 @@
+use eframe::egui;
+use crate::calendar::{BusinessCalendar, HOLIDAYS_FILE_NAME};
+use crate::models::AppState;
+use crate::schedule::format_local;
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("予約送信");
+    ui.separator();
+
+    // 営業日カレンダー
+    let holidays_path = state.data_dir.join(HOLIDAYS_FILE_NAME);
+    ui.horizontal(|ui| {
+        ui.label(format!("祝日ファイル: {} ({}日)", holidays_path.display(), state.calendar.holiday_count()));
+        if ui.small_button("🔄 再読み込み").clicked() {
+            match BusinessCalendar::load(&holidays_path) {
+                Ok(calendar) => {
+                    state.status_message = format!("祝日を{}日読み込みました", calendar.holiday_count());
+                    state.calendar = calendar;
+                }
+                Err(e) => state.status_message = format!("❌ {}", e),
+            }
+        }
+    });
+    ui.weak("「翌営業日」は土日と祝日ファイルの日付を飛ばします。1行に1日、2026-01-01 元日 の形式で記入してください。");
+
+    ui.add_space(10.0);
+
+    let mut cancel_id = None;
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        if state.schedule.items().is_empty() {
+            ui.label("予約されたメールはありません");
+        } else {
+            egui::Grid::new("schedule_grid")
+                .num_columns(5)
+                .spacing([10.0, 10.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("送信日時");
+                    ui.label("宛先");
+                    ui.label("件名");
+                    ui.label("添付");
+                    ui.label("操作");
+                    ui.end_row();
+
+                    for item in state.schedule.items() {
+                        ui.label(format_local(item.send_at));
+
+                        let recipients: Vec<String> = item.pending.recipients.iter()
+                            .map(|r| if r.company.is_empty() {
+                                r.email.clone()
+                            } else {
+                                format!("{} ({})", r.email, r.company)
+                            })
+                            .collect();
+                        ui.label(recipients.join("\n"));
+                        let subjects: Vec<&str> = item.pending.recipients.iter().map(|r| r.subject.as_str()).collect();
+                        ui.label(subjects.join("\n"));
+
+                        let attachment_count: usize = item.pending.recipients.iter()
+                            .map(|r| r.attachments.len())
+                            .sum();
+                        ui.label(format!("{}件", attachment_count));
+
+                        if ui.button("予約を取り消す").clicked() {
+                            cancel_id = Some(item.id.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+    });
+
+    if let Some(id) = cancel_id {
+        if let Some(cancelled) = state.schedule.cancel(&id) {
+            state.status_message = match state.schedule.save() {
+                Ok(()) => format!("{} の予約を取り消しました", format_local(cancelled.send_at)),
+                Err(e) => format!("❌ {}", e),
+            };
+        }
+    }
+}
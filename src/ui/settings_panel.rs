@@ -1,24 +1,477 @@
 use eframe::egui;
-use crate::models::AppState;
-use crate::api::GasClient;
+use crate::audit::{self, AuditAction};
+use crate::backend::BackendKind;
+use crate::auth::{Role, UserStore, MIN_PASSWORD_CHARS};
+use crate::models::{AppState, StartupPhase, UserAdminForm};
+use crate::schedule;
+use crate::ui::mail_panel::MAX_UNDO_SEND_SECS;
+use crate::utils::now_unix_secs;
+use crate::worker::Job;
+use std::collections::HashMap;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 150, 150);
 
 pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
     ui.heading("設定");
     ui.separator();
 
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        // 接続設定とユーザー管理は管理者だけ
+        if state.auth_role >= Role::Admin {
+            show_connection(ui, state);
+            ui.add_space(10.0);
+            show_users(ui, state);
+            ui.add_space(10.0);
+        }
+        show_display(ui);
+        ui.add_space(10.0);
+        show_password_change(ui, state);
+    });
+}
+
+/// 表示倍率（設定ファイルに保存され、次回も同じ倍率で開く）
+fn show_display(ui: &mut egui::Ui) {
+    ui.group(|ui| {
+        ui.horizontal(|ui| {
+            let zoom = ui.ctx().zoom_factor();
+            ui.label(format!("表示倍率: {:.0}%", zoom * 100.0));
+            if ui.add_enabled(zoom != 1.0, egui::Button::new("100%に戻す")).clicked() {
+                ui.ctx().set_zoom_factor(1.0);
+            }
+        });
+        ui.weak("Ctrl + / Ctrl - で変更できます");
+    });
+}
+
+fn show_connection(ui: &mut egui::Ui, state: &mut AppState) {
+    show_profiles(ui, state);
+
+    ui.add_space(10.0);
+
     ui.group(|ui| {
+        let backend = &mut state.config.active_mut().backend;
         ui.label("GAS ウェブアプリ URL:");
-        ui.text_edit_singleline(&mut state.gas_url);
-        
+        ui.text_edit_singleline(&mut backend.gas_url);
+        ui.weak("テンプレート・宛先・署名などのマスターデータは常にこのURLから取得します");
+
+        ui.add_space(6.0);
+        ui.label("共有シークレット:");
+        ui.add(egui::TextEdit::singleline(&mut backend.gas_secret).password(true));
+        ui.weak("Code.gs のスクリプトプロパティ SHARED_SECRET と同じ値を入れてください。署名のないリクエストは GAS が拒否します");
+    });
+
+    ui.add_space(10.0);
+
+    ui.group(|ui| {
+        ui.label("送信方法:");
+        ui.horizontal(|ui| {
+            for kind in BackendKind::ALL {
+                ui.radio_value(&mut state.config.active_mut().backend.kind, kind, kind.label());
+            }
+        });
+
+        ui.add_space(4.0);
+
+        let config = &mut state.config.active_mut().backend;
+        match config.kind {
+            BackendKind::Gas => {
+                ui.weak("GAS ウェブアプリ経由で Gmail から送信します");
+            }
+            BackendKind::Smtp => {
+                egui::Grid::new("smtp_settings_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("差出人アドレス:");
+                        ui.text_edit_singleline(&mut config.from_address);
+                        ui.end_row();
+
+                        ui.label("SMTPサーバー:");
+                        ui.text_edit_singleline(&mut config.smtp.host);
+                        ui.end_row();
+
+                        ui.label("ポート:");
+                        ui.add(egui::DragValue::new(&mut config.smtp.port).range(1..=65535));
+                        ui.end_row();
+
+                        ui.label("ユーザー名:");
+                        ui.text_edit_singleline(&mut config.smtp.username);
+                        ui.end_row();
+
+                        ui.label("パスワード:");
+                        ui.add(egui::TextEdit::singleline(&mut config.smtp.password).password(true));
+                        ui.end_row();
+                    });
+                ui.weak("ポート465はSMTPS、それ以外はSTARTTLSで接続します");
+            }
+            BackendKind::FileDrop => {
+                egui::Grid::new("file_drop_settings_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("差出人アドレス:");
+                        ui.text_edit_singleline(&mut config.from_address);
+                        ui.end_row();
+
+                        ui.label("出力先フォルダ:");
+                        ui.text_edit_singleline(&mut config.drop_dir);
+                        ui.end_row();
+                    });
+                ui.weak("送信せずに、宛先ごとの .eml ファイルをフォルダに書き出します");
+            }
+        }
+
+        ui.add_space(4.0);
+
         if ui.button("接続テスト").clicked() {
-            let client = GasClient::new(state.gas_url.clone());
-            match client.get_templates() {
-                Ok(_) => state.status_message = "✅ 接続成功！".to_string(),
-                Err(e) => state.status_message = format!("❌ {}", e),
+            state.status_message = "接続中...".to_string();
+            state.job_queue.push(Job::TestConnection);
+        }
+    });
+
+    ui.add_space(10.0);
+
+    ui.group(|ui| {
+        ui.label("通信:");
+        let backend = &mut state.config.active_mut().backend;
+        egui::Grid::new("network_settings_grid")
+            .num_columns(2)
+            .spacing([10.0, 6.0])
+            .show(ui, |ui| {
+                ui.label("タイムアウト:");
+                ui.add(egui::DragValue::new(&mut backend.timeout_secs).range(5..=300).suffix(" 秒"));
+                ui.end_row();
+
+                let retry = &mut backend.retry;
+                ui.label("試行回数:");
+                ui.add(egui::DragValue::new(&mut retry.max_attempts).range(1..=10).suffix(" 回"));
+                ui.end_row();
+
+                ui.label("再試行の間隔:");
+                ui.add(egui::DragValue::new(&mut retry.initial_delay_ms).range(100..=10_000).suffix(" ミリ秒"));
+                ui.end_row();
+
+                ui.label("間隔の上限:");
+                ui.add(egui::DragValue::new(&mut retry.max_delay_ms).range(retry.initial_delay_ms..=60_000).suffix(" ミリ秒"));
+                ui.end_row();
+            });
+        ui.weak("通信に失敗したときは、間隔を倍にしながら上限まで待って再試行します");
+    });
+
+    ui.add_space(10.0);
+
+    ui.group(|ui| {
+        ui.horizontal(|ui| {
+            ui.label("送信取り消しの猶予:");
+            let response = ui.add(egui::DragValue::new(&mut state.undo_send_secs)
+                .range(0..=MAX_UNDO_SEND_SECS)
+                .suffix(" 秒"));
+            if response.drag_stopped() || response.lost_focus() {
+                let mut settings = HashMap::new();
+                settings.insert("undo_send_seconds".to_string(), state.undo_send_secs.to_string());
+                state.job_queue.push(Job::SaveSettings(settings));
+            }
+        });
+        ui.weak("「送信する」を押してからこの秒数の間は送信を取り消せます（0で即送信）");
+    });
+
+    if let Some(path) = state.config.path() {
+        ui.add_space(10.0);
+        ui.weak(format!("設定は {} に自動で保存されます", path.display()));
+    }
+}
+
+/// 接続先のプロファイルの切り替え・追加・削除
+fn show_profiles(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.group(|ui| {
+        ui.strong("接続先プロファイル");
+        ui.add_space(4.0);
+
+        let mut selected = state.config.active_profile.clone();
+        let mut remove = None;
+        let mut add = false;
+        ui.horizontal(|ui| {
+            ui.label("使用中:");
+            egui::ComboBox::from_id_salt("active_profile")
+                .selected_text(&selected)
+                .show_ui(ui, |ui| {
+                    for profile in &state.config.profiles {
+                        ui.selectable_value(&mut selected, profile.name.clone(), &profile.name);
+                    }
+                });
+
+            ui.add_space(12.0);
+            ui.add(egui::TextEdit::singleline(&mut state.profile_name_input)
+                .hint_text("新しいプロファイル名")
+                .desired_width(140.0));
+            add = ui.button("➕ 複製して追加").on_hover_text("使用中のプロファイルの設定を複製します").clicked();
+        });
+
+        let others: Vec<String> = state.config.profiles.iter()
+            .map(|p| p.name.clone())
+            .filter(|name| *name != state.config.active_profile)
+            .collect();
+        if !others.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("削除:");
+                for name in others {
+                    if ui.small_button(format!("🗑 {}", name)).clicked() {
+                        remove = Some(name);
+                    }
+                }
+            });
+        }
+        ui.weak("本番と検証用など、接続先（URL・共有シークレット・送信方法・通信）ごとに保存します。切り替えるとデータを読み込み直します");
+
+        if selected != state.config.active_profile {
+            switch_profile(state, &selected);
+        }
+        if add {
+            let name = state.profile_name_input.clone();
+            state.status_message = match state.config.add_profile(&name) {
+                Ok(()) => {
+                    state.profile_name_input.clear();
+                    format!("✅ プロファイル「{}」を追加しました", name.trim())
+                }
+                Err(e) => format!("❌ {}", e),
+            };
+        }
+        if let Some(name) = remove {
+            state.status_message = match state.config.remove_profile(&name) {
+                Ok(()) => format!("✅ プロファイル「{}」を削除しました", name),
+                Err(e) => format!("❌ {}", e),
+            };
+        }
+    });
+}
+
+/// 別のスプレッドシートに切り替えるので、読み込んだデータを捨てて読み込み直す
+/// 送信待ち・予約送信が別の接続先から送られないよう、残っている間は切り替えない
+fn switch_profile(state: &mut AppState, name: &str) {
+    if state.job_progress.is_some() || !state.job_queue.is_empty() {
+        state.status_message = "❌ 処理中はプロファイルを切り替えられません".to_string();
+        return;
+    }
+    if !state.outbox.is_empty() || !state.schedule.items().is_empty() {
+        state.status_message = "❌ 送信待ち・予約送信があるためプロファイルを切り替えられません".to_string();
+        return;
+    }
+    if let Err(e) = state.config.switch_profile(name) {
+        state.status_message = format!("❌ {}", e);
+        return;
+    }
+    state.clear_master_data();
+    state.loading_message = format!("プロファイル「{}」に切り替えています...", name);
+    state.startup_phase = StartupPhase::Splash;
+}
+
+/// ユーザー管理の操作（ユーザー名, …）
+enum UserChange {
+    Add(String, String, Role),
+    SetRole(String, Role),
+    ResetPassword(String, String),
+    Remove(String),
+}
+
+impl UserChange {
+    /// 反映して結果のメッセージを返す
+    fn apply(self, store: &mut UserStore) -> Result<String, String> {
+        match self {
+            UserChange::Add(name, password, role) => {
+                store.add_user(&name, &password, role)?;
+                Ok(format!("{} を追加しました", name.trim()))
+            }
+            UserChange::SetRole(name, role) => {
+                store.set_role(&name, role)?;
+                Ok(format!("{} の権限を「{}」にしました", name, role.label()))
+            }
+            UserChange::ResetPassword(name, password) => {
+                store.set_password(&name, &password)?;
+                Ok(format!("{} のパスワードを再設定しました", name))
             }
+            UserChange::Remove(name) => {
+                store.remove_user(&name)?;
+                Ok(format!("{} を削除しました", name))
+            }
+        }
+    }
+}
+
+/// ユーザーの追加・権限の変更・パスワードの再設定・削除
+fn show_users(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.group(|ui| {
+        ui.strong("ユーザー管理");
+        ui.add_space(4.0);
+
+        let mut change = None;
+        let mut unlock = None;
+        let now = now_unix_secs();
+        let form = &mut state.user_admin;
+        egui::Grid::new("user_grid")
+            .num_columns(3)
+            .spacing([16.0, 6.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for user in state.user_store.users() {
+                    let name = user.username.clone();
+                    let is_me = name == state.auth_username;
+                    ui.label(if is_me { format!("{}（自分）", name) } else { name.clone() });
+
+                    let mut role = user.role;
+                    egui::ComboBox::from_id_salt(("user_role", &name))
+                        .selected_text(role.label())
+                        .show_ui(ui, |ui| {
+                            for r in Role::ALL {
+                                ui.selectable_value(&mut role, r, r.label());
+                            }
+                        });
+                    if role != user.role {
+                        change = Some(UserChange::SetRole(name.clone(), role));
+                    }
+
+                    ui.horizontal(|ui| {
+                        match &mut form.reset {
+                            Some((target, password)) if *target == name => {
+                                ui.add(egui::TextEdit::singleline(password)
+                                    .password(true)
+                                    .hint_text("新しいパスワード")
+                                    .desired_width(140.0));
+                                if ui.button("設定").clicked() {
+                                    change = Some(UserChange::ResetPassword(name.clone(), password.clone()));
+                                }
+                                if ui.button("やめる").clicked() {
+                                    form.reset = None;
+                                }
+                            }
+                            _ => {
+                                if ui.small_button("🔑 パスワード再設定").clicked() {
+                                    form.reset = Some((name.clone(), String::new()));
+                                }
+                            }
+                        }
+                        if let Some(until) = state.login_throttle.locked_until(&name, now) {
+                            if ui.small_button("🔓 ロック解除")
+                                .on_hover_text(format!("ログインに続けて失敗したため {} までロック中", schedule::format_local(until)))
+                                .clicked()
+                            {
+                                unlock = Some(name.clone());
+                            }
+                        }
+                        if is_me {
+                            return;
+                        }
+                        if form.confirm_delete.as_ref() == Some(&name) {
+                            ui.colored_label(ERROR_COLOR, format!("{} を削除しますか？", name));
+                            if ui.button("削除する").clicked() {
+                                change = Some(UserChange::Remove(name.clone()));
+                            }
+                            if ui.button("やめる").clicked() {
+                                form.confirm_delete = None;
+                            }
+                        } else if ui.small_button("🗑").on_hover_text("削除").clicked() {
+                            form.confirm_delete = Some(name.clone());
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+        ui.add_space(6.0);
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut form.new_username)
+                .hint_text("ユーザー名")
+                .desired_width(120.0));
+            ui.add(egui::TextEdit::singleline(&mut form.new_password)
+                .password(true)
+                .hint_text(format!("パスワード（{}文字以上）", MIN_PASSWORD_CHARS))
+                .desired_width(160.0));
+            egui::ComboBox::from_id_salt("new_user_role")
+                .selected_text(form.new_role.label())
+                .show_ui(ui, |ui| {
+                    for r in Role::ALL {
+                        ui.selectable_value(&mut form.new_role, r, r.label());
+                    }
+                });
+            if ui.button("➕ 追加").clicked() {
+                change = Some(UserChange::Add(form.new_username.clone(), form.new_password.clone(), form.new_role));
+            }
+        });
+        ui.weak("送信者: メールの作成・送信 / テンプレート編集者: テンプレート・宛先・紐付け・署名の編集も / 管理者: 接続設定とユーザー管理も");
+
+        if let Some(name) = unlock {
+            state.login_throttle.clear(&name);
+            state.user_admin.message = Some(match state.login_throttle.save() {
+                Ok(()) => format!("✅ {} のロックを解除しました", name),
+                Err(e) => format!("❌ {}", e),
+            });
+            audit::record(state, AuditAction::UserAdmin, format!("{} のロックを解除", name));
+        }
+        if let Some(change) = change {
+            // パスワードを再設定したら、それまでのログイン失敗も消す
+            let unlocks = match &change {
+                UserChange::ResetPassword(name, _) | UserChange::Remove(name) => Some(name.clone()),
+                _ => None,
+            };
+            // 保存できたときだけ反映する
+            let mut store = state.user_store.clone();
+            let result = change.apply(&mut store).and_then(|message| store.save().map(|_| message));
+            match result {
+                Ok(message) => {
+                    state.user_store = store;
+                    if let Some(name) = unlocks {
+                        state.login_throttle.clear(&name);
+                        let _ = state.login_throttle.save();
+                    }
+                    audit::record(state, AuditAction::UserAdmin, message.clone());
+                    state.user_admin = UserAdminForm { message: Some(format!("✅ {}", message)), ..Default::default() };
+                }
+                Err(e) => state.user_admin.message = Some(format!("❌ {}", e)),
+            }
+        }
+        if let Some(message) = &state.user_admin.message {
+            ui.label(message);
         }
     });
+}
+
+/// ログイン中のユーザー自身のパスワード変更
+fn show_password_change(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.group(|ui| {
+        ui.strong("パスワード変更");
+        let form = &mut state.user_admin;
+        egui::Grid::new("password_change_grid")
+            .num_columns(2)
+            .spacing([10.0, 6.0])
+            .show(ui, |ui| {
+                ui.label("現在のパスワード:");
+                ui.add(egui::TextEdit::singleline(&mut form.current_password).password(true));
+                ui.end_row();
 
-    ui.add_space(20.0);
-    ui.label("注意: URLは自動的に保存・固定されていますが、変更が必要な場合はこちらで編集可能です。");
+                ui.label("新しいパスワード:");
+                ui.add(egui::TextEdit::singleline(&mut form.changed_password).password(true));
+                ui.end_row();
+            });
+        let ready = !form.current_password.is_empty() && !form.changed_password.is_empty();
+        if ui.add_enabled(ready, egui::Button::new("変更")).clicked() {
+            let mut store = state.user_store.clone();
+            let username = state.auth_username.clone();
+            let result = store.authenticate(&username, &form.current_password)
+                .map_err(|_| "現在のパスワードが正しくありません".to_string())
+                .and_then(|_| store.set_password(&username, &form.changed_password))
+                .and_then(|_| store.save());
+            match result {
+                Ok(()) => {
+                    state.user_store = store;
+                    state.user_admin = UserAdminForm { message: Some("✅ パスワードを変更しました".to_string()), ..Default::default() };
+                    audit::record(state, AuditAction::UserAdmin, "自分のパスワードを変更");
+                }
+                Err(e) => form.message = Some(format!("❌ {}", e)),
+            }
+        }
+        if state.auth_role < Role::Admin {
+            if let Some(message) = &state.user_admin.message {
+                ui.label(message);
+            }
+        }
+    });
 }
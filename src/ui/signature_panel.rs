@@ -0,0 +1,412 @@
+use eframe::egui;
+use crate::api::ApiError;
+use crate::auth::Role;
+use crate::models::{AppState, Signature};
+use crate::signature;
+use crate::template_engine;
+use crate::utils::find_placeholders;
+use crate::worker::Job;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 150, 150);
+
+/// 署名で使える変数（表示名, 挿入する文字列）
+const VARIABLES: [(&str, &str); 4] = [
+    ("送信者名", "{{sender_name}}"),
+    ("今日", "{{today}}"),
+    ("今日（和暦）", "{{today|和暦}}"),
+    ("翌月末", "{{next_month_end}}"),
+];
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("署名");
+    ui.separator();
+
+    show_profile(ui, state);
+    ui.add_space(10.0);
+    ui.separator();
+
+    ui.horizontal_top(|ui| {
+        ui.vertical(|ui| {
+            ui.set_width(200.0);
+            show_list(ui, state);
+        });
+        ui.separator();
+        ui.vertical(|ui| {
+            show_editor(ui, state);
+        });
+    });
+}
+
+/// ログイン中のユーザーの送信者名（他のユーザーとは別に保存する）
+fn show_profile(ui: &mut egui::Ui, state: &mut AppState) {
+    if state.auth_username.is_empty() {
+        ui.weak("ログインし直すと、送信者名と既定の署名をユーザーごとに保存できます");
+        return;
+    }
+    let mut sender_name = signature::sender_name(state).to_string();
+    ui.horizontal(|ui| {
+        ui.label(format!("{} さんの送信者名:", state.auth_username));
+        let response = ui.add(egui::TextEdit::singleline(&mut sender_name)
+            .hint_text("例: 田中 太郎")
+            .desired_width(200.0));
+        if response.changed() {
+            state.user_profiles.entry(state.auth_username.clone()).or_default().sender_name = sender_name.clone();
+        }
+        if response.lost_focus() {
+            signature::save_profile(state, |profile| profile.sender_name = sender_name);
+        }
+    });
+    ui.weak("署名の {{sender_name}} に入ります。既定の署名（⭐）もユーザーごとに保存します。");
+}
+
+/// 新しい署名の編集を始める
+pub fn open_new(state: &mut AppState) {
+    let draft = Signature { name: "新しい署名".to_string(), content: "--\n{{sender_name}}\n".to_string() };
+    state.signature_editor = Default::default();
+    state.signature_editor.draft = Some(draft);
+}
+
+fn open_existing(state: &mut AppState, index: usize) {
+    if let Some(sig) = state.signatures.get(index).cloned() {
+        state.signature_editor = Default::default();
+        state.signature_editor.original_name = Some(sig.name.clone());
+        state.signature_editor.draft = Some(sig);
+    }
+}
+
+/// 署名の作成・編集・削除はテンプレート編集者から（送信者は既定の署名を選ぶだけ）
+fn can_edit(state: &AppState) -> bool {
+    state.auth_role >= Role::TemplateEditor
+}
+
+/// シート上の内容から変更されているか（新規作成は常に未保存）
+fn is_dirty(state: &AppState) -> bool {
+    let editor = &state.signature_editor;
+    let Some(draft) = &editor.draft else {
+        return false;
+    };
+    let saved = editor.original_name.as_ref()
+        .and_then(|name| state.signatures.iter().find(|s| &s.name == name));
+    saved != Some(draft)
+}
+
+fn show_list(ui: &mut egui::Ui, state: &mut AppState) {
+    let dirty = is_dirty(state);
+    if can_edit(state) && ui.add_enabled(!dirty, egui::Button::new("➕ 新規")).clicked() {
+        open_new(state);
+    }
+    ui.add_space(4.0);
+
+    let mut open_idx = None;
+    egui::ScrollArea::vertical()
+        .id_salt("signature_list")
+        .show(ui, |ui| {
+            for (i, sig) in state.signatures.iter().enumerate() {
+                let is_open = state.signature_editor.original_name.as_ref() == Some(&sig.name);
+                let label = if state.selected_signature_index == Some(i) {
+                    format!("⭐ {}", sig.name)
+                } else {
+                    sig.name.clone()
+                };
+                if ui.selectable_label(is_open, label).clicked() && !is_open {
+                    open_idx = Some(i);
+                }
+            }
+            if state.signatures.is_empty() {
+                ui.weak("署名なし");
+            }
+        });
+    if dirty {
+        ui.weak("保存するか破棄してから切り替えてください");
+    } else if let Some(i) = open_idx {
+        open_existing(state, i);
+    }
+}
+
+fn show_editor(ui: &mut egui::Ui, state: &mut AppState) {
+    if state.signature_editor.draft.is_none() {
+        ui.weak("左の一覧から署名を選ぶか、「➕ 新規」で作成してください");
+        return;
+    }
+
+    let dirty = is_dirty(state);
+    let editable = can_edit(state);
+    let taken_names: Vec<String> = state.signatures.iter()
+        .filter(|s| Some(&s.name) != state.signature_editor.original_name.as_ref())
+        .map(|s| s.name.clone())
+        .collect();
+    let is_default = state.selected_signature_index
+        .and_then(|i| state.signatures.get(i))
+        .is_some_and(|s| Some(&s.name) == state.signature_editor.original_name.as_ref());
+    let editor = &mut state.signature_editor;
+    let Some(draft) = editor.draft.as_mut() else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("名前:");
+        ui.add(egui::TextEdit::singleline(&mut draft.name).interactive(editable).desired_width(300.0));
+    });
+
+    ui.add_space(4.0);
+    let mut insert = None;
+    if editable {
+        ui.horizontal_wrapped(|ui| {
+            ui.weak("変数:");
+            for (label, snippet) in VARIABLES {
+                if ui.small_button(label).on_hover_text(snippet).clicked() {
+                    insert = Some(snippet);
+                }
+            }
+        });
+    }
+    let inserted = insert.map(|snippet| {
+        let at = editor.content_cursor.unwrap_or(draft.content.chars().count());
+        super::template_panel::insert_at(&mut draft.content, at, snippet)
+    });
+
+    let mut output = egui::TextEdit::multiline(&mut draft.content)
+        .hint_text("署名を入力...")
+        .interactive(editable)
+        .desired_width(f32::INFINITY)
+        .desired_rows(8)
+        .show(ui);
+    if let Some(cursor) = inserted {
+        let range = egui::text::CCursorRange::one(egui::text::CCursor::new(cursor));
+        output.state.cursor.set_char_range(Some(range));
+        output.state.store(ui.ctx(), output.response.id);
+        editor.content_cursor = Some(cursor);
+    } else if let Some(range) = output.cursor_range {
+        editor.content_cursor = Some(range.primary.ccursor.index);
+    }
+
+    let mut problems = Vec::new();
+    if draft.name.trim().is_empty() {
+        problems.push("名前を入力してください".to_string());
+    } else if taken_names.contains(&draft.name) {
+        problems.push(format!("同じ名前の署名「{}」がすでにあります", draft.name));
+    }
+    if let Err(e) = template_engine::validate(&draft.content) {
+        problems.push(format!("署名の {}", e));
+    }
+    for problem in &problems {
+        ui.colored_label(ERROR_COLOR, format!("⚠ {}", problem));
+    }
+
+    ui.add_space(6.0);
+    let mut discard = false;
+    let mut make_default = None;
+    ui.horizontal(|ui| {
+        let save = egui::Button::new(if editor.original_name.is_some() { "💾 保存" } else { "💾 作成" });
+        if editable && ui.add_enabled(dirty && problems.is_empty(), save).clicked() {
+            state.job_queue.push(Job::SaveSignature {
+                signature: draft.clone(),
+                original_name: editor.original_name.clone(),
+            });
+        }
+        if let Some(original_name) = editor.original_name.clone() {
+            if editor.confirm_delete {
+                ui.colored_label(ERROR_COLOR, format!("「{}」をシートから削除しますか？", original_name));
+                if ui.button("削除する").clicked() {
+                    state.job_queue.push(Job::DeleteSignature(original_name));
+                    editor.confirm_delete = false;
+                }
+                if ui.button("やめる").clicked() {
+                    editor.confirm_delete = false;
+                }
+            } else {
+                if editable && ui.button("🗑 削除").clicked() {
+                    editor.confirm_delete = true;
+                }
+                let button = egui::Button::new(if is_default { "⭐ 既定の署名" } else { "⭐ 既定にする" });
+                if ui.add_enabled(!is_default, button).on_hover_text("ログイン中のユーザーがメール作成で使う署名にします").clicked() {
+                    make_default = Some(original_name);
+                }
+            }
+        }
+        if dirty && ui.button("破棄").clicked() {
+            discard = true;
+        }
+        if dirty {
+            ui.weak("未保存の変更があります");
+        }
+    });
+    if discard {
+        match state.signature_editor.original_name.clone() {
+            Some(name) => match state.signatures.iter().position(|s| s.name == name) {
+                Some(i) => open_existing(state, i),
+                None => state.signature_editor = Default::default(),
+            },
+            None => state.signature_editor = Default::default(),
+        }
+        return;
+    }
+    if let Some(name) = make_default {
+        if let Some(i) = state.signatures.iter().position(|s| s.name == name) {
+            signature::remember_default(state, i);
+        }
+    }
+
+    ui.add_space(10.0);
+    ui.separator();
+    show_preview(ui, state);
+}
+
+/// 送信者名と今日の日付を差し込んだ結果
+fn show_preview(ui: &mut egui::Ui, state: &AppState) {
+    let Some(draft) = &state.signature_editor.draft else {
+        return;
+    };
+    let rendered = signature::render(&draft.content, signature::sender_name(state));
+    let unresolved = find_placeholders(&rendered);
+
+    ui.strong("プレビュー");
+    egui::Frame::none()
+        .fill(ui.visuals().extreme_bg_color)
+        .inner_margin(8.0)
+        .rounding(4.0)
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.label(rendered);
+        });
+    if !unresolved.is_empty() {
+        ui.colored_label(ERROR_COLOR, format!("⚠ 値のない変数があります: {}", unresolved.join(", ")));
+    }
+}
+
+/// シートから取り直した一覧に差し替え、選択中の署名を名前で選び直す
+fn replace_signatures(state: &mut AppState, signatures: Vec<Signature>, renamed: Option<(&str, &str)>) {
+    let selected = state.selected_signature_index
+        .and_then(|i| state.signatures.get(i))
+        .map(|s| match renamed {
+            Some((from, to)) if s.name == from => to.to_string(),
+            _ => s.name.clone(),
+        });
+    state.signatures = signatures;
+    state.selected_signature_index = selected.and_then(|name| state.signatures.iter().position(|s| s.name == name));
+    if state.selected_signature_index.is_none() {
+        signature::apply_user_defaults(state);
+    }
+}
+
+/// 保存の結果を反映する（一覧を取り直せなかった場合は手元の一覧を書き換える）
+pub fn apply_saved(
+    state: &mut AppState,
+    saved: Signature,
+    original_name: Option<String>,
+    result: Result<(), ApiError>,
+    signatures: Option<Vec<Signature>>,
+) {
+    match result {
+        Ok(()) => {
+            let signatures = signatures.unwrap_or_else(|| {
+                let mut local = state.signatures.clone();
+                match original_name.as_ref().and_then(|n| local.iter().position(|s| &s.name == n)) {
+                    Some(i) => local[i] = saved.clone(),
+                    None => local.push(saved.clone()),
+                }
+                local
+            });
+            let renamed = original_name.as_deref()
+                .filter(|from| *from != saved.name)
+                .map(|from| (from, saved.name.as_str()));
+            replace_signatures(state, signatures, renamed);
+            if let Some((from, to)) = renamed {
+                // 名前を変えた署名を既定にしているユーザーの設定も書き換える
+                let users: Vec<String> = state.user_profiles.iter()
+                    .filter(|(_, p)| p.signature.as_deref() == Some(from))
+                    .map(|(user, _)| user.clone())
+                    .collect();
+                for user in users {
+                    if let Some(profile) = state.user_profiles.get_mut(&user) {
+                        profile.signature = Some(to.to_string());
+                        state.job_queue.push(Job::SaveSettings(signature::signature_setting(&user, to)));
+                    }
+                }
+            }
+
+            let editor = &mut state.signature_editor;
+            if editor.original_name == original_name {
+                editor.original_name = Some(saved.name.clone());
+            }
+            state.status_message = format!("✅ 署名「{}」を保存しました", saved.name);
+        }
+        Err(e) => {
+            if let Some(signatures) = signatures {
+                replace_signatures(state, signatures, None);
+            }
+            state.status_message = format!("❌ 署名保存エラー: {}", e);
+        }
+    }
+}
+
+/// 削除の結果を反映する
+pub fn apply_deleted(state: &mut AppState, name: &str, result: Result<(), ApiError>, signatures: Option<Vec<Signature>>) {
+    match result {
+        Ok(()) => {
+            let signatures = signatures.unwrap_or_else(|| {
+                state.signatures.iter().filter(|s| s.name != name).cloned().collect()
+            });
+            replace_signatures(state, signatures, None);
+            if state.signature_editor.original_name.as_deref() == Some(name) {
+                state.signature_editor = Default::default();
+            }
+            state.status_message = format!("🗑 署名「{}」を削除しました", name);
+        }
+        Err(e) => {
+            if let Some(signatures) = signatures {
+                replace_signatures(state, signatures, None);
+            }
+            state.status_message = format!("❌ 署名削除エラー: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserProfile;
+
+    fn signature(name: &str) -> Signature {
+        Signature { name: name.to_string(), content: format!("--\n{}", name) }
+    }
+
+    #[test]
+    fn test_rename_moves_users_default() {
+        let mut state = AppState {
+            signatures: vec![signature("デフォルト"), signature("営業用")],
+            selected_signature_index: Some(1),
+            auth_username: "tanaka".to_string(),
+            ..Default::default()
+        };
+        for user in ["tanaka", "suzuki"] {
+            let profile = UserProfile { signature: Some("営業用".to_string()), ..Default::default() };
+            state.user_profiles.insert(user.to_string(), profile);
+        }
+
+        let renamed = Signature { name: "営業部".to_string(), ..signature("営業用") };
+        let latest = vec![renamed.clone(), signature("デフォルト")];
+        apply_saved(&mut state, renamed, Some("営業用".to_string()), Ok(()), Some(latest));
+
+        assert_eq!(state.selected_signature_index, Some(0), "名前を変えた署名を選んだまま");
+        for user in ["tanaka", "suzuki"] {
+            assert_eq!(state.user_profiles[user].signature.as_deref(), Some("営業部"));
+        }
+        assert_eq!(state.job_queue.len(), 2, "ユーザーごとに設定を保存する");
+    }
+
+    #[test]
+    fn test_deleting_selected_signature_falls_back() {
+        let mut state = AppState {
+            signatures: vec![signature("デフォルト"), signature("営業用")],
+            selected_signature_index: Some(1),
+            ..Default::default()
+        };
+        state.signature_editor.original_name = Some("営業用".to_string());
+        apply_deleted(&mut state, "営業用", Ok(()), None);
+
+        assert_eq!(state.signatures.len(), 1);
+        assert_eq!(state.selected_signature_index, Some(0));
+        assert!(state.signature_editor.draft.is_none());
+    }
+}
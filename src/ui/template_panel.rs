@@ -0,0 +1,472 @@
+use eframe::egui;
+use crate::api::ApiError;
+use crate::models::{AppState, Tab, Template};
+use crate::template_engine;
+use crate::utils::{apply_variables, find_placeholders};
+use crate::worker::Job;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 150, 150);
+
+/// 変数パレットの組み込み項目（表示名, 挿入する文字列）
+const STANDARD_VARIABLES: [(&str, &str); 4] = [
+    ("会社名", "{{company}}"),
+    ("氏名", "{{name}}"),
+    ("メール", "{{email}}"),
+    ("ID", "{{id}}"),
+];
+const BUILTIN_VARIABLES: [(&str, &str); 4] = [
+    ("今日", "{{today}}"),
+    ("今日（和暦）", "{{today|和暦}}"),
+    ("翌月末", "{{next_month_end}}"),
+    ("#if", "{{#if 変数}}\n{{else}}\n{{/if}}"),
+];
+
+pub fn show(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.heading("テンプレート");
+    ui.separator();
+
+    ui.horizontal_top(|ui| {
+        ui.vertical(|ui| {
+            ui.set_width(200.0);
+            show_list(ui, state);
+        });
+        ui.separator();
+        ui.vertical(|ui| {
+            show_editor(ui, state);
+        });
+    });
+}
+
+/// 新しいテンプレートの編集を始める（メール作成画面の ➕ からも呼ぶ）
+pub fn open_new(state: &mut AppState) {
+    let template = Template { name: "新しいテンプレート".to_string(), ..Default::default() };
+    request_open(state, template, None);
+}
+
+/// 既存のテンプレートを編集する
+pub fn open_existing(state: &mut AppState, index: usize) {
+    if let Some(template) = state.templates.get(index).cloned() {
+        let name = template.name.clone();
+        request_open(state, template, Some(name));
+    }
+}
+
+/// 未保存の変更があれば、破棄するか確認してから開く
+fn request_open(state: &mut AppState, template: Template, original_name: Option<String>) {
+    state.tab = Tab::Templates;
+    let editor = &mut state.template_editor;
+    if editor.is_dirty(&state.templates) {
+        editor.pending_open = Some((template, original_name));
+    } else {
+        editor.open(template, original_name);
+    }
+}
+
+fn show_list(ui: &mut egui::Ui, state: &mut AppState) {
+    if ui.button("➕ 新規").clicked() {
+        open_new(state);
+    }
+    ui.add_space(4.0);
+
+    let mut open_idx = None;
+    egui::ScrollArea::vertical()
+        .id_salt("template_list")
+        .show(ui, |ui| {
+            for (i, template) in state.templates.iter().enumerate() {
+                let is_open = state.template_editor.original_name.as_ref() == Some(&template.name);
+                if ui.selectable_label(is_open, &template.name).clicked() && !is_open {
+                    open_idx = Some(i);
+                }
+            }
+            if state.templates.is_empty() {
+                ui.weak("テンプレートなし");
+            }
+        });
+    if let Some(i) = open_idx {
+        open_existing(state, i);
+    }
+}
+
+fn show_editor(ui: &mut egui::Ui, state: &mut AppState) {
+    // 未保存の変更の破棄確認
+    if let Some((next, _)) = &state.template_editor.pending_open {
+        let next_name = next.name.clone();
+        ui.colored_label(ERROR_COLOR, format!("⚠ 編集中の変更が保存されていません。破棄して「{}」を開きますか？", next_name));
+        ui.horizontal(|ui| {
+            if ui.button("破棄して開く").clicked() {
+                if let Some((template, original_name)) = state.template_editor.pending_open.take() {
+                    state.template_editor.open(template, original_name);
+                }
+            }
+            if ui.button("編集を続ける").clicked() {
+                state.template_editor.pending_open = None;
+            }
+        });
+        ui.separator();
+    }
+
+    if state.template_editor.draft.is_none() {
+        ui.weak("左の一覧からテンプレートを選ぶか、「➕ 新規」で作成してください");
+        return;
+    }
+
+    show_conflict(ui, state);
+
+    let dirty = state.template_editor.is_dirty(&state.templates);
+    let custom_fields = custom_field_names(state);
+    let editor = &mut state.template_editor;
+    let Some(draft) = editor.draft.as_mut() else {
+        return;
+    };
+
+    egui::Grid::new("template_editor_grid")
+        .num_columns(2)
+        .spacing([10.0, 6.0])
+        .show(ui, |ui| {
+            ui.label("名前:");
+            ui.add(egui::TextEdit::singleline(&mut draft.name).desired_width(400.0));
+            ui.end_row();
+
+            ui.label("件名:");
+            ui.add(egui::TextEdit::singleline(&mut draft.subject).desired_width(400.0));
+            ui.end_row();
+        });
+
+    // 変数パレット（本文のカーソル位置に挿入）
+    ui.add_space(4.0);
+    let mut insert = None;
+    ui.horizontal_wrapped(|ui| {
+        ui.weak("変数:");
+        for (label, snippet) in STANDARD_VARIABLES.iter().chain(&BUILTIN_VARIABLES) {
+            if ui.small_button(*label).on_hover_text(*snippet).clicked() {
+                insert = Some(snippet.to_string());
+            }
+        }
+        for field in &custom_fields {
+            let snippet = format!("{{{{{}}}}}", field);
+            if ui.small_button(field).on_hover_text(&snippet).clicked() {
+                insert = Some(snippet);
+            }
+        }
+    });
+    let inserted = insert.map(|snippet| {
+        let at = editor.body_cursor.unwrap_or(draft.body.chars().count());
+        insert_at(&mut draft.body, at, &snippet)
+    });
+
+    ui.add_space(4.0);
+    let mut output = egui::TextEdit::multiline(&mut draft.body)
+        .hint_text("本文を入力...")
+        .desired_width(f32::INFINITY)
+        .desired_rows(12)
+        .show(ui);
+    if let Some(cursor) = inserted {
+        // 続けて挿入できるよう、エディタのカーソルも挿入した変数の後ろに動かす
+        let range = egui::text::CCursorRange::one(egui::text::CCursor::new(cursor));
+        output.state.cursor.set_char_range(Some(range));
+        output.state.store(ui.ctx(), output.response.id);
+        editor.body_cursor = Some(cursor);
+    } else if let Some(range) = output.cursor_range {
+        editor.body_cursor = Some(range.primary.ccursor.index);
+    }
+
+    // 書式チェック
+    let mut problems = Vec::new();
+    if draft.name.trim().is_empty() {
+        problems.push("名前を入力してください".to_string());
+    }
+    for (label, text) in [("件名", &draft.subject), ("本文", &draft.body)] {
+        if let Err(e) = template_engine::validate(text) {
+            problems.push(format!("{}の {}", label, e));
+        }
+    }
+    for problem in &problems {
+        ui.colored_label(ERROR_COLOR, format!("⚠ {}", problem));
+    }
+
+    ui.add_space(6.0);
+    let mut discard = false;
+    ui.horizontal(|ui| {
+        let save = egui::Button::new(if editor.original_name.is_some() { "💾 保存" } else { "💾 作成" });
+        if ui.add_enabled(dirty && problems.is_empty(), save).clicked() {
+            state.job_queue.push(Job::SaveTemplate {
+                template: draft.clone(),
+                original_name: editor.original_name.clone(),
+            });
+        }
+        if let Some(original_name) = editor.original_name.clone() {
+            if editor.confirm_delete {
+                ui.colored_label(ERROR_COLOR, format!("「{}」をシートから削除しますか？", original_name));
+                if ui.button("削除する").clicked() {
+                    let template = Template { name: original_name, updated_at: draft.updated_at.clone(), ..Default::default() };
+                    state.job_queue.push(Job::DeleteTemplate(template));
+                    editor.confirm_delete = false;
+                }
+                if ui.button("やめる").clicked() {
+                    editor.confirm_delete = false;
+                }
+            } else if ui.button("🗑 削除").clicked() {
+                editor.confirm_delete = true;
+            }
+        } else if ui.button("破棄").clicked() {
+            discard = true;
+        }
+        if dirty {
+            ui.weak("未保存の変更があります");
+        }
+    });
+    if discard {
+        state.template_editor = Default::default();
+        return;
+    }
+
+    ui.add_space(10.0);
+    ui.separator();
+    show_preview(ui, state);
+}
+
+/// 保存・削除が競合したとき、シートの最新を読み込むか上書きするかを選ぶ
+fn show_conflict(ui: &mut egui::Ui, state: &mut AppState) {
+    let editor = &mut state.template_editor;
+    let Some(message) = editor.conflict.clone() else {
+        return;
+    };
+    ui.colored_label(ERROR_COLOR, format!("⚠ {}", message));
+
+    let Some(original_name) = editor.original_name.clone() else {
+        ui.weak("名前を変えて作成してください");
+        ui.separator();
+        return;
+    };
+    let latest = state.templates.iter().find(|t| t.name == original_name).cloned();
+    ui.horizontal(|ui| {
+        let load_label = if latest.is_some() { "最新を読み込む" } else { "編集をやめる" };
+        if ui.button(load_label).clicked() {
+            match latest.clone() {
+                Some(latest) => editor.open(latest, Some(original_name.clone())),
+                None => *editor = Default::default(),
+            }
+        }
+        if ui.button("上書き保存").on_hover_text("他のユーザーの変更を破棄して、編集中の内容で保存します").clicked() {
+            if let Some(draft) = editor.draft.as_mut() {
+                // 削除されていた場合は新規として作り直す
+                match &latest {
+                    Some(latest) => draft.updated_at = latest.updated_at.clone(),
+                    None => editor.original_name = None,
+                }
+                state.job_queue.push(Job::SaveTemplate {
+                    template: draft.clone(),
+                    original_name: editor.original_name.clone(),
+                });
+            }
+            editor.conflict = None;
+        }
+    });
+    ui.separator();
+}
+
+/// 宛先マスターの宛先に差し込んだ結果を表示する
+fn show_preview(ui: &mut egui::Ui, state: &mut AppState) {
+    ui.horizontal(|ui| {
+        ui.strong("プレビュー");
+        let selected = state.template_editor.preview_recipient_index
+            .and_then(|i| state.recipients_master.get(i))
+            .map(|r| format!("{} {}", r.company, r.name))
+            .unwrap_or_else(|| "宛先を選択".to_string());
+        egui::ComboBox::from_id_salt("template_preview_recipient")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (i, rec) in state.recipients_master.iter().enumerate() {
+                    let label = format!("{} {}（{}）", rec.company, rec.name, rec.email);
+                    ui.selectable_value(&mut state.template_editor.preview_recipient_index, Some(i), label);
+                }
+            });
+    });
+
+    let (Some(draft), Some(recipient)) = (
+        &state.template_editor.draft,
+        state.template_editor.preview_recipient_index.and_then(|i| state.recipients_master.get(i)),
+    ) else {
+        ui.weak("宛先を選ぶと、差し込み後の件名と本文を表示します");
+        return;
+    };
+
+    let subject = apply_variables(draft.subject.clone(), recipient);
+    let body = apply_variables(draft.body.clone(), recipient);
+    let mut unresolved = find_placeholders(&subject);
+    for placeholder in find_placeholders(&body) {
+        if !unresolved.contains(&placeholder) {
+            unresolved.push(placeholder);
+        }
+    }
+
+    ui.label(format!("件名: {}", subject));
+    egui::Frame::none()
+        .fill(ui.visuals().extreme_bg_color)
+        .inner_margin(8.0)
+        .rounding(4.0)
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt("template_preview")
+                .max_height(ui.available_height() - 40.0)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.label(body);
+                });
+        });
+    if !unresolved.is_empty() {
+        ui.colored_label(ERROR_COLOR, format!("⚠ この宛先では値のない変数があります: {}", unresolved.join(", ")));
+    }
+}
+
+/// 宛先マスターにある追加列の名前（重複なし・名前順）
+fn custom_field_names(state: &AppState) -> Vec<String> {
+    let names: std::collections::BTreeSet<&String> = state.recipients_master.iter()
+        .flat_map(|r| r.custom_fields.keys())
+        .collect();
+    names.into_iter().cloned().collect()
+}
+
+/// text の at 文字目に snippet を挿入し、挿入後のカーソル位置（文字数）を返す
+pub fn insert_at(text: &mut String, at: usize, snippet: &str) -> usize {
+    let byte = text.char_indices().nth(at).map(|(b, _)| b).unwrap_or(text.len());
+    text.insert_str(byte, snippet);
+    text[..byte].chars().count() + snippet.chars().count()
+}
+
+/// シートから取り直した一覧に差し替え、選択中のテンプレートを名前で選び直す
+fn replace_templates(state: &mut AppState, templates: Vec<Template>, renamed: Option<(&str, &str)>) {
+    let name_of = |index: Option<usize>, state: &AppState| {
+        let name = index.and_then(|i| state.templates.get(i)).map(|t| t.name.clone())?;
+        Some(match renamed {
+            Some((from, to)) if name == from => to.to_string(),
+            _ => name,
+        })
+    };
+    let selected = name_of(state.selected_template_index, state);
+    let merge = name_of(state.merge_template_index, state);
+    let position = |name: Option<String>| name.and_then(|n| templates.iter().position(|t| t.name == n));
+    state.selected_template_index = position(selected);
+    state.merge_template_index = position(merge);
+    state.templates = templates;
+}
+
+/// 保存の結果を反映する（一覧を取り直せなかった場合は手元の一覧を書き換える）
+pub fn apply_saved(
+    state: &mut AppState,
+    template: Template,
+    original_name: Option<String>,
+    result: Result<String, ApiError>,
+    templates: Option<Vec<Template>>,
+) {
+    let renamed = original_name.as_deref().map(|from| (from, template.name.as_str()));
+    match result {
+        Ok(version) => {
+            let templates = templates.unwrap_or_else(|| {
+                let mut local = state.templates.clone();
+                let saved = Template { updated_at: version.clone(), ..template.clone() };
+                match original_name.as_ref().and_then(|n| local.iter().position(|t| &t.name == n)) {
+                    Some(i) => local[i] = saved,
+                    None => local.push(saved),
+                }
+                local
+            });
+            replace_templates(state, templates, renamed);
+
+            // 保存中に別のテンプレートを開いていなければ、編集中の版を進める
+            let editor = &mut state.template_editor;
+            if editor.original_name == original_name {
+                if let Some(draft) = editor.draft.as_mut() {
+                    draft.updated_at = version;
+                }
+                editor.original_name = Some(template.name.clone());
+                editor.conflict = None;
+            }
+            state.status_message = format!("✅ テンプレート「{}」を保存しました", template.name);
+        }
+        Err(e) => {
+            if let Some(templates) = templates {
+                replace_templates(state, templates, None);
+            }
+            if let ApiError::Conflict(message) = &e {
+                if state.template_editor.original_name == original_name {
+                    state.template_editor.conflict = Some(message.clone());
+                }
+            }
+            state.status_message = format!("❌ テンプレート保存エラー: {}", e);
+        }
+    }
+}
+
+/// 削除の結果を反映する
+pub fn apply_deleted(state: &mut AppState, name: &str, result: Result<(), ApiError>, templates: Option<Vec<Template>>) {
+    match result {
+        Ok(()) => {
+            let templates = templates.unwrap_or_else(|| {
+                state.templates.iter().filter(|t| t.name != name).cloned().collect()
+            });
+            replace_templates(state, templates, None);
+            if state.template_editor.original_name.as_deref() == Some(name) {
+                state.template_editor = Default::default();
+            }
+            state.status_message = format!("🗑 テンプレート「{}」を削除しました", name);
+        }
+        Err(e) => {
+            if let Some(templates) = templates {
+                replace_templates(state, templates, None);
+            }
+            if let ApiError::Conflict(message) = &e {
+                if state.template_editor.original_name.as_deref() == Some(name) {
+                    state.template_editor.conflict = Some(message.clone());
+                }
+            }
+            state.status_message = format!("❌ テンプレート削除エラー: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(name: &str) -> Template {
+        Template { name: name.to_string(), body: format!("{}の本文", name), updated_at: "v1".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_insert_at_char_position() {
+        let mut body = "株式会社様".to_string();
+        assert_eq!(insert_at(&mut body, 4, "{{name}}"), 12);
+        assert_eq!(body, "株式会社{{name}}様");
+        assert_eq!(insert_at(&mut body, 100, "!"), 14, "範囲外は末尾");
+    }
+
+    #[test]
+    fn test_rename_keeps_selection_and_dirty_state() {
+        let mut state = AppState { templates: vec![template("請求書"), template("見積書")], ..Default::default() };
+        state.selected_template_index = Some(1);
+        state.merge_template_index = Some(0);
+
+        open_existing(&mut state, 0);
+        assert!(!state.template_editor.is_dirty(&state.templates));
+        let mut draft = state.template_editor.draft.clone().unwrap();
+        draft.name = "請求書（新）".to_string();
+        state.template_editor.draft = Some(draft.clone());
+        assert!(state.template_editor.is_dirty(&state.templates));
+
+        // 未保存のまま別のテンプレートを開こうとすると確認する
+        open_existing(&mut state, 1);
+        assert!(state.template_editor.pending_open.is_some());
+        assert_eq!(state.template_editor.draft.as_ref().unwrap().name, "請求書（新）");
+        state.template_editor.pending_open = None;
+
+        let latest = vec![template("見積書"), Template { updated_at: "v2".to_string(), ..draft.clone() }];
+        apply_saved(&mut state, draft, Some("請求書".to_string()), Ok("v2".to_string()), Some(latest));
+
+        assert_eq!(state.selected_template_index, Some(0), "見積書を選んだまま");
+        assert_eq!(state.merge_template_index, Some(1), "名前を変えたテンプレートを選んだまま");
+        assert_eq!(state.template_editor.original_name.as_deref(), Some("請求書（新）"));
+        assert_eq!(state.template_editor.draft.as_ref().unwrap().updated_at, "v2");
+        assert!(!state.template_editor.is_dirty(&state.templates));
+    }
+}
@@ -1,11 +1,147 @@
-use crate::models::{RecipientData, RecipientInfo, Attachment};
+use crate::models::{RecipientData, RecipientInfo, Attachment, LinkingData};
+use crate::template_engine::{self, Context};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn apply_variables(mut text: String, recipient: &RecipientData) -> String {
-    text = text.replace("{{name}}", &recipient.name);
-    text = text.replace("{{company}}", &recipient.company);
-    text = text.replace("{{email}}", &recipient.email);
-    text = text.replace("{{id}}", &recipient.id);
-    text
+/// 現在時刻（UNIX秒）
+pub fn now_unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// ローカルで一意なIDを生成（時刻 + プロセスID + 連番）
+pub fn generate_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    format!("{:x}-{:x}-{:x}", nanos, std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// カンマ・セミコロン・読点・改行で区切られたアドレスを分割
+pub fn parse_address_list(s: &str) -> Vec<String> {
+    s.split([',', ';', '、', '\n'])
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// メールアドレスとして最低限の形式か（local@domain.tld）
+pub fn is_valid_email(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains('@')
+        && !address.chars().any(char::is_whitespace)
+}
+
+/// CC・BCC・返信先の形式と、To との重複をチェック
+pub fn validate_extra_addresses(recipient: &RecipientInfo) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    let to = recipient.email.trim().to_lowercase();
+
+    for (label, field) in [("CC", &recipient.cc), ("BCC", &recipient.bcc), ("返信先", &recipient.reply_to)] {
+        for address in parse_address_list(field) {
+            if !is_valid_email(&address) {
+                errors.push(format!("⚠️ {}「{}」はメールアドレスの形式ではありません", label, address));
+            } else if label != "返信先" && address.to_lowercase() == to {
+                errors.push(format!("⚠️ {}「{}」は To と同じアドレスです", label, address));
+            }
+        }
+    }
+
+    if parse_address_list(&recipient.reply_to).len() > 1 {
+        errors.push("⚠️ 返信先は1件だけ指定してください".to_string());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// 宛先マスターに保存する前のチェック（master に同じ ID の行があれば、その行との重複は除く）
+pub fn validate_recipient(recipient: &RecipientData, master: &[RecipientData]) -> Vec<String> {
+    let mut errors = Vec::new();
+    let email = recipient.email.trim();
+    if recipient.company.trim().is_empty() {
+        errors.push("⚠️ 会社名を入力してください".to_string());
+    }
+    if email.is_empty() {
+        errors.push("⚠️ メールアドレスを入力してください".to_string());
+    } else if !is_valid_email(email) {
+        errors.push(format!("⚠️「{}」はメールアドレスの形式ではありません", email));
+    } else if let Some(other) = master.iter()
+        .find(|r| r.id != recipient.id && r.email.trim().eq_ignore_ascii_case(email))
+    {
+        errors.push(format!("⚠️ {} は {} {} と重複しています", email, other.company, other.name));
+    }
+
+    let info = RecipientInfo {
+        email: email.to_string(),
+        cc: recipient.cc.clone(),
+        bcc: recipient.bcc.clone(),
+        reply_to: recipient.reply_to.clone(),
+        ..Default::default()
+    };
+    if let Err(extra) = validate_extra_addresses(&info) {
+        errors.extend(extra);
+    }
+    errors
+}
+
+/// 宛先に紐付いたテンプレート
+/// 添付ファイル名にキーワードを含む紐付けを優先し（複数なら長いキーワード）、なければキーワードなしの既定の紐付け
+pub fn find_linking<'a>(linkings: &'a [LinkingData], recipient_id: &str, file_names: &[String]) -> Option<&'a LinkingData> {
+    let normalize = |s: &str| s.replace([' ', '　'], "").to_lowercase();
+    let file_names: Vec<String> = file_names.iter().map(|n| normalize(n)).collect();
+    let links = || linkings.iter().filter(move |l| l.recipient_id == recipient_id);
+
+    links()
+        .filter(|l| {
+            let keyword = normalize(&l.keyword);
+            !keyword.is_empty() && file_names.iter().any(|n| n.contains(&keyword))
+        })
+        .max_by_key(|l| l.keyword.chars().count())
+        .or_else(|| links().find(|l| l.keyword.trim().is_empty()))
+}
+
+/// 宛先の値（標準項目と追加項目）を差し込み用に並べる
+pub fn recipient_context(recipient: &RecipientData) -> Context {
+    let mut ctx = Context::today();
+    ctx.set("name", &recipient.name);
+    ctx.set("company", &recipient.company);
+    ctx.set("email", &recipient.email);
+    ctx.set("id", &recipient.id);
+    for (field, value) in &recipient.custom_fields {
+        ctx.set(field, value);
+    }
+    ctx
+}
+
+pub fn apply_variables(text: String, recipient: &RecipientData) -> String {
+    template_engine::render(&text, &recipient_context(recipient))
+}
+
+/// 置き換えられずに残った `{{変数}}` を出現順に返す（重複なし）
+pub fn find_placeholders(text: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let placeholder = format!("{{{{{}}}}}", &after[..end]);
+        if !found.contains(&placeholder) {
+            found.push(placeholder);
+        }
+        rest = &after[end + 2..];
+    }
+    found
 }
 
 /// 文字列を正規化（スペース除去、小文字化）
@@ -95,22 +231,35 @@ pub fn validate_body_recipient_match(
     }
 }
 
-/// 重複チェック：同じ添付ファイルが複数の宛先に有効になっていないか
-pub fn validate_no_cross_recipient_attachments(
-    _recipients: &[RecipientInfo],
-    attachments: &[Attachment],
-    active_recipient_index: usize,
+/// 件名に他の宛先の会社名・氏名が入っていないかの照合
+/// 同じ件名を全宛先に使ったときに、差し込み済みの別の宛先の名前が残っていないかを見る
+pub fn validate_subject_recipient_match(
+    subject: &str,
+    recipient_data: Option<&RecipientData>,
+    others: &[&RecipientData],
 ) -> Result<(), Vec<String>> {
     let mut errors = Vec::new();
+    let subject_normalized = normalize_string(subject);
+    let own: Vec<String> = recipient_data
+        .map(|rec| vec![normalize_string(&rec.company), normalize_string(&rec.name)])
+        .unwrap_or_default();
 
-    for att in attachments.iter().filter(|a| a.enabled) {
-        if let Some(linked_idx) = att.linked_recipient_index {
-            if linked_idx != active_recipient_index {
-                errors.push(format!(
-                    "⚠️ 添付ファイル「{}」は宛先{}に紐付けられています",
-                    att.file_name,
-                    linked_idx + 1
-                ));
+    for other in others {
+        if recipient_data.is_some_and(|rec| rec.id == other.id) {
+            continue;
+        }
+        for value in [&other.company, &other.name] {
+            let normalized = normalize_string(value);
+            if normalized.is_empty() || !subject_normalized.contains(&normalized) {
+                continue;
+            }
+            // 同じ会社の別の担当者など、自分の会社名・氏名にも含まれる場合は問題にしない
+            if own.iter().any(|o| o.contains(&normalized)) {
+                continue;
+            }
+            let message = format!("⚠️ 件名に別の宛先の「{}」が含まれています", value);
+            if !errors.contains(&message) {
+                errors.push(message);
             }
         }
     }
@@ -130,14 +279,20 @@ pub fn validate_send_safety(
 ) -> Vec<String> {
     let mut all_errors = Vec::new();
 
+    // 宛先マスターから対応するデータを取得
+    let locked_data = |recipient: &RecipientInfo| recipient.locked_recipient_id.as_ref()
+        .and_then(|id| recipients_master.iter().find(|r| &r.id == id));
+    let all_recipient_data: Vec<&RecipientData> = recipients.iter()
+        .filter(|r| !r.email.is_empty())
+        .filter_map(locked_data)
+        .collect();
+
     for (idx, recipient) in recipients.iter().enumerate() {
         if recipient.email.is_empty() {
             continue;
         }
 
-        // 宛先マスターから対応するデータを取得
-        let recipient_data = recipient.locked_recipient_id.as_ref()
-            .and_then(|id| recipients_master.iter().find(|r| &r.id == id));
+        let recipient_data = locked_data(recipient);
 
         // 1. 添付ファイルと宛先の整合性チェック
         // この宛先に対して有効な添付ファイルをフィルタ
@@ -155,7 +310,27 @@ pub fn validate_send_safety(
             all_errors.extend(errs.into_iter().map(|e| format!("[宛先{}] {}", idx + 1, e)));
         }
 
-        // 3. ロックされた宛先IDと現在の宛先が一致しているか
+        // 件名と他の宛先の照合
+        if let Err(errs) = validate_subject_recipient_match(&recipient.subject, recipient_data, &all_recipient_data) {
+            all_errors.extend(errs.into_iter().map(|e| format!("[宛先{}] {}", idx + 1, e)));
+        }
+
+        // 3. CC・BCC・返信先の形式
+        if let Err(errs) = validate_extra_addresses(recipient) {
+            all_errors.extend(errs.into_iter().map(|e| format!("[宛先{}] {}", idx + 1, e)));
+        }
+
+        // 4. 件名が空でないか、件名・本文に置き換えられていない差し込み変数が残っていないか
+        if recipient.subject.trim().is_empty() {
+            all_errors.push(format!("[宛先{}] ⚠️ 件名が空です", idx + 1));
+        }
+        for (label, text) in [("件名", &recipient.subject), ("本文", &recipient.body)] {
+            for placeholder in find_placeholders(text) {
+                all_errors.push(format!("[宛先{}] ⚠️ {}の {} を置き換えられる値がありません", idx + 1, label, placeholder));
+            }
+        }
+
+        // 5. ロックされた宛先IDと現在の宛先が一致しているか
         if let Some(ref locked_id) = recipient.locked_recipient_id {
             let current_matches = recipient_data.map(|r| &r.id == locked_id).unwrap_or(false);
             if !current_matches && recipient_data.is_some() {
@@ -167,7 +342,21 @@ pub fn validate_send_safety(
         }
     }
 
-    // 4. 同じメールアドレスが複数の宛先に設定されていないか
+    // 6. 添付ファイルは紐付けられた宛先にだけ送られるため、送信先のないものを検出
+    for att in attachments.iter().filter(|a| a.enabled) {
+        let has_target = att.linked_recipient_index
+            .and_then(|idx| recipients.get(idx))
+            .map(|r| !r.email.is_empty())
+            .unwrap_or(false);
+        if !has_target {
+            all_errors.push(format!(
+                "⚠️ 添付ファイル「{}」の送信先の宛先が設定されていません",
+                att.file_name
+            ));
+        }
+    }
+
+    // 7. 同じメールアドレスが複数の宛先に設定されていないか
     let valid_emails: Vec<_> = recipients.iter()
         .filter(|r| !r.email.is_empty())
         .map(|r| &r.email)
@@ -187,3 +376,114 @@ pub fn validate_send_safety(
 
     all_errors
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address_list() {
+        assert_eq!(
+            parse_address_list(" sales@example.com, boss@example.com;archive@example.com、\n"),
+            vec!["sales@example.com", "boss@example.com", "archive@example.com"]
+        );
+    }
+
+    #[test]
+    fn test_find_linking_prefers_attachment_keyword() {
+        let link = |template_id: &str, keyword: &str| LinkingData {
+            recipient_id: "1".to_string(),
+            template_id: template_id.to_string(),
+            keyword: keyword.to_string(),
+        };
+        let linkings = vec![link("2", ""), link("3", "請求書"), link("4", "請求書 再発行"), link("5", "見積書")];
+        let find = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+            find_linking(&linkings, "1", &names).map(|l| l.template_id.clone())
+        };
+
+        assert_eq!(find(&["見積書_A社.pdf"]).as_deref(), Some("5"));
+        assert_eq!(find(&["請求書再発行_A社.pdf"]).as_deref(), Some("4"), "長いキーワードを優先");
+        assert_eq!(find(&["納品書.pdf"]).as_deref(), Some("2"), "一致しなければ既定");
+        assert_eq!(find(&[]).as_deref(), Some("2"));
+        assert!(find_linking(&linkings, "9", &[]).is_none());
+    }
+
+    #[test]
+    fn test_validate_recipient() {
+        let master = vec![RecipientData { id: "1".to_string(), email: "a@example.com".to_string(), ..Default::default() }];
+        let mut edited = RecipientData {
+            id: "1".to_string(),
+            company: "A社".to_string(),
+            email: " a@example.com ".to_string(),
+            ..Default::default()
+        };
+        assert!(validate_recipient(&edited, &master).is_empty(), "自分自身とは重複しない");
+
+        edited.id = String::new();
+        edited.email = "A@Example.com".to_string();
+        edited.cc = "a@example.com".to_string();
+        let errors = validate_recipient(&edited, &master);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("重複"));
+        assert!(errors[1].contains("To と同じ"));
+
+        edited.company = String::new();
+        edited.email = "a@".to_string();
+        assert_eq!(validate_recipient(&edited, &master).len(), 2);
+    }
+
+    #[test]
+    fn test_subject_with_other_recipient_company() {
+        let a = RecipientData { id: "1".to_string(), company: "株式会社A".to_string(), name: "田中 太郎".to_string(), ..Default::default() };
+        let b = RecipientData { id: "2".to_string(), company: "株式会社B".to_string(), name: "鈴木 花子".to_string(), ..Default::default() };
+        let others = [&a, &b];
+
+        assert!(validate_subject_recipient_match("株式会社B 御中 ご請求書", Some(&b), &others).is_ok());
+        let errors = validate_subject_recipient_match("株式会社A 田中太郎様 ご請求書", Some(&b), &others).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("株式会社A"));
+        assert!(validate_subject_recipient_match("ご請求書", Some(&b), &others).is_ok());
+    }
+
+    #[test]
+    fn test_validate_extra_addresses() {
+        let recipient = RecipientInfo {
+            email: "a@example.com".to_string(),
+            cc: "sales@example.com, A@example.com".to_string(),
+            bcc: "archive@".to_string(),
+            reply_to: "x@example.com, y@example.com".to_string(),
+            ..Default::default()
+        };
+
+        let errors = validate_extra_addresses(&recipient).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("To と同じ"));
+        assert!(errors[1].contains("archive@"));
+        assert!(errors[2].contains("1件だけ"));
+    }
+
+    #[test]
+    fn test_custom_fields_and_unknown_placeholders() {
+        let recipient = RecipientData {
+            company: "A社".to_string(),
+            custom_fields: [("部署".to_string(), "経理部".to_string()), ("契約番号".to_string(), "C-001".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let body = apply_variables("{{company}} {{部署}} 御中\n契約番号: {{契約番号}} {{支払期日}}".to_string(), &recipient);
+        assert_eq!(body, "A社 経理部 御中\n契約番号: C-001 {{支払期日}}");
+
+        let draft = [RecipientInfo {
+            email: "a@example.com".to_string(),
+            subject: "{{役職}} 様 ご請求書".to_string(),
+            body,
+            ..Default::default()
+        }];
+        let errors = validate_send_safety(&draft, &[], &[]);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].contains("件名の {{役職}}"));
+        assert!(errors[1].contains("本文の {{支払期日}}"));
+    }
+}
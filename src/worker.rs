@@ -0,0 +1,635 @@
+//! UIスレッドを止めないためのバックグラウンド処理
+//!
+//! 各パネルは `AppState::job_queue` に Job を積むだけにして、
+//! MailApp が1件ずつ別スレッドで実行し、進捗と結果をチャネルで受け取る。
+
+use crate::api::{ApiError, BatchSendReport, RecipientSendResult};
+use crate::audit::{self, AuditAction};
+use crate::auth::Role;
+use crate::backend::{create_backend, BackendConfig};
+use crate::models::{AppState, HistoryItem, LinkingData, MailDraft, PendingSendData, RecipientData, Signature, Template};
+use crate::outbox::{Disposition, OutboxItem};
+use crate::signature;
+use crate::utils::now_unix_secs;
+use eframe::egui;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+
+/// バックグラウンドで実行する処理
+pub enum Job {
+    /// 送信待ちのメールを送信する
+    /// pending は確認ダイアログからの送信時のみ（結果を下書きに反映するため）
+    /// held_draft は送信取り消しの猶予を経た送信の、送信時の下書き（失敗した宛先を戻すため）
+    SendOutbox { items: Vec<OutboxItem>, pending: Option<PendingSendData>, held_draft: Option<MailDraft> },
+    /// CSVから読み込んだ宛先をマスターに保存
+    ImportRecipients(Vec<RecipientData>),
+    RefreshHistory,
+    TestConnection,
+    SaveSettings(HashMap<String, String>),
+    /// original_name はシート上の元の名前（新規作成なら None）
+    SaveTemplate { template: Template, original_name: Option<String> },
+    DeleteTemplate(Template),
+    /// ID が空なら新規追加、そうでなければ ID で更新
+    SaveRecipient(RecipientData),
+    DeleteRecipient(RecipientData),
+    SaveLinking(LinkingData),
+    DeleteLinking(LinkingData),
+    /// original_name はシート上の元の名前（新規作成なら None）
+    SaveSignature { signature: Signature, original_name: Option<String> },
+    DeleteSignature(String),
+}
+
+impl Job {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Job::SendOutbox { pending: Some(_), .. } => "メール送信",
+            Job::SendOutbox { pending: None, .. } => "送信待ちの再送",
+            Job::ImportRecipients(_) => "宛先インポート",
+            Job::RefreshHistory => "履歴取得",
+            Job::TestConnection => "接続テスト",
+            Job::SaveSettings(_) => "設定保存",
+            Job::SaveTemplate { .. } => "テンプレート保存",
+            Job::DeleteTemplate(_) => "テンプレート削除",
+            Job::SaveRecipient(_) => "宛先保存",
+            Job::DeleteRecipient(_) => "宛先削除",
+            Job::SaveLinking(_) => "紐付け保存",
+            Job::DeleteLinking(_) => "紐付け削除",
+            Job::SaveSignature { .. } => "署名保存",
+            Job::DeleteSignature(_) => "署名削除",
+        }
+    }
+
+    /// ログイン中のユーザーがこの処理を実行できるか（UI で隠していても、ここで必ず確かめる）
+    pub fn permitted(&self, username: &str, role: Role) -> bool {
+        match self {
+            Job::SendOutbox { .. } | Job::RefreshHistory => true,
+            Job::SaveSettings(settings) => settings.keys().all(|key| signature::may_save_setting(key, username, role)),
+            Job::TestConnection => role >= Role::Admin,
+            Job::ImportRecipients(_)
+            | Job::SaveTemplate { .. }
+            | Job::DeleteTemplate(_)
+            | Job::SaveRecipient(_)
+            | Job::DeleteRecipient(_)
+            | Job::SaveLinking(_)
+            | Job::DeleteLinking(_)
+            | Job::SaveSignature { .. }
+            | Job::DeleteSignature(_) => role >= Role::TemplateEditor,
+        }
+    }
+
+    fn total(&self) -> usize {
+        match self {
+            Job::SendOutbox { items, .. } => items.len(),
+            Job::ImportRecipients(recipients) => recipients.len(),
+            _ => 1,
+        }
+    }
+}
+
+/// 実行中の処理の進捗（ステータスバーに表示）
+#[derive(Clone, Debug)]
+pub struct JobProgress {
+    pub title: String,
+    pub done: usize,
+    pub total: usize,
+    pub detail: String,
+    pub is_send: bool,
+    pub cancel_requested: bool,
+    /// 処理を始めたユーザー（監査ログ用。終わる前にログアウトしても、このユーザーの操作として残す）
+    pub started_by: String,
+}
+
+pub enum JobEvent {
+    Progress { done: usize, total: usize, detail: String },
+    Finished(Box<JobOutcome>),
+}
+
+pub enum JobOutcome {
+    /// report.results は送信を試みた分だけ（キャンセル以降の items は含まない）
+    Sent { items: Vec<OutboxItem>, pending: Option<PendingSendData>, held_draft: Option<MailDraft>, report: BatchSendReport },
+    Imported { saved: Vec<RecipientData>, failed: usize, cancelled: bool },
+    HistoryLoaded(Result<Vec<HistoryItem>, ApiError>),
+    ConnectionTested(Result<(), ApiError>),
+    SettingsSaved(Result<(), ApiError>),
+    /// 保存・削除の後はシートの最新のテンプレート一覧を取り直す（失敗時は None）
+    /// 保存に成功した場合、result は保存後の版
+    TemplateSaved {
+        template: Template,
+        original_name: Option<String>,
+        result: Result<String, ApiError>,
+        templates: Option<Vec<Template>>,
+    },
+    /// テンプレートを削除するとその紐付けも消えるので、紐付けも取り直す
+    TemplateDeleted {
+        name: String,
+        result: Result<(), ApiError>,
+        templates: Option<Vec<Template>>,
+        linkings: Option<Vec<LinkingData>>,
+    },
+    /// 宛先の保存・削除の後も宛先マスターを取り直す（失敗時は None）
+    RecipientSaved { recipient: RecipientData, result: Result<(), ApiError>, recipients: Option<Vec<RecipientData>> },
+    /// 宛先を削除するとその紐付けも消えるので、紐付けも取り直す
+    RecipientDeleted {
+        recipient: RecipientData,
+        result: Result<(), ApiError>,
+        recipients: Option<Vec<RecipientData>>,
+        linkings: Option<Vec<LinkingData>>,
+    },
+    /// deleted は削除の結果か。後で紐付けマスターを取り直す（失敗時は None）
+    LinkingChanged { linking: LinkingData, deleted: bool, result: Result<(), ApiError>, linkings: Option<Vec<LinkingData>> },
+    /// 署名の保存・削除の後も署名一覧を取り直す（失敗時は None）
+    SignatureSaved {
+        signature: Signature,
+        original_name: Option<String>,
+        result: Result<(), ApiError>,
+        signatures: Option<Vec<Signature>>,
+    },
+    SignatureDeleted { name: String, result: Result<(), ApiError>, signatures: Option<Vec<Signature>> },
+}
+
+/// 処理の開始時に表示する進捗
+pub fn initial_progress(job: &Job, started_by: &str) -> JobProgress {
+    JobProgress {
+        title: job.title().to_string(),
+        done: 0,
+        total: job.total(),
+        detail: "開始中...".to_string(),
+        is_send: matches!(job, Job::SendOutbox { pending: Some(_), .. }),
+        cancel_requested: false,
+        started_by: started_by.to_string(),
+    }
+}
+
+/// 別スレッドで処理を実行する。キャンセルは宛先ごとの区切りで反映される
+pub fn spawn_job(
+    job: Job,
+    config: BackendConfig,
+    cancel: Arc<AtomicBool>,
+    events: Sender<JobEvent>,
+    ctx: egui::Context,
+) {
+    thread::spawn(move || {
+        let backend = create_backend(&config);
+        let progress = |done: usize, total: usize, detail: String| {
+            let _ = events.send(JobEvent::Progress { done, total, detail });
+            ctx.request_repaint();
+        };
+
+        let outcome = match job {
+            Job::SendOutbox { items, pending, held_draft } => {
+                let total = items.len();
+                let mut results = Vec::with_capacity(total);
+
+                // 1通ずつ送信して宛先ごとに進捗を通知する
+                for (i, item) in items.iter().enumerate() {
+                    if cancel.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    progress(i, total, format!("{} に送信中...", item.to));
+                    match backend.send_batch_mail(&[item.as_batch_item()]) {
+                        Ok(report) => results.extend(report.results),
+                        Err(e) => results.push(RecipientSendResult {
+                            to: item.to.clone(),
+                            success: false,
+                            error: Some(e.to_string()),
+                            retryable: e.is_retryable(),
+                        }),
+                    }
+                }
+                progress(results.len(), total, "完了".to_string());
+
+                JobOutcome::Sent { items, pending, held_draft, report: BatchSendReport { results } }
+            }
+            Job::ImportRecipients(recipients) => {
+                let total = recipients.len();
+                let mut saved = Vec::new();
+                let mut failed = 0;
+                let mut cancelled = false;
+
+                for (i, rec) in recipients.into_iter().enumerate() {
+                    if cancel.load(Ordering::SeqCst) {
+                        cancelled = true;
+                        break;
+                    }
+                    progress(i, total, format!("{} を保存中...", rec.name));
+                    match backend.save_recipient(&rec) {
+                        Ok(_) => saved.push(rec),
+                        Err(_) => failed += 1,
+                    }
+                }
+
+                JobOutcome::Imported { saved, failed, cancelled }
+            }
+            Job::RefreshHistory => JobOutcome::HistoryLoaded(backend.get_history()),
+            Job::TestConnection => JobOutcome::ConnectionTested(backend.test_connection()),
+            Job::SaveSettings(settings) => JobOutcome::SettingsSaved(backend.save_settings(&settings)),
+            Job::SaveTemplate { template, original_name } => {
+                let result = backend.save_template(&template, original_name.as_deref());
+                JobOutcome::TemplateSaved { template, original_name, result, templates: backend.get_templates().ok() }
+            }
+            Job::DeleteTemplate(template) => {
+                let result = backend.delete_template(&template.name, &template.updated_at);
+                JobOutcome::TemplateDeleted {
+                    name: template.name,
+                    result,
+                    templates: backend.get_templates().ok(),
+                    linkings: backend.get_linkings().ok(),
+                }
+            }
+            Job::SaveRecipient(recipient) => {
+                let result = if recipient.id.is_empty() {
+                    backend.save_recipient(&recipient)
+                } else {
+                    backend.update_recipient(&recipient)
+                };
+                JobOutcome::RecipientSaved { recipient, result, recipients: backend.get_recipients().ok() }
+            }
+            Job::DeleteRecipient(recipient) => {
+                let result = backend.delete_recipient(&recipient.id);
+                JobOutcome::RecipientDeleted {
+                    recipient,
+                    result,
+                    recipients: backend.get_recipients().ok(),
+                    linkings: backend.get_linkings().ok(),
+                }
+            }
+            Job::SaveLinking(linking) => {
+                let result = backend.save_linking(&linking);
+                JobOutcome::LinkingChanged { linking, deleted: false, result, linkings: backend.get_linkings().ok() }
+            }
+            Job::DeleteLinking(linking) => {
+                let result = backend.delete_linking(&linking);
+                JobOutcome::LinkingChanged { linking, deleted: true, result, linkings: backend.get_linkings().ok() }
+            }
+            Job::SaveSignature { signature, original_name } => {
+                let result = backend.save_signature(&signature, original_name.as_deref());
+                JobOutcome::SignatureSaved { signature, original_name, result, signatures: backend.get_signatures().ok() }
+            }
+            Job::DeleteSignature(name) => {
+                let result = backend.delete_signature(&name);
+                JobOutcome::SignatureDeleted { name, result, signatures: backend.get_signatures().ok() }
+            }
+        };
+
+        let _ = events.send(JobEvent::Finished(Box::new(outcome)));
+        ctx.request_repaint();
+    });
+}
+
+/// 宛先ごとの送信結果（送信待ちに反映した後の扱い）
+#[derive(Clone, Debug, PartialEq)]
+pub enum SendOutcome {
+    Sent,
+    /// 通信障害のため送信待ちに残し、後で自動的に再送する
+    Queued,
+    Failed(String),
+    Cancelled,
+}
+
+/// 処理結果を画面の状態に反映する（started_by は処理を始めたユーザー）
+pub fn apply_outcome(state: &mut AppState, outcome: JobOutcome, started_by: &str) {
+    // マスターを取り直す前に、名前を引けるうちに記録する内容を作っておく
+    let audit_entries = audit_entries(state, &outcome, started_by);
+    match outcome {
+        JobOutcome::Sent { items, pending, held_draft, report } => {
+            let now = now_unix_secs();
+            let outcomes: Vec<SendOutcome> = items.iter().enumerate()
+                .map(|(i, item)| match report.results.get(i) {
+                    Some(result) => match state.outbox.record_result(&item.id, result, now) {
+                        Disposition::Sent => SendOutcome::Sent,
+                        Disposition::RetryLater => SendOutcome::Queued,
+                        Disposition::Failed => SendOutcome::Failed(
+                            result.error.clone().unwrap_or_else(|| "不明なエラー".to_string())
+                        ),
+                    },
+                    None => SendOutcome::Cancelled,
+                })
+                .collect();
+
+            match pending {
+                Some(pending) => {
+                    // 確認ダイアログからの送信: 失敗・キャンセルした宛先は下書きに戻すので送信待ちから外す
+                    for (item, outcome) in items.iter().zip(&outcomes) {
+                        if matches!(outcome, SendOutcome::Failed(_) | SendOutcome::Cancelled) {
+                            state.outbox.remove(&item.id);
+                        }
+                    }
+                    crate::ui::mail_panel::apply_send_outcomes(state, &pending, &outcomes, held_draft.as_ref());
+                }
+                None => {
+                    // キャンセルで送らなかった分は送信待ちのまま
+                    for (item, outcome) in items.iter().zip(&outcomes) {
+                        if *outcome == SendOutcome::Cancelled {
+                            state.outbox.retry_now(&item.id, now);
+                        }
+                    }
+                    let count = |f: fn(&SendOutcome) -> bool| outcomes.iter().filter(|o| f(o)).count();
+                    state.status_message = format!(
+                        "送信待ちの再送: {}件送信, {}件再送待ち, {}件失敗",
+                        count(|o| *o == SendOutcome::Sent),
+                        count(|o| matches!(o, SendOutcome::Queued | SendOutcome::Cancelled)),
+                        count(|o| matches!(o, SendOutcome::Failed(_))),
+                    );
+                }
+            }
+
+            if let Err(e) = state.outbox.save() {
+                state.status_message = format!("❌ {}", e);
+            }
+        }
+        JobOutcome::Imported { saved, failed, cancelled } => {
+            let imported_count = saved.len();
+            state.recipients_master.extend(saved);
+            state.status_message = if cancelled {
+                format!("⚠ CSVインポートをキャンセルしました: {}件成功, {}件失敗", imported_count, failed)
+            } else if failed == 0 {
+                format!("✅ CSVから{}件の宛先をインポートしました", imported_count)
+            } else {
+                format!("⚠ CSVインポート: {}件成功, {}件失敗", imported_count, failed)
+            };
+        }
+        JobOutcome::HistoryLoaded(result) => {
+            match result {
+                Ok(logs) => {
+                    state.history = logs;
+                    state.status_message = "履歴を更新しました".to_string();
+                }
+                Err(e) => state.status_message = format!("履歴取得エラー: {}", e),
+            }
+            state.is_loading = false;
+        }
+        JobOutcome::ConnectionTested(result) => {
+            state.status_message = match result {
+                Ok(_) => "✅ 接続成功！".to_string(),
+                Err(e) => format!("❌ {}", e),
+            };
+        }
+        JobOutcome::SettingsSaved(result) => {
+            if let Err(e) = result {
+                state.status_message = format!("❌ 設定保存エラー: {}", e);
+            }
+        }
+        JobOutcome::TemplateSaved { template, original_name, result, templates } => {
+            crate::ui::template_panel::apply_saved(state, template, original_name, result, templates);
+        }
+        JobOutcome::TemplateDeleted { name, result, templates, linkings } => {
+            if let Some(linkings) = linkings {
+                state.linkings_master = linkings;
+            }
+            crate::ui::template_panel::apply_deleted(state, &name, result, templates);
+        }
+        JobOutcome::RecipientSaved { recipient, result, recipients } => {
+            crate::ui::recipient_panel::apply_saved(state, recipient, result, recipients);
+        }
+        JobOutcome::RecipientDeleted { recipient, result, recipients, linkings } => {
+            if let Some(linkings) = linkings {
+                state.linkings_master = linkings;
+            }
+            crate::ui::recipient_panel::apply_deleted(state, recipient, result, recipients);
+        }
+        JobOutcome::LinkingChanged { deleted, result, linkings, .. } => {
+            if let Some(linkings) = linkings {
+                state.linkings_master = linkings;
+            }
+            let action = if deleted { "削除" } else { "保存" };
+            state.status_message = match result {
+                Ok(()) => format!("✅ 紐付けを{}しました", action),
+                Err(e) => format!("❌ 紐付けの{}エラー: {}", action, e),
+            };
+        }
+        JobOutcome::SignatureSaved { signature, original_name, result, signatures } => {
+            crate::ui::signature_panel::apply_saved(state, signature, original_name, result, signatures);
+        }
+        JobOutcome::SignatureDeleted { name, result, signatures } => {
+            crate::ui::signature_panel::apply_deleted(state, &name, result, signatures);
+        }
+    }
+
+    for (user, action, detail) in audit_entries {
+        audit::record_as(state, &user, action, detail);
+    }
+}
+
+/// 監査ログに残す (ユーザー, 操作, 内容)。成功した変更と、送信を試みた結果だけを記録する
+fn audit_entries(state: &AppState, outcome: &JobOutcome, started_by: &str) -> Vec<(String, AuditAction, String)> {
+    let current = |action, detail: String| vec![(started_by.to_string(), action, detail)];
+    match outcome {
+        JobOutcome::Sent { items, report, .. } => items.iter()
+            .zip(&report.results)
+            .map(|(item, result)| {
+                // 送信待ちからの再送は、送信を指示したユーザーの操作として残す
+                let user = if item.queued_by.is_empty() { started_by } else { &item.queued_by };
+                let detail = match (&result.success, &result.error) {
+                    (true, _) => format!("{}「{}」", item.to, item.subject),
+                    (false, error) => format!("{}「{}」失敗: {}", item.to, item.subject, error.as_deref().unwrap_or("不明なエラー")),
+                };
+                (user.to_string(), AuditAction::Send, detail)
+            })
+            .collect(),
+        JobOutcome::Imported { saved, .. } if !saved.is_empty() => {
+            current(AuditAction::Recipient, format!("CSVから{}件をインポート", saved.len()))
+        }
+        JobOutcome::TemplateSaved { template, original_name, result: Ok(_), .. } => {
+            let detail = match original_name {
+                Some(original) if *original != template.name => format!("「{}」を「{}」に名前を変えて保存", original, template.name),
+                Some(_) => format!("「{}」を保存", template.name),
+                None => format!("「{}」を作成", template.name),
+            };
+            current(AuditAction::Template, detail)
+        }
+        JobOutcome::TemplateDeleted { name, result: Ok(()), .. } => {
+            current(AuditAction::Template, format!("「{}」を削除", name))
+        }
+        JobOutcome::RecipientSaved { recipient, result: Ok(()), .. } => {
+            let verb = if recipient.id.is_empty() { "追加" } else { "更新" };
+            current(AuditAction::Recipient, format!("{} {} <{}> を{}", recipient.company, recipient.name, recipient.email, verb))
+        }
+        JobOutcome::RecipientDeleted { recipient, result: Ok(()), .. } => {
+            current(AuditAction::Recipient, format!("{} {} <{}> を削除", recipient.company, recipient.name, recipient.email))
+        }
+        JobOutcome::LinkingChanged { linking, deleted, result: Ok(()), .. } => {
+            let recipient = state.recipients_master.iter()
+                .find(|r| r.id == linking.recipient_id)
+                .map(|r| format!("{} {}", r.company, r.name))
+                .unwrap_or_else(|| format!("宛先 ID {}", linking.recipient_id));
+            let template = state.templates.iter()
+                .find(|t| t.id == linking.template_id)
+                .map(|t| t.name.clone())
+                .unwrap_or_else(|| format!("テンプレート ID {}", linking.template_id));
+            let keyword = if linking.keyword.is_empty() { "既定" } else { &linking.keyword };
+            let verb = if *deleted { "削除" } else { "保存" };
+            current(AuditAction::Linking, format!("{}（{}）→ {} を{}", recipient, keyword, template, verb))
+        }
+        JobOutcome::SignatureSaved { signature, original_name, result: Ok(()), .. } => {
+            let detail = match original_name {
+                Some(original) if *original != signature.name => format!("「{}」を「{}」に名前を変えて保存", original, signature.name),
+                Some(_) => format!("「{}」を保存", signature.name),
+                None => format!("「{}」を作成", signature.name),
+            };
+            current(AuditAction::Signature, detail)
+        }
+        JobOutcome::SignatureDeleted { name, result: Ok(()), .. } => {
+            current(AuditAction::Signature, format!("「{}」を削除", name))
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendKind;
+    use crate::mock_gas::{MockGasServer, MOCK_SHARED_SECRET};
+    use crate::outbox::Outbox;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn config(url: String) -> BackendConfig {
+        BackendConfig {
+            kind: BackendKind::Gas,
+            gas_url: url,
+            gas_secret: MOCK_SHARED_SECRET.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 処理を実行して、進捗イベントの数と結果を返す
+    fn run(job: Job, url: String, cancel: bool) -> (usize, JobOutcome) {
+        let (tx, rx) = mpsc::channel();
+        spawn_job(job, config(url), Arc::new(AtomicBool::new(cancel)), tx, egui::Context::default());
+
+        let mut progress_events = 0;
+        loop {
+            match rx.recv_timeout(Duration::from_secs(10)).expect("job finished") {
+                JobEvent::Progress { .. } => progress_events += 1,
+                JobEvent::Finished(outcome) => return (progress_events, *outcome),
+            }
+        }
+    }
+
+    /// 送信待ちに入れてから送る送信ジョブ
+    fn send_job_from(outbox: &mut Outbox, pending: PendingSendData) -> Job {
+        let items = outbox.enqueue(&pending, 0);
+        Job::SendOutbox { items, pending: Some(pending), held_draft: None }
+    }
+
+    fn send_job(emails: &[&str]) -> Job {
+        send_job_from(&mut Outbox::default(), PendingSendData::sample(emails))
+    }
+
+    #[test]
+    fn test_send_job_reports_progress_per_recipient() {
+        let server = MockGasServer::start();
+        let (progress_events, outcome) = run(send_job(&["a@example.com", "b@example.com"]), server.url(), false);
+
+        // 宛先ごとの進捗 + 完了
+        assert_eq!(progress_events, 3);
+        let JobOutcome::Sent { report, .. } = outcome else { panic!("送信結果を期待") };
+        assert_eq!(report.results.len(), 2);
+        assert!(report.is_all_success());
+        assert_eq!(server.sent().len(), 2);
+    }
+
+    #[test]
+    fn test_cancelled_send_leaves_recipients_unsent() {
+        let server = MockGasServer::start();
+        let (_, outcome) = run(send_job(&["a@example.com"]), server.url(), true);
+
+        let JobOutcome::Sent { report, .. } = outcome else { panic!("送信結果を期待") };
+        assert!(report.results.is_empty());
+        assert!(server.sent().is_empty());
+    }
+
+    #[test]
+    fn test_template_conflict_keeps_editor_draft() {
+        let server = MockGasServer::start();
+        let mut state = AppState::default();
+        let (_, outcome) = run(Job::SaveTemplate {
+            template: Template { name: "請求書".to_string(), body: "v1".to_string(), ..Default::default() },
+            original_name: None,
+        }, server.url(), false);
+        apply_outcome(&mut state, outcome, "sato");
+        assert_eq!(state.templates.len(), 1);
+        let stale = state.templates[0].clone();
+
+        // 別の端末で更新された後に、古い版を元に編集して保存する
+        let mut theirs = stale.clone();
+        theirs.body = "他の人の変更".to_string();
+        run(Job::SaveTemplate { template: theirs, original_name: Some("請求書".to_string()) }, server.url(), false);
+
+        let mut mine = stale.clone();
+        mine.body = "自分の変更".to_string();
+        state.template_editor.open(mine.clone(), Some("請求書".to_string()));
+        let (_, outcome) = run(Job::SaveTemplate { template: mine, original_name: Some("請求書".to_string()) }, server.url(), false);
+        apply_outcome(&mut state, outcome, "sato");
+
+        assert!(state.template_editor.conflict.is_some());
+        assert_eq!(state.template_editor.draft.as_ref().unwrap().body, "自分の変更", "編集内容は残す");
+        assert_eq!(state.templates[0].body, "他の人の変更", "一覧は最新に更新");
+    }
+
+    #[test]
+    fn test_network_failure_keeps_mail_in_outbox() {
+        // 接続できないURL
+        let mut state = AppState::default();
+        let job = send_job_from(&mut state.outbox, PendingSendData::sample(&["a@example.com"]));
+        let (_, outcome) = run(job, "http://127.0.0.1:9/exec".to_string(), false);
+
+        apply_outcome(&mut state, outcome, "sato");
+
+        assert_eq!(state.outbox.items().len(), 1);
+        assert_eq!(state.outbox.items()[0].attempts, 1);
+        assert!(state.send_failures.is_empty(), "再送待ちは失敗として扱わない");
+    }
+
+    #[test]
+    fn test_sends_and_edits_are_audited() {
+        let server = MockGasServer::start();
+        let mut state = AppState { auth_username: "sato".to_string(), ..Default::default() };
+        let pending = PendingSendData { sent_by: "tanaka".to_string(), ..PendingSendData::sample(&["a@example.com"]) };
+        let (_, outcome) = run(send_job_from(&mut state.outbox, pending), server.url(), false);
+        apply_outcome(&mut state, outcome, "sato");
+
+        // 失敗した変更は記録しない
+        let (_, outcome) = run(Job::SaveTemplate {
+            template: Template { name: "督促".to_string(), body: "{{#if department}}閉じていない".to_string(), ..Default::default() },
+            original_name: None,
+        }, server.url(), false);
+        apply_outcome(&mut state, outcome, "sato");
+        // 保存が終わる前にログアウトして別のユーザーがログインしても、保存したユーザーの操作として残す
+        let (_, outcome) = run(Job::SaveTemplate {
+            template: Template { name: "請求書".to_string(), body: "本文".to_string(), ..Default::default() },
+            original_name: None,
+        }, server.url(), false);
+        state.auth_username = "suzuki".to_string();
+        apply_outcome(&mut state, outcome, "sato");
+
+        let entries = state.audit_log.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].user.as_str(), entries[0].action), ("tanaka", AuditAction::Send), "送信を指示したユーザー");
+        assert!(entries[0].detail.starts_with("a@example.com"));
+        assert_eq!((entries[1].user.as_str(), entries[1].action), ("sato", AuditAction::Template));
+        assert_eq!(state.audit_log.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_jobs_are_gated_by_role() {
+        let template = Job::DeleteTemplate(Template::default());
+        assert!(!template.permitted("tanaka", Role::Sender));
+        assert!(template.permitted("tanaka", Role::TemplateEditor));
+        assert!(!Job::TestConnection.permitted("suzuki", Role::TemplateEditor));
+
+        // 設定は自分の分だけ。共通の設定は管理者だけ
+        let own = Job::SaveSettings(signature::signature_setting("tanaka", "営業用"));
+        assert!(own.permitted("tanaka", Role::Sender));
+        assert!(!own.permitted("sato", Role::Sender));
+        assert!(own.permitted("sato", Role::TemplateEditor), "署名の名前変更に伴う書き換え");
+        let global = Job::SaveSettings(HashMap::from([("undo_send_seconds".to_string(), "0".to_string())]));
+        assert!(!global.permitted("tanaka", Role::TemplateEditor));
+        assert!(global.permitted("tanaka", Role::Admin));
+    }
+}